@@ -0,0 +1,3 @@
+fn main() {
+    app_args::report();
+}
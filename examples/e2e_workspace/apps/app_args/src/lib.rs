@@ -0,0 +1,15 @@
+/// Prints the enabled scope, the build profile and the program arguments, one per line.
+pub fn report() {
+    println!("{}", codec::yaml());
+    println!(
+        "{}",
+        if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        }
+    );
+    for arg in std::env::args().skip(1) {
+        println!("{arg}");
+    }
+}
@@ -0,0 +1,4 @@
+fn main() {
+    println!("{}", codec::banner());
+    println!("{}", codec::json());
+}
@@ -0,0 +1,16 @@
+fn main() {
+    println!("{}", codec::banner());
+    println!("{}", codec::json());
+    println!("{}", codec::yaml());
+    println!("{}", telemetry::metrics());
+    println!("{}", telemetry::observed());
+    println!("{}", telemetry::metrics_only());
+
+    let config: telemetry::Config =
+        serde_json::from_str(r#"{"interval": 5, "metrics": {"port": 9000}}"#).unwrap();
+    match config.metrics() {
+        Some(metrics) => println!("metrics port {}", metrics.port),
+        None => println!("metrics off"),
+    }
+    println!("{}", serde_json::to_string(&config).unwrap());
+}
@@ -0,0 +1,14 @@
+fn main() {
+    println!("{}", codec::json());
+    println!("{}", codec::yaml());
+    println!("{}", codec::pretty(codec::json()));
+    println!("{}", codec::value());
+    println!("{}", codec::raw());
+    println!("{}", telemetry::trace());
+    println!("{}", telemetry::spans());
+    println!("{}", telemetry::metrics());
+    println!("{}", telemetry::log_level());
+    println!("{}", telemetry::log());
+    println!("{}", telemetry::observed());
+    println!("{}", greeting::greet());
+}
@@ -0,0 +1,12 @@
+fn main() {
+    println!("{}", codec::yaml());
+    println!("built for {}", codec::built_for());
+
+    let config: telemetry::Config =
+        serde_json::from_str(r#"{"interval": 5, "metrics": {"port": 9000}}"#).unwrap();
+    match config.metrics() {
+        Some(metrics) => println!("metrics port {}", metrics.port),
+        None => println!("metrics off"),
+    }
+    println!("{}", serde_json::to_string(&config).unwrap());
+}
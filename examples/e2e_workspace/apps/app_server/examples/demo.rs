@@ -0,0 +1,4 @@
+fn main() {
+    println!("demo");
+    println!("{}", service::describe());
+}
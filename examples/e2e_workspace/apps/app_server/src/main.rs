@@ -0,0 +1,4 @@
+fn main() {
+    println!("server");
+    println!("{}", service::describe());
+}
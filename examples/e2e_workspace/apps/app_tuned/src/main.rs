@@ -0,0 +1,6 @@
+fn main() {
+    println!("{}", storage::scopes());
+    println!("{}", codec::json());
+    println!("{}", codec::yaml());
+    println!("{}", codec::pretty(codec::json()));
+}
@@ -0,0 +1,10 @@
+// Records which package the scopes were resolved for, see `FEATURE_SCOPE_TARGET_PACKAGE`, and
+// passes the scopes of that package to plain cargo builds
+fn main() {
+    println!("cargo:rerun-if-env-changed=FEATURE_SCOPE_TARGET_PACKAGE");
+
+    let target = std::env::var("FEATURE_SCOPE_TARGET_PACKAGE").unwrap_or_default();
+    println!("cargo:rustc-env=CODEC_BUILT_FOR={target}");
+
+    feature_scope_core::load().unwrap();
+}
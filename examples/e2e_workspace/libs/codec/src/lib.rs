@@ -0,0 +1,40 @@
+feature_scope::feature_scope_setup!();
+
+use feature_scope::{feature_scope, feature_scope_default};
+
+#[feature_scope_default]
+pub fn banner() -> &'static str {
+    "codec defaults"
+}
+
+#[feature_scope_default(json)]
+pub fn json() -> &'static str {
+    "json"
+}
+
+#[feature_scope(yaml)]
+pub fn yaml() -> &'static str {
+    "yaml"
+}
+
+#[feature_scope(pretty)]
+pub fn pretty(input: &str) -> String {
+    format!("pretty {input}")
+}
+
+#[feature_scope(value)]
+pub fn value() -> String {
+    serde_json::json!({ "scope": "value" }).to_string()
+}
+
+#[feature_scope(raw)]
+pub fn raw() -> String {
+    serde_json::value::RawValue::from_string(String::from("[1, 2]"))
+        .unwrap()
+        .get()
+        .to_string()
+}
+
+pub fn built_for() -> &'static str {
+    env!("CODEC_BUILT_FOR")
+}
@@ -0,0 +1,7 @@
+use feature_scope::cfg_scope;
+
+/// The scopes of `storage` the service runs on, and whether it audits.
+pub fn describe() -> String {
+    let audit = if cfg_scope!(audit) { "audit" } else { "no audit" };
+    format!("service ({audit}) on {}", storage::scopes())
+}
@@ -0,0 +1,19 @@
+use feature_scope::cfg_scope;
+
+/// The enabled scopes, like `storage: sqlite, cache`.
+pub fn scopes() -> String {
+    let scopes = [
+        ("sqlite", cfg_scope!(sqlite)),
+        ("postgres", cfg_scope!(postgres)),
+        ("cache", cfg_scope!(cache)),
+        ("pool", cfg_scope!(pool)),
+        ("simd", cfg_scope!(simd)),
+        ("iocp", cfg_scope!(iocp)),
+        ("epoll", cfg_scope!(epoll)),
+    ];
+    let enabled: Vec<&str> = scopes
+        .into_iter()
+        .filter_map(|(name, enabled)| enabled.then_some(name))
+        .collect();
+    format!("storage: {}", enabled.join(", "))
+}
@@ -0,0 +1,4 @@
+// Passes the scopes of `FEATURE_SCOPE_TARGET_PACKAGE` to plain cargo builds
+fn main() {
+    feature_scope_core::load().unwrap();
+}
@@ -0,0 +1,57 @@
+use feature_scope::feature_scope;
+
+// `trace` is also declared in Cargo.toml, and both declarations have to agree
+feature_scope::declare_feature_scopes! {
+    trace;
+    spans = [trace];
+}
+
+#[feature_scope(trace)]
+pub fn trace() -> &'static str {
+    "trace"
+}
+
+#[feature_scope(feature = "metrics")]
+pub fn metrics() -> &'static str {
+    "metrics"
+}
+
+#[feature_scope(spans)]
+pub fn spans() -> &'static str {
+    "spans"
+}
+
+#[feature_scope(feature = "log-level")]
+pub fn log_level() -> &'static str {
+    "log-level"
+}
+
+#[feature_scope(日志)]
+pub fn log() -> &'static str {
+    "日志"
+}
+
+#[feature_scope(any(trace, metrics))]
+pub fn observed() -> &'static str {
+    "observed"
+}
+
+#[feature_scope(all(metrics, not(trace)))]
+pub fn metrics_only() -> &'static str {
+    "metrics only"
+}
+
+// Configs keep accepting the `metrics` key when the scope is off, even with unknown fields denied
+#[feature_scope::feature_scope_serde]
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub interval: u32,
+    #[feature_scope_serde(metrics)]
+    pub metrics: MetricsConfig,
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct MetricsConfig {
+    pub port: u16,
+}
@@ -0,0 +1,114 @@
+//! Named copies of the binaries of a variant build.
+//!
+//! `cargo feature-scope build --variant lite --artifact-dir dist` copies every executable the
+//! build produces to `dist/<name>-lite`, so packaging scripts pick up the flavors of a binary
+//! from one place instead of from `target/feature-scope/<variant>/<profile>`. The copies are
+//! named by `artifact-name` in the `feature-scope-options` of the root manifest, with `{name}`
+//! standing for the binary and `{variant}` for the variant, and keep the extension of the
+//! binary, like `.exe` on Windows:
+//!
+//! ```toml
+//! [workspace.metadata.feature-scope-options]
+//! artifact-name = "{name}-{variant}"
+//! ```
+//!
+//! The executables are the ones cargo reports for the build. They are asked for by replaying it
+//! with `--message-format json`, which compiles nothing once the build has finished.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    fs,
+    io::BufRead,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// The pattern naming the copies when the root manifest gives none.
+pub const DEFAULT_PATTERN: &str = "{name}-{variant}";
+
+// The part of cargo's JSON messages naming the executables
+#[derive(Debug, Deserialize)]
+struct Message {
+    reason: String,
+    executable: Option<PathBuf>,
+}
+
+/// Returns the executables of a finished build, from the messages `cmd`, the same build with
+/// `--message-format json`, prints.
+pub fn executables(mut cmd: process::Command) -> Result<Vec<PathBuf>> {
+    let output = cmd
+        .stderr(process::Stdio::null())
+        .output()
+        .with_context(|| "Failed to execute cargo command")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to list the binaries of the build, cargo exited with {}",
+            output.status
+        );
+    }
+
+    let mut executables = Vec::new();
+    for line in output.stdout.lines() {
+        // Build scripts may print lines of their own among the messages
+        let Ok(message) = serde_json::from_str::<Message>(&line?) else {
+            continue;
+        };
+        if message.reason != "compiler-artifact" {
+            continue;
+        }
+        if let Some(executable) = message.executable {
+            if !executables.contains(&executable) {
+                executables.push(executable);
+            }
+        }
+    }
+    Ok(executables)
+}
+
+/// Copies every executable into `dir` under the name `pattern` gives it, returning the copies.
+pub fn copy(
+    executables: &[PathBuf],
+    dir: &Path,
+    pattern: &str,
+    variant: &str,
+) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let mut copies = Vec::new();
+    for executable in executables {
+        let name = executable
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+        let mut file_name = pattern
+            .replace("{name}", &name)
+            .replace("{variant}", variant);
+        if file_name.is_empty() || file_name.contains(['/', '\\']) {
+            anyhow::bail!(
+                "artifact-name '{pattern}' gives '{file_name}' for {}, which is not a file name",
+                executable.display()
+            );
+        }
+        if let Some(extension) = executable.extension() {
+            file_name = format!("{file_name}.{}", extension.to_string_lossy());
+        }
+
+        let copy = dir.join(file_name);
+        if copies.contains(&copy) {
+            anyhow::bail!(
+                "artifact-name '{pattern}' gives several binaries the name {}",
+                copy.display()
+            );
+        }
+        fs::copy(executable, &copy).with_context(|| {
+            format!(
+                "Failed to copy {} to {}",
+                executable.display(),
+                copy.display()
+            )
+        })?;
+        copies.push(copy);
+    }
+    Ok(copies)
+}
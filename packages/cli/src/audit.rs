@@ -0,0 +1,128 @@
+//! Detection of scope cfgs emitted by something other than this tool.
+//!
+//! `cargo feature-scope` passes the scope cfgs through the rustflags. A build script printing
+//! `cargo:rustc-cfg=__scope_*`, a `.cargo/config.toml` with scope flags or a `RUSTFLAGS` that
+//! already contains them makes a crate see the flags twice, possibly with conflicting sets.
+//! `cargo feature-scope audit` reports such configurations.
+//!
+//! Build scripts that emit scope cfgs for plain `cargo` builds can coordinate with the CLI by
+//! skipping their emission when `FEATURE_SCOPE_CFGS` is set, which the audit recognizes. A build
+//! script reading one of the variables feature-scope exports without a matching
+//! `cargo:rerun-if-env-changed` keeps its stale output when the scopes change, so that is
+//! reported too.
+
+use anyhow::{Context, Result};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{env_vars, rustflags, CargoToml};
+
+pub fn run(root_cargo_toml: &CargoToml, root_manifest_path: &Path) -> Result<()> {
+    let mut findings = Vec::new();
+
+    for var in ["RUSTFLAGS", "CARGO_ENCODED_RUSTFLAGS"] {
+        if env::var(var).is_ok_and(|flags| flags.contains("__scope_")) {
+            findings.push(format!(
+                "{var} already contains scope cfgs, which feature-scope sets itself"
+            ));
+        }
+    }
+
+    let root_dir = root_manifest_path.parent().unwrap();
+    for config_path in rustflags::cargo_config_files(root_dir) {
+        audit_cargo_config(&config_path, &mut findings)?;
+    }
+
+    let mut manifests = crate::workspace_member_manifests(root_cargo_toml, root_manifest_path)?;
+    if root_cargo_toml.package.is_some() {
+        manifests.insert(0, root_manifest_path.to_path_buf());
+    }
+    for manifest_path in manifests {
+        audit_build_script(&manifest_path, &mut findings)?;
+    }
+
+    if findings.is_empty() {
+        println!("✅ No scope cfgs are emitted besides the ones of feature-scope, and build scripts rerun when the scopes change");
+        return Ok(());
+    }
+
+    for finding in &findings {
+        println!("⚠️  {finding}");
+    }
+    println!();
+    anyhow::bail!("{} problem(s) found", findings.len())
+}
+
+fn audit_cargo_config(config_path: &Path, findings: &mut Vec<String>) -> Result<()> {
+    let content = fs::read_to_string(config_path)
+        .with_context(|| format!("Failed to read {}", config_path.display()))?;
+    let table: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", config_path.display()))?;
+
+    let mentions_scope = |value: Option<&toml::Value>| {
+        value.is_some_and(|value| value.to_string().contains("__scope_"))
+    };
+
+    let build = table.get("build").and_then(|build| build.as_table());
+    if mentions_scope(build.and_then(|build| build.get("rustflags"))) {
+        findings.push(format!(
+            "build.rustflags in {} contains scope cfgs",
+            config_path.display()
+        ));
+    }
+
+    let targets = table.get("target").and_then(|target| target.as_table());
+    for (target, config) in targets.into_iter().flatten() {
+        if mentions_scope(config.get("rustflags")) {
+            findings.push(format!(
+                "target.{target}.rustflags in {} contains scope cfgs",
+                config_path.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn audit_build_script(manifest_path: &Path, findings: &mut Vec<String>) -> Result<()> {
+    let content = fs::read_to_string(manifest_path)
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+    let manifest: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", manifest_path.display()))?;
+
+    let package = manifest
+        .get("package")
+        .and_then(|package| package.as_table());
+    let build = match package.and_then(|package| package.get("build")) {
+        Some(toml::Value::String(path)) => PathBuf::from(path),
+        Some(toml::Value::Boolean(false)) => return Ok(()),
+        _ => PathBuf::from("build.rs"),
+    };
+    let build_script = manifest_path.parent().unwrap().join(build);
+
+    let Ok(source) = fs::read_to_string(&build_script) else {
+        return Ok(());
+    };
+    if source.contains("rustc-cfg=__scope_") && !source.contains(env_vars::CFGS) {
+        findings.push(format!(
+            "{} emits scope cfgs without checking {}, so builds through feature-scope get them twice",
+            build_script.display(),
+            env_vars::CFGS
+        ));
+    }
+
+    for var in env_vars::ALL {
+        let reads = source.contains(&format!("\"{var}\""));
+        let reruns = source.contains(&format!("rerun-if-env-changed={var}\""));
+        if reads && !reruns {
+            findings.push(format!(
+                "{} reads {var} without printing cargo:rerun-if-env-changed={var}, so it is not rerun when the scopes change",
+                build_script.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
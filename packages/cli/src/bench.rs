@@ -0,0 +1,229 @@
+//! Benchmark comparison across scope sets.
+//!
+//! `cargo feature-scope bench --compare-scopes base,tracing` runs the same criterion benchmarks
+//! once per scope set and prints a table comparing the mean times. Each set is a `+`-joined list
+//! of `[package/]feature` selections enabled on top of the resolved configuration, and `base`
+//! stands for the resolved configuration alone.
+//!
+//! Every set builds into its own variant target directory and gets its own `CRITERION_HOME`, so
+//! the results of different sets never overwrite each other. Benchmarks of
+//! `#[feature_scope_bench]` whose scope a set disables leave a marker there instead of results,
+//! and are listed as skipped for it.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{collections::BTreeMap, fs, path::Path, time::SystemTime};
+
+use crate::{display, history, progress::Progress, target_dir, Invocation};
+
+// Written by the placeholders of `#[feature_scope_bench]`, must stay in sync with the macros
+const SKIPPED_MARKER: &str = "feature-scope-skipped";
+
+#[derive(Debug, Deserialize)]
+struct Estimates {
+    mean: Estimate,
+}
+
+#[derive(Debug, Deserialize)]
+struct Estimate {
+    point_estimate: f64,
+}
+
+pub fn compare_scopes(invocation: &Invocation, scope_sets: &str) -> Result<()> {
+    let scope_sets: Vec<&str> = scope_sets
+        .split(',')
+        .map(str::trim)
+        .filter(|set| !set.is_empty())
+        .collect();
+    if scope_sets.is_empty() {
+        anyhow::bail!("--compare-scopes needs at least one scope set, e.g. `base,tracing`");
+    }
+
+    let root_dir = invocation.root_manifest_path.parent().unwrap();
+    let base_target_dir = target_dir::base_target_dir(root_dir, None);
+
+    // Mean time in nanoseconds, per benchmark and per scope set, `None` where it was skipped
+    let mut results: BTreeMap<String, BTreeMap<usize, Option<f64>>> = BTreeMap::new();
+    let mut progress = Progress::new("Scope sets", scope_sets.len());
+    for (index, scope_set) in scope_sets.iter().enumerate() {
+        let mut cfg_args = invocation.cfg_args.clone();
+        if *scope_set != "base" {
+            for selection in scope_set.split('+') {
+                cfg_args.extend(crate::resolve_feature_selection(
+                    invocation.root_cargo_toml,
+                    invocation.root_manifest_path,
+                    selection,
+                )?);
+            }
+        }
+
+        let variant = format!("bench-{}", scope_set.replace(['/', '+'], "_"));
+        let variant_target_dir =
+            target_dir::prepare_variant_target_dir(&base_target_dir, &variant, false)?;
+        let criterion_home = variant_target_dir.join("criterion");
+
+        let mut cargo_cmd = crate::build_cargo_command(
+            "bench",
+            invocation.package,
+            Some(&variant_target_dir),
+            &invocation.config_rustflags,
+            &cfg_args,
+            &invocation.check_cfg_args,
+            &invocation.additional_args,
+        );
+        cargo_cmd
+            .env("CRITERION_HOME", &criterion_home)
+            .env(crate::env_vars::TARGET_PACKAGE, invocation.target_package);
+
+        progress.header(scope_set);
+        for line in display::describe_invocation(
+            &cargo_cmd,
+            &invocation.scope_names,
+            &cfg_args,
+            &invocation.check_cfg_args,
+            invocation.verbose,
+        ) {
+            println!("{line}");
+        }
+
+        let started = SystemTime::now();
+        let entry = history::Entry {
+            root_manifest_path: invocation.root_manifest_path,
+            package: invocation.target_package,
+            scope_names: &invocation.scope_names,
+            cfg_args: &cfg_args,
+            check_cfg_args: &invocation.check_cfg_args,
+        };
+        let status = history::status(&mut cargo_cmd, entry)?;
+        if !status.success() {
+            anyhow::bail!("Benchmarks failed for scope set '{scope_set}' with {status}");
+        }
+
+        for (benchmark, mean) in collect_estimates(&criterion_home, started)? {
+            results.entry(benchmark).or_default().insert(index, mean);
+        }
+    }
+
+    if results.is_empty() {
+        println!("No criterion results found, only criterion benchmarks can be compared");
+        return Ok(());
+    }
+
+    print_report(&scope_sets, &results);
+    Ok(())
+}
+
+// Collects the mean estimates that criterion wrote during this run, and the benchmarks skipped
+// in it without an estimate
+fn collect_estimates(
+    criterion_home: &Path,
+    since: SystemTime,
+) -> Result<Vec<(String, Option<f64>)>> {
+    let mut estimates = Vec::new();
+    let mut pending = vec![criterion_home.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+            if path.file_name().is_some_and(|name| name == SKIPPED_MARKER) {
+                if fs::metadata(&path)?.modified()? >= since {
+                    estimates.push((benchmark_id(criterion_home, &dir), None));
+                }
+                continue;
+            }
+            if !path.is_dir() {
+                continue;
+            }
+
+            let estimates_path = path.join("estimates.json");
+            if path.file_name().is_some_and(|name| name == "new") && estimates_path.is_file() {
+                let modified = fs::metadata(&estimates_path)?.modified()?;
+                if modified < since {
+                    continue;
+                }
+
+                let content = fs::read_to_string(&estimates_path)
+                    .with_context(|| format!("Failed to read {}", estimates_path.display()))?;
+                let parsed: Estimates = serde_json::from_str(&content)
+                    .with_context(|| format!("Failed to parse {}", estimates_path.display()))?;
+                estimates.push((
+                    benchmark_id(criterion_home, &dir),
+                    Some(parsed.mean.point_estimate),
+                ));
+            } else {
+                pending.push(path);
+            }
+        }
+    }
+
+    Ok(estimates)
+}
+
+fn benchmark_id(criterion_home: &Path, dir: &Path) -> String {
+    dir.strip_prefix(criterion_home)
+        .unwrap_or(dir)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn print_report(scope_sets: &[&str], results: &BTreeMap<String, BTreeMap<usize, Option<f64>>>) {
+    let mut rows = vec![std::iter::once("Benchmark".to_string())
+        .chain(scope_sets.iter().map(|set| set.to_string()))
+        .collect::<Vec<_>>()];
+
+    for (benchmark, means) in results {
+        let baseline = means.get(&0).copied().flatten();
+        let mut row = vec![benchmark.clone()];
+        for index in 0..scope_sets.len() {
+            let cell = match (means.get(&index), baseline) {
+                (Some(Some(mean)), Some(baseline)) if index > 0 && baseline > 0.0 => format!(
+                    "{} ({:+.1}%)",
+                    format_duration(*mean),
+                    (mean / baseline - 1.0) * 100.0
+                ),
+                (Some(Some(mean)), _) => format_duration(*mean),
+                (Some(None), _) => String::from("skipped"),
+                (None, _) => String::from("-"),
+            };
+            row.push(cell);
+        }
+        rows.push(row);
+    }
+
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    println!();
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+}
+
+pub fn format_duration(nanoseconds: f64) -> String {
+    if nanoseconds >= 1e9 {
+        format!("{:.3} s", nanoseconds / 1e9)
+    } else if nanoseconds >= 1e6 {
+        format!("{:.3} ms", nanoseconds / 1e6)
+    } else if nanoseconds >= 1e3 {
+        format!("{:.3} µs", nanoseconds / 1e3)
+    } else {
+        format!("{nanoseconds:.3} ns")
+    }
+}
@@ -0,0 +1,131 @@
+//! Arguments forwarded to cargo.
+//!
+//! The arguments after the command are split at the first `--`: the ones before it are flags for
+//! cargo itself (like `--release`), and the ones after it go to the program cargo runs, such as
+//! the binary of `run` or the harness of `test` and `bench`. Flags added by feature-scope always
+//! end up before the separator, so they are never mistaken for program arguments.
+//!
+//! Like with cargo, the first `--` is the separator wherever it appears, even right after the
+//! command or the options of feature-scope: `cargo feature-scope run -p app -- --release` passes
+//! `--release` to the program, and only later separators reach the program as arguments.
+
+use std::process;
+
+#[derive(Debug, Clone, Default)]
+pub struct ForwardedArgs {
+    /// Arguments for cargo itself.
+    pub cargo: Vec<String>,
+    /// Arguments after the `--` separator, if there is one.
+    pub program: Option<Vec<String>>,
+}
+
+impl ForwardedArgs {
+    pub fn parse(mut args: Vec<String>) -> Self {
+        let Some(separator) = args.iter().position(|arg| arg == "--") else {
+            return ForwardedArgs {
+                cargo: args,
+                program: None,
+            };
+        };
+
+        let program = args.split_off(separator + 1);
+        args.pop();
+        ForwardedArgs {
+            cargo: args,
+            program: Some(program),
+        }
+    }
+
+    /// Removes the first `-p`/`--package` from the cargo arguments and returns its value.
+    ///
+    /// Clap stops parsing our own options at the first unknown cargo flag, so in
+    /// `run --release -p app` the package ends up among the forwarded arguments.
+    pub fn take_package(&mut self) -> Option<String> {
+        self.take_option(Some('p'), "--package")
+    }
+
+    /// Removes the first occurrence of one of our own options from the cargo arguments and
+    /// returns its value, like [`ForwardedArgs::take_package`].
+    pub fn take_option(&mut self, short: Option<char>, long: &str) -> Option<String> {
+        let short = short.map(|short| format!("-{short}"));
+        let mut index = 0;
+
+        while index < self.cargo.len() {
+            let arg = &self.cargo[index];
+            let is_short = short.as_deref() == Some(arg.as_str());
+            if (is_short || arg == long) && index + 1 < self.cargo.len() {
+                let value = self.cargo.remove(index + 1);
+                self.cargo.remove(index);
+                return Some(value);
+            }
+
+            let value = arg
+                .strip_prefix(long)
+                .and_then(|rest| rest.strip_prefix('='))
+                .or_else(|| {
+                    let short = short.as_deref()?;
+                    arg.strip_prefix(short).filter(|value| !value.is_empty())
+                });
+            if let Some(value) = value {
+                let value = value.to_string();
+                self.cargo.remove(index);
+                return Some(value);
+            }
+
+            index += 1;
+        }
+
+        None
+    }
+
+    /// Returns the values of every occurrence of an option in the cargo arguments, leaving
+    /// them in place.
+    pub fn option_values(&self, short: Option<char>, long: &str) -> Vec<String> {
+        let mut remaining = self.clone();
+        std::iter::from_fn(|| remaining.take_option(short, long)).collect()
+    }
+
+    /// Returns the targets cargo is asked to build as `kind:name`, like `bin:server`, with
+    /// `kind:*` for flags like `--examples` that build every target of a kind and `lib:*` for
+    /// the library.
+    pub fn selected_targets(&self) -> Vec<String> {
+        let kinds = [
+            ("bin", "--bins"),
+            ("example", "--examples"),
+            ("test", "--tests"),
+            ("bench", "--benches"),
+        ];
+        let all_targets = self.cargo.iter().any(|arg| arg == "--all-targets");
+        let mut targets = Vec::new();
+        let lib = ["--lib", "--doc"];
+        if all_targets || self.cargo.iter().any(|arg| lib.contains(&arg.as_str())) {
+            targets.push(String::from("lib:*"));
+        }
+        for (kind, every) in kinds {
+            for name in self.option_values(None, &format!("--{kind}")) {
+                targets.push(format!("{kind}:{name}"));
+            }
+            if all_targets || self.cargo.iter().any(|arg| arg == every) {
+                targets.push(format!("{kind}:*"));
+            }
+        }
+        targets
+    }
+
+    /// Removes every occurrence of one of our own flags from the cargo arguments and returns
+    /// whether there was one.
+    pub fn take_flag(&mut self, long: &str) -> bool {
+        let count = self.cargo.len();
+        self.cargo.retain(|arg| arg != long);
+        self.cargo.len() != count
+    }
+
+    /// Appends the arguments to a command, after every flag it already has.
+    pub fn apply(&self, cmd: &mut process::Command) {
+        cmd.args(&self.cargo);
+
+        if let Some(program) = &self.program {
+            cmd.arg("--").args(program);
+        }
+    }
+}
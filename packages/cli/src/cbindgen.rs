@@ -0,0 +1,93 @@
+//! C headers of the symbols compiled under the resolved scopes.
+//!
+//! `cargo feature-scope cbindgen [-p <PACKAGE>] [CBINDGEN ARGS]...` runs cbindgen on a package
+//! under the scope environment, like `exec`. cbindgen reads the sources as written unless it
+//! expands them first, and scope attributes only become `cfg`s during expansion, so the
+//! package's `cbindgen.toml`, or the one given with `--config`, is copied with `parse.expand`
+//! set to the package. The expansion then runs `cargo rustc` with the resolved cfgs, and the
+//! header declares exactly the items compiled in.
+
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use toml_edit::{DocumentMut, Item, Table};
+
+use crate::{cargo_args::ForwardedArgs, items, target_dir, CargoToml};
+
+/// Returns the cbindgen command line for the package, after writing its expanding config.
+pub fn program_args(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    package: &str,
+    args: &ForwardedArgs,
+) -> Result<Vec<String>> {
+    let (manifest_path, _) = items::find_package(root_cargo_toml, root_manifest_path, package)?;
+    let package_dir = manifest_path.parent().unwrap();
+
+    // The package is the one cbindgen runs on, which cbindgen takes as its input
+    let mut args = args.clone();
+    while args.take_package().is_some() {}
+    let config_path = match args.take_option(Some('c'), "--config") {
+        Some(config) => Some(PathBuf::from(config)),
+        None => Some(package_dir.join("cbindgen.toml")).filter(|config| config.is_file()),
+    };
+    let mut config = match &config_path {
+        Some(config_path) => fs::read_to_string(config_path)
+            .with_context(|| format!("Failed to read {}", config_path.display()))?
+            .parse::<DocumentMut>()
+            .with_context(|| format!("Failed to parse {}", config_path.display()))?,
+        None => DocumentMut::new(),
+    };
+    set_expand_crate(&mut config, package)?;
+
+    // Next to the variant directories, where cargo already keeps what it generates
+    let base = target_dir::base_target_dir(root_manifest_path.parent().unwrap(), None);
+    let expanding_config = base
+        .join("feature-scope")
+        .join(format!("{package}.cbindgen.toml"));
+    fs::create_dir_all(expanding_config.parent().unwrap())?;
+    fs::write(&expanding_config, config.to_string())
+        .with_context(|| format!("Failed to write {}", expanding_config.display()))?;
+
+    let mut program_args = vec![
+        String::from("cbindgen"),
+        String::from("--config"),
+        expanding_config.display().to_string(),
+        String::from("--crate"),
+        package.to_string(),
+    ];
+    program_args.extend(args.cargo);
+    program_args.extend(args.program.into_iter().flatten());
+    program_args.push(package_dir.display().to_string());
+    Ok(program_args)
+}
+
+// Adds the package to the crates cbindgen expands, keeping the other expand settings
+fn set_expand_crate(config: &mut DocumentMut, package: &str) -> Result<()> {
+    let expand = config
+        .entry("parse")
+        .or_insert_with(|| Item::Table(Table::new()))
+        .as_table_like_mut()
+        .context("`parse` of the cbindgen config is not a table")?
+        .entry("expand")
+        .or_insert_with(|| Item::Table(Table::new()));
+    let expand = expand
+        .as_table_like_mut()
+        .context("`parse.expand` of the cbindgen config is not a table")?;
+
+    let mut crates: Vec<String> = expand
+        .get("crates")
+        .and_then(Item::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|name| name.as_str().map(String::from))
+        .collect();
+    if !crates.iter().any(|name| name == package) {
+        crates.push(package.to_string());
+    }
+    let crates: toml_edit::Array = crates.iter().collect();
+    expand.insert("crates", toml_edit::value(crates));
+    Ok(())
+}
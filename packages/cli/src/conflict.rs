@@ -0,0 +1,489 @@
+//! Fixing conflicting scopes in the manifests.
+//!
+//! A resolution enabling two features of an exclusive group of a library, or two features
+//! declaring a conflict, fails. With `--interactive`, the CLI lists the ways of dropping one of
+//! them instead, writes the chosen one to the consumer's or the library's `Cargo.toml` and
+//! resolves again. For CI, `--resolution prefer-consumer` keeps what the consumer selects and
+//! drops the features only the library's defaults enable, by selecting the other defaults
+//! explicitly, and `--resolution prefer-library` drops the features only the consumer's
+//! selections enable. A conflict the policy cannot decide still fails.
+
+use anyhow::{Context, Result};
+use feature_scope_core::{ConflictError, ConflictingFeature, Origin};
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+use toml_edit::{DocumentMut, TableLike};
+
+use crate::{items, CargoToml, Package};
+
+/// How conflicts are handled instead of failing the resolution.
+#[derive(Debug, Clone, Copy)]
+pub enum Mode {
+    Interactive,
+    PreferConsumer,
+    PreferLibrary,
+}
+
+impl Mode {
+    pub fn parse(policy: &str) -> Result<Self> {
+        match policy {
+            "prefer-consumer" => Ok(Mode::PreferConsumer),
+            "prefer-library" => Ok(Mode::PreferLibrary),
+            _ => anyhow::bail!(
+                "Unknown resolution policy '{policy}', expected `prefer-consumer` or `prefer-library`"
+            ),
+        }
+    }
+}
+
+// A change to one manifest that stops enabling a feature
+enum Edit {
+    // Drops features from the consumer's entries of the library
+    RemoveSelections {
+        manifest: PathBuf,
+        features: Vec<String>,
+    },
+    // Selects these features in the consumer's entries of the library that rely on its defaults
+    SelectDefaults {
+        manifest: PathBuf,
+        features: Vec<String>,
+    },
+    // Drops features from the library's defaults
+    RemoveDefaults {
+        manifest: PathBuf,
+        features: Vec<String>,
+    },
+}
+
+impl Edit {
+    fn describe(&self, library: &str, root_dir: &Path) -> String {
+        let quoted = |features: &[String]| {
+            let quoted: Vec<String> = features.iter().map(|f| format!("'{f}'")).collect();
+            quoted.join(", ")
+        };
+        let relative = |manifest: &Path| {
+            manifest
+                .strip_prefix(root_dir)
+                .unwrap_or(manifest)
+                .display()
+                .to_string()
+        };
+
+        match self {
+            Edit::RemoveSelections { manifest, features } => format!(
+                "stop selecting {} of '{library}' in {}",
+                quoted(features),
+                relative(manifest)
+            ),
+            Edit::SelectDefaults { manifest, features } if features.is_empty() => format!(
+                "disable the defaults of '{library}' in {}",
+                relative(manifest)
+            ),
+            Edit::SelectDefaults { manifest, features } => format!(
+                "select {} instead of the defaults of '{library}' in {}",
+                quoted(features),
+                relative(manifest)
+            ),
+            Edit::RemoveDefaults { manifest, features } => format!(
+                "drop {} from the defaults of '{library}' in {}",
+                quoted(features),
+                relative(manifest)
+            ),
+        }
+    }
+}
+
+// One way of resolving the conflict: the feature it drops and the edits doing so
+struct Fix {
+    feature: String,
+    edits: Vec<Edit>,
+}
+
+// What the fixes of a conflict need to know about its packages
+struct Packages {
+    consumer_manifest: PathBuf,
+    library: Option<(PathBuf, Package)>,
+}
+
+impl Packages {
+    fn locate(
+        conflict: &ConflictError,
+        root_cargo_toml: &CargoToml,
+        root_manifest_path: &Path,
+    ) -> Result<Self> {
+        let (consumer_manifest, _) =
+            items::find_package(root_cargo_toml, root_manifest_path, &conflict.consumer)?;
+
+        // Libraries outside the workspace are only known by the path of the consumer's entry
+        let library =
+            match items::find_package(root_cargo_toml, root_manifest_path, &conflict.library) {
+                Ok(library) => Some(library),
+                Err(_) => entries_library(&consumer_manifest, &conflict.library)?,
+            };
+
+        Ok(Packages {
+            consumer_manifest,
+            library,
+        })
+    }
+
+    // The defaults of the library that enable the feature, and the ones that don't
+    fn split_defaults(&self, feature: &str) -> (Vec<String>, Vec<String>) {
+        let decl = self
+            .library
+            .as_ref()
+            .and_then(|(_, package)| package.metadata.as_ref())
+            .and_then(|metadata| metadata.feature_scope_decl.as_ref());
+        let Some(decl) = decl else {
+            return (Vec::new(), Vec::new());
+        };
+
+        decl.default.iter().flatten().cloned().partition(|default| {
+            let mut enabled = HashSet::new();
+            crate::resolve_requested_feature(default, decl, &mut enabled);
+            enabled.contains(feature)
+        })
+    }
+
+    // Drops the feature through the consumer's manifest, or the library's if it only comes from
+    // the defaults of a package consuming itself
+    fn consumer_fix(&self, conflict: &ConflictError, feature: &ConflictingFeature) -> Fix {
+        let mut edits = self.selection_edits(feature);
+        if feature.origins.iter().any(is_defaults) {
+            let (_, others) = self.split_defaults(&feature.feature);
+            if conflict.consumer == conflict.library {
+                edits.extend(self.library_defaults_edit(&feature.feature));
+            } else {
+                edits.push(Edit::SelectDefaults {
+                    manifest: self.consumer_manifest.clone(),
+                    features: others,
+                });
+            }
+        }
+        Fix {
+            feature: feature.feature.clone(),
+            edits,
+        }
+    }
+
+    // Drops the feature from the library's defaults, for every consumer
+    fn library_fix(&self, feature: &ConflictingFeature) -> Option<Fix> {
+        let defaults_edit = self.library_defaults_edit(&feature.feature)?;
+        let mut edits = self.selection_edits(feature);
+        edits.push(defaults_edit);
+        Some(Fix {
+            feature: feature.feature.clone(),
+            edits,
+        })
+    }
+
+    fn selection_edits(&self, feature: &ConflictingFeature) -> Vec<Edit> {
+        let selections: Vec<String> = feature
+            .origins
+            .iter()
+            .filter_map(|origin| match origin {
+                Origin::Selection { feature, .. } => Some(feature.clone()),
+                Origin::Defaults { .. } | Origin::Probe { .. } => None,
+            })
+            .collect();
+        if selections.is_empty() {
+            return Vec::new();
+        }
+        vec![Edit::RemoveSelections {
+            manifest: self.consumer_manifest.clone(),
+            features: selections,
+        }]
+    }
+
+    fn library_defaults_edit(&self, feature: &str) -> Option<Edit> {
+        let (manifest, _) = self.library.as_ref()?;
+        let (enabling, _) = self.split_defaults(feature);
+        if enabling.is_empty() {
+            return None;
+        }
+        Some(Edit::RemoveDefaults {
+            manifest: manifest.clone(),
+            features: enabling,
+        })
+    }
+}
+
+fn is_defaults(origin: &Origin) -> bool {
+    matches!(origin, Origin::Defaults { .. })
+}
+
+/// Fixes the manifests so the conflict goes away, as the mode decides.
+pub fn resolve(
+    conflict: &ConflictError,
+    mode: Mode,
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+) -> Result<()> {
+    let root_dir = root_manifest_path.parent().unwrap();
+    let packages = Packages::locate(conflict, root_cargo_toml, root_manifest_path)?;
+
+    let fixes = match mode {
+        Mode::Interactive => vec![choose(conflict, &packages, root_dir)?],
+        Mode::PreferConsumer | Mode::PreferLibrary => {
+            // The features the policy gives up, all but one of the group have to go
+            let dropped: Vec<&ConflictingFeature> = conflict
+                .features
+                .iter()
+                .filter(|feature| match mode {
+                    Mode::PreferConsumer => feature.origins.iter().all(is_defaults),
+                    _ => !feature.origins.iter().any(is_defaults),
+                })
+                .collect();
+            if conflict.features.len() - dropped.len() != 1 {
+                let policy = match mode {
+                    Mode::PreferConsumer => "prefer-consumer",
+                    _ => "prefer-library",
+                };
+                return Err(anyhow::Error::new(conflict.clone()).context(format!(
+                    "--resolution {policy} cannot decide which feature of '{}' to keep",
+                    conflict.library
+                )));
+            }
+            dropped
+                .into_iter()
+                .map(|feature| packages.consumer_fix(conflict, feature))
+                .collect()
+        }
+    };
+
+    for fix in fixes {
+        for edit in &fix.edits {
+            apply(edit, &conflict.library)?;
+            println!(
+                "Dropped '{}': {}",
+                fix.feature,
+                edit.describe(&conflict.library, root_dir)
+            );
+        }
+    }
+    Ok(())
+}
+
+// Asks which feature to drop, and how
+fn choose(conflict: &ConflictError, packages: &Packages, root_dir: &Path) -> Result<Fix> {
+    if !io::stdin().is_terminal() {
+        return Err(anyhow::Error::new(conflict.clone()).context(
+            "--interactive needs a terminal, use `--resolution prefer-consumer|prefer-library` in CI",
+        ));
+    }
+
+    let mut fixes = Vec::new();
+    for feature in &conflict.features {
+        let consumer_fix = packages.consumer_fix(conflict, feature);
+        let has_defaults = feature.origins.iter().any(is_defaults);
+        fixes.push(consumer_fix);
+        if has_defaults && conflict.consumer != conflict.library {
+            fixes.extend(packages.library_fix(feature));
+        }
+    }
+
+    println!("{conflict}");
+    println!();
+    for (index, fix) in fixes.iter().enumerate() {
+        let edits: Vec<String> = fix
+            .edits
+            .iter()
+            .map(|edit| edit.describe(&conflict.library, root_dir))
+            .collect();
+        println!(
+            "  {}) drop '{}': {}",
+            index + 1,
+            fix.feature,
+            edits.join(", then ")
+        );
+    }
+    println!("  0) abort");
+
+    loop {
+        print!("Choose a fix [0-{}]: ", fixes.len());
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer)? == 0 {
+            anyhow::bail!("Aborted");
+        }
+        match answer.trim().parse::<usize>() {
+            Ok(0) => anyhow::bail!("Aborted"),
+            Ok(choice) if choice <= fixes.len() => return Ok(fixes.swap_remove(choice - 1)),
+            _ => println!("Please enter a number between 0 and {}", fixes.len()),
+        }
+    }
+}
+
+fn apply(edit: &Edit, library: &str) -> Result<()> {
+    let manifest = match edit {
+        Edit::RemoveSelections { manifest, .. }
+        | Edit::SelectDefaults { manifest, .. }
+        | Edit::RemoveDefaults { manifest, .. } => manifest,
+    };
+    let content = fs::read_to_string(manifest)
+        .with_context(|| format!("Failed to read {}", manifest.display()))?;
+    let mut document: DocumentMut = content
+        .parse()
+        .with_context(|| format!("Failed to parse {}", manifest.display()))?;
+
+    match edit {
+        Edit::RemoveSelections { features, .. } => {
+            for entry in library_entries(&mut document, manifest, library)? {
+                if let Some(selected) = entry.get_mut("features").and_then(|f| f.as_array_mut()) {
+                    remove_features(selected, features);
+                }
+            }
+        }
+        Edit::SelectDefaults { features, .. } => {
+            for entry in library_entries(&mut document, manifest, library)? {
+                let relies_on_defaults = entry
+                    .get("features")
+                    .and_then(|f| f.as_array())
+                    .is_none_or(|selected| selected.is_empty())
+                    && entry
+                        .get("default-features")
+                        .and_then(|d| d.as_bool())
+                        .unwrap_or(true);
+                if !relies_on_defaults {
+                    continue;
+                }
+                if features.is_empty() {
+                    entry.insert("default-features", toml_edit::value(false));
+                } else {
+                    let selected: toml_edit::Array = features.iter().collect();
+                    entry.insert("features", toml_edit::value(selected));
+                }
+            }
+        }
+        Edit::RemoveDefaults { features, .. } => {
+            let defaults = ["package", "metadata", "feature-scope-decl"]
+                .iter()
+                .try_fold(document.as_item_mut(), |item, key| item.get_mut(*key))
+                .and_then(|decl| decl.get_mut("default"))
+                .and_then(|defaults| defaults.as_array_mut())
+                .with_context(|| {
+                    format!(
+                        "The defaults of '{library}' are not declared in {}",
+                        manifest.display()
+                    )
+                })?;
+            remove_features(defaults, features);
+        }
+    }
+
+    fs::write(manifest, document.to_string())
+        .with_context(|| format!("Failed to write {}", manifest.display()))
+}
+
+/// Removes the features from an array of selections, keeping the other items.
+pub fn remove_features(array: &mut toml_edit::Array, features: &[String]) {
+    array.retain(|feature| {
+        feature
+            .as_str()
+            .is_none_or(|feature| !features.iter().any(|f| f == feature))
+    });
+    // The new first element keeps the space that separated it from the one before
+    if let Some(first) = array.get_mut(0) {
+        first.decor_mut().set_prefix("");
+    }
+}
+
+// The feature-scope entries of a consumer referring to the library, by name, by path or, for
+// the entries of a single package, by naming neither
+fn library_entries<'a>(
+    document: &'a mut DocumentMut,
+    manifest: &Path,
+    library: &str,
+) -> Result<Vec<&'a mut dyn TableLike>> {
+    let own_name = document
+        .get("package")
+        .and_then(|package| package.get("name"))
+        .and_then(|name| name.as_str())
+        .map(String::from);
+    let Some(entries) = document
+        .get_mut("package")
+        .and_then(|package| package.get_mut("metadata"))
+        .and_then(|metadata| metadata.get_mut("feature-scope"))
+    else {
+        return Ok(Vec::new());
+    };
+
+    let entries: Vec<&mut dyn TableLike> = match entries {
+        toml_edit::Item::ArrayOfTables(tables) => tables
+            .iter_mut()
+            .map(|table| table as &mut dyn TableLike)
+            .collect(),
+        toml_edit::Item::Value(toml_edit::Value::Array(array)) => array
+            .iter_mut()
+            .filter_map(|value| value.as_inline_table_mut())
+            .map(|table| table as &mut dyn TableLike)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let mut matching = Vec::new();
+    for entry in entries {
+        if entry_refers(&*entry, manifest, own_name.as_deref(), library)? {
+            matching.push(entry);
+        }
+    }
+    Ok(matching)
+}
+
+/// Whether a feature-scope entry of the consumer with the manifest and the package name refers
+/// to the library.
+pub fn entry_refers(
+    entry: &dyn TableLike,
+    manifest: &Path,
+    own_name: Option<&str>,
+    library: &str,
+) -> Result<bool> {
+    let package = entry.get("package").and_then(|p| p.as_str());
+    let path = entry.get("path").and_then(|p| p.as_str());
+    Ok(match (package, path) {
+        (Some(package), _) => package == library,
+        (None, Some(path)) => read_entry_package(manifest, path)?.name == library,
+        (None, None) => own_name == Some(library),
+    })
+}
+
+// The library a consumer's entry references by path, if one does
+fn entries_library(consumer_manifest: &Path, library: &str) -> Result<Option<(PathBuf, Package)>> {
+    let consumer = crate::read_package(consumer_manifest)?;
+    let entries = consumer
+        .metadata
+        .and_then(|metadata| metadata.feature_scope);
+    for path in entries
+        .iter()
+        .flatten()
+        .filter_map(|entry| entry.path.as_ref())
+    {
+        let manifest_path = entry_manifest(consumer_manifest, path);
+        let package = crate::read_package(&manifest_path)?;
+        if package.name == library {
+            return Ok(Some((manifest_path, package)));
+        }
+    }
+    Ok(None)
+}
+
+fn read_entry_package(consumer_manifest: &Path, path: &str) -> Result<Package> {
+    crate::read_package(&entry_manifest(consumer_manifest, path))
+}
+
+// Entry paths name the package directory or its manifest, relative to the consumer's manifest
+fn entry_manifest(consumer_manifest: &Path, path: &str) -> PathBuf {
+    let path = consumer_manifest.parent().unwrap().join(path);
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "toml")
+    {
+        path
+    } else {
+        path.join("Cargo.toml")
+    }
+}
@@ -0,0 +1,77 @@
+//! Comparison of the resolved scopes with those of another revision.
+//!
+//! `cargo feature-scope diff [<REF>]` resolves every package, or the ones selected with `-p`,
+//! in the working tree and at the git revision `REF`, `HEAD` by default, checked out into a
+//! temporary worktree, and lists the scopes each package gains or loses and the ones the
+//! library defaults stop or start enabling. Both sides resolve with the same `--features`,
+//! `--no-default-features`, `--release` or `--profile` and `--target`, so CI can check the
+//! release scopes alone. `--exit-code` fails when anything changed, like `git diff` does.
+
+use anyhow::{Context, Result};
+use feature_scope_core::{Overrides, ResolvedScopes, Resolver};
+use std::path::Path;
+
+use crate::verify;
+
+const USAGE: &str = "Usage: cargo feature-scope diff [<REF>] [--exit-code] [--release | --profile <NAME>] [--target <TRIPLE>]";
+
+pub fn run(
+    root_manifest_path: &Path,
+    packages: &[String],
+    overrides: &Overrides,
+    args: &[String],
+) -> Result<()> {
+    let mut git_ref = None;
+    let mut exit_code = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--exit-code" => exit_code = true,
+            // Already part of the overrides
+            "--release" | "-r" => {}
+            "--profile" | "--target" => {
+                args.next().context(USAGE)?;
+            }
+            _ if arg.starts_with("--profile=") || arg.starts_with("--target=") => {}
+            _ if !arg.starts_with('-') && git_ref.is_none() => git_ref = Some(arg.as_str()),
+            _ => anyhow::bail!(USAGE),
+        }
+    }
+    let git_ref = git_ref.unwrap_or("HEAD");
+
+    let (worktree, ref_manifest_path) = verify::checkout(root_manifest_path, git_ref)?;
+    let before = resolve(&ref_manifest_path, packages, overrides)
+        .with_context(|| format!("Failed to resolve the scopes at {git_ref}"))?;
+    drop(worktree);
+    let after = resolve(root_manifest_path, packages, overrides)?;
+
+    let diff = before.diff(&after);
+    if diff.is_empty() {
+        println!("No scope changes since {git_ref}");
+        return Ok(());
+    }
+    println!("Scope changes since {git_ref}:");
+    for line in diff.to_string().lines() {
+        println!("  {line}");
+    }
+    if exit_code {
+        println!();
+        anyhow::bail!("The resolved scopes differ from {git_ref}");
+    }
+    Ok(())
+}
+
+// Resolves the selected packages, or all of them, those a revision does not have left out
+fn resolve(
+    root_manifest_path: &Path,
+    packages: &[String],
+    overrides: &Overrides,
+) -> Result<ResolvedScopes> {
+    let mut scopes = Resolver::from_workspace(root_manifest_path)?.resolve_all(overrides)?;
+    if !packages.is_empty() {
+        scopes
+            .packages
+            .retain(|package, _| packages.contains(package));
+    }
+    Ok(scopes)
+}
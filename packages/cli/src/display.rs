@@ -0,0 +1,237 @@
+//! Presentation of scopes in user-facing output.
+//!
+//! Internally every scope is a `__scope_<feature>` cfg. Diagnostics and reports show scopes as
+//! `package/feature` instead, and the raw cfg names only appear at `-vv`.
+
+use std::{collections::HashMap, path::Path, process};
+
+use crate::{cfg_name, i18n, CargoToml, Package};
+
+/// Maps internal cfg names back to the packages that declare them.
+#[derive(Debug, Default)]
+pub struct ScopeNames {
+    names: HashMap<String, String>,
+    // The `doc` of the features declared with one, by cfg name
+    docs: HashMap<String, String>,
+    // The `package/preset` names of the presets with the cfgs of the features they select
+    presets: Vec<(String, Vec<String>)>,
+}
+
+impl ScopeNames {
+    /// Collects the declarations of the root package and every workspace member.
+    pub fn from_workspace(root_cargo_toml: &CargoToml, root_manifest_path: &Path) -> Self {
+        let mut declarations: HashMap<String, Vec<String>> = HashMap::new();
+        let mut docs = HashMap::new();
+        let mut presets = Vec::new();
+        let mut collect = |package: &Package| {
+            let Some(decl) = package
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.feature_scope_decl.as_ref())
+            else {
+                return;
+            };
+
+            let defaults = decl.default.iter().flatten();
+            let defaults = defaults.filter(|feature| !decl.presets.contains_key(*feature));
+            for feature in decl.features.keys().chain(defaults) {
+                let packages = declarations.entry(feature.clone()).or_default();
+                if !packages.contains(&package.name) {
+                    packages.push(package.name.clone());
+                }
+            }
+            for (feature, details) in &decl.details {
+                if let Some(doc) = &details.doc {
+                    docs.insert(cfg_name::scope_cfg(feature), doc.clone());
+                }
+            }
+            for (preset, features) in &decl.presets {
+                let cfgs = features.iter().map(|f| cfg_name::scope_cfg(f)).collect();
+                presets.push((format!("{}/{preset}", package.name), cfgs));
+            }
+        };
+
+        if let Some(package) = &root_cargo_toml.package {
+            collect(package);
+        }
+        // Members that fail to resolve or parse are reported by the resolution itself
+        for member_manifest in
+            crate::workspace_member_manifests(root_cargo_toml, root_manifest_path)
+                .unwrap_or_default()
+        {
+            if let Ok(package) = crate::read_package(&member_manifest) {
+                collect(&package);
+            }
+        }
+
+        let names = declarations
+            .into_iter()
+            .map(|(feature, mut packages)| {
+                packages.sort();
+                let owner = if packages.len() == 1 {
+                    packages.remove(0)
+                } else {
+                    format!("{{{}}}", packages.join(","))
+                };
+                (cfg_name::scope_cfg(&feature), format!("{owner}/{feature}"))
+            })
+            .collect();
+
+        presets.sort();
+        ScopeNames {
+            names,
+            docs,
+            presets,
+        }
+    }
+
+    /// Returns the `package/feature` name of a cfg, or the bare feature if its package is unknown.
+    pub fn pretty(&self, cfg: &str) -> String {
+        if cfg == "__scope_default" {
+            return String::from("default");
+        }
+
+        self.names
+            .get(cfg)
+            .cloned()
+            .unwrap_or_else(|| cfg.strip_prefix("__scope_").unwrap_or(cfg).to_string())
+    }
+
+    /// Returns the `package/feature` names and docs of the documented cfgs among the pairs, sorted.
+    pub fn documented_args(&self, args: &[String]) -> Vec<(String, &str)> {
+        let mut documented: Vec<(String, &str)> = args
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .filter_map(|arg| {
+                let cfg = flag_cfg(arg);
+                let doc = self.docs.get(cfg)?;
+                Some((self.pretty(cfg), doc.as_str()))
+            })
+            .collect();
+        documented.sort();
+        documented.dedup();
+        documented
+    }
+
+    /// Returns the presets selecting one of the cfgs among the pairs, with the features they
+    /// select.
+    pub fn presets_args(&self, args: &[String]) -> Vec<(&str, Vec<String>)> {
+        let cfgs: Vec<&str> = args
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .map(|arg| flag_cfg(arg))
+            .collect();
+        self.presets
+            .iter()
+            .filter(|(_, features)| features.iter().any(|cfg| cfgs.contains(&cfg.as_str())))
+            .map(|(preset, features)| {
+                let features = features.iter().map(|cfg| self.pretty(cfg)).collect();
+                (preset.as_str(), features)
+            })
+            .collect()
+    }
+
+    /// Returns the sorted pretty names of the cfgs in `--cfg NAME` / `--check-cfg cfg(NAME)` pairs.
+    pub fn pretty_args(&self, args: &[String]) -> Vec<String> {
+        let mut names: Vec<String> = args
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .map(|arg| self.pretty(flag_cfg(arg)))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+// The cfg name in the value of a `--cfg NAME` or `--check-cfg cfg(NAME)` flag
+fn flag_cfg(value: &str) -> &str {
+    value
+        .strip_prefix("cfg(")
+        .and_then(|value| value.strip_suffix(')'))
+        .unwrap_or(value)
+}
+
+/// Describes a command about to be spawned, with the amount of detail given by `verbose`.
+pub fn describe_invocation(
+    cmd: &process::Command,
+    names: &ScopeNames,
+    cfg_args: &[String],
+    check_cfg_args: &[String],
+    verbose: u8,
+) -> Vec<String> {
+    if verbose >= 2 {
+        let mut lines = vec![format!("{}: {cmd:?}", i18n::Label::Running.text())];
+        if !cfg_args.is_empty() {
+            lines.push(format!("cfg_args: {cfg_args:?}"));
+        }
+        if !check_cfg_args.is_empty() {
+            lines.push(format!("check_cfg_args: {check_cfg_args:?}"));
+        }
+        return lines;
+    }
+
+    let mut lines = vec![
+        format!("{}: {}", i18n::Label::Running.text(), describe_command(cmd)),
+        describe_scopes(names, cfg_args),
+    ];
+    if verbose == 1 {
+        lines.push(format!(
+            "{}: {}",
+            i18n::Label::DeclaredScopes.text(),
+            describe_list(&names.pretty_args(check_cfg_args))
+        ));
+        for (name, doc) in names.documented_args(check_cfg_args) {
+            lines.push(format!("  {name}: {doc}"));
+        }
+        let presets = names.presets_args(check_cfg_args);
+        if !presets.is_empty() {
+            let presets: Vec<String> = presets
+                .into_iter()
+                .map(|(preset, features)| format!("{preset} = {}", features.join(" + ")))
+                .collect();
+            lines.push(format!(
+                "{}: {}",
+                i18n::Label::Presets.text(),
+                presets.join(", ")
+            ));
+        }
+    }
+    lines
+}
+
+/// Renders the line naming the enabled scopes.
+pub fn describe_scopes(names: &ScopeNames, cfg_args: &[String]) -> String {
+    format!(
+        "{}: {}",
+        i18n::Label::Scopes.text(),
+        describe_list(&names.pretty_args(cfg_args))
+    )
+}
+
+/// Renders a command line the way it is shown before running it.
+pub fn describe_command(cmd: &process::Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                format!("{arg:?}")
+            } else {
+                arg.into_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn describe_list(names: &[String]) -> String {
+    if names.is_empty() {
+        String::from("(none)")
+    } else {
+        names.join(", ")
+    }
+}
@@ -0,0 +1,356 @@
+//! End-to-end checks over an example workspace.
+//!
+//! Every workspace member with a `[package.metadata.feature-scope-e2e]` table is built and run
+//! with its own resolved scopes, and its standard output is compared line by line with the
+//! `expect` list of that table:
+//!
+//! ```toml
+//! [package.metadata.feature-scope-e2e]
+//! expect = ["a type", "default type"]
+//! ```
+//!
+//! A member can also be checked several times with other commands and arguments, which are
+//! parsed and forwarded like the ones given on the command line, `--` separator included, and
+//! select the entries with a `target` or `profile` like there:
+//!
+//! ```toml
+//! [[package.metadata.feature-scope-e2e]]
+//! command = "test"
+//! args = ["--test", "args", "--", "hello"]
+//! expect = ["debug", "hello"]
+//! ```
+//!
+//! With `plain = true`, a check runs plain cargo without any scope flags instead, leaving the
+//! scopes to the build scripts calling `feature_scope_core::load()`, which resolve the member
+//! from `FEATURE_SCOPE_TARGET_PACKAGE` like for the other checks:
+//!
+//! ```toml
+//! [[package.metadata.feature-scope-e2e]]
+//! plain = true
+//! expect = ["yaml"]
+//! ```
+//!
+//! `warnings` lists the warnings the resolution of the member has to report, in the English
+//! wording, in order and with the manifests relative to the root of the workspace, an empty list
+//! asserting that it reports none:
+//!
+//! ```toml
+//! [package.metadata.feature-scope-e2e]
+//! expect = ["a type"]
+//! warnings = []
+//! ```
+//!
+//! With `error`, the resolution has to fail with an error containing it, and nothing is built:
+//!
+//! ```toml
+//! [[package.metadata.feature-scope-e2e]]
+//! args = ["--release"]
+//! error = "mutually exclusive"
+//! ```
+//!
+//! Each member is built into its own variant target directory, so the differing scope flags
+//! don't force a full rebuild for every member.
+//!
+//! One more check compares the packages cargo builds without `-p`, in the root of the workspace
+//! and in the directory of every member, with the ones the scopes are resolved for then.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{fs, path::Path, process};
+
+use feature_scope_core::Overrides;
+
+use crate::{cargo_args::ForwardedArgs, progress::Progress, target_dir, CargoToml};
+
+const DEFAULT_PACKAGES_CHECK: &str = "default packages";
+
+// The parts of a member manifest the checks read
+#[derive(Debug, Deserialize)]
+struct E2eManifest {
+    package: Option<E2ePackage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct E2ePackage {
+    name: String,
+    metadata: Option<E2eMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+struct E2eMetadata {
+    #[serde(rename = "feature-scope-e2e")]
+    feature_scope_e2e: Option<Expectations>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Expectations {
+    One(Expectation),
+    Many(Vec<Expectation>),
+}
+
+#[derive(Debug, Deserialize)]
+struct Expectation {
+    command: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    plain: bool,
+    #[serde(default)]
+    expect: Vec<String>,
+    warnings: Option<Vec<String>>,
+    error: Option<String>,
+}
+
+impl Expectation {
+    fn describe(&self, package: &str) -> String {
+        match &self.command {
+            None if self.args.is_empty() && !self.plain => package.to_string(),
+            command => format!(
+                "{package} ({}{})",
+                if self.plain { "plain cargo " } else { "" },
+                std::iter::once(command.as_deref().unwrap_or("run"))
+                    .chain(self.args.iter().map(String::as_str))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        }
+    }
+}
+
+pub fn run(root_cargo_toml: &CargoToml, root_manifest_path: &Path) -> Result<()> {
+    if root_cargo_toml.workspace.is_none() {
+        anyhow::bail!("The e2e command must be run inside a workspace");
+    }
+
+    let base_target_dir = target_dir::base_target_dir(root_manifest_path.parent().unwrap(), None);
+
+    let mut cases = Vec::new();
+    for member_manifest in crate::workspace_member_manifests(root_cargo_toml, root_manifest_path)? {
+        let content = fs::read_to_string(&member_manifest)
+            .with_context(|| format!("Failed to read {}", member_manifest.display()))?;
+        let member_cargo_toml: E2eManifest = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", member_manifest.display()))?;
+
+        if let Some(package) = member_cargo_toml.package {
+            match package.metadata.and_then(|m| m.feature_scope_e2e) {
+                Some(Expectations::One(expectation)) => cases.push((package.name, expectation)),
+                Some(Expectations::Many(expectations)) => cases.extend(
+                    expectations
+                        .into_iter()
+                        .map(|expectation| (package.name.clone(), expectation)),
+                ),
+                None => {}
+            }
+        }
+    }
+
+    if cases.is_empty() {
+        anyhow::bail!("No workspace member has a [package.metadata.feature-scope-e2e] table");
+    }
+    // Stable, so the checks of one member keep their declared order
+    cases.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    // The manifests the warnings locate are given relative to the root of the workspace
+    let root_prefix = format!(
+        "{}{}",
+        root_manifest_path.parent().unwrap().display(),
+        std::path::MAIN_SEPARATOR
+    );
+    let total = cases.len() + 1;
+    let mut failed = 0;
+    let mut progress = Progress::new("Checks", total);
+    progress.header(DEFAULT_PACKAGES_CHECK);
+    let mismatches = default_package_mismatches(root_cargo_toml, root_manifest_path)?;
+    if mismatches.is_empty() {
+        println!("✅ {DEFAULT_PACKAGES_CHECK}");
+    } else {
+        println!("❌ {DEFAULT_PACKAGES_CHECK} differ from cargo's");
+        for mismatch in mismatches {
+            println!("   {mismatch}");
+        }
+        failed += 1;
+    }
+
+    for (package, expectation) in &cases {
+        let case = expectation.describe(package);
+        progress.header(&case);
+
+        let command = expectation.command.as_deref().unwrap_or("run");
+        // Parsed like the command line, so the checks cover where clap leaves the separator
+        let matches = crate::scope_command(command)
+            .try_get_matches_from(
+                std::iter::once(command).chain(expectation.args.iter().map(String::as_str)),
+            )
+            .with_context(|| format!("Invalid arguments for {case}"))?;
+        let mut args = ForwardedArgs::parse(crate::forwarded_args(command, &matches));
+
+        // The targets and the profile select the entries like for the command line
+        let overrides = Overrides {
+            targets: args.selected_targets(),
+            command: Some(command.to_string()),
+            profiles: crate::build_profiles(root_cargo_toml, command, &args),
+            ..Overrides::default()
+        };
+        let result =
+            crate::resolve_scope_with(root_cargo_toml, root_manifest_path, package, &overrides);
+        let resolved = match (result, &expectation.error) {
+            (Ok(resolved), None) => resolved,
+            (Err(err), None) => return Err(err),
+            (Err(err), Some(expected)) if format!("{err:#}").contains(expected.as_str()) => {
+                println!("✅ {case}");
+                continue;
+            }
+            (Err(err), Some(expected)) => {
+                println!("❌ {case} failed to resolve with an unexpected error");
+                println!("   expected: {expected:?}");
+                println!("   actual:   {:?}", format!("{err:#}"));
+                failed += 1;
+                continue;
+            }
+            (Ok(_), Some(expected)) => {
+                println!("❌ {case} resolved, but was expected to fail with {expected:?}");
+                failed += 1;
+                continue;
+            }
+        };
+
+        let variant = if expectation.plain {
+            format!("e2e-plain-{package}")
+        } else {
+            format!("e2e-{package}")
+        };
+        let variant_target_dir =
+            target_dir::prepare_variant_target_dir(&base_target_dir, &variant, false)?;
+
+        args.cargo.insert(0, String::from("--quiet"));
+        // Plain cargo is left to the build scripts, which cannot activate dependencies
+        if !expectation.plain {
+            args.cargo
+                .extend(crate::cargo_feature_args(&resolved.cargo_features));
+        }
+
+        let mut cargo_cmd = if expectation.plain {
+            let mut cargo_cmd = process::Command::new("cargo");
+            cargo_cmd
+                .args(command.split(' '))
+                .arg("-p")
+                .arg(package)
+                .arg("--target-dir")
+                .arg(&variant_target_dir);
+            args.apply(&mut cargo_cmd);
+            cargo_cmd
+        } else {
+            crate::build_cargo_command(
+                command,
+                Some(package),
+                Some(&variant_target_dir),
+                &[],
+                &resolved.cfg_args,
+                &resolved.check_cfg_args,
+                &args,
+            )
+        };
+        let output = cargo_cmd
+            .env(crate::env_vars::TARGET_PACKAGE, package)
+            .stderr(process::Stdio::inherit())
+            .output()
+            .with_context(|| "Failed to execute cargo command")?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let actual: Vec<&str> = stdout
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        let warnings: Vec<String> = resolved
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| !diagnostic.is_note())
+            .map(|diagnostic| diagnostic.to_string().replace(&root_prefix, ""))
+            .collect();
+
+        if let Some(expected) = expectation.warnings.as_ref().filter(|w| **w != warnings) {
+            println!("❌ {case} reported unexpected warnings");
+            println!("   expected: {expected:?}");
+            println!("   actual:   {warnings:?}");
+            failed += 1;
+        } else if !output.status.success() {
+            println!("❌ {case} failed with {}", output.status);
+            failed += 1;
+        } else if actual != expectation.expect {
+            println!("❌ {case} printed unexpected output");
+            println!("   expected: {:?}", expectation.expect);
+            println!("   actual:   {actual:?}");
+            failed += 1;
+        } else {
+            println!("✅ {case}");
+        }
+    }
+
+    println!();
+    if failed > 0 {
+        anyhow::bail!("{failed} of {total} end-to-end checks failed");
+    }
+
+    println!("🎉 All {total} end-to-end checks passed!");
+    Ok(())
+}
+
+// Where the packages cargo selects without `-p` differ from the resolved ones, one line per
+// directory
+fn default_package_mismatches(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+) -> Result<Vec<String>> {
+    let root_dir = root_manifest_path.parent().unwrap();
+    let mut dirs = vec![root_dir.to_path_buf()];
+    for member_manifest in crate::workspace_member_manifests(root_cargo_toml, root_manifest_path)? {
+        dirs.push(member_manifest.parent().unwrap().to_path_buf());
+    }
+
+    let mut mismatches = Vec::new();
+    for dir in dirs {
+        // `cargo tree` prints one tree per selected package, each starting with the package
+        let output = process::Command::new("cargo")
+            .args(["tree", "--quiet", "--depth", "0", "--prefix", "none"])
+            .args(["--format", "{p}"])
+            .current_dir(&dir)
+            .stderr(process::Stdio::inherit())
+            .output()
+            .with_context(|| "Failed to execute cargo tree")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "cargo tree failed in {} with {}",
+                dir.display(),
+                output.status
+            );
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut cargo_packages: Vec<&str> = stdout
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .collect();
+        cargo_packages.sort();
+        cargo_packages.dedup();
+
+        let mut resolved = crate::default_packages(root_cargo_toml, root_manifest_path, &dir)?;
+        resolved.sort();
+        if resolved != cargo_packages {
+            let shown = dir.strip_prefix(root_dir).unwrap_or(&dir);
+            mismatches.push(format!(
+                "in '{}': cargo builds {}, resolved for {}",
+                if shown.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    shown
+                }
+                .display(),
+                cargo_packages.join(", "),
+                resolved.join(", ")
+            ));
+        }
+    }
+    Ok(mismatches)
+}
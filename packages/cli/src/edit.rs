@@ -0,0 +1,388 @@
+//! Editing of the selections of a consumer and the declarations of a library.
+//!
+//! `cargo feature-scope add [-p <CONSUMER>] <LIBRARY> <FEATURE>... [--declare]` selects features
+//! of a library in the consumer's `[[package.metadata.feature-scope]]` entries: it adds them to
+//! the entry for the library that applies everywhere, without `target`, `platform` or
+//! `profile`, and creates that entry if there is none. Features the library does not declare
+//! are refused, or with `--declare` added to its `feature-scope-decl` as features of their own.
+//!
+//! `cargo feature-scope remove [-p <CONSUMER>] <LIBRARY> <FEATURE>... [--undeclare]` drops them
+//! from every entry for the library. An entry left without features would select the defaults
+//! instead, so it is removed. `--undeclare` removes the declarations too, unless other features,
+//! presets, the defaults or exclusive groups of the library list them.
+//!
+//! Both edit the manifests in place, keeping their formatting and comments.
+
+use anyhow::{Context, Result};
+use feature_scope_core::manifest::FeatureScopeDecl;
+use std::path::{Path, PathBuf};
+use toml_edit::{Array, ArrayOfTables, DocumentMut, InlineTable, Item, Table, TableLike, Value};
+
+use crate::{
+    conflict::{entry_refers, remove_features},
+    items,
+    lints::{read_document, write_document},
+    CargoToml,
+};
+
+// Keys limiting where an entry applies
+const QUALIFIERS: [&str; 3] = ["target", "platform", "profile"];
+
+/// What an edit leaves of an entry.
+pub enum Outcome {
+    Kept,
+    Emptied,
+}
+
+pub fn add(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    consumer: Option<&String>,
+    args: &[String],
+) -> Result<()> {
+    const USAGE: &str =
+        "Usage: cargo feature-scope add [-p <CONSUMER>] <LIBRARY> <FEATURE>... [--declare]";
+    let (library, features, declare) = parse_args(args, "--declare", USAGE)?;
+    let consumer_manifest = consumer_manifest(root_cargo_toml, root_manifest_path, consumer)?;
+    let mut document = read_document(&consumer_manifest)?;
+
+    let member = items::find_package(root_cargo_toml, root_manifest_path, &library).ok();
+    if let Some((library_manifest, package)) = &member {
+        let decl = package
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.feature_scope_decl.as_ref());
+        let undeclared: Vec<&String> = features
+            .iter()
+            .filter(|feature| !is_declared(decl, feature))
+            .collect();
+        if !undeclared.is_empty() && !declare {
+            anyhow::bail!(
+                "Package '{library}' does not declare {}; pass --declare to declare them",
+                quoted(&undeclared)
+            );
+        }
+        if !undeclared.is_empty() {
+            let is_consumer = library_manifest == &consumer_manifest;
+            let mut library_document = if is_consumer {
+                None
+            } else {
+                Some(read_document(library_manifest)?)
+            };
+            let decl_table = decl_table(library_document.as_mut().unwrap_or(&mut document))?;
+            for feature in undeclared {
+                decl_table.insert(feature, toml_edit::value(Array::new()));
+            }
+            if let Some(library_document) = &library_document {
+                write_document(library_manifest, library_document)?;
+            }
+        }
+    } else if declare {
+        anyhow::bail!(
+            "Package '{library}' is not a workspace member, its declarations cannot be edited"
+        );
+    }
+
+    let own_name = own_name(&document);
+    let entries = entries_item(&mut document)?;
+    let mut added = false;
+    for_each_entry(entries, |entry| {
+        let applies_everywhere = !QUALIFIERS.iter().any(|key| entry.contains_key(key));
+        if added
+            || !applies_everywhere
+            || !entry_refers(entry, &consumer_manifest, own_name.as_deref(), &library)?
+        {
+            return Ok(Outcome::Kept);
+        }
+        let selected = entry
+            .entry("features")
+            .or_insert_with(|| toml_edit::value(Array::new()))
+            .as_array_mut()
+            .context("`features` of an entry is not an array")?;
+        for feature in &features {
+            if !selected.iter().any(|item| item.as_str() == Some(feature)) {
+                selected.push(feature.as_str());
+            }
+        }
+        added = true;
+        Ok(Outcome::Kept)
+    })?;
+
+    if !added {
+        let selected: Array = features.iter().collect();
+        match entries {
+            Item::Value(Value::Array(array)) => {
+                let mut entry = InlineTable::new();
+                entry.insert("package", Value::from(library.as_str()));
+                entry.insert("features", Value::Array(selected));
+                array.push(entry);
+            }
+            _ => {
+                let mut entry = Table::new();
+                entry.insert("package", toml_edit::value(library.as_str()));
+                entry.insert("features", toml_edit::value(selected));
+                entries
+                    .as_array_of_tables_mut()
+                    .context("`package.metadata.feature-scope` is not an array of tables")?
+                    .push(entry);
+            }
+        }
+    }
+    write_document(&consumer_manifest, &document)
+}
+
+pub fn remove(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    consumer: Option<&String>,
+    args: &[String],
+) -> Result<()> {
+    const USAGE: &str =
+        "Usage: cargo feature-scope remove [-p <CONSUMER>] <LIBRARY> <FEATURE>... [--undeclare]";
+    let (library, features, undeclare) = parse_args(args, "--undeclare", USAGE)?;
+    let consumer_manifest = consumer_manifest(root_cargo_toml, root_manifest_path, consumer)?;
+    let mut document = read_document(&consumer_manifest)?;
+
+    // Checked before editing anything, so a refusal leaves both manifests alone
+    let library_manifest = if undeclare {
+        let (library_manifest, package) =
+            items::find_package(root_cargo_toml, root_manifest_path, &library)?;
+        let decl = package
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.feature_scope_decl.as_ref())
+            .with_context(|| format!("Package '{library}' declares no scopes"))?;
+        for feature in &features {
+            let users = users(decl, feature);
+            if !users.is_empty() {
+                anyhow::bail!(
+                    "Feature '{feature}' of '{library}' is still listed by {}, remove it there first",
+                    users.join(", ")
+                );
+            }
+        }
+        Some(library_manifest)
+    } else {
+        None
+    };
+
+    let own_name = own_name(&document);
+    let mut removed = false;
+    if let Some(entries) = document
+        .get_mut("package")
+        .and_then(Item::as_table_like_mut)
+        .and_then(|package| package.get_mut("metadata"))
+        .and_then(Item::as_table_like_mut)
+        .and_then(|metadata| metadata.get_mut("feature-scope"))
+    {
+        for_each_entry(entries, |entry| {
+            if !entry_refers(entry, &consumer_manifest, own_name.as_deref(), &library)? {
+                return Ok(Outcome::Kept);
+            }
+            let Some(selected) = entry.get_mut("features").and_then(Item::as_array_mut) else {
+                return Ok(Outcome::Kept);
+            };
+            let before = selected.len();
+            remove_features(selected, &features);
+            if selected.len() == before {
+                return Ok(Outcome::Kept);
+            }
+            removed = true;
+            Ok(if selected.is_empty() {
+                Outcome::Emptied
+            } else {
+                Outcome::Kept
+            })
+        })?;
+    }
+    if removed {
+        write_document(&consumer_manifest, &document)?;
+    } else {
+        println!(
+            "⚠️  No entry of {} selects {} of '{library}'",
+            consumer_manifest.display(),
+            quoted(&features.iter().collect::<Vec<_>>())
+        );
+    }
+
+    if let Some(library_manifest) = library_manifest {
+        // The consumer's manifest was just written, and may be the library's
+        let mut library_document = read_document(&library_manifest)?;
+        let decl_table = decl_table(&mut library_document)?;
+        for feature in &features {
+            decl_table.remove(feature);
+        }
+        write_document(&library_manifest, &library_document)?;
+    }
+    Ok(())
+}
+
+// The library, the features and whether the flag is given
+fn parse_args(args: &[String], flag: &str, usage: &str) -> Result<(String, Vec<String>, bool)> {
+    let mut given = false;
+    let mut positional = Vec::new();
+    for arg in args {
+        if arg == flag {
+            given = true;
+        } else if arg.starts_with('-') {
+            anyhow::bail!("{usage}");
+        } else {
+            positional.push(arg.clone());
+        }
+    }
+    match positional.split_first() {
+        Some((library, features)) if !features.is_empty() => {
+            Ok((library.clone(), features.to_vec(), given))
+        }
+        _ => anyhow::bail!("{usage}"),
+    }
+}
+
+fn consumer_manifest(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    consumer: Option<&String>,
+) -> Result<PathBuf> {
+    let consumer = match consumer {
+        Some(consumer) => consumer.clone(),
+        None => feature_scope_core::determine_default_package(root_cargo_toml, root_manifest_path)?,
+    };
+    Ok(items::find_package(root_cargo_toml, root_manifest_path, &consumer)?.0)
+}
+
+fn is_declared(decl: Option<&FeatureScopeDecl>, feature: &str) -> bool {
+    feature == "default"
+        || decl.is_some_and(|decl| {
+            decl.declares(feature) || decl.default.iter().flatten().any(|f| f == feature)
+        })
+}
+
+// What lists a feature of the library besides its consumers
+fn users(decl: &FeatureScopeDecl, feature: &str) -> Vec<String> {
+    let mut users: Vec<String> = decl
+        .features
+        .iter()
+        .filter(|(_, dependencies)| dependencies.iter().any(|d| d == feature))
+        .map(|(name, _)| format!("feature '{name}'"))
+        .chain(
+            decl.details
+                .iter()
+                .filter(|(_, details)| details.conflicts.iter().any(|c| c == feature))
+                .map(|(name, _)| format!("the conflicts of '{name}'")),
+        )
+        .chain(
+            decl.presets
+                .iter()
+                .filter(|(_, members)| members.iter().any(|m| m == feature))
+                .map(|(name, _)| format!("preset '{name}'")),
+        )
+        .collect();
+    users.sort();
+    if decl.default.iter().flatten().any(|f| f == feature) {
+        users.push(String::from("the defaults"));
+    }
+    if decl
+        .exclusive
+        .iter()
+        .flatten()
+        .flatten()
+        .any(|f| f == feature)
+    {
+        users.push(String::from("an exclusive group"));
+    }
+    users
+}
+
+/// The name of the package of a manifest, which its entries without `package` refer to.
+pub fn own_name(document: &DocumentMut) -> Option<String> {
+    document
+        .get("package")
+        .and_then(|package| package.get("name"))
+        .and_then(Item::as_str)
+        .map(String::from)
+}
+
+// The entries of the consumer, created as an empty array of tables if there are none
+fn entries_item(document: &mut DocumentMut) -> Result<&mut Item> {
+    let metadata = document
+        .get_mut("package")
+        .and_then(Item::as_table_mut)
+        .context("The manifest has no [package] table")?
+        .entry("metadata")
+        .or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        })
+        .as_table_like_mut()
+        .context("`package.metadata` is not a table")?;
+    Ok(metadata
+        .entry("feature-scope")
+        .or_insert_with(|| Item::ArrayOfTables(ArrayOfTables::new())))
+}
+
+/// Edits the feature-scope entries of a consumer in order, removing the ones an edit empties.
+pub fn for_each_entry(
+    entries: &mut Item,
+    mut edit: impl FnMut(&mut dyn TableLike) -> Result<Outcome>,
+) -> Result<()> {
+    match entries {
+        Item::ArrayOfTables(tables) => {
+            let mut index = 0;
+            while let Some(entry) = tables.get_mut(index) {
+                match edit(entry)? {
+                    Outcome::Emptied => {
+                        tables.remove(index);
+                    }
+                    Outcome::Kept => index += 1,
+                }
+            }
+        }
+        Item::Value(Value::Array(array)) => {
+            let mut index = 0;
+            while let Some(value) = array.get_mut(index) {
+                let outcome = match value.as_inline_table_mut() {
+                    Some(entry) => edit(entry)?,
+                    None => Outcome::Kept,
+                };
+                match outcome {
+                    Outcome::Emptied => {
+                        array.remove(index);
+                    }
+                    Outcome::Kept => index += 1,
+                }
+            }
+        }
+        _ => anyhow::bail!("`package.metadata.feature-scope` is not an array of entries"),
+    }
+    Ok(())
+}
+
+// The feature-scope-decl table of a library, created if it declares nothing yet
+fn decl_table(document: &mut DocumentMut) -> Result<&mut dyn TableLike> {
+    let metadata = document
+        .get_mut("package")
+        .and_then(Item::as_table_mut)
+        .context("The manifest has no [package] table")?
+        .entry("metadata")
+        .or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        })
+        .as_table_like_mut()
+        .context("`package.metadata` is not a table")?;
+    metadata
+        .entry("feature-scope-decl")
+        .or_insert_with(|| Item::Table(Table::new()))
+        .as_table_like_mut()
+        .context("`package.metadata.feature-scope-decl` is not a table")
+}
+
+fn quoted(features: &[&String]) -> String {
+    features
+        .iter()
+        .map(|feature| format!("'{feature}'"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
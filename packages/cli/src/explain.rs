@@ -0,0 +1,247 @@
+//! Explanations of why a scope is enabled or not.
+//!
+//! `cargo feature-scope explain <library> <feature>` resolves the package given with `-p`, or
+//! the default one, like a build would, `--features` and `--no-default-features` included, and
+//! prints what enabled the scope: the consumer entries selecting it or a feature depending on
+//! it, or the library defaults, each with the chain of feature dependencies leading to the
+//! scope. A scope that is not enabled is explained by the entries referencing the library and
+//! the features that would enable it.
+
+use anyhow::Result;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::Path,
+};
+
+use feature_scope_core::{manifest::FeatureScopeDecl, Origin, Overrides};
+
+use crate::{cfg_name, items, list, CargoToml};
+
+pub fn run(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    consumer: &str,
+    overrides: &Overrides,
+    args: &[String],
+) -> Result<()> {
+    let [library, feature] = args else {
+        anyhow::bail!("Usage: cargo feature-scope explain [-p <CONSUMER>] <PACKAGE> <FEATURE>");
+    };
+
+    let (_, library_package) = items::find_package(root_cargo_toml, root_manifest_path, library)?;
+    let decl = library_package
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.feature_scope_decl.as_ref())
+        .ok_or_else(|| anyhow::anyhow!("Package '{library}' does not declare any scopes"))?;
+    if !decl.features.contains_key(feature) {
+        let mut declared: Vec<&str> = decl.features.keys().map(String::as_str).collect();
+        declared.sort();
+        let preset = if decl.presets.contains_key(feature) {
+            ", it is a preset and has no scope of its own"
+        } else {
+            ""
+        };
+        anyhow::bail!(
+            "Package '{library}' does not declare scope '{feature}'{preset}, declared scopes are: {}",
+            declared.join(", ")
+        );
+    }
+
+    let resolved = feature_scope_core::resolve_cfg_args_with(
+        root_cargo_toml,
+        root_manifest_path,
+        consumer,
+        overrides,
+    )?;
+    let cfg = cfg_name::scope_cfg(feature);
+    let origins = resolved
+        .origins
+        .get(library)
+        .and_then(|features| features.get(feature));
+
+    match origins {
+        Some(origins) => {
+            println!("{library}/{feature} ({cfg}) is enabled for {consumer}:");
+            for origin in origins {
+                let (what, from) = match origin {
+                    Origin::Selection {
+                        consumer,
+                        feature: selected,
+                    } => (
+                        format!("{consumer} selects {library}/{selected}"),
+                        selected.as_str(),
+                    ),
+                    Origin::Defaults { .. } => (format!("the defaults of {library}"), "default"),
+                    Origin::Probe {
+                        feature: probed,
+                        probe,
+                        ..
+                    } => (
+                        format!("the probe '{probe}' of {library}/{probed}"),
+                        probed.as_str(),
+                    ),
+                };
+                match chain(decl, from, feature) {
+                    Some(chain) if chain.len() > 1 => println!("  {what}: {}", chain.join(" -> ")),
+                    _ => println!("  {what}"),
+                }
+            }
+        }
+        None => {
+            println!("{library}/{feature} ({cfg}) is not enabled for {consumer}:");
+            explain_disabled(
+                root_cargo_toml,
+                root_manifest_path,
+                consumer,
+                overrides,
+                library,
+                decl,
+                feature,
+            )?;
+
+            let mut enabling: Vec<&String> = decl
+                .features
+                .keys()
+                .chain(decl.presets.keys())
+                .filter(|from| chain(decl, from, feature).is_some())
+                .collect();
+            enabling.sort();
+            let enabling: Vec<&str> = enabling.iter().map(|name| name.as_str()).collect();
+            println!("  selecting any of {} enables it", enabling.join(", "));
+        }
+    }
+
+    // The cfg only names the feature, so libraries declaring the same one enable it too
+    let others: Vec<&String> = resolved
+        .origins
+        .iter()
+        .filter(|(other, features)| *other != library && features.contains_key(feature))
+        .map(|(other, _)| other)
+        .collect();
+    for other in others {
+        println!("{cfg} is also enabled through {other}/{feature}");
+    }
+
+    Ok(())
+}
+
+// The entries of the consumer that reference the library, and what keeps them from the feature
+fn explain_disabled(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    consumer: &str,
+    overrides: &Overrides,
+    library: &str,
+    decl: &FeatureScopeDecl,
+    feature: &str,
+) -> Result<()> {
+    let (consumer_manifest, consumer_package) =
+        items::find_package(root_cargo_toml, root_manifest_path, consumer)?;
+    let entries: Vec<_> = consumer_package
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.feature_scope.as_ref())
+        .into_iter()
+        .flatten()
+        .filter(|entry| {
+            list::entry_library(&consumer_manifest, &consumer_package, entry).as_deref()
+                == Some(library)
+        })
+        .collect();
+
+    // A single package applies its own defaults without an entry
+    let is_single_package = root_cargo_toml.workspace.is_none();
+    if is_single_package && consumer == library {
+        let defaults = decl.default.as_deref().unwrap_or_default();
+        if overrides.no_default_features {
+            println!("  --no-default-features drops the defaults of {library}");
+        } else if defaults.is_empty() {
+            println!("  {library} has no defaults");
+        } else {
+            println!("  the defaults of {library} are {}", defaults.join(", "));
+        }
+    } else if entries.is_empty() {
+        println!(
+            "  no [[package.metadata.feature-scope]] entry of {consumer} references {library}"
+        );
+    }
+
+    for entry in entries {
+        let features = if entry.features.is_empty() && entry.removed.is_empty() {
+            String::from("no features")
+        } else {
+            entry.selection().join(", ")
+        };
+        if let Some(target) = entry
+            .target
+            .as_ref()
+            .filter(|target| !overrides.selects_target(target))
+        {
+            println!("  an entry of {consumer} selects {features} only when building {target}");
+            continue;
+        }
+        if let Some(removed) = entry
+            .removed
+            .iter()
+            .find(|removed| chain(decl, removed, feature).is_some())
+        {
+            println!(
+                "  an entry of {consumer} selects {features}, removing {removed} leaves it out"
+            );
+            continue;
+        }
+        let defaults = if overrides.no_default_features {
+            "--no-default-features drops the defaults"
+        } else if entry.features.iter().any(|f| f == "default") {
+            "the defaults are selected"
+        } else if entry.default_features == Some(false) {
+            "default-features = false drops the defaults"
+        } else if !entry.features.is_empty() {
+            "the defaults only apply to entries selecting no features"
+        } else {
+            "the defaults apply"
+        };
+        println!("  an entry of {consumer} selects {features}, {defaults}");
+    }
+
+    Ok(())
+}
+
+// The shortest chain of dependencies from one feature, a preset or `default` to another
+fn chain(decl: &FeatureScopeDecl, from: &str, to: &str) -> Option<Vec<String>> {
+    let defaults = decl.default.clone().unwrap_or_default();
+    let next = |feature: &str| -> Vec<String> {
+        match feature {
+            "default" => defaults.clone(),
+            _ => decl
+                .presets
+                .get(feature)
+                .or_else(|| decl.features.get(feature))
+                .cloned()
+                .unwrap_or_default(),
+        }
+    };
+
+    let mut previous: HashMap<String, String> = HashMap::new();
+    let mut seen = HashSet::from([from.to_string()]);
+    let mut queue = VecDeque::from([from.to_string()]);
+    while let Some(feature) = queue.pop_front() {
+        if feature == to {
+            let mut chain = vec![feature];
+            while let Some(before) = previous.get(chain.last().unwrap()) {
+                chain.push(before.clone());
+            }
+            chain.reverse();
+            return Some(chain);
+        }
+        for dependency in next(&feature) {
+            if seen.insert(dependency.clone()) {
+                previous.insert(dependency.clone(), feature.clone());
+                queue.push_back(dependency);
+            }
+        }
+    }
+
+    None
+}
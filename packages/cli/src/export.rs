@@ -0,0 +1,513 @@
+//! Export of the scopes of a library as cargo features, for publishing.
+//!
+//! `cargo feature-scope export [<PACKAGE>] [--dual]` is the inverse of `migrate`: it writes the
+//! features of `feature-scope-decl` into `[features]`, with the features they depend on, the
+//! defaults as `default` and the presets as features bundling theirs, keeping the entries
+//! `[features]` already has. In the sources of the package, `#[feature_scope(x)]` becomes
+//! `#[cfg(feature = "x")]`, `#[feature_scope_default(x)]` becomes
+//! `#[cfg(any(feature = "default", feature = "x"))]`, `cfg_scope!(x)` becomes
+//! `cfg!(feature = "x")` and the scope cfgs of plain `cfg`s become the features, so the crate
+//! builds with plain cargo from crates.io.
+//!
+//! `--dual` keeps the scopes working instead: the attributes become
+//! `#[cfg_attr(not(feature = "x"), feature_scope(x))]`, compiled when the cargo features select
+//! the item and otherwise gated by the scopes, and `cfg_scope!(x)` becomes
+//! `(cfg!(feature = "x") || cfg_scope!(x))`.
+//!
+//! What cargo features cannot carry over is reported: exclusive groups and conflicts, probes and
+//! platforms, stubs and `doc = "show-with-note"` without `--dual`, and the macros choosing
+//! between scopes, which plain cargo builds resolve to their fallbacks.
+
+use anyhow::{Context, Result};
+use feature_scope_core::manifest::FeatureScopeDecl;
+use proc_macro2::{Span, TokenStream, TokenTree};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+use syn::{
+    punctuated::Punctuated,
+    visit::{self, Visit},
+    Attribute, ItemUse, Macro, MacroDelimiter, Meta, Token, UseTree,
+};
+use toml_edit::{Array, Item, Table};
+
+use crate::{
+    cfg_name, items,
+    lints::{read_document, write_document},
+    rename::{apply_edits, offset},
+    CargoToml,
+};
+
+const USAGE: &str = "Usage: cargo feature-scope export [<PACKAGE>] [--dual]";
+
+// The attribute macros gating items, which both modes rewrite
+const GATE_ATTRIBUTES: [&str; 2] = ["feature_scope", "feature_scope_default"];
+
+pub fn run(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    package: Option<&String>,
+    args: &[String],
+) -> Result<()> {
+    let mut dual = false;
+    let mut positional = None;
+    for arg in args {
+        match arg.as_str() {
+            "--dual" => dual = true,
+            _ if !arg.starts_with('-') && positional.is_none() => positional = Some(arg.clone()),
+            _ => anyhow::bail!(USAGE),
+        }
+    }
+    let package = match positional.or_else(|| package.cloned()) {
+        Some(package) => package,
+        None => feature_scope_core::determine_default_package(root_cargo_toml, root_manifest_path)?,
+    };
+    let (manifest_path, declared) =
+        items::find_package(root_cargo_toml, root_manifest_path, &package)?;
+    let decl = declared
+        .metadata
+        .and_then(|metadata| metadata.feature_scope_decl)
+        .with_context(|| format!("Package '{package}' declares no scopes to export"))?;
+
+    let mut notes = Vec::new();
+    export_features(&manifest_path, &decl, &mut notes)?;
+
+    // The features of the scope cfgs, for the plain `cfg`s using them
+    let mut cfgs: HashMap<String, String> = decl
+        .features
+        .keys()
+        .map(|feature| (cfg_name::scope_cfg(feature), feature.clone()))
+        .collect();
+    cfgs.insert(String::from("__scope_default"), String::from("default"));
+
+    let root_dir = root_manifest_path.parent().unwrap();
+    let package_dir = manifest_path.parent().unwrap();
+    let mut files = Vec::new();
+    items::collect_sources(package_dir, package_dir, &mut files)?;
+    files.sort();
+    let mut exported = 0;
+    for file in files {
+        let content = fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let parsed = syn::parse_file(&content)
+            .with_context(|| format!("Failed to parse {}", file.display()))?;
+
+        let mut export = Export {
+            content: &content,
+            decl: &decl,
+            cfgs: &cfgs,
+            dual,
+            edits: BTreeMap::new(),
+            notes: Vec::new(),
+        };
+        export.visit_file(&parsed);
+        let shown = file.strip_prefix(root_dir).unwrap_or(&file);
+        for (line, note) in export.notes {
+            notes.push(format!("{}:{line}: {note}", shown.display()));
+        }
+        if !export.edits.is_empty() {
+            exported += export.edits.len();
+            fs::write(&file, apply_edits(&content, &export.edits))
+                .with_context(|| format!("Failed to write {}", file.display()))?;
+            println!("Updated {}", file.display());
+        }
+    }
+
+    println!(
+        "Exported {} scope(s) and {exported} gate(s) of '{package}' as cargo features",
+        decl.features.len()
+    );
+    for note in &notes {
+        println!("⚠️  {note}");
+    }
+    Ok(())
+}
+
+// Adds the features, defaults and presets of the declaration to `[features]`
+fn export_features(
+    manifest_path: &Path,
+    decl: &FeatureScopeDecl,
+    notes: &mut Vec<String>,
+) -> Result<()> {
+    let mut document = read_document(manifest_path)?;
+    // The order of the declaration, with the features declared in code after it
+    let mut names: Vec<String> = document
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("feature-scope-decl"))
+        .and_then(Item::as_table_like)
+        .map(|table| table.iter().map(|(name, _)| name.to_string()).collect())
+        .unwrap_or_default();
+    let mut in_code: Vec<&String> = decl
+        .features
+        .keys()
+        .filter(|feature| !names.contains(feature))
+        .collect();
+    in_code.sort();
+    names.extend(in_code.into_iter().cloned());
+
+    let mut exported: Vec<(&str, Vec<String>)> =
+        vec![("default", decl.default.clone().unwrap_or_default())];
+    for name in &names {
+        if let Some(deps) = decl.features.get(name) {
+            let dependencies = decl.optional_dependencies.get(name).into_iter().flatten();
+            let external = decl.dependency_features.get(name).into_iter().flatten();
+            let deps = deps
+                .iter()
+                .cloned()
+                .chain(dependencies.map(|dependency| format!("dep:{dependency}")))
+                .chain(external.cloned())
+                .collect();
+            exported.push((name, deps));
+        } else if let Some(features) = decl.presets.get(name) {
+            exported.push((name, features.clone()));
+        }
+    }
+    let mut presets: Vec<&String> = decl
+        .presets
+        .keys()
+        .filter(|preset| !names.contains(preset))
+        .collect();
+    presets.sort();
+    exported.extend(
+        presets
+            .into_iter()
+            .map(|preset| (preset.as_str(), decl.presets[preset].clone())),
+    );
+
+    let features = document
+        .entry("features")
+        .or_insert_with(|| Item::Table(Table::new()))
+        .as_table_like_mut()
+        .context("`features` is not a table")?;
+    for (name, deps) in exported {
+        let entry = features
+            .entry(name)
+            .or_insert_with(|| toml_edit::value(Array::new()));
+        let array = entry
+            .as_array_mut()
+            .with_context(|| format!("`features.{name}` is not an array"))?;
+        for dep in deps {
+            if !array.iter().any(|value| value.as_str() == Some(&dep)) {
+                array.push(dep);
+            }
+        }
+    }
+    write_document(manifest_path, &document)?;
+
+    for group in decl.exclusive.iter().flatten() {
+        notes.push(format!(
+            "exclusive group [{}] is not enforced, cargo features cannot exclude each other",
+            group.join(", ")
+        ));
+    }
+    let mut details: Vec<_> = decl.details.iter().collect();
+    details.sort_by_key(|(name, _)| *name);
+    for (name, details) in details {
+        if !details.conflicts.is_empty() {
+            notes.push(format!(
+                "the conflicts of '{name}' with {} are not enforced, cargo features cannot exclude each other",
+                details.conflicts.join(", ")
+            ));
+        }
+        if let Some(probe) = &details.probe {
+            notes.push(format!(
+                "'{name}' is enabled by the probe '{probe}' only with the scopes, consumers select the feature themselves"
+            ));
+        }
+        if let Some(platform) = &details.platform {
+            notes.push(format!(
+                "'{name}' is limited to {platform} only with the scopes, cargo enables the feature on every platform"
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Collects the edits of a source file, with notes on what it cannot export
+struct Export<'a> {
+    content: &'a str,
+    decl: &'a FeatureScopeDecl,
+    cfgs: &'a HashMap<String, String>,
+    dual: bool,
+    edits: BTreeMap<usize, (usize, String)>,
+    notes: Vec<(usize, String)>,
+}
+
+impl Export<'_> {
+    fn replace(&mut self, start: Span, end: Span, text: String) {
+        let start = offset(self.content, start.start());
+        self.edits
+            .insert(start, (offset(self.content, end.end()), text));
+    }
+
+    // The source text between two spans
+    fn between(&self, start: Span, end: Span) -> &str {
+        &self.content[offset(self.content, start.end())..offset(self.content, end.start())]
+    }
+
+    // Translates a scope predicate into the cfg of the cargo features
+    fn predicate(&mut self, meta: &Meta, line: usize) -> Option<String> {
+        match meta {
+            Meta::Path(path) => {
+                let feature = path.get_ident()?.to_string();
+                Some(self.feature(feature, line))
+            }
+            Meta::NameValue(name_value) if name_value.path.is_ident("feature") => {
+                let syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(feature),
+                    ..
+                }) = &name_value.value
+                else {
+                    return None;
+                };
+                Some(self.feature(feature.value(), line))
+            }
+            Meta::List(list)
+                if ["all", "any", "not"]
+                    .iter()
+                    .any(|op| list.path.is_ident(op)) =>
+            {
+                let operands = list
+                    .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                    .ok()?;
+                let operands = operands
+                    .iter()
+                    .map(|operand| self.predicate(operand, line))
+                    .collect::<Option<Vec<_>>>()?;
+                Some(format!(
+                    "{}({})",
+                    list.path.get_ident().unwrap(),
+                    operands.join(", ")
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn feature(&mut self, feature: String, line: usize) -> String {
+        if !self.decl.features.contains_key(&feature) {
+            self.notes.push((
+                line,
+                format!("scope '{feature}' is not declared, so [features] does not have it either"),
+            ));
+        }
+        format!("feature = {feature:?}")
+    }
+
+    // Rewrites `#[feature_scope(...)]` and `#[feature_scope_default(...)]`
+    fn gate(&mut self, attr: &Attribute, default: bool, line: usize) {
+        let arguments = match &attr.meta {
+            Meta::Path(_) => Punctuated::new(),
+            _ => match attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated) {
+                Ok(arguments) => arguments,
+                Err(_) => return,
+            },
+        };
+        let mut arguments = arguments.iter().peekable();
+        let is_option = |meta: &Meta| match meta {
+            Meta::Path(path) => path.is_ident("stub"),
+            Meta::NameValue(name_value) => !name_value.path.is_ident("feature"),
+            Meta::List(_) => false,
+        };
+        let predicate = match arguments.next_if(|meta| !is_option(meta)) {
+            Some(meta) => match self.predicate(meta, line) {
+                Some(predicate) => Some(predicate),
+                None => {
+                    self.notes
+                        .push((line, String::from("the predicate is not understood, kept")));
+                    return;
+                }
+            },
+            None => None,
+        };
+        let features = match (default, predicate) {
+            (false, Some(predicate)) => predicate,
+            (true, Some(predicate)) => format!("any(feature = \"default\", {predicate})"),
+            (true, None) => String::from("feature = \"default\""),
+            (false, None) => return,
+        };
+
+        let bracket = &attr.bracket_token.span;
+        if self.dual {
+            let inner = self.between(bracket.open(), bracket.close()).to_string();
+            let bang = if matches!(attr.style, syn::AttrStyle::Inner(_)) {
+                "!"
+            } else {
+                ""
+            };
+            self.replace(
+                attr.pound_token.span,
+                bracket.close(),
+                format!("#{bang}[cfg_attr(not({features}), {inner})]"),
+            );
+            return;
+        }
+        for option in arguments {
+            let dropped = match option {
+                Meta::Path(path) if path.is_ident("stub") => {
+                    "`stub` is dropped, callers of the item need the feature too"
+                }
+                Meta::NameValue(name_value) if name_value.path.is_ident("doc") => {
+                    "the `doc` option is dropped, rustdoc documents the item with the feature only"
+                }
+                _ => continue,
+            };
+            self.notes.push((line, String::from(dropped)));
+        }
+        let bang = if matches!(attr.style, syn::AttrStyle::Inner(_)) {
+            "!"
+        } else {
+            ""
+        };
+        self.replace(
+            attr.pound_token.span,
+            bracket.close(),
+            format!("#{bang}[cfg({features})]"),
+        );
+    }
+
+    // Replaces the scope cfgs in the tokens of a plain `cfg` by the features
+    fn scope_cfgs(&mut self, tokens: TokenStream) {
+        for token in tokens {
+            match token {
+                TokenTree::Group(group) => self.scope_cfgs(group.stream()),
+                TokenTree::Ident(ident) => {
+                    let Some(feature) = self.cfgs.get(&ident.to_string()) else {
+                        continue;
+                    };
+                    let text = if self.dual {
+                        format!("any({ident}, feature = {feature:?})")
+                    } else {
+                        format!("feature = {feature:?}")
+                    };
+                    self.replace(ident.span(), ident.span(), text);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// The name of an attribute or macro of feature-scope, by its last segment when the path is
+// the name alone or starts with `feature_scope`
+fn scope_name(path: &syn::Path) -> Option<String> {
+    let segments: Vec<String> = path
+        .segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect();
+    match segments.as_slice() {
+        [name] => Some(name.clone()),
+        [krate, name] if krate == "feature_scope" => Some(name.clone()),
+        _ => None,
+    }
+}
+
+// The names a `use` imports below `feature_scope`
+fn imported(tree: &UseTree, in_crate: bool, names: &mut Vec<String>) {
+    match tree {
+        UseTree::Path(path) => {
+            imported(&path.tree, in_crate || path.ident == "feature_scope", names)
+        }
+        UseTree::Name(name) if in_crate => names.push(name.ident.to_string()),
+        UseTree::Rename(rename) if in_crate => names.push(rename.ident.to_string()),
+        UseTree::Group(group) => {
+            for tree in &group.items {
+                imported(tree, in_crate, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+impl<'ast> Visit<'ast> for Export<'_> {
+    fn visit_item_use(&mut self, item: &'ast ItemUse) {
+        let mut names = Vec::new();
+        imported(&item.tree, false, &mut names);
+        names.retain(|name| GATE_ATTRIBUTES.contains(&name.as_str()) || name == "cfg_scope");
+        if !self.dual && !names.is_empty() {
+            self.notes.push((
+                item.use_token.span.start().line,
+                format!("the import of {} is unused now", names.join(", ")),
+            ));
+        }
+    }
+
+    fn visit_attribute(&mut self, attr: &'ast Attribute) {
+        let line = attr.pound_token.span.start().line;
+        if attr.path().is_ident("cfg") || attr.path().is_ident("cfg_attr") {
+            if let Meta::List(list) = &attr.meta {
+                self.scope_cfgs(list.tokens.clone());
+            }
+            return;
+        }
+        match scope_name(attr.path()).as_deref() {
+            Some(name) if GATE_ATTRIBUTES.contains(&name) => {
+                self.gate(attr, name == "feature_scope_default", line);
+            }
+            Some("feature_scope_bench") => self.notes.push((
+                line,
+                String::from("`#[feature_scope_bench]` is kept, it gates by the scopes only"),
+            )),
+            Some("feature_scope_serde") => self.notes.push((
+                line,
+                String::from(
+                    "`#[feature_scope_serde]` is kept, it gates fields by the scopes only",
+                ),
+            )),
+            _ => {}
+        }
+    }
+
+    fn visit_macro(&mut self, mac: &'ast Macro) {
+        let line = mac.path.segments[0].ident.span().start().line;
+        if mac.path.is_ident("cfg") {
+            self.scope_cfgs(mac.tokens.clone());
+        }
+        match scope_name(&mac.path).as_deref() {
+            Some("cfg_scope") => {
+                let predicate = syn::parse2::<Meta>(mac.tokens.clone())
+                    .ok()
+                    .and_then(|meta| self.predicate(&meta, line));
+                let Some(features) = predicate else {
+                    self.notes
+                        .push((line, String::from("the predicate is not understood, kept")));
+                    return;
+                };
+                let close = match &mac.delimiter {
+                    MacroDelimiter::Paren(paren) => paren.span.close(),
+                    MacroDelimiter::Brace(brace) => brace.span.close(),
+                    MacroDelimiter::Bracket(bracket) => bracket.span.close(),
+                };
+                let start = mac.path.segments[0].ident.span();
+                let text = if self.dual {
+                    let original = &self.content
+                        [offset(self.content, start.start())..offset(self.content, close.end())];
+                    format!("(cfg!({features}) || {original})")
+                } else {
+                    format!("cfg!({features})")
+                };
+                self.replace(start, close, text);
+            }
+            Some(name @ ("feature_scope_select" | "feature_scope_switch")) => {
+                self.notes.push((
+                    line,
+                    format!("`{name}!` is kept, plain cargo builds take its fallback arm"),
+                ));
+            }
+            Some("feature_scope_capabilities") => self.notes.push((
+                line,
+                String::from("`feature_scope_capabilities!` follows the scopes, plain cargo builds enable none"),
+            )),
+            Some("feature_scope_setup") => self.notes.push((
+                line,
+                String::from("`feature_scope_setup!` warns, or fails, in builds without the CLI"),
+            )),
+            _ => {}
+        }
+        visit::visit_macro(self, mac);
+    }
+}
@@ -0,0 +1,109 @@
+//! Stable fingerprints of resolved scope configurations.
+//!
+//! `cargo feature-scope fingerprint` prints a hash per workspace member and one for the whole
+//! workspace, or one per package given with `-p`. The hashes only depend on the resolved cfg sets, not on the order in which they
+//! were computed or on the Rust version, so they can be used as Docker layer or CI cache keys.
+
+use anyhow::Result;
+use std::path::Path;
+
+use feature_scope_core::stable_hash;
+
+use crate::{progress::Progress, CargoToml};
+
+/// Fingerprints a resolved configuration independently of the order of its flags.
+pub fn fingerprint_cfg_args(cfg_args: &[String], check_cfg_args: &[String]) -> u64 {
+    let mut cfgs: Vec<&str> = cfg_args
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .map(String::as_str)
+        .collect();
+    cfgs.sort_unstable();
+    cfgs.dedup();
+
+    let mut check_cfgs: Vec<&str> = check_cfg_args
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .map(String::as_str)
+        .collect();
+    check_cfgs.sort_unstable();
+    check_cfgs.dedup();
+
+    let canonical = format!(
+        "cfg={}\ncheck-cfg={}\n",
+        cfgs.join(","),
+        check_cfgs.join(",")
+    );
+    stable_hash(canonical.as_bytes())
+}
+
+pub fn run(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    requested: &[String],
+) -> Result<()> {
+    // A single package prints only its hash, which is handy for shell scripts
+    if let [package] = requested {
+        let (cfg_args, check_cfg_args) =
+            crate::resolve_cfg_args(root_cargo_toml, root_manifest_path, package)?;
+        println!("{:016x}", fingerprint_cfg_args(&cfg_args, &check_cfg_args));
+        return Ok(());
+    }
+
+    let packages = if requested.is_empty() {
+        package_names(root_cargo_toml, root_manifest_path)?
+    } else {
+        let mut requested = requested.to_vec();
+        requested.sort();
+        requested.dedup();
+        requested
+    };
+    let mut fingerprints = Vec::new();
+    let mut progress = Progress::new("Resolving", packages.len());
+    for package in &packages {
+        progress.step(package);
+        let (cfg_args, check_cfg_args) =
+            crate::resolve_cfg_args(root_cargo_toml, root_manifest_path, package)?;
+        fingerprints.push((package, fingerprint_cfg_args(&cfg_args, &check_cfg_args)));
+    }
+    drop(progress);
+
+    let width = packages.iter().map(String::len).max().unwrap_or(0).max(9);
+    let mut canonical = String::new();
+    for (package, fingerprint) in &fingerprints {
+        println!("{package:width$}  {fingerprint:016x}");
+        canonical.push_str(&format!("{package}={fingerprint:016x}\n"));
+    }
+    // Only the whole workspace gets a combined hash, a few packages are not a cache key of it
+    if requested.is_empty() {
+        println!(
+            "{:width$}  {:016x}",
+            "workspace",
+            stable_hash(canonical.as_bytes())
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the sorted names of the packages that get a fingerprint of their own.
+pub fn package_names(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+) -> Result<Vec<String>> {
+    let mut packages = Vec::new();
+    if root_cargo_toml.workspace.is_some() {
+        for member_manifest in
+            crate::workspace_member_manifests(root_cargo_toml, root_manifest_path)?
+        {
+            packages.push(crate::extract_package_name_from_manifest(&member_manifest)?);
+        }
+    } else if let Some(package) = &root_cargo_toml.package {
+        packages.push(package.name.clone());
+    }
+    packages.sort();
+
+    Ok(packages)
+}
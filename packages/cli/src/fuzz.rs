@@ -0,0 +1,454 @@
+//! Randomized workspaces for fuzzing the scope resolution.
+//!
+//! `cargo feature-scope fuzz` writes workspaces of random libraries, and of an `app` selecting
+//! their scopes, into a temporary directory and resolves the scopes of `app`. The result is
+//! compared with the scopes the generated declarations call for, and with the resolution of
+//! the same workspace through an index, then a `cargo check` of `app` calls every item the
+//! expected scopes enable. A panicking resolution counts as a failure too. Failing workspaces
+//! are kept and their seeds printed, so `--seed` reproduces them:
+//!
+//! ```bash
+//! cargo feature-scope fuzz --iterations 50 --packages 4 --fan-out 3 --cycle-probability 0.2
+//! ```
+//!
+//! `--no-build` skips the `cargo check`, which takes most of the time. The generated libraries
+//! gate their items with plain `#[cfg]`s of the scopes, so no macros need to be fetched.
+
+use anyhow::{Context, Result};
+use feature_scope_core::{cfg_name::scope_cfg, workspace, Resolver};
+use std::{
+    collections::BTreeSet,
+    env, fs,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::cargo_args::ForwardedArgs;
+
+struct Options {
+    iterations: u64,
+    packages: usize,
+    fan_out: usize,
+    cycle_probability: f64,
+    seed: Option<u64>,
+    build: bool,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut options = Options {
+            iterations: 20,
+            packages: 3,
+            fan_out: 3,
+            cycle_probability: 0.1,
+            seed: None,
+            build: true,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .or_else(|| args.next().map(String::as_str))
+                    .ok_or_else(|| anyhow::anyhow!("{name} takes a value"))
+            };
+
+            match name {
+                "--iterations" => options.iterations = parse_value(name, value()?)?,
+                "--packages" => options.packages = parse_value(name, value()?)?,
+                "--fan-out" => options.fan_out = parse_value(name, value()?)?,
+                "--cycle-probability" => options.cycle_probability = parse_value(name, value()?)?,
+                "--seed" => options.seed = Some(parse_value(name, value()?)?),
+                "--no-build" => options.build = false,
+                _ => anyhow::bail!(
+                    "Usage: cargo feature-scope fuzz [--iterations <NUM>] [--packages <NUM>] \
+                     [--fan-out <NUM>] [--cycle-probability <P>] [--seed <SEED>] [--no-build]"
+                ),
+            }
+        }
+
+        if options.packages == 0 || options.fan_out == 0 {
+            anyhow::bail!("--packages and --fan-out must be at least 1");
+        }
+        Ok(options)
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid value '{value}' for {name}"))
+}
+
+// xorshift64*, reproducible from the seed without pulling in a dependency
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // A number in `0..n`, `n` must not be 0
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+struct Library {
+    name: String,
+    // Features with their dependencies, in declaration order
+    features: Vec<(String, Vec<String>)>,
+    default: Vec<String>,
+}
+
+impl Library {
+    // The features a feature enables, itself included
+    fn closure(&self, feature: &str, enabled: &mut BTreeSet<String>) {
+        if !enabled.insert(feature.to_string()) {
+            return;
+        }
+        if let Some((_, dependencies)) = self.features.iter().find(|(name, _)| name == feature) {
+            for dependency in dependencies {
+                self.closure(dependency, enabled);
+            }
+        }
+    }
+}
+
+// One feature-scope entry of `app`
+struct Entry {
+    library: usize,
+    by_path: bool,
+    features: Vec<String>,
+    default_features: Option<bool>,
+}
+
+struct GeneratedWorkspace {
+    libraries: Vec<Library>,
+    entries: Vec<Entry>,
+}
+
+impl GeneratedWorkspace {
+    fn generate(rng: &mut Rng, options: &Options) -> Self {
+        let mut libraries = Vec::new();
+        for index in 0..options.packages {
+            // Unique feature names keep the scopes of different libraries apart
+            let count = 1 + rng.below(options.fan_out);
+            let names: Vec<String> = (0..count).map(|j| format!("l{index}_s{j}")).collect();
+
+            let mut features = Vec::new();
+            for (j, name) in names.iter().enumerate() {
+                let mut dependencies = BTreeSet::new();
+                if j + 1 < count {
+                    for _ in 0..rng.below(options.fan_out) {
+                        dependencies.insert(names[j + 1 + rng.below(count - j - 1)].clone());
+                    }
+                }
+                // Depending on an earlier feature, or on itself, closes a cycle
+                if rng.chance(options.cycle_probability) {
+                    dependencies.insert(names[rng.below(j + 1)].clone());
+                }
+                features.push((name.clone(), dependencies.into_iter().collect()));
+            }
+            let default = names.iter().filter(|_| rng.chance(0.3)).cloned().collect();
+
+            libraries.push(Library {
+                name: format!("lib_{index}"),
+                features,
+                default,
+            });
+        }
+
+        let mut entries = Vec::new();
+        for (index, library) in libraries.iter().enumerate() {
+            if !rng.chance(0.8) {
+                continue;
+            }
+            let mut features = BTreeSet::new();
+            for _ in 0..rng.below(options.fan_out + 1) {
+                let feature = &library.features[rng.below(library.features.len())].0;
+                features.insert(feature.clone());
+            }
+            if rng.chance(0.2) {
+                features.insert(String::from("default"));
+            }
+            entries.push(Entry {
+                library: index,
+                by_path: rng.chance(0.3),
+                features: features.into_iter().collect(),
+                default_features: [None, Some(true), Some(false)][rng.below(3)],
+            });
+        }
+
+        GeneratedWorkspace { libraries, entries }
+    }
+
+    // The enabled cfgs of `app`, following the documented rules rather than the resolver
+    fn expected_cfgs(&self) -> BTreeSet<String> {
+        let mut enabled = BTreeSet::new();
+        let mut scope_default = true;
+        for entry in &self.entries {
+            let library = &self.libraries[entry.library];
+            let defaults = entry.default_features.unwrap_or(true)
+                || entry.features.iter().any(|f| f == "default");
+            scope_default &= defaults;
+
+            for feature in &entry.features {
+                if feature == "default" {
+                    for default_feature in &library.default {
+                        library.closure(default_feature, &mut enabled);
+                    }
+                } else {
+                    library.closure(feature, &mut enabled);
+                }
+            }
+            if defaults && entry.features.is_empty() {
+                for default_feature in &library.default {
+                    library.closure(default_feature, &mut enabled);
+                }
+            }
+        }
+
+        let mut cfgs: BTreeSet<String> = enabled.iter().map(|f| scope_cfg(f)).collect();
+        if scope_default {
+            cfgs.insert(String::from("__scope_default"));
+        }
+        cfgs
+    }
+
+    fn expected_check_cfgs(&self) -> BTreeSet<String> {
+        let features = self
+            .libraries
+            .iter()
+            .flat_map(|library| library.features.iter().map(|(name, _)| scope_cfg(name)));
+        std::iter::once(String::from("__scope_default"))
+            .chain(features)
+            .map(|cfg| format!("cfg({cfg})"))
+            .collect()
+    }
+
+    fn write(&self, dir: &Path, expected_cfgs: &BTreeSet<String>) -> Result<()> {
+        let mut members: Vec<&str> = self.libraries.iter().map(|l| l.name.as_str()).collect();
+        members.push("app");
+        write_file(
+            &dir.join("Cargo.toml"),
+            &format!("[workspace]\nmembers = {members:?}\nresolver = \"2\"\n"),
+        )?;
+
+        let mut app_manifest = String::from(
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\n",
+        );
+        let mut app_main = String::from("fn main() {\n");
+        for library in &self.libraries {
+            let mut manifest = format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+                 [package.metadata.feature-scope-decl]\ndefault = {:?}\n",
+                library.name, library.default
+            );
+            let mut lib = String::new();
+            for (feature, dependencies) in &library.features {
+                manifest.push_str(&format!("{feature} = {dependencies:?}\n"));
+                let cfg = scope_cfg(feature);
+                lib.push_str(&format!("#[cfg({cfg})]\npub fn {feature}() {{}}\n\n"));
+                if expected_cfgs.contains(&cfg) {
+                    app_main.push_str(&format!("    {}::{feature}();\n", library.name));
+                }
+            }
+            write_file(&dir.join(&library.name).join("Cargo.toml"), &manifest)?;
+            write_file(&dir.join(&library.name).join("src/lib.rs"), &lib)?;
+
+            app_manifest.push_str(&format!("{0} = {{ path = \"../{0}\" }}\n", library.name));
+        }
+        app_main.push_str("}\n");
+
+        for entry in &self.entries {
+            let library = &self.libraries[entry.library].name;
+            app_manifest.push_str("\n[[package.metadata.feature-scope]]\n");
+            if entry.by_path {
+                app_manifest.push_str(&format!("path = \"../{library}\"\n"));
+            } else {
+                app_manifest.push_str(&format!("package = \"{library}\"\n"));
+            }
+            app_manifest.push_str(&format!("features = {:?}\n", entry.features));
+            if let Some(default_features) = entry.default_features {
+                app_manifest.push_str(&format!("default-features = {default_features}\n"));
+            }
+        }
+        write_file(&dir.join("app/Cargo.toml"), &app_manifest)?;
+        write_file(&dir.join("app/src/main.rs"), &app_main)
+    }
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+pub fn run(args: &[String]) -> Result<()> {
+    let options = Options::parse(args)?;
+    let base_seed = options.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos() as u64)
+    });
+    let base_dir = env::temp_dir().join(format!("feature-scope-fuzz-{}", process::id()));
+    // Every workspace builds into the same target directory, their packages are tiny
+    let target_dir = base_dir.join("target");
+
+    let mut problems = 0;
+    for iteration in 0..options.iterations {
+        let seed = base_seed.wrapping_add(iteration);
+        let dir = base_dir.join(format!("seed-{seed}"));
+        let workspace = GeneratedWorkspace::generate(&mut Rng::new(seed), &options);
+        let expected_cfgs = workspace.expected_cfgs();
+        workspace.write(&dir, &expected_cfgs)?;
+
+        let found = check(&dir, &workspace, &expected_cfgs, &options, &target_dir);
+        if found.is_empty() {
+            println!("✅ seed {seed}");
+            fs::remove_dir_all(&dir)?;
+            continue;
+        }
+        for problem in &found {
+            println!("⚠️  seed {seed}: {problem}");
+        }
+        println!("   kept at {}", dir.display());
+        problems += found.len();
+    }
+    println!();
+
+    if problems == 0 {
+        let _ = fs::remove_dir_all(&base_dir);
+        println!(
+            "✅ {} generated workspaces resolved as expected",
+            options.iterations
+        );
+        return Ok(());
+    }
+    anyhow::bail!("{problems} problem(s) found")
+}
+
+// Resolves and builds a generated workspace, returning where it diverged from the expectations
+fn check(
+    dir: &Path,
+    workspace: &GeneratedWorkspace,
+    expected_cfgs: &BTreeSet<String>,
+    options: &Options,
+    target_dir: &Path,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    let root_manifest_path = dir.join("Cargo.toml");
+
+    let resolve = |from_index: bool| {
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            let resolver = if from_index {
+                let index_path = dir.join(workspace::INDEX_FILE_NAME);
+                workspace::write_index(&root_manifest_path, &index_path, &[])?;
+                Resolver::from_index(&index_path)?
+            } else {
+                Resolver::from_workspace(&root_manifest_path)?
+            };
+            resolver.resolve("app")
+        }))
+    };
+
+    let resolved = match resolve(false) {
+        Ok(Ok(resolved)) => resolved,
+        Ok(Err(err)) => {
+            problems.push(format!("resolution failed: {err:#}"));
+            return problems;
+        }
+        Err(_) => {
+            problems.push(String::from("resolution panicked"));
+            return problems;
+        }
+    };
+    for diagnostic in &resolved.diagnostics {
+        problems.push(format!("unexpected diagnostic: {diagnostic}"));
+    }
+
+    let cfgs: BTreeSet<String> = resolved.enabled_cfgs().map(String::from).collect();
+    if cfgs != *expected_cfgs {
+        problems.push(format!(
+            "enabled {}, expected {}",
+            join(&cfgs),
+            join(expected_cfgs)
+        ));
+    }
+    let check_cfgs: BTreeSet<String> = resolved
+        .check_cfg_args
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .cloned()
+        .collect();
+    let expected_check_cfgs = workspace.expected_check_cfgs();
+    if check_cfgs != expected_check_cfgs {
+        problems.push(format!(
+            "checked {}, expected {}",
+            join(&check_cfgs),
+            join(&expected_check_cfgs)
+        ));
+    }
+
+    match resolve(true) {
+        Ok(Ok(indexed)) => {
+            if indexed.cfg_args != resolved.cfg_args
+                || indexed.check_cfg_args != resolved.check_cfg_args
+            {
+                problems.push(String::from(
+                    "the resolution through an index differs from the one through cargo metadata",
+                ));
+            }
+        }
+        Ok(Err(err)) => problems.push(format!("resolution through an index failed: {err:#}")),
+        Err(_) => problems.push(String::from("resolution through an index panicked")),
+    }
+
+    if options.build {
+        let mut cargo_cmd = crate::build_cargo_command(
+            "check",
+            Some(&String::from("app")),
+            Some(target_dir),
+            &[],
+            &resolved.cfg_args,
+            &resolved.check_cfg_args,
+            &ForwardedArgs::default(),
+        );
+        cargo_cmd.arg("--quiet").current_dir(dir);
+        match cargo_cmd.output() {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => problems.push(format!(
+                "cargo check failed:\n{}",
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            )),
+            Err(err) => problems.push(format!("Failed to execute cargo check: {err}")),
+        }
+    }
+
+    problems
+}
+
+fn join(set: &BTreeSet<String>) -> String {
+    if set.is_empty() {
+        return String::from("nothing");
+    }
+    set.iter().cloned().collect::<Vec<_>>().join(", ")
+}
@@ -0,0 +1,258 @@
+//! A log of the commands run with resolved scopes.
+//!
+//! Every cargo command or program feature-scope spawns is appended to
+//! `target/feature-scope/history.jsonl`, one JSON object per line, with the time, the command
+//! line, the package, the enabled scopes and the fingerprint of the configuration and, when it
+//! is not the default one, the target directory. `cargo feature-scope history` shows the latest
+//! records, `-p` those of one package, and prunes the log with `--keep` or `--prune`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{display, fingerprint, i18n, target_dir};
+
+const HISTORY_FILE: &str = "history.jsonl";
+
+/// What a spawned command was resolved for, for its record in the history.
+pub struct Entry<'a> {
+    pub root_manifest_path: &'a Path,
+    pub package: &'a str,
+    pub scope_names: &'a display::ScopeNames,
+    pub cfg_args: &'a [String],
+    pub check_cfg_args: &'a [String],
+}
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    // Seconds since the Unix epoch
+    time: u64,
+    command: String,
+    package: String,
+    scopes: Vec<String>,
+    fingerprint: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target_dir: Option<String>,
+    success: bool,
+}
+
+/// Runs a command like [`Command::status`] and appends it to the history with its outcome.
+pub fn status(cmd: &mut Command, entry: Entry) -> Result<ExitStatus> {
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to execute {}", cmd.get_program().to_string_lossy()))?;
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let fingerprint = fingerprint::fingerprint_cfg_args(entry.cfg_args, entry.check_cfg_args);
+    let record = Record {
+        time,
+        command: display::describe_command(cmd),
+        package: entry.package.to_string(),
+        scopes: entry.scope_names.pretty_args(entry.cfg_args),
+        fingerprint: format!("{fingerprint:016x}"),
+        target_dir: command_target_dir(cmd),
+        success: status.success(),
+    };
+
+    // A build is not failed for its log entry
+    let path = history_path(entry.root_manifest_path);
+    if let Err(err) = append(&path, &record) {
+        i18n::warn(i18n::Message::HistoryNotWritten {
+            path: &path.display().to_string(),
+            error: &format!("{err:#}"),
+        });
+    }
+
+    Ok(status)
+}
+
+fn history_path(root_manifest_path: &Path) -> PathBuf {
+    target_dir::base_target_dir(root_manifest_path.parent().unwrap(), None)
+        .join("feature-scope")
+        .join(HISTORY_FILE)
+}
+
+fn append(path: &Path, record: &Record) -> Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+// The target directory given to the command, by argument or for nested cargo invocations
+fn command_target_dir(cmd: &Command) -> Option<String> {
+    let args: Vec<_> = cmd.get_args().collect();
+    let from_args = args
+        .windows(2)
+        .find(|pair| pair[0] == "--target-dir")
+        .map(|pair| pair[1].to_string_lossy().into_owned());
+    from_args.or_else(|| {
+        cmd.get_envs()
+            .find(|(name, _)| *name == "CARGO_TARGET_DIR")
+            .and_then(|(_, value)| value)
+            .map(|value| value.to_string_lossy().into_owned())
+    })
+}
+
+pub fn run(root_manifest_path: &Path, package: Option<&String>, args: &[String]) -> Result<()> {
+    const USAGE: &str = "Usage: cargo feature-scope history [-p <PACKAGE>] [--limit <NUM>] [--format text|json] [--keep <NUM>] [--prune <AGE>]";
+
+    let mut limit = 20;
+    let mut json = false;
+    let mut keep = None;
+    let mut prune = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| USAGE);
+        match arg.as_str() {
+            "--limit" => limit = value()?.parse().context("--limit takes a number")?,
+            "--format" => {
+                json = match value()?.as_str() {
+                    "text" => false,
+                    "json" => true,
+                    other => anyhow::bail!("Unknown format '{other}', expected 'text' or 'json'"),
+                }
+            }
+            "--keep" => keep = Some(value()?.parse::<usize>().context("--keep takes a number")?),
+            "--prune" => prune = Some(parse_age(value()?)?),
+            _ => anyhow::bail!(USAGE),
+        }
+    }
+
+    let path = history_path(root_manifest_path);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    // Lines cut short by an interrupted write are skipped
+    let records: Vec<Record> = content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+
+    if keep.is_some() || prune.is_some() {
+        return prune_records(&path, records, keep, prune);
+    }
+
+    let records: Vec<&Record> = records
+        .iter()
+        .filter(|record| package.is_none_or(|package| record.package == *package))
+        .collect();
+    let shown = &records[records.len().saturating_sub(limit)..];
+    if json {
+        for record in shown {
+            println!("{}", serde_json::to_string(record)?);
+        }
+        return Ok(());
+    }
+
+    if shown.is_empty() {
+        println!("No commands recorded in {}", path.display());
+        return Ok(());
+    }
+    for record in shown {
+        let result = if record.success { "✅" } else { "❌" };
+        println!("{} {result} {}", format_time(record.time), record.command);
+        let scopes = if record.scopes.is_empty() {
+            String::from("none")
+        } else {
+            record.scopes.join(", ")
+        };
+        println!("    {}: {scopes} ({})", record.package, record.fingerprint);
+        if let Some(target_dir) = &record.target_dir {
+            println!("    target directory: {target_dir}");
+        }
+    }
+    Ok(())
+}
+
+// Drops the records older than `max_age` and all but the last `keep`
+fn prune_records(
+    path: &Path,
+    mut records: Vec<Record>,
+    keep: Option<usize>,
+    max_age: Option<Duration>,
+) -> Result<()> {
+    let count = records.len();
+    if let Some(max_age) = max_age {
+        let oldest = SystemTime::now()
+            .checked_sub(max_age)
+            .and_then(|oldest| oldest.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |oldest| oldest.as_secs());
+        records.retain(|record| record.time >= oldest);
+    }
+    if let Some(keep) = keep {
+        records.drain(..records.len().saturating_sub(keep));
+    }
+
+    let mut content = String::new();
+    for record in &records {
+        content.push_str(&serde_json::to_string(record)?);
+        content.push('\n');
+    }
+    if path.exists() {
+        fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    println!(
+        "Pruned {} of {count} records, {} left",
+        count - records.len(),
+        records.len()
+    );
+    Ok(())
+}
+
+// Ages like `30d`, `12h`, `45m` or `90s`
+fn parse_age(age: &str) -> Result<Duration> {
+    let units = [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)];
+    let Some((number, seconds)) = units
+        .iter()
+        .find_map(|(unit, seconds)| Some((age.strip_suffix(unit)?, *seconds)))
+    else {
+        anyhow::bail!("Unknown age '{age}', expected a number followed by d, h, m or s");
+    };
+    let number: u64 = number.parse().with_context(|| {
+        format!("Unknown age '{age}', expected a number followed by d, h, m or s")
+    })?;
+    Ok(Duration::from_secs(number * seconds))
+}
+
+// `YYYY-MM-DD HH:MM:SS` in UTC, from the days-to-civil algorithm of Howard Hinnant
+fn format_time(time: u64) -> String {
+    let days = (time / 86400) as i64;
+    let seconds = time % 86400;
+
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,798 @@
+//! Localization of the CLI diagnostics and help text.
+//!
+//! Messages come from a small catalog in English and Simplified Chinese. `FEATURE_SCOPE_LANG`
+//! picks the language (`en` or `zh`, also accepting locales like `zh_CN.UTF-8`), otherwise the
+//! usual `LC_ALL`, `LC_MESSAGES` and `LANG` locale variables do. Anything else falls back to
+//! English. Raw cfg names, paths and commands are never translated.
+
+use feature_scope_core::{diagnostic::Listing, ConflictError, DeclError, Diagnostic, Origin};
+use std::{env, fmt, sync::OnceLock};
+
+pub const LANG_VAR: &str = "FEATURE_SCOPE_LANG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    En,
+    Zh,
+}
+
+/// Returns the language of the messages, determined once per run.
+pub fn lang() -> Lang {
+    static LANG: OnceLock<Lang> = OnceLock::new();
+
+    *LANG.get_or_init(|| {
+        [LANG_VAR, "LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .map(|value| parse_lang(&value))
+            .unwrap_or(Lang::En)
+    })
+}
+
+fn parse_lang(value: &str) -> Lang {
+    if value.to_ascii_lowercase().starts_with("zh") {
+        Lang::Zh
+    } else {
+        Lang::En
+    }
+}
+
+/// A diagnostic printed by the CLI.
+#[derive(Debug)]
+pub enum Message<'a> {
+    Resolution(&'a Diagnostic),
+    Decl(&'a DeclError),
+    Conflict(&'a ConflictError),
+    ConfigRustflagsShadowed,
+    EnvScopeFlagsIgnored,
+    TargetRustflagsOverridden {
+        target: &'a str,
+        origin: &'a str,
+    },
+    NoVcs,
+    UncommittedScopeChanges {
+        manifest: &'a str,
+        changes: &'a str,
+    },
+    StrictModeDenied {
+        package: &'a str,
+        count: usize,
+    },
+    HistoryNotWritten {
+        path: &'a str,
+        error: &'a str,
+    },
+    ProbeFired {
+        scope: &'a str,
+        probe: &'a str,
+    },
+    InferenceMode,
+    ArchiveScopesDiffer {
+        archive: &'a str,
+        package: &'a str,
+        archived: &'a str,
+        resolved: &'a str,
+    },
+}
+
+impl fmt::Display for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Message::*;
+
+        match (lang(), self) {
+            (Lang::En, Resolution(diagnostic)) => write!(f, "{diagnostic}"),
+            (Lang::Zh, Resolution(diagnostic)) => write_zh_diagnostic(f, diagnostic),
+            (Lang::En, Decl(error)) => write!(f, "{error}"),
+            (Lang::Zh, Decl(error)) => write_zh_decl_error(f, error),
+            (Lang::En, Conflict(error)) => write!(f, "{error}"),
+            (Lang::Zh, Conflict(error)) => write_zh_conflict(f, error),
+            (Lang::En, ConfigRustflagsShadowed) => write!(
+                f,
+                "ignoring build.rustflags from --config because RUSTFLAGS is set, as cargo would"
+            ),
+            (Lang::Zh, ConfigRustflagsShadowed) => write!(
+                f,
+                "已设置 RUSTFLAGS，与 cargo 一样忽略 --config 中的 build.rustflags"
+            ),
+            (Lang::En, EnvScopeFlagsIgnored) => write!(
+                f,
+                "ignoring the scope cfgs already in RUSTFLAGS, feature-scope passes its own"
+            ),
+            (Lang::Zh, EnvScopeFlagsIgnored) => write!(
+                f,
+                "忽略 RUSTFLAGS 中已有的作用域 cfg，feature-scope 会传入自己的"
+            ),
+            (Lang::En, ProbeFired { scope, probe }) => {
+                write!(f, "enabling {scope}, the probe '{probe}' found it")
+            }
+            (Lang::Zh, ProbeFired { scope, probe }) => {
+                write!(f, "探测 '{probe}' 已命中，启用 {scope}")
+            }
+            (Lang::En, TargetRustflagsOverridden { target, origin }) => write!(
+                f,
+                "target.{target}.rustflags from '{origin}' is overridden by the scope rustflags; use build.rustflags instead"
+            ),
+            (Lang::Zh, TargetRustflagsOverridden { target, origin }) => write!(
+                f,
+                "'{origin}' 中的 target.{target}.rustflags 会被作用域 rustflags 覆盖；请改用 build.rustflags"
+            ),
+            (Lang::En, NoVcs) => write!(
+                f,
+                "--vcs-check found no git or Mercurial checkout, skipping the check"
+            ),
+            (Lang::Zh, NoVcs) => write!(f, "--vcs-check 没有找到 git 或 Mercurial 仓库，跳过检查"),
+            (Lang::En, UncommittedScopeChanges { manifest, changes }) => write!(
+                f,
+                "{manifest} has uncommitted changes to the scope configuration: {changes}"
+            ),
+            (Lang::Zh, UncommittedScopeChanges { manifest, changes }) => {
+                write!(f, "{manifest} 的作用域配置有未提交的修改：{changes}")
+            }
+            (Lang::En, StrictModeDenied { package, count }) => write!(
+                f,
+                "strict mode turns the warnings above into errors ({count} in the scope configuration of '{package}'); \
+                 fix them, or drop --deny-warnings and `strict = true` to only warn"
+            ),
+            (Lang::Zh, StrictModeDenied { package, count }) => write!(
+                f,
+                "严格模式把上述警告视为错误（'{package}' 的作用域配置中共 {count} 个）；\
+                 请修复它们，或去掉 --deny-warnings 和 `strict = true` 以只给出警告"
+            ),
+            (Lang::En, HistoryNotWritten { path, error }) => {
+                write!(f, "could not record the command in {path}: {error}")
+            }
+            (Lang::Zh, HistoryNotWritten { path, error }) => {
+                write!(f, "无法把命令记录到 {path}：{error}")
+            }
+            (Lang::En, InferenceMode) => write!(
+                f,
+                "inference mode is on: packages declaring no scopes declare every scope their #[feature_scope] attributes name; \
+                 write them to feature-scope-decl to keep them"
+            ),
+            (Lang::Zh, InferenceMode) => write!(
+                f,
+                "推断模式已开启：未声明作用域的包会声明其 #[feature_scope] 属性中出现的所有作用域；\
+                 请把它们写入 feature-scope-decl 以便保留"
+            ),
+            (
+                Lang::En,
+                ArchiveScopesDiffer {
+                    archive,
+                    package,
+                    archived,
+                    resolved,
+                },
+            ) => write!(
+                f,
+                "{archive} was built for '{package}' with the scopes {archived}, not {resolved}; running it with the scopes it was built with"
+            ),
+            (
+                Lang::Zh,
+                ArchiveScopesDiffer {
+                    archive,
+                    package,
+                    archived,
+                    resolved,
+                },
+            ) => write!(
+                f,
+                "{archive} 是以作用域 {archived}（而不是 {resolved}）为 '{package}' 构建的；将按构建时的作用域运行它"
+            ),
+        }
+    }
+}
+
+// The English texts live with the diagnostics themselves
+fn write_zh_diagnostic(f: &mut fmt::Formatter<'_>, diagnostic: &Diagnostic) -> fmt::Result {
+    match diagnostic {
+        Diagnostic::FeatureNotDeclared { feature } => {
+            write!(
+                f,
+                "特性 '{feature}' 未在 feature-scope-decl 中声明；请在那里声明它，或从选择中移除它"
+            )
+        }
+        Diagnostic::FeatureNotDeclaredInPackage { feature, package } => {
+            write!(
+                f,
+                "包 '{package}' 未声明特性 '{feature}'；请对照 '{package}' 的 feature-scope-decl 检查名称"
+            )
+        }
+        Diagnostic::UndeclaredDefault { feature, package } => write!(
+            f,
+            "包 '{package}' 的默认特性 '{feature}' 未在 feature-scope-decl 中声明；\
+             请添加 `{feature} = []`（未声明的默认特性已被弃用）"
+        ),
+        Diagnostic::DeprecatedFeature {
+            feature,
+            package,
+            note,
+        } => write!(f, "包 '{package}' 的特性 '{feature}' 已弃用：{note}"),
+        Diagnostic::PackageWithoutDecl { package } => {
+            write!(
+                f,
+                "包 '{package}' 没有 feature-scope-decl；请声明它的特性，或移除引用它的条目"
+            )
+        }
+        Diagnostic::PackageNotFound { package } => write!(
+            f,
+            "在工作区中找不到依赖包 '{package}'；\
+             请把它加入 workspace.members，或通过 `path` 依赖引用它"
+        ),
+        Diagnostic::NotADependency {
+            consumer,
+            package,
+            manifest: None,
+            ..
+        } => write!(
+            f,
+            "--features 选择了 '{package}' 的作用域，但包 '{consumer}' 并不依赖它，因此忽略该选择"
+        ),
+        Diagnostic::NotADependency {
+            consumer,
+            package,
+            manifest: Some(manifest),
+            line,
+        } => {
+            write!(f, "{}", manifest.display())?;
+            if let Some(line) = line {
+                write!(f, ":{line}")?;
+            }
+            write!(
+                f,
+                "：包 '{consumer}' 的 feature-scope 条目选择了 '{package}' 的作用域，但并不依赖它，因此忽略该条目；\
+                 请把 '{package}' 加入它的依赖，或移除该条目"
+            )
+        }
+        Diagnostic::DuplicateEntry {
+            consumer,
+            package,
+            manifest,
+            lines,
+            count,
+        } => {
+            write!(f, "{}", manifest.display())?;
+            if let Some(line) = lines.first() {
+                write!(f, ":{line}")?;
+            }
+            write!(f, "：包 '{consumer}' 有 {count} 个针对 '{package}' 的 feature-scope 条目")?;
+            if !lines.is_empty() {
+                let lines: Vec<String> = lines.iter().map(usize::to_string).collect();
+                write!(f, "（第 {} 行）", lines.join("、"))?;
+            }
+            write!(f, "，它们会叠加，各自如同单独存在一样生效；请把它们合并为一个条目")
+        }
+        Diagnostic::NoPreferredFeature {
+            consumer,
+            package,
+            features,
+        } => write!(
+            f,
+            "包 '{consumer}' 偏好包 '{package}' 的 {} 之一，但没有一个已声明、适用于目标平台且不冲突，因此均未启用",
+            features
+                .iter()
+                .map(|feature| format!("'{feature}'"))
+                .collect::<Vec<_>>()
+                .join("、")
+        ),
+        Diagnostic::RemovedFeatureEnabled {
+            consumer,
+            package,
+            feature,
+        } => write!(
+            f,
+            "包 '{consumer}' 移除了包 '{package}' 的特性 '{feature}'，但它仍启用的某个特性依赖它，因此它仍被启用"
+        ),
+        Diagnostic::DefaultMeaningChanged {
+            consumer,
+            package,
+            features,
+        } => write!(
+            f,
+            "包 '{consumer}' 选择了包 '{package}' 的 'default'，在它表示声明的默认集合之前还会启用 {}；如需保留请显式选择它们",
+            features
+                .iter()
+                .map(|feature| format!("'{feature}'"))
+                .collect::<Vec<_>>()
+                .join("、")
+        ),
+        Diagnostic::UnreadableMember { manifest, message } => write!(
+            f,
+            "无法读取工作区成员 {}，由于正在解析的包并不需要它，已将其略过：{message}",
+            manifest.display()
+        ),
+        Diagnostic::UnknownKey {
+            manifest,
+            table,
+            key,
+        } => write!(
+            f,
+            "{}：{table} 中的键 '{key}' 未知，feature-scope 会忽略它；\
+             请检查拼写，如果它属于其他工具，请不要开启 strict-keys",
+            manifest.display()
+        ),
+        Diagnostic::PathDependencyFallback {
+            package,
+            manifest,
+            member,
+        } => write!(
+            f,
+            "包 '{package}' 不是工作区成员，改用位于 {} 的 path 依赖；\
+             可以把 \"{}\" 加入 workspace.members 以明确这一点",
+            manifest.display(),
+            member.display()
+        ),
+    }
+}
+
+/// Prints a warning to stderr.
+pub fn warn(message: Message) {
+    crate::progress::clear_bar();
+    match lang() {
+        Lang::En => eprintln!("Warning: {message}"),
+        Lang::Zh => eprintln!("警告：{message}"),
+    }
+}
+
+fn write_zh_decl_error(f: &mut fmt::Formatter<'_>, error: &DeclError) -> fmt::Result {
+    match error {
+        DeclError::InvalidManifest {
+            manifest,
+            message,
+            span: Some(span),
+        } => write!(
+            f,
+            "无法解析 {}:{}:{}：{message}",
+            manifest.display(),
+            span.line,
+            span.column
+        ),
+        DeclError::InvalidManifest {
+            manifest,
+            message,
+            span: None,
+        } => write!(f, "无法解析 {}：{message}", manifest.display()),
+        DeclError::ReservedName { package, name } if name.is_empty() => {
+            write!(f, "包 '{package}' 声明了一个名字为空的作用域")
+        }
+        DeclError::ReservedName { package, name } if name.contains('/') => write!(
+            f,
+            "包 '{package}' 声明了作用域 '{name}'，但作用域名不能包含用于分隔包名和特性名的 '/'"
+        ),
+        DeclError::ReservedName { package, name } => write!(
+            f,
+            "包 '{package}' 声明了作用域 {name:?}，但作用域名不能包含控制字符"
+        ),
+        DeclError::DuplicateName { package, name } => {
+            write!(f, "包 '{package}' 把 '{name}' 同时声明为特性和预设")
+        }
+        DeclError::CfgNameCollision {
+            package,
+            features: [first, second],
+            cfg,
+        } => write!(
+            f,
+            "包 '{package}' 声明的特性 '{first}' 和 '{second}' 映射到同一个 cfg 名 '{cfg}'；请重命名其中一个"
+        ),
+        DeclError::UnknownFeature {
+            package,
+            feature,
+            listed_in: Listing::Preset(preset),
+        } => write!(
+            f,
+            "包 '{package}' 的预设 '{preset}' 列出了 '{feature}'，但 feature-scope-decl 并未声明它"
+        ),
+        DeclError::UnknownFeature {
+            package,
+            feature,
+            listed_in: Listing::ExclusiveGroup,
+        } => write!(
+            f,
+            "包 '{package}' 在互斥组中列出了 '{feature}'，但没有在 feature-scope-decl 中声明它"
+        ),
+        DeclError::UnknownFeature {
+            package,
+            feature,
+            listed_in: Listing::Conflicts(conflicting),
+        } => write!(
+            f,
+            "包 '{package}' 的特性 '{conflicting}' 与 '{feature}' 冲突，但 feature-scope-decl 并未声明 '{feature}'"
+        ),
+        DeclError::CodeMismatch {
+            package,
+            key,
+            toml,
+            code,
+        } => write!(
+            f,
+            "包 '{package}' 在 Cargo.toml 中声明了 {key} = {toml:?}，但在 declare_feature_scopes! 中声明的是 {key} = {code:?}"
+        ),
+        DeclError::MissingCargoFeature {
+            package,
+            manifest,
+            feature,
+        } => write!(
+            f,
+            "包 '{package}' 的特性 '{feature}' 会激活依赖或依赖的特性，但 {} 的 [features] 中没有 cargo 激活它们所需的 '{feature}' 键",
+            manifest.display()
+        ),
+        // Errors added to feature-scope-core later than this catalog
+        _ => write!(f, "{error}"),
+    }
+}
+
+fn write_zh_conflict(f: &mut fmt::Formatter<'_>, error: &ConflictError) -> fmt::Result {
+    let names: Vec<String> = error
+        .features
+        .iter()
+        .map(|feature| format!("'{}'", feature.feature))
+        .collect();
+    write!(
+        f,
+        "包 '{}' 启用了包 '{}' 的 {}，它们互斥",
+        error.consumer,
+        error.library,
+        names.join(" 和 ")
+    )?;
+    for feature in &error.features {
+        let origins: Vec<String> = feature.origins.iter().map(zh_origin).collect();
+        write!(
+            f,
+            "\n  '{}' 由 {} 启用",
+            feature.feature,
+            origins.join("、")
+        )?;
+    }
+    Ok(())
+}
+
+fn zh_origin(origin: &Origin) -> String {
+    match origin {
+        Origin::Selection { consumer, feature } => format!("'{consumer}' 选择的 '{feature}'"),
+        Origin::Defaults { library } => format!("'{library}' 的默认特性"),
+        Origin::Probe {
+            library,
+            feature,
+            probe,
+        } => format!("'{library}/{feature}' 的探测 '{probe}'"),
+    }
+}
+
+/// Prints the error a run fails with, like returning it from `main` does, with the declaration
+/// and conflict errors in its chain in the language of the messages.
+pub fn fail(err: &anyhow::Error) {
+    crate::progress::clear_bar();
+    if lang() == Lang::En {
+        eprintln!("Error: {err:?}");
+        return;
+    }
+
+    let mut messages = err.chain().map(|cause| {
+        if let Some(error) = cause.downcast_ref::<DeclError>() {
+            Message::Decl(error).to_string()
+        } else if let Some(error) = cause.downcast_ref::<ConflictError>() {
+            Message::Conflict(error).to_string()
+        } else {
+            cause.to_string()
+        }
+    });
+    eprintln!("错误：{}", messages.next().unwrap_or_default());
+    let causes: Vec<String> = messages.collect();
+    if !causes.is_empty() {
+        eprintln!("\n原因：");
+    }
+    for (index, cause) in causes.iter().enumerate() {
+        if causes.len() == 1 {
+            eprintln!("    {}", cause.replace('\n', "\n    "));
+        } else {
+            eprintln!("{index:>5}: {}", cause.replace('\n', "\n       "));
+        }
+    }
+}
+
+/// Prints an error to stderr, for problems reported before the command fails.
+pub fn error(message: Message) {
+    crate::progress::clear_bar();
+    match lang() {
+        Lang::En => eprintln!("Error: {message}"),
+        Lang::Zh => eprintln!("错误：{message}"),
+    }
+}
+
+/// Prints a note to stderr.
+pub fn note(message: Message) {
+    crate::progress::clear_bar();
+    match lang() {
+        Lang::En => eprintln!("Note: {message}"),
+        Lang::Zh => eprintln!("提示：{message}"),
+    }
+}
+
+/// Prints a diagnostic of the scope resolution as a warning or a note.
+pub fn report(diagnostic: &Diagnostic) {
+    if diagnostic.is_note() {
+        note(Message::Resolution(diagnostic));
+    } else {
+        warn(Message::Resolution(diagnostic));
+    }
+}
+
+/// A label of the invocation summary printed before running a command.
+#[derive(Debug, Clone, Copy)]
+pub enum Label {
+    Running,
+    Scopes,
+    DeclaredScopes,
+    Presets,
+}
+
+impl Label {
+    pub fn text(self) -> &'static str {
+        match (lang(), self) {
+            (Lang::En, Label::Running) => "Running",
+            (Lang::Zh, Label::Running) => "运行",
+            (Lang::En, Label::Scopes) => "Scopes",
+            (Lang::Zh, Label::Scopes) => "作用域",
+            (Lang::En, Label::DeclaredScopes) => "Declared scopes",
+            (Lang::Zh, Label::DeclaredScopes) => "已声明的作用域",
+            (Lang::En, Label::Presets) => "Presets",
+            (Lang::Zh, Label::Presets) => "预设",
+        }
+    }
+}
+
+/// The help text of a command line argument.
+#[derive(Debug, Clone, Copy)]
+pub enum Help {
+    About,
+    Add,
+    Audit,
+    Cbindgen,
+    Diff,
+    E2e,
+    Env,
+    Exec,
+    Explain,
+    Export,
+    Fingerprint,
+    Fuzz,
+    History,
+    IdeSetup,
+    Index,
+    Init,
+    Items,
+    Lints,
+    List,
+    MacroTimings,
+    Migrate,
+    Nextest,
+    Pipeline,
+    Profile,
+    Remove,
+    RenameFeature,
+    Template,
+    Unused,
+    Update,
+    Vendor,
+    Verify,
+    Package,
+    Features,
+    NoDefaultFeatures,
+    NoProbes,
+    Infer,
+    ExcludeHost,
+    Variant,
+    ArtifactDir,
+    VcsCheck,
+    DenyWarnings,
+    Interactive,
+    Resolution,
+    IsolateTargetDir,
+    ReuseDeps,
+    CompareScopes,
+    AllScopes,
+    FeaturePowerset,
+    Depth,
+    NoRunCargo,
+    ScriptFormat,
+    Verbose,
+    Args,
+    ProgramArgs,
+}
+
+impl Help {
+    pub fn text(self) -> &'static str {
+        match (lang(), self) {
+            (Lang::En, Help::About) => "Cargo feature scope helper",
+            (Lang::Zh, Help::About) => "Cargo 特性作用域助手",
+            (Lang::En, Help::Add) => "Select features of a library in a consumer's entries",
+            (Lang::Zh, Help::Add) => "在使用方的条目中选择库的特性",
+            (Lang::En, Help::Audit) => {
+                "Check the workspace for mistakes in the scope configuration"
+            }
+            (Lang::Zh, Help::Audit) => "检查工作区作用域配置中的错误",
+            (Lang::En, Help::Cbindgen) => {
+                "Generate C headers with cbindgen from the code compiled under the resolved scopes"
+            }
+            (Lang::Zh, Help::Cbindgen) => "用 cbindgen 根据在解析出的作用域下编译的代码生成 C 头文件",
+            (Lang::En, Help::Diff) => "Compare the resolved scopes with those of a git revision",
+            (Lang::Zh, Help::Diff) => "比较解析出的作用域与某个 git 修订版本中的作用域",
+            (Lang::En, Help::E2e) => "Run every app and check its output against the expected one",
+            (Lang::Zh, Help::E2e) => "运行每个应用并检查其输出是否符合预期",
+            (Lang::En, Help::Env) => "Print the resolved scope environment as shell export lines",
+            (Lang::Zh, Help::Env) => "以 shell export 语句的形式打印解析出的作用域环境",
+            (Lang::En, Help::Exec) => "Run any program under the resolved scope environment",
+            (Lang::Zh, Help::Exec) => "在解析出的作用域环境中运行任意程序",
+            (Lang::En, Help::Explain) => "Explain why a scope is enabled or not for a package",
+            (Lang::Zh, Help::Explain) => "解释某个作用域为何对一个包启用或未启用",
+            (Lang::En, Help::Export) => "Export the scopes of a library as cargo features for publishing",
+            (Lang::Zh, Help::Export) => "把库的作用域导出为 cargo feature 以便发布",
+            (Lang::En, Help::Fingerprint) => "Print stable hashes of the resolved configurations",
+            (Lang::Zh, Help::Fingerprint) => "打印解析出的配置的稳定哈希",
+            (Lang::En, Help::Fuzz) => {
+                "Resolve and check randomized workspaces to find resolution bugs"
+            }
+            (Lang::Zh, Help::Fuzz) => "解析并检查随机生成的工作区，以发现解析错误",
+            (Lang::En, Help::History) => {
+                "Show or prune the log of commands run with resolved scopes"
+            }
+            (Lang::Zh, Help::History) => "查看或清理使用解析出的作用域运行过的命令记录",
+            (Lang::En, Help::IdeSetup) => {
+                "Write the resolved scopes to the rust-analyzer settings of the editor"
+            }
+            (Lang::Zh, Help::IdeSetup) => "把解析出的作用域写入编辑器的 rust-analyzer 设置",
+            (Lang::En, Help::Index) => {
+                "Write the workspace members and platform cfgs to an index for builds that cannot spawn processes"
+            }
+            (Lang::Zh, Help::Index) => "把工作区成员和平台 cfg 写入索引，供无法启动进程的构建使用",
+            (Lang::En, Help::Init) => {
+                "Add scope declarations, the dependencies and a build script to a package"
+            }
+            (Lang::Zh, Help::Init) => "为包添加作用域声明、所需依赖和构建脚本",
+            (Lang::En, Help::Items) => "List the items of a package gated by a scope",
+            (Lang::Zh, Help::Items) => "列出包中受某个作用域控制的条目",
+            (Lang::En, Help::Lints) => {
+                "Write the scope cfgs to the unexpected_cfgs lint configuration of the manifests"
+            }
+            (Lang::Zh, Help::Lints) => "把作用域的 cfg 写入各清单的 unexpected_cfgs lint 配置",
+            (Lang::En, Help::List) => {
+                "List the declared scopes, their dependencies, defaults and consumers"
+            }
+            (Lang::Zh, Help::List) => "列出声明的作用域及其依赖、默认值和使用者",
+            (Lang::En, Help::MacroTimings) => "Summarize the time spent in the macros",
+            (Lang::Zh, Help::MacroTimings) => "汇总宏的耗时",
+            (Lang::En, Help::Migrate) => "Move a package from cargo features onto scopes",
+            (Lang::Zh, Help::Migrate) => "把包从 cargo feature 迁移到作用域",
+            (Lang::En, Help::Nextest) => "Run cargo-nextest with the resolved scopes, from archives too",
+            (Lang::Zh, Help::Nextest) => "在解析出的作用域下运行 cargo-nextest，也支持从归档运行",
+            (Lang::En, Help::Pipeline) => {
+                "Run the commands of a pipeline from the root manifest with one resolved configuration"
+            }
+            (Lang::Zh, Help::Pipeline) => "使用同一份解析出的配置依次运行根清单中某个流水线的命令",
+            (Lang::En, Help::Profile) => {
+                "Run a binary built with the resolved scopes under perf or samply"
+            }
+            (Lang::Zh, Help::Profile) => "在 perf 或 samply 下运行以解析出的作用域构建的二进制",
+            (Lang::En, Help::Remove) => "Drop features of a library from a consumer's entries",
+            (Lang::Zh, Help::Remove) => "从使用方的条目中去掉库的特性",
+            (Lang::En, Help::RenameFeature) => {
+                "Rename a declared feature in its library, the consumers' entries and the sources"
+            }
+            (Lang::Zh, Help::RenameFeature) => "在声明它的库、使用方的条目和源码中重命名一个已声明的特性",
+            (Lang::En, Help::Template) => "List the bundled project templates or write one out",
+            (Lang::Zh, Help::Template) => "列出内置的项目模板或写出其中一个",
+            (Lang::En, Help::Unused) => {
+                "Report scopes used in code but not declared, and declared scopes gating nothing"
+            }
+            (Lang::Zh, Help::Unused) => "报告代码中使用但未声明的作用域，以及已声明却不控制任何代码的作用域",
+            (Lang::En, Help::Update) => "Write the resolved scopes of every package to feature-scope.lock",
+            (Lang::Zh, Help::Update) => "把每个包解析出的作用域写入 feature-scope.lock",
+            (Lang::En, Help::Vendor) => "Vendor the dependencies and lock the resolved scopes",
+            (Lang::Zh, Help::Vendor) => "vendor 依赖并锁定解析出的作用域",
+            (Lang::En, Help::Verify) => {
+                "Check every package's scopes without building, or the scope changes since a git revision"
+            }
+            (Lang::Zh, Help::Verify) => {
+                "不构建而检查每个包的作用域，或检查自某个 git 版本以来的作用域修改"
+            }
+            (Lang::En, Help::Package) => "Package to build",
+            (Lang::Zh, Help::Package) => "要构建的包",
+            (Lang::En, Help::Features) => {
+                "Scopes to enable on top of the configured ones, as `[package/]feature`"
+            }
+            (Lang::Zh, Help::Features) => {
+                "在已配置的作用域之外额外启用的作用域，格式为 `[package/]feature`"
+            }
+            (Lang::En, Help::NoDefaultFeatures) => {
+                "Do not enable the default scopes of the libraries"
+            }
+            (Lang::Zh, Help::NoDefaultFeatures) => "不启用各个库的默认作用域",
+            (Lang::En, Help::NoProbes) => {
+                "Do not enable the scopes whose probes find them in the build environment"
+            }
+            (Lang::Zh, Help::NoProbes) => "不启用由构建环境探测启用的作用域",
+            (Lang::En, Help::Infer) => {
+                "Declare the scopes named by #[feature_scope] attributes for packages declaring none"
+            }
+            (Lang::Zh, Help::Infer) => "未声明作用域的包按其 #[feature_scope] 属性中出现的名称声明作用域",
+            (Lang::En, Help::ExcludeHost) => {
+                "Keep the scope cfgs off build scripts and proc macros by building for the host triple as --target"
+            }
+            (Lang::Zh, Help::ExcludeHost) => {
+                "以主机三元组作为 --target 构建，使构建脚本与过程宏不接收作用域 cfg"
+            }
+            (Lang::En, Help::Variant) => {
+                "Build into a dedicated target directory for this scope variant"
+            }
+            (Lang::Zh, Help::Variant) => "为该作用域变体使用独立的目标目录构建",
+            (Lang::En, Help::ArtifactDir) => {
+                "Copy the built binaries into this directory, named after the variant"
+            }
+            (Lang::Zh, Help::ArtifactDir) => "把构建出的二进制文件复制到该目录，并以变体命名",
+            (Lang::En, Help::VcsCheck) => {
+                "Warn about uncommitted changes to the scope configuration first"
+            }
+            (Lang::Zh, Help::VcsCheck) => "先检查作用域配置是否有未提交的修改并给出警告",
+            (Lang::En, Help::DenyWarnings) => {
+                "Fail on undeclared features, unknown packages and missing scope declarations"
+            }
+            (Lang::Zh, Help::DenyWarnings) => "遇到未声明的特性、未知的包或缺少作用域声明时失败",
+            (Lang::En, Help::Interactive) => {
+                "Offer fixes for conflicting scopes and write the chosen one to the manifests"
+            }
+            (Lang::Zh, Help::Interactive) => "为冲突的作用域提供修复方案，并把所选方案写回清单",
+            (Lang::En, Help::Resolution) => {
+                "Fix conflicting scopes without asking, keeping the consumer's or the library's choice"
+            }
+            (Lang::Zh, Help::Resolution) => "不经询问直接修复冲突的作用域，保留使用方或库的选择",
+            (Lang::En, Help::IsolateTargetDir) => {
+                "Build into a target directory of its own for every resolved scope set"
+            }
+            (Lang::Zh, Help::IsolateTargetDir) => "为每个解析出的作用域集合使用独立的目标目录构建",
+            (Lang::En, Help::ReuseDeps) => {
+                "Seed a new variant target directory with the existing artifacts"
+            }
+            (Lang::Zh, Help::ReuseDeps) => "用已有产物初始化新的变体目标目录",
+            (Lang::En, Help::CompareScopes) => {
+                "Run benchmarks once per comma-separated scope set and compare them"
+            }
+            (Lang::Zh, Help::CompareScopes) => {
+                "对每个逗号分隔的作用域集合各运行一次基准测试并比较结果"
+            }
+            (Lang::En, Help::AllScopes) => {
+                "Run the command once per scope set and summarize which ones pass"
+            }
+            (Lang::Zh, Help::AllScopes) => "对每个作用域集合各运行一次命令，并汇总哪些通过",
+            (Lang::En, Help::FeaturePowerset) => {
+                "With --all-scopes, run every combination of the declared scopes"
+            }
+            (Lang::Zh, Help::FeaturePowerset) => "配合 --all-scopes，运行已声明作用域的所有组合",
+            (Lang::En, Help::Depth) => "Combine at most this many scopes with --feature-powerset",
+            (Lang::Zh, Help::Depth) => "配合 --feature-powerset，每个组合最多包含这么多作用域",
+            (Lang::En, Help::NoRunCargo) => {
+                "Print a script that runs the command instead of running it"
+            }
+            (Lang::Zh, Help::NoRunCargo) => "不运行命令，而是打印运行它的脚本",
+            (Lang::En, Help::ScriptFormat) => {
+                "Format of the --no-run-cargo script, defaults to the platform's"
+            }
+            (Lang::Zh, Help::ScriptFormat) => "--no-run-cargo 脚本的格式，默认与平台一致",
+            (Lang::En, Help::Verbose) => "Show more details, `-vv` shows the raw cfg flags",
+            (Lang::Zh, Help::Verbose) => "显示更多细节，`-vv` 显示原始 cfg 参数",
+            (Lang::En, Help::Args) => {
+                "Additional arguments to pass to cargo, `--` separates the ones of the program"
+            }
+            (Lang::Zh, Help::Args) => "传给 cargo 的其他参数，`--` 之后的参数传给程序",
+            (Lang::En, Help::ProgramArgs) => "Arguments for the program cargo runs",
+            (Lang::Zh, Help::ProgramArgs) => "传给 cargo 所运行程序的参数",
+        }
+    }
+}
+
+/// The help text of a subcommand that runs the cargo command of the same name.
+pub fn cargo_command_help(command: &str) -> String {
+    match lang() {
+        Lang::En => format!("Run `cargo {command}` with the resolved scopes"),
+        Lang::Zh => format!("使用解析出的作用域运行 `cargo {command}`"),
+    }
+}
@@ -0,0 +1,135 @@
+//! Editor settings giving rust-analyzer the resolved scopes.
+//!
+//! rust-analyzer runs plain cargo, so without the scope cfgs every gated item looks disabled.
+//! `cargo feature-scope ide-setup [-p <PACKAGE>]` resolves the scopes of the package and writes
+//! them to `.vscode/settings.json` next to the root manifest: `rust-analyzer.cargo.cfgs` gets
+//! the enabled cfgs for the analysis, and `rust-analyzer.cargo.extraEnv` the same `RUSTFLAGS`
+//! and environment as a build through the CLI, so its `cargo check` also shares the artifacts of
+//! those builds. The other settings are kept. `--print` prints the settings instead, for
+//! editors configuring rust-analyzer elsewhere. The settings go stale when the scopes change,
+//! and are written again by running the command again.
+
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::{fs, path::Path};
+
+use crate::{env_vars, rustflags, CargoToml};
+
+const CFGS_KEY: &str = "rust-analyzer.cargo.cfgs";
+const EXTRA_ENV_KEY: &str = "rust-analyzer.cargo.extraEnv";
+
+// What rust-analyzer enables when `rust-analyzer.cargo.cfgs` is not set
+const DEFAULT_CFGS: [&str; 2] = ["debug_assertions", "miri"];
+
+pub fn run(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    packages: &[String],
+    args: &[String],
+) -> Result<()> {
+    let print = match args {
+        [] => false,
+        [flag] if flag == "--print" => true,
+        _ => anyhow::bail!("Usage: cargo feature-scope ide-setup [-p <PACKAGE>] [--print]"),
+    };
+
+    // rust-analyzer checks the whole workspace with one set of flags
+    let package = match packages {
+        [] => crate::determine_default_package(root_cargo_toml, root_manifest_path)?,
+        [package] => package.clone(),
+        _ => anyhow::bail!(
+            "ide-setup writes the scopes of a single package, but several -p were given"
+        ),
+    };
+    let (cfg_args, check_cfg_args) =
+        crate::resolve_cfg_args(root_cargo_toml, root_manifest_path, &package)?;
+    let enabled_cfgs: Vec<&str> = cfg_args
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .map(String::as_str)
+        .collect();
+
+    let mut extra_env = Map::new();
+    extra_env.insert(
+        String::from("RUSTFLAGS"),
+        Value::from(rustflags::compose(&[], &cfg_args, &check_cfg_args).join(" ")),
+    );
+    extra_env.insert(
+        String::from(env_vars::CFGS),
+        Value::from(enabled_cfgs.join(" ")),
+    );
+    extra_env.insert(
+        String::from(env_vars::TARGET_PACKAGE),
+        Value::from(package.as_str()),
+    );
+
+    if print {
+        let mut settings = Map::new();
+        settings.insert(String::from(CFGS_KEY), cfgs_setting(None, &enabled_cfgs));
+        settings.insert(String::from(EXTRA_ENV_KEY), Value::Object(extra_env));
+        println!("{}", serde_json::to_string_pretty(&settings)?);
+        return Ok(());
+    }
+
+    let settings_path = root_manifest_path
+        .parent()
+        .unwrap()
+        .join(".vscode")
+        .join("settings.json");
+    let mut settings = match fs::read_to_string(&settings_path) {
+        Ok(content) if content.trim().is_empty() => Map::new(),
+        Ok(content) => serde_json::from_str(&content).with_context(|| {
+            format!(
+                "Failed to parse {}, settings with comments can only be updated by hand, see `--print`",
+                settings_path.display()
+            )
+        })?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Map::new(),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to read {}", settings_path.display()))
+        }
+    };
+
+    let cfgs = cfgs_setting(settings.get(CFGS_KEY), &enabled_cfgs);
+    settings.insert(String::from(CFGS_KEY), cfgs);
+    // Other variables the user gave rust-analyzer stay
+    let env = settings
+        .entry(EXTRA_ENV_KEY)
+        .or_insert_with(|| Value::Object(Map::new()));
+    match env.as_object_mut() {
+        Some(env) => env.extend(extra_env),
+        None => *env = Value::Object(extra_env),
+    }
+
+    fs::create_dir_all(settings_path.parent().unwrap())?;
+    fs::write(
+        &settings_path,
+        format!("{}\n", serde_json::to_string_pretty(&settings)?),
+    )
+    .with_context(|| format!("Failed to write {}", settings_path.display()))?;
+    println!(
+        "Wrote the scopes of '{package}' to {}: {}",
+        settings_path.display(),
+        if enabled_cfgs.is_empty() {
+            String::from("none enabled")
+        } else {
+            enabled_cfgs.join(", ")
+        }
+    );
+    Ok(())
+}
+
+// The cfgs that are not scopes, rust-analyzer's defaults if there are none yet, then the scopes
+fn cfgs_setting(existing: Option<&Value>, enabled_cfgs: &[&str]) -> Value {
+    let mut cfgs: Vec<Value> = match existing.and_then(Value::as_array) {
+        Some(existing) => existing
+            .iter()
+            .filter(|cfg| !cfg.as_str().is_some_and(|cfg| cfg.starts_with("__scope_")))
+            .cloned()
+            .collect(),
+        None => DEFAULT_CFGS.into_iter().map(Value::from).collect(),
+    };
+    cfgs.extend(enabled_cfgs.iter().copied().map(Value::from));
+    Value::Array(cfgs)
+}
@@ -0,0 +1,202 @@
+//! Builds through the cargo library instead of the cargo binary.
+//!
+//! With the `in-process` feature of the CLI, `build` and `check` run inside the CLI through
+//! `cargo::ops::compile`, for environments that cannot spawn `cargo` or want to skip its
+//! startup. rustc and build scripts are still spawned by cargo as usual. Only the common options
+//! of the two commands are understood, any other forwarded argument is reported as an error, so
+//! the command can be retried with a build of the CLI without the feature.
+
+use anyhow::Result;
+use cargo::{
+    core::{
+        compiler::{CompileKind, UserIntent},
+        resolver::CliFeatures,
+        Workspace,
+    },
+    ops::{self, CompileFilter, CompileOptions, Packages},
+    util::important_paths::find_root_manifest_for_wd,
+    GlobalContext,
+};
+use std::{env, path::PathBuf, process};
+
+/// Returns whether the command can run in process.
+pub fn supports(command: &str) -> bool {
+    matches!(command, "build" | "check")
+}
+
+// The options of `cargo build` and `cargo check` that can be run in process
+struct CompileArgs {
+    packages: Vec<String>,
+    workspace: bool,
+    exclude: Vec<String>,
+    manifest_path: Option<PathBuf>,
+    target_dir: Option<PathBuf>,
+    profile: String,
+    targets: Vec<String>,
+    features: Vec<String>,
+    all_features: bool,
+    no_default_features: bool,
+    lib: bool,
+    bins: Vec<String>,
+    all_bins: bool,
+    examples: Vec<String>,
+    all_examples: bool,
+    tests: Vec<String>,
+    all_tests: bool,
+    all_targets: bool,
+    verbose: u32,
+    quiet: bool,
+    frozen: bool,
+    locked: bool,
+    offline: bool,
+}
+
+impl CompileArgs {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut parsed = CompileArgs {
+            packages: Vec::new(),
+            workspace: false,
+            exclude: Vec::new(),
+            manifest_path: None,
+            target_dir: None,
+            profile: String::from("dev"),
+            targets: Vec::new(),
+            features: Vec::new(),
+            all_features: false,
+            no_default_features: false,
+            lib: false,
+            bins: Vec::new(),
+            all_bins: false,
+            examples: Vec::new(),
+            all_examples: false,
+            tests: Vec::new(),
+            all_tests: false,
+            all_targets: false,
+            verbose: 0,
+            quiet: false,
+            frozen: false,
+            locked: false,
+            offline: false,
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| anyhow::anyhow!("{name} takes a value"))
+            };
+
+            match name {
+                "-p" | "--package" => parsed.packages.push(value()?),
+                "--workspace" | "--all" => parsed.workspace = true,
+                "--exclude" => parsed.exclude.push(value()?),
+                "--manifest-path" => parsed.manifest_path = Some(PathBuf::from(value()?)),
+                "--target-dir" => parsed.target_dir = Some(PathBuf::from(value()?)),
+                "-r" | "--release" => parsed.profile = String::from("release"),
+                "--profile" => parsed.profile = value()?,
+                "--target" => parsed.targets.push(value()?),
+                "-F" | "--features" => parsed.features.push(value()?),
+                "--all-features" => parsed.all_features = true,
+                "--no-default-features" => parsed.no_default_features = true,
+                "--lib" => parsed.lib = true,
+                "--bin" => parsed.bins.push(value()?),
+                "--bins" => parsed.all_bins = true,
+                "--example" => parsed.examples.push(value()?),
+                "--examples" => parsed.all_examples = true,
+                "--test" => parsed.tests.push(value()?),
+                "--tests" => parsed.all_tests = true,
+                "--all-targets" => parsed.all_targets = true,
+                "-v" | "--verbose" => parsed.verbose += 1,
+                "-vv" => parsed.verbose += 2,
+                "-q" | "--quiet" => parsed.quiet = true,
+                "--frozen" => parsed.frozen = true,
+                "--locked" => parsed.locked = true,
+                "--offline" => parsed.offline = true,
+                _ => anyhow::bail!(
+                    "'{arg}' is not supported by in-process builds, use a build of \
+                     cargo-feature-scope without the in-process feature"
+                ),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Runs a cargo command, as prepared for spawning the cargo binary, in this process instead.
+pub fn run(cargo_cmd: &process::Command) -> Result<()> {
+    let mut args = cargo_cmd
+        .get_args()
+        .map(|arg| arg.to_string_lossy().into_owned());
+    let command = args.next().unwrap_or_default();
+    let args = CompileArgs::parse(args)?;
+
+    // The cargo context reads the environment when it is created
+    for (key, value) in cargo_cmd.get_envs() {
+        match value {
+            Some(value) => env::set_var(key, value),
+            None => env::remove_var(key),
+        }
+    }
+
+    let mut gctx = GlobalContext::default()?;
+    gctx.configure(
+        args.verbose,
+        args.quiet,
+        None,
+        args.frozen,
+        args.locked,
+        args.offline,
+        &args.target_dir,
+        &[],
+        &[],
+    )?;
+
+    let manifest_path = match args.manifest_path {
+        Some(manifest_path) => gctx.cwd().join(manifest_path),
+        None => find_root_manifest_for_wd(gctx.cwd())?,
+    };
+    let workspace = Workspace::new(&manifest_path, &gctx)?;
+
+    let intent = if command == "check" {
+        UserIntent::Check { test: false }
+    } else {
+        UserIntent::Build
+    };
+    let mut options = CompileOptions::new(&gctx, intent)?;
+    options.spec = Packages::from_flags(args.workspace, args.exclude, args.packages)?;
+    options.cli_features = CliFeatures::from_command_line(
+        &args.features,
+        args.all_features,
+        !args.no_default_features,
+    )?;
+    options.filter = CompileFilter::from_raw_arguments(
+        args.lib,
+        args.bins,
+        args.all_bins,
+        args.tests,
+        args.all_tests,
+        args.examples,
+        args.all_examples,
+        Vec::new(),
+        false,
+        args.all_targets,
+    );
+    options.build_config.requested_profile = args.profile.as_str().into();
+    options.build_config.requested_kinds =
+        CompileKind::from_requested_targets(&gctx, &args.targets)?;
+
+    // Report like the cargo binary does, with its exit code
+    if let Err(err) = ops::compile(&workspace, &options) {
+        cargo::display_error(&err, &mut gctx.shell());
+        process::exit(101);
+    }
+
+    Ok(())
+}
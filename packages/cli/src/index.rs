@@ -0,0 +1,42 @@
+//! Workspace indexes for builds that cannot spawn processes.
+//!
+//! `cargo feature-scope index [--target <TRIPLE>]... [PATH]` writes the workspace members to
+//! `feature-scope-index.json` next to the root manifest, or to `PATH`, along with the cfgs of the
+//! host and of each `--target`. Build scripts and tools resolving scopes with
+//! `feature-scope-core` in a sandbox that forbids spawning processes pass it to
+//! `Resolver::from_index`, or point `FEATURE_SCOPE_INDEX` to it, instead of having
+//! `cargo metadata` and rustc run. Regenerate the index whenever the members change.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use feature_scope_core::workspace;
+
+const USAGE: &str = "Usage: cargo feature-scope index [--target <TRIPLE>]... [PATH]";
+
+pub fn run(root_manifest_path: &Path, args: &[String]) -> Result<()> {
+    let mut targets = Vec::new();
+    let mut index_path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--target" {
+            targets.push(args.next().context(USAGE)?.clone());
+        } else if let Some(target) = arg.strip_prefix("--target=") {
+            targets.push(target.to_string());
+        } else if index_path.is_none() && !arg.starts_with('-') {
+            index_path = Some(PathBuf::from(arg));
+        } else {
+            anyhow::bail!(USAGE);
+        }
+    }
+    let index_path = index_path.unwrap_or_else(|| {
+        root_manifest_path
+            .parent()
+            .unwrap()
+            .join(workspace::INDEX_FILE_NAME)
+    });
+
+    workspace::write_index(root_manifest_path, &index_path, &targets)?;
+    println!("Wrote {}", index_path.display());
+    Ok(())
+}
@@ -0,0 +1,314 @@
+//! Scaffolding of the scope declarations of a library.
+//!
+//! `cargo feature-scope init [-p <PACKAGE>] [--import-features]` prepares a package to declare
+//! scopes: it adds an empty `[package.metadata.feature-scope-decl]`, or one importing the
+//! features of `[features]` with `--import-features`, the `feature-scope` dependency for the
+//! attributes and the `feature-scope-core` build dependency, and a build script calling
+//! `feature_scope_core::load()` so plain `cargo build` sees the scopes too. A build script the
+//! package already has gets the call at the top of its `main`. Declared features missing from
+//! `[features]` get an empty entry there, and `default` the default scopes, for the
+//! `cargo-features` mode of the macros. The manifest is edited in place,
+//! keeping its formatting and comments, and what the package already has is left alone, so
+//! running it twice changes nothing.
+
+use anyhow::{Context, Result};
+use std::{fs, path::Path};
+use syn::spanned::Spanned;
+use toml_edit::{Array, DocumentMut, InlineTable, Item, Table, Value};
+
+use crate::{
+    items,
+    lints::{read_document, write_document},
+    CargoToml,
+};
+
+const USAGE: &str = "Usage: cargo feature-scope init [-p <PACKAGE>] [--import-features]";
+
+/// Keys of feature-scope-decl that are not features.
+pub const RESERVED_KEYS: [&str; 4] = ["default", "exclusive", "presets", "stub-disabled-items"];
+
+const LOAD_CALL: &str = "feature_scope_core::load()";
+
+const BUILD_SCRIPT: &str = "\
+// Passes the resolved scopes to plain `cargo build`, `cargo feature-scope` passes them itself
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    feature_scope_core::load()?;
+    Ok(())
+}
+";
+
+pub fn run(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    package: Option<&String>,
+    args: &[String],
+) -> Result<()> {
+    let import_features = match args {
+        [] => false,
+        [flag] if flag == "--import-features" => true,
+        _ => anyhow::bail!(USAGE),
+    };
+    let package = match package {
+        Some(package) => package.clone(),
+        None => feature_scope_core::determine_default_package(root_cargo_toml, root_manifest_path)?,
+    };
+    let (manifest_path, _) = items::find_package(root_cargo_toml, root_manifest_path, &package)?;
+    scaffold(
+        root_manifest_path,
+        &manifest_path,
+        &package,
+        import_features,
+    )
+}
+
+/// Adds the declarations, the dependencies and the build script `init` adds to a package.
+pub fn scaffold(
+    root_manifest_path: &Path,
+    manifest_path: &Path,
+    package: &str,
+    import_features: bool,
+) -> Result<()> {
+    let root_document = read_document(root_manifest_path)?;
+    let mut document = read_document(manifest_path)?;
+    add_decl(&mut document, import_features)?;
+    add_cargo_features(&mut document)?;
+    add_dependency(
+        &mut document,
+        &root_document,
+        "dependencies",
+        "feature-scope",
+    );
+    add_dependency(
+        &mut document,
+        &root_document,
+        "build-dependencies",
+        "feature-scope-core",
+    );
+    let build_script = document
+        .get("package")
+        .and_then(|package| package.get("build"))
+        .cloned();
+    write_document(manifest_path, &document)?;
+
+    let package_dir = manifest_path.parent().unwrap();
+    match build_script {
+        Some(build) if build.as_bool() == Some(false) => println!(
+            "⚠️  {package} turns its build script off with `build = false`, \
+             plain `cargo build` will not see the scopes"
+        ),
+        Some(build) => {
+            let path = build.as_str().context("`package.build` is not a path")?;
+            add_load_call(&package_dir.join(path))?;
+        }
+        None => add_load_call(&package_dir.join("build.rs"))?,
+    }
+    Ok(())
+}
+
+// Adds the decl table unless there is one, with the features of `[features]` if asked to
+fn add_decl(document: &mut DocumentMut, import_features: bool) -> Result<()> {
+    let package = document
+        .get_mut("package")
+        .and_then(Item::as_table_mut)
+        .context("The manifest has no [package] table")?;
+    let metadata = package.entry("metadata").or_insert_with(|| {
+        let mut table = Table::new();
+        table.set_implicit(true);
+        Item::Table(table)
+    });
+    let metadata = metadata
+        .as_table_like_mut()
+        .context("`package.metadata` is not a table")?;
+    if metadata.contains_key("feature-scope-decl") {
+        println!("The package already declares scopes");
+        return Ok(());
+    }
+
+    let mut decl = Table::new();
+    decl.decor_mut()
+        .set_prefix("\n# The scopes consumers can select, `default` applies unless they opt out\n");
+    let imported = if import_features {
+        document.get("features").and_then(Item::as_table_like)
+    } else {
+        None
+    };
+    match imported {
+        Some(features) => {
+            let local: Vec<&str> = features.iter().map(|(name, _)| name).collect();
+            for (name, value) in features.iter() {
+                if name != "default" && RESERVED_KEYS.contains(&name) {
+                    println!(
+                        "⚠️  feature '{name}' is not imported, \
+                         the name is reserved in feature-scope-decl"
+                    );
+                    continue;
+                }
+                decl.insert(name, toml_edit::value(local_features(value, &local)));
+            }
+            if !decl.contains_key("default") {
+                decl.insert("default", toml_edit::value(Array::new()));
+            }
+            // `default` first, like in `[features]` usually
+            decl.sort_values_by(|a, _, b, _| (a.get() != "default").cmp(&(b.get() != "default")));
+        }
+        None => {
+            decl.insert("default", toml_edit::value(Array::new()));
+        }
+    }
+
+    let package = document["package"].as_table_mut().unwrap();
+    let metadata = package["metadata"].as_table_like_mut().unwrap();
+    metadata.insert("feature-scope-decl", Item::Table(decl));
+    Ok(())
+}
+
+// Adds a `[features]` entry for every declared feature missing one, empty but for its `dep:`
+// and `crate/feature` entries, and a `default` one with the default scopes, so with the
+// `cargo-features` feature of the macros, plain cargo builds can select the scopes too
+fn add_cargo_features(document: &mut DocumentMut) -> Result<()> {
+    let Some(decl) = document
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("feature-scope-decl"))
+        .and_then(Item::as_table_like)
+    else {
+        return Ok(());
+    };
+    // With what they enable for cargo, which only cargo features can
+    let declared: Vec<(String, Array)> = decl
+        .iter()
+        .filter(|(name, _)| !RESERVED_KEYS.contains(name))
+        .map(|(name, value)| {
+            let deps = value.get("deps").unwrap_or(value);
+            let dependencies = deps
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .filter(|dep| is_cargo_entry(dep))
+                .collect();
+            (name.to_string(), dependencies)
+        })
+        .collect();
+    if declared.is_empty() {
+        return Ok(());
+    }
+    let default: Array = decl
+        .get("default")
+        .and_then(Item::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+
+    let features = document
+        .entry("features")
+        .or_insert_with(|| Item::Table(Table::new()))
+        .as_table_like_mut()
+        .context("`features` is not a table")?;
+    if !features.contains_key("default") {
+        features.insert("default", toml_edit::value(default));
+    }
+    for (name, dependencies) in declared {
+        if !features.contains_key(&name) {
+            features.insert(&name, toml_edit::value(dependencies));
+        }
+    }
+    Ok(())
+}
+
+// The features of a `[features]` entry that are features of the package itself, and what it
+// enables for cargo, like `dep:serde` or `serde/std`
+fn local_features(value: &Item, local: &[&str]) -> Array {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .filter(|feature| {
+            is_cargo_entry(feature) || local.contains(feature) && *feature != "default"
+        })
+        .collect()
+}
+
+// Whether an entry enables an optional dependency or a feature of a dependency
+fn is_cargo_entry(entry: &str) -> bool {
+    entry.starts_with("dep:") || entry.contains('/')
+}
+
+// Adds a dependency on the version of this CLI, or inherited if the workspace declares it
+fn add_dependency(document: &mut DocumentMut, root_document: &DocumentMut, kind: &str, name: &str) {
+    let dependencies = document
+        .entry(kind)
+        .or_insert_with(|| Item::Table(Table::new()));
+    let Some(dependencies) = dependencies.as_table_like_mut() else {
+        return;
+    };
+    let renamed = dependencies
+        .iter()
+        .any(|(_, dependency)| dependency.get("package").and_then(Item::as_str) == Some(name));
+    if dependencies.contains_key(name) || renamed {
+        return;
+    }
+
+    let in_workspace = root_document
+        .get("workspace")
+        .and_then(|workspace| workspace.get("dependencies"))
+        .is_some_and(|dependencies| dependencies.get(name).is_some());
+    let dependency = if in_workspace {
+        let mut table = InlineTable::new();
+        table.insert("workspace", Value::from(true));
+        toml_edit::value(table)
+    } else {
+        toml_edit::value(env!("CARGO_PKG_VERSION"))
+    };
+    dependencies.insert(name, dependency);
+}
+
+// Writes a build script calling `load`, or adds the call to the `main` of an existing one
+fn add_load_call(path: &Path) -> Result<()> {
+    let Ok(content) = fs::read_to_string(path) else {
+        fs::write(path, BUILD_SCRIPT)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        println!("Wrote {}", path.display());
+        return Ok(());
+    };
+    if content.contains(LOAD_CALL) {
+        println!("{} is up to date", path.display());
+        return Ok(());
+    }
+
+    let file = syn::parse_file(&content)
+        .map_err(|err| anyhow::anyhow!("Failed to parse {}: {err}", path.display()))?;
+    let main = file.items.iter().find_map(|item| match item {
+        syn::Item::Fn(function) if function.sig.ident == "main" => Some(function),
+        _ => None,
+    });
+    let Some(main) = main else {
+        anyhow::bail!(
+            "{} has no `fn main`, call `{LOAD_CALL}` from the build script yourself",
+            path.display()
+        );
+    };
+
+    // The position right after the opening brace of the body
+    let open = main.block.brace_token.span.open().span().end();
+    let line_start: usize = content
+        .split_inclusive('\n')
+        .take(open.line - 1)
+        .map(str::len)
+        .sum();
+    let offset = line_start
+        + content[line_start..]
+            .char_indices()
+            .nth(open.column)
+            .map_or(content.len() - line_start, |(index, _)| index);
+    let mut updated = content.clone();
+    updated.insert_str(
+        offset,
+        &format!("\n    {LOAD_CALL}.expect(\"Failed to resolve the feature scopes\");"),
+    );
+    fs::write(path, updated).with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Updated {}", path.display());
+    Ok(())
+}
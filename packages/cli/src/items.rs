@@ -0,0 +1,745 @@
+//! Reverse lookup of the items a scope gates.
+//!
+//! `cargo feature-scope items <package> <feature>` parses the sources of a package and lists
+//! every item gated by the scope, or by one of the scopes it pulls in through its dependencies,
+//! since enabling the scope enables those items as well. Items are recognized by their
+//! `#[feature_scope]` and `#[feature_scope_default]` attributes and by `cfg` attributes that
+//! mention a scope cfg directly.
+
+use anyhow::{Context, Result};
+use feature_scope_core::manifest::FeatureScopeDecl;
+use proc_macro2::{TokenStream, TokenTree};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+use syn::{
+    spanned::Spanned,
+    visit::{self, Visit},
+    Attribute, ExprMethodCall, Ident, ImplItem, ImplItemFn, Item, Stmt, TraitItem, TraitItemFn,
+    UseName, UseRename,
+};
+
+use crate::{cfg_name, CargoToml, Package};
+
+// The scope cfgs an attribute gates its item with
+#[derive(Debug, Clone, Default)]
+struct Gate {
+    cfgs: Vec<String>,
+    default: bool,
+    // The features named in `#[feature_scope]` attributes, as written
+    features: Vec<String>,
+    // The features they name inside `not(...)`, which gate nothing but must be declared as well
+    negated: Vec<String>,
+    // Disabled functions keep their signatures, so calling them compiles either way
+    stub: bool,
+}
+
+/// A feature named by a `#[feature_scope]` or `#[feature_scope_default]` attribute.
+#[derive(Debug)]
+pub struct ScopeUsage {
+    pub feature: String,
+    pub file: PathBuf,
+    /// The line of the item the attribute is on.
+    pub line: usize,
+}
+
+/// What the sources of a package gate their items with.
+#[derive(Debug, Default)]
+pub struct SourceScopes {
+    /// Every feature the attributes name, negated ones included, in the order of the sources.
+    pub usages: Vec<ScopeUsage>,
+    /// The scope cfgs gating items, from the attributes and from plain `cfg` attributes.
+    pub cfgs: BTreeSet<String>,
+}
+
+struct GatedItem {
+    file: PathBuf,
+    line: usize,
+    scope: String,
+    signature: String,
+}
+
+pub fn run(root_cargo_toml: &CargoToml, root_manifest_path: &Path, args: &[String]) -> Result<()> {
+    let [package_name, feature] = args else {
+        anyhow::bail!("Usage: cargo feature-scope items <PACKAGE> <FEATURE>");
+    };
+
+    let (manifest_path, package) = find_package(root_cargo_toml, root_manifest_path, package_name)?;
+    let decl = package
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.feature_scope_decl.as_ref())
+        .ok_or_else(|| anyhow::anyhow!("Package '{package_name}' does not declare any scopes"))?;
+
+    let mut scopes = HashSet::new();
+    if !crate::resolve_requested_feature(feature, decl, &mut scopes) {
+        let mut declared: Vec<&String> = decl.features.keys().collect();
+        declared.sort();
+        anyhow::bail!(
+            "Package '{package_name}' does not declare scope '{feature}', declared scopes are: {}",
+            declared
+                .iter()
+                .map(|feature| feature.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    let includes_default = feature == "default";
+    let scopes: HashMap<String, String> = scopes
+        .into_iter()
+        .map(|feature| (cfg_name::scope_cfg(&feature), feature))
+        .collect();
+
+    let package_dir = manifest_path.parent().unwrap();
+    let mut files = Vec::new();
+    collect_sources(package_dir, package_dir, &mut files)?;
+    files.sort();
+
+    let root_dir = root_manifest_path.parent().unwrap();
+    let mut items = Vec::new();
+    for file in files {
+        let content = fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let parsed = syn::parse_file(&content)
+            .with_context(|| format!("Failed to parse {}", file.display()))?;
+
+        let mut found = Vec::new();
+        visit_items(&parsed.items, &mut found);
+
+        let lines: Vec<&str> = content.lines().collect();
+        for (line, gate) in found {
+            let scope = if includes_default && gate.default {
+                String::from("default")
+            } else if let Some(feature) = gate.cfgs.iter().find_map(|cfg| scopes.get(cfg)) {
+                format!("{package_name}/{feature}")
+            } else {
+                continue;
+            };
+
+            items.push(GatedItem {
+                file: file.strip_prefix(root_dir).unwrap_or(&file).to_path_buf(),
+                line,
+                scope,
+                signature: signature(&lines, line),
+            });
+        }
+    }
+
+    if items.is_empty() {
+        println!("No items are gated by {package_name}/{feature}");
+        return Ok(());
+    }
+
+    let locations: Vec<String> = items
+        .iter()
+        .map(|item| format!("{}:{}", item.file.display(), item.line))
+        .collect();
+    let location_width = locations.iter().map(String::len).max().unwrap_or(0);
+    let scope_width = items
+        .iter()
+        .map(|item| item.scope.chars().count())
+        .max()
+        .unwrap_or(0);
+    for (location, item) in locations.iter().zip(&items) {
+        println!(
+            "{location:location_width$}  {:scope_width$}  {}",
+            item.scope, item.signature
+        );
+    }
+
+    Ok(())
+}
+
+/// A reference from code every build compiles to an item that only some scopes compile.
+#[derive(Debug)]
+pub struct GatedReference {
+    pub name: String,
+    pub file: PathBuf,
+    pub line: usize,
+    /// Where the referenced item is defined.
+    pub item_file: PathBuf,
+    pub item_line: usize,
+    /// The scopes gating the item.
+    pub scopes: Vec<String>,
+}
+
+// An item name defined in the sources of a package
+struct Definition {
+    file: PathBuf,
+    line: usize,
+    // The gate of the item, or of the innermost gated item around it
+    gate: Option<Gate>,
+    is_fn: bool,
+}
+
+// Collects the item names of a package with their gates
+#[derive(Default)]
+struct Definitions {
+    file: PathBuf,
+    gates: Vec<Gate>,
+    items: HashMap<String, Vec<Definition>>,
+}
+
+impl Definitions {
+    fn define(&mut self, attrs: &[Attribute], ident: Option<&Ident>, is_fn: bool) -> bool {
+        let gate = gate(attrs);
+        let is_gated = gate.is_some();
+        self.gates.extend(gate);
+        if let Some(ident) = ident {
+            self.items
+                .entry(ident.to_string())
+                .or_default()
+                .push(Definition {
+                    file: self.file.clone(),
+                    line: ident.span().start().line,
+                    gate: self.gates.last().cloned(),
+                    is_fn,
+                });
+        }
+        is_gated
+    }
+}
+
+impl<'ast> Visit<'ast> for Definitions {
+    fn visit_item(&mut self, item: &'ast Item) {
+        let is_fn = matches!(item, Item::Fn(_));
+        let is_gated = self.define(item_attrs(item), item_ident(item), is_fn);
+        visit::visit_item(self, item);
+        if is_gated {
+            self.gates.pop();
+        }
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast ImplItemFn) {
+        let is_gated = self.define(&item.attrs, Some(&item.sig.ident), true);
+        visit::visit_impl_item_fn(self, item);
+        if is_gated {
+            self.gates.pop();
+        }
+    }
+
+    fn visit_trait_item_fn(&mut self, item: &'ast TraitItemFn) {
+        let is_gated = self.define(&item.attrs, Some(&item.sig.ident), true);
+        visit::visit_trait_item_fn(self, item);
+        if is_gated {
+            self.gates.pop();
+        }
+    }
+}
+
+// Collects the names ungated code refers to, with their lines
+struct References {
+    file: PathBuf,
+    found: Vec<(String, PathBuf, usize)>,
+}
+
+impl References {
+    fn refer(&mut self, ident: &Ident) {
+        let line = ident.span().start().line;
+        self.found
+            .push((ident.to_string(), self.file.clone(), line));
+    }
+}
+
+impl<'ast> Visit<'ast> for References {
+    fn visit_item(&mut self, item: &'ast Item) {
+        if gate(item_attrs(item)).is_none() {
+            visit::visit_item(self, item);
+        }
+    }
+
+    fn visit_impl_item(&mut self, item: &'ast ImplItem) {
+        let attrs: &[Attribute] = match item {
+            ImplItem::Const(item) => &item.attrs,
+            ImplItem::Fn(item) => &item.attrs,
+            ImplItem::Type(item) => &item.attrs,
+            ImplItem::Macro(item) => &item.attrs,
+            _ => &[],
+        };
+        if gate(attrs).is_none() {
+            visit::visit_impl_item(self, item);
+        }
+    }
+
+    fn visit_trait_item(&mut self, item: &'ast TraitItem) {
+        let attrs: &[Attribute] = match item {
+            TraitItem::Const(item) => &item.attrs,
+            TraitItem::Fn(item) => &item.attrs,
+            TraitItem::Type(item) => &item.attrs,
+            TraitItem::Macro(item) => &item.attrs,
+            _ => &[],
+        };
+        if gate(attrs).is_none() {
+            visit::visit_trait_item(self, item);
+        }
+    }
+
+    // Statements can carry a `cfg` of their own
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        let attrs: &[Attribute] = match stmt {
+            Stmt::Local(local) => &local.attrs,
+            Stmt::Macro(mac) => &mac.attrs,
+            _ => &[],
+        };
+        if gate(attrs).is_none() {
+            visit::visit_stmt(self, stmt);
+        }
+    }
+
+    fn visit_arm(&mut self, arm: &'ast syn::Arm) {
+        if gate(&arm.attrs).is_none() {
+            visit::visit_arm(self, arm);
+        }
+    }
+
+    fn visit_path(&mut self, path: &'ast syn::Path) {
+        for segment in &path.segments {
+            self.refer(&segment.ident);
+        }
+        visit::visit_path(self, path);
+    }
+
+    fn visit_expr_method_call(&mut self, call: &'ast ExprMethodCall) {
+        self.refer(&call.method);
+        visit::visit_expr_method_call(self, call);
+    }
+
+    fn visit_use_name(&mut self, name: &'ast UseName) {
+        self.refer(&name.ident);
+    }
+
+    fn visit_use_rename(&mut self, rename: &'ast UseRename) {
+        self.refer(&rename.ident);
+    }
+}
+
+/// Finds references from ungated code to items gated by scopes the defaults do not enable,
+/// which then fail to compile without those scopes. The analysis goes by names alone, so it
+/// leaves out names defined more than once, like alternative implementations gated by opposite
+/// scopes, stubbed functions and the bodies of macros such as `feature_scope_select!`, and still
+/// reports a local variable that shares its name with a gated item.
+pub fn gated_references(
+    package_dir: &Path,
+    decl: &FeatureScopeDecl,
+) -> Result<Vec<GatedReference>> {
+    let mut default_features = HashSet::new();
+    crate::resolve_requested_feature("default", decl, &mut default_features);
+    let default_cfgs: HashSet<String> = default_features
+        .iter()
+        .map(|feature| cfg_name::scope_cfg(feature))
+        .collect();
+
+    let mut files = Vec::new();
+    collect_sources(package_dir, package_dir, &mut files)?;
+    files.sort();
+    let mut parsed = Vec::new();
+    for file in files {
+        let content = fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let syntax = syn::parse_file(&content)
+            .with_context(|| format!("Failed to parse {}", file.display()))?;
+        parsed.push((file, syntax));
+    }
+
+    let mut definitions = Definitions::default();
+    let mut references = References {
+        file: PathBuf::new(),
+        found: Vec::new(),
+    };
+    for (file, syntax) in &parsed {
+        definitions.file = file.clone();
+        definitions.visit_file(syntax);
+        references.file = file.clone();
+        references.visit_file(syntax);
+    }
+
+    let mut gated = Vec::new();
+    for (name, file, line) in references.found {
+        let Some([definition]) = definitions.items.get(&name).map(Vec::as_slice) else {
+            continue;
+        };
+        let Some(gate) = &definition.gate else {
+            continue;
+        };
+        // Negations alone, like `not(a)`, keep the item in default builds
+        let compiled_by_default = gate.default
+            || gate.cfgs.is_empty()
+            || gate.cfgs.iter().any(|cfg| default_cfgs.contains(cfg));
+        let stubbed = definition.is_fn && (gate.stub || decl.stub_disabled_items);
+        if compiled_by_default || stubbed {
+            continue;
+        }
+        let is_duplicate = gated.iter().any(|found: &GatedReference| {
+            found.name == name && found.file == file && found.line == line
+        });
+        if is_duplicate {
+            continue;
+        }
+
+        gated.push(GatedReference {
+            name,
+            file,
+            line,
+            item_file: definition.file.clone(),
+            item_line: definition.line,
+            scopes: if gate.features.is_empty() {
+                gate.cfgs.clone()
+            } else {
+                gate.features.clone()
+            },
+        });
+    }
+    Ok(gated)
+}
+
+impl SourceScopes {
+    /// The usages of features the package does not declare, typos among them.
+    pub fn undeclared(&self, decl: Option<&FeatureScopeDecl>) -> Vec<&ScopeUsage> {
+        self.usages
+            .iter()
+            .filter(|usage| !decl.is_some_and(|decl| decl.declares(&usage.feature)))
+            .collect()
+    }
+
+    /// The declared features no item is gated with, sorted. Features that depend on others
+    /// are left out, since they bundle features rather than gate items.
+    pub fn unused<'a>(&self, decl: &'a FeatureScopeDecl) -> Vec<&'a String> {
+        let mut unused: Vec<&String> = decl
+            .features
+            .iter()
+            .filter(|(feature, dependencies)| {
+                dependencies.is_empty()
+                    && !self.cfgs.contains(&cfg_name::scope_cfg(feature))
+                    && !self
+                        .usages
+                        .iter()
+                        .any(|usage| usage.feature == feature.as_str())
+            })
+            .map(|(feature, _)| feature)
+            .collect();
+        unused.sort();
+        unused
+    }
+}
+
+/// Scans the sources of a package for the scopes its items are gated with.
+pub fn scan_sources(package_dir: &Path) -> Result<SourceScopes> {
+    let mut files = Vec::new();
+    collect_sources(package_dir, package_dir, &mut files)?;
+    files.sort();
+
+    let mut scopes = SourceScopes::default();
+    for file in files {
+        let content = fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let parsed = syn::parse_file(&content)
+            .with_context(|| format!("Failed to parse {}", file.display()))?;
+
+        let mut found = Vec::new();
+        visit_items(&parsed.items, &mut found);
+        for (line, gate) in found {
+            scopes.cfgs.extend(gate.cfgs);
+            for feature in gate.features.into_iter().chain(gate.negated) {
+                scopes.usages.push(ScopeUsage {
+                    feature,
+                    file: file.clone(),
+                    line,
+                });
+            }
+        }
+    }
+
+    Ok(scopes)
+}
+
+/// Returns the features the `#[feature_scope]` and `#[feature_scope_default]` attributes in the
+/// sources of a package gate items with. Plain `cfg` attributes only name cfgs and are left out.
+pub fn gated_features(package_dir: &Path) -> Result<BTreeSet<String>> {
+    let mut files = Vec::new();
+    collect_sources(package_dir, package_dir, &mut files)?;
+
+    let mut features = BTreeSet::new();
+    for file in files {
+        let content = fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let parsed = syn::parse_file(&content)
+            .with_context(|| format!("Failed to parse {}", file.display()))?;
+
+        let mut found = Vec::new();
+        visit_items(&parsed.items, &mut found);
+        features.extend(found.into_iter().flat_map(|(_, gate)| gate.features));
+    }
+
+    Ok(features)
+}
+
+/// Finds the manifest of a package that is the root package or a workspace member.
+pub fn find_package(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    package_name: &str,
+) -> Result<(PathBuf, Package)> {
+    if root_cargo_toml
+        .package
+        .as_ref()
+        .is_some_and(|package| package.name == package_name)
+    {
+        let package = crate::read_package(root_manifest_path)?;
+        return Ok((root_manifest_path.to_path_buf(), package));
+    }
+
+    for member_manifest in crate::workspace_member_manifests(root_cargo_toml, root_manifest_path)? {
+        let package = crate::read_package(&member_manifest)?;
+        if package.name == package_name {
+            return Ok((member_manifest, package));
+        }
+    }
+
+    anyhow::bail!("Package '{package_name}' not found in workspace")
+}
+
+/// Collects the Rust sources of a package, leaving out build output and nested packages.
+pub fn collect_sources(package_dir: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy();
+
+        if path.is_dir() {
+            let is_nested_package = path != package_dir && path.join("Cargo.toml").exists();
+            if name != "target" && !name.starts_with('.') && !is_nested_package {
+                collect_sources(package_dir, &path, files)?;
+            }
+        } else if name.ends_with(".rs") {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// The attributes of an item.
+pub fn item_attrs(item: &Item) -> &[Attribute] {
+    match item {
+        Item::Const(item) => &item.attrs,
+        Item::Enum(item) => &item.attrs,
+        Item::ExternCrate(item) => &item.attrs,
+        Item::Fn(item) => &item.attrs,
+        Item::ForeignMod(item) => &item.attrs,
+        Item::Impl(item) => &item.attrs,
+        Item::Macro(item) => &item.attrs,
+        Item::Mod(item) => &item.attrs,
+        Item::Static(item) => &item.attrs,
+        Item::Struct(item) => &item.attrs,
+        Item::Trait(item) => &item.attrs,
+        Item::TraitAlias(item) => &item.attrs,
+        Item::Type(item) => &item.attrs,
+        Item::Union(item) => &item.attrs,
+        Item::Use(item) => &item.attrs,
+        _ => &[],
+    }
+}
+
+// The name an item can be referred to by
+fn item_ident(item: &Item) -> Option<&Ident> {
+    match item {
+        Item::Const(item) => Some(&item.ident),
+        Item::Enum(item) => Some(&item.ident),
+        Item::Fn(item) => Some(&item.sig.ident),
+        Item::Macro(item) => item.ident.as_ref(),
+        Item::Mod(item) => Some(&item.ident),
+        Item::Static(item) => Some(&item.ident),
+        Item::Struct(item) => Some(&item.ident),
+        Item::Trait(item) => Some(&item.ident),
+        Item::TraitAlias(item) => Some(&item.ident),
+        Item::Type(item) => Some(&item.ident),
+        Item::Union(item) => Some(&item.ident),
+        _ => None,
+    }
+}
+
+fn visit_items(items: &[Item], found: &mut Vec<(usize, Gate)>) {
+    for item in items {
+        record(item_attrs(item), found);
+
+        match item {
+            Item::Mod(item) => {
+                if let Some((_, items)) = &item.content {
+                    visit_items(items, found);
+                }
+            }
+            Item::Impl(item) => {
+                for impl_item in &item.items {
+                    let attrs: &[Attribute] = match impl_item {
+                        ImplItem::Const(item) => &item.attrs,
+                        ImplItem::Fn(item) => &item.attrs,
+                        ImplItem::Type(item) => &item.attrs,
+                        ImplItem::Macro(item) => &item.attrs,
+                        _ => &[],
+                    };
+                    record(attrs, found);
+                }
+            }
+            Item::Trait(item) => {
+                for trait_item in &item.items {
+                    let attrs: &[Attribute] = match trait_item {
+                        TraitItem::Const(item) => &item.attrs,
+                        TraitItem::Fn(item) => &item.attrs,
+                        TraitItem::Type(item) => &item.attrs,
+                        TraitItem::Macro(item) => &item.attrs,
+                        _ => &[],
+                    };
+                    record(attrs, found);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Records the attributes of an item if it is gated, at the line following its attributes
+fn record(attrs: &[Attribute], found: &mut Vec<(usize, Gate)>) {
+    if let Some(gate) = gate(attrs) {
+        let line = attrs
+            .iter()
+            .map(|attr| attr.span().end().line)
+            .max()
+            .unwrap_or(0);
+        found.push((line + 1, gate));
+    }
+}
+
+// The gate of an item's attributes, if they gate it
+fn gate(attrs: &[Attribute]) -> Option<Gate> {
+    let mut gate = Gate::default();
+    for attr in attrs {
+        let Some(name) = attr.path().segments.last().map(|s| s.ident.to_string()) else {
+            continue;
+        };
+        let tokens = match &attr.meta {
+            syn::Meta::List(list) => list.tokens.clone(),
+            _ => TokenStream::new(),
+        };
+
+        match name.as_str() {
+            "feature_scope" | "feature_scope_default" | "feature_scope_bench" => {
+                gate.stub |= has_stub_option(&tokens);
+                let (features, negated) = attribute_features(tokens);
+                gate.cfgs
+                    .extend(features.iter().map(|feature| cfg_name::scope_cfg(feature)));
+                gate.features.extend(features);
+                gate.negated.extend(negated);
+                gate.default |= name == "feature_scope_default";
+            }
+            "cfg" | "cfg_attr" => {
+                for cfg in scope_cfgs(tokens) {
+                    match cfg.as_str() {
+                        "__scope_default" => gate.default = true,
+                        _ => gate.cfgs.push(cfg),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Items gated only by negations count for their names, they match no scope
+    (gate.default || !gate.cfgs.is_empty() || !gate.negated.is_empty()).then_some(gate)
+}
+
+// Whether `stub` follows the predicate of a `#[feature_scope]` attribute
+fn has_stub_option(tokens: &TokenStream) -> bool {
+    let tokens: Vec<TokenTree> = tokens.clone().into_iter().collect();
+    tokens
+        .split(|token| matches!(token, TokenTree::Punct(punct) if punct.as_char() == ','))
+        .skip(1)
+        .any(|option| matches!(option, [TokenTree::Ident(ident)] if ident == "stub"))
+}
+
+// Reads the features of `#[feature_scope(a)]`, `#[feature_scope(feature = "a")]` or a predicate
+// like `#[feature_scope(all(a, not(b)))]`, with the negated ones apart
+fn attribute_features(tokens: TokenStream) -> (Vec<String>, Vec<String>) {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+
+    let named = tokens.windows(3).find_map(|window| match window {
+        [TokenTree::Ident(key), TokenTree::Punct(eq), TokenTree::Literal(value)]
+            if key == "feature" && eq.as_char() == '=' =>
+        {
+            syn::parse2::<syn::LitStr>(TokenTree::Literal(value.clone()).into())
+                .ok()
+                .map(|value| value.value())
+        }
+        _ => None,
+    });
+    if let Some(feature) = named {
+        return (vec![feature], Vec::new());
+    }
+
+    let (mut features, mut negated) = (Vec::new(), Vec::new());
+    collect_predicate_features(&tokens, true, &mut features, &mut negated);
+    (features, negated)
+}
+
+fn collect_predicate_features(
+    tokens: &[TokenTree],
+    top_level: bool,
+    features: &mut Vec<String>,
+    negated: &mut Vec<String>,
+) {
+    let mut index = 0;
+    while let Some(token) = tokens.get(index) {
+        match (token, tokens.get(index + 1)) {
+            (TokenTree::Ident(ident), Some(TokenTree::Group(group))) => {
+                let operands: Vec<TokenTree> = group.stream().into_iter().collect();
+                if ident == "not" {
+                    // Everything below a negation is negated, whatever operators follow
+                    let mut below = Vec::new();
+                    collect_predicate_features(&operands, false, &mut below, negated);
+                    negated.append(&mut below);
+                } else {
+                    collect_predicate_features(&operands, false, features, negated);
+                }
+                index += 2;
+            }
+            (TokenTree::Ident(ident), _) => {
+                features.push(ident.to_string().trim_start_matches("r#").to_string());
+                index += 1;
+            }
+            // Options follow the predicate after a comma at the top level
+            (TokenTree::Punct(punct), _) if punct.as_char() == ',' && top_level => break,
+            _ => index += 1,
+        }
+    }
+}
+
+// Finds the `__scope_*` cfgs a `cfg` predicate mentions
+fn scope_cfgs(tokens: TokenStream) -> Vec<String> {
+    let mut cfgs = Vec::new();
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => {
+                let ident = ident.to_string();
+                if ident.starts_with("__scope_") {
+                    cfgs.push(ident);
+                }
+            }
+            TokenTree::Group(group) => cfgs.extend(scope_cfgs(group.stream())),
+            _ => {}
+        }
+    }
+    cfgs
+}
+
+// The first line of the item itself, which is usually enough to recognize it
+fn signature(lines: &[&str], line: usize) -> String {
+    lines
+        .iter()
+        .skip(line.saturating_sub(1))
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with("//"))
+        .unwrap_or_default()
+        .trim_end_matches('{')
+        .trim_end()
+        .to_string()
+}
@@ -0,0 +1,179 @@
+//! `unexpected_cfgs` lint configuration for builds without the wrapper.
+//!
+//! `cargo feature-scope lints` writes the cfg names of the declared scopes to the
+//! `check-cfg` list of `[lints.rust] unexpected_cfgs` in every member's manifest, so a plain
+//! `cargo build` does not warn about the scopes gating code. `--workspace` writes them once to
+//! `[workspace.lints.rust]` of the root manifest instead and makes the members without lints of
+//! their own inherit them, while members with their own lints still get the cfgs written to
+//! them. Entries of the list that are not scope cfgs are kept.
+
+use anyhow::{Context, Result};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+use toml_edit::{Array, DocumentMut, InlineTable, Item, Table, TableLike, Value};
+
+use crate::CargoToml;
+
+// The prefix of every scope cfg, see `feature_scope_core::cfg_name`
+const SCOPE_CFG_PREFIX: &str = "cfg(__scope_";
+
+pub fn run(root_cargo_toml: &CargoToml, root_manifest_path: &Path, args: &[String]) -> Result<()> {
+    let workspace = match args {
+        [] => false,
+        [flag] if flag == "--workspace" => true,
+        _ => anyhow::bail!("Usage: cargo feature-scope lints [--workspace]"),
+    };
+    if workspace && root_cargo_toml.workspace.is_none() {
+        anyhow::bail!("--workspace needs a root manifest with a [workspace] table");
+    }
+
+    let mut manifests = crate::workspace_member_manifests(root_cargo_toml, root_manifest_path)?;
+    if root_cargo_toml.package.is_some() {
+        manifests.insert(0, root_manifest_path.to_path_buf());
+    }
+
+    let mut members: Vec<(PathBuf, BTreeSet<String>)> = Vec::new();
+    for manifest_path in manifests {
+        let package = crate::read_package(&manifest_path)?;
+        let (_, check_cfg_args) =
+            crate::resolve_cfg_args(root_cargo_toml, root_manifest_path, &package.name)
+                .with_context(|| format!("Failed to resolve the scopes of '{}'", package.name))?;
+        let check_cfgs = check_cfg_args
+            .into_iter()
+            .filter(|arg| arg != "--check-cfg")
+            .collect();
+        members.push((manifest_path, check_cfgs));
+    }
+
+    if !workspace {
+        for (manifest_path, check_cfgs) in &members {
+            let mut document = read_document(manifest_path)?;
+            if inherits_workspace_lints(&document) {
+                println!(
+                    "⚠️  {} inherits the workspace lints, use --workspace to configure them",
+                    manifest_path.display()
+                );
+                continue;
+            }
+            let rust = table_at(&mut document, &["lints", "rust"])?;
+            set_check_cfgs(rust, check_cfgs);
+            write_document(manifest_path, &document)?;
+        }
+        return Ok(());
+    }
+
+    let all_check_cfgs: BTreeSet<String> = members
+        .iter()
+        .flat_map(|(_, check_cfgs)| check_cfgs.iter().cloned())
+        .collect();
+    let mut root_document = read_document(root_manifest_path)?;
+    let rust = table_at(&mut root_document, &["workspace", "lints", "rust"])?;
+    set_check_cfgs(rust, &all_check_cfgs);
+
+    for (manifest_path, check_cfgs) in &members {
+        // The root package is written together with the workspace table
+        let is_root = manifest_path == root_manifest_path;
+        let mut member_document = if is_root {
+            None
+        } else {
+            Some(read_document(manifest_path)?)
+        };
+        let document = member_document.as_mut().unwrap_or(&mut root_document);
+        if inherits_workspace_lints(document) {
+            continue;
+        }
+
+        // Cargo does not combine inherited lints with a member's own, so those get the cfgs too
+        if document.contains_key("lints") {
+            let rust = table_at(document, &["lints", "rust"])?;
+            set_check_cfgs(rust, check_cfgs);
+        } else {
+            let mut lints = Table::new();
+            lints.insert("workspace", toml_edit::value(true));
+            document.insert("lints", Item::Table(lints));
+        }
+        if let Some(document) = &member_document {
+            write_document(manifest_path, document)?;
+        }
+    }
+    write_document(root_manifest_path, &root_document)
+}
+
+/// Reads a manifest for editing, keeping its formatting and comments.
+pub fn read_document(manifest_path: &Path) -> Result<DocumentMut> {
+    let content = fs::read_to_string(manifest_path)
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+    content
+        .parse()
+        .with_context(|| format!("Failed to parse {}", manifest_path.display()))
+}
+
+/// Writes an edited manifest back. Unchanged manifests are left alone, so they keep their
+/// modification time.
+pub fn write_document(manifest_path: &Path, document: &DocumentMut) -> Result<()> {
+    let content = document.to_string();
+    if fs::read_to_string(manifest_path).is_ok_and(|old| old == content) {
+        println!("{} is up to date", manifest_path.display());
+        return Ok(());
+    }
+    fs::write(manifest_path, content)
+        .with_context(|| format!("Failed to write {}", manifest_path.display()))?;
+    println!("Updated {}", manifest_path.display());
+    Ok(())
+}
+
+fn inherits_workspace_lints(document: &DocumentMut) -> bool {
+    document
+        .get("lints")
+        .and_then(|lints| lints.get("workspace"))
+        .and_then(Item::as_bool)
+        .unwrap_or(false)
+}
+
+// The table at the path, created as needed
+fn table_at<'a>(document: &'a mut DocumentMut, path: &[&str]) -> Result<&'a mut dyn TableLike> {
+    let mut table: &mut dyn TableLike = document.as_table_mut();
+    for (depth, key) in path.iter().enumerate() {
+        let item = table.entry(key).or_insert_with(|| {
+            let mut table = Table::new();
+            // `[lints.rust]` is written as a table of its own, the ones above it stay implicit
+            table.set_implicit(depth + 1 < path.len());
+            Item::Table(table)
+        });
+        table = item
+            .as_table_like_mut()
+            .with_context(|| format!("`{}` is not a table", path[..=depth].join(".")))?;
+    }
+    Ok(table)
+}
+
+// Replaces the scope cfgs of `unexpected_cfgs`, which may be just a level, keeping the others
+fn set_check_cfgs(rust: &mut dyn TableLike, check_cfgs: &BTreeSet<String>) {
+    let lint = rust
+        .entry("unexpected_cfgs")
+        .or_insert_with(|| toml_edit::value("warn"));
+    if let Some(level) = lint.as_str().map(String::from) {
+        let mut table = InlineTable::new();
+        table.insert("level", Value::from(level));
+        *lint = toml_edit::value(table);
+    }
+    let Some(lint) = lint.as_table_like_mut() else {
+        return;
+    };
+
+    let mut kept: Vec<String> = lint
+        .get("check-cfg")
+        .and_then(Item::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|value| value.as_str())
+        .filter(|check_cfg| !check_cfg.starts_with(SCOPE_CFG_PREFIX))
+        .map(String::from)
+        .collect();
+    kept.extend(check_cfgs.iter().cloned());
+    let array: Array = kept.iter().collect();
+    lint.insert("check-cfg", toml_edit::value(array));
+}
@@ -0,0 +1,281 @@
+//! An overview of the declared scopes of a workspace.
+//!
+//! `cargo feature-scope list` prints every package with a `feature-scope-decl`, its features with
+//! the features they depend on and, through them, enable, its default features, exclusive
+//! groups and presets, and the consumers whose entries reference it. `--format json` prints the same as JSON
+//! for other tools.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::Path,
+};
+
+use feature_scope_core::manifest::FeatureScope;
+
+use crate::{CargoToml, Package};
+
+#[derive(Serialize)]
+struct ListedPackage {
+    name: String,
+    // Relative to the root manifest's directory
+    manifest: String,
+    default: Vec<String>,
+    features: Vec<ListedFeature>,
+    exclusive: Vec<Vec<String>>,
+    // Presets have no scope of their own, so they are kept apart from the features
+    presets: BTreeMap<String, Vec<String>>,
+    consumers: Vec<Consumer>,
+}
+
+#[derive(Serialize)]
+struct ListedFeature {
+    name: String,
+    requires: Vec<String>,
+    // Every feature enabling this one enables, through its dependencies
+    enables: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    doc: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    conflicts: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deprecated: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    platform: Option<String>,
+}
+
+// One feature-scope entry referencing the package
+#[derive(Serialize)]
+struct Consumer {
+    name: String,
+    features: Vec<String>,
+    default_features: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    platform: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<String>,
+}
+
+enum Format {
+    Text,
+    Json,
+}
+
+pub fn run(root_cargo_toml: &CargoToml, root_manifest_path: &Path, args: &[String]) -> Result<()> {
+    let format = match args {
+        [] => "text",
+        [flag, format] if flag == "--format" => format.as_str(),
+        [flag] if flag.starts_with("--format=") => &flag["--format=".len()..],
+        _ => anyhow::bail!("Usage: cargo feature-scope list [--format text|json]"),
+    };
+    let format = match format {
+        "text" => Format::Text,
+        "json" => Format::Json,
+        _ => anyhow::bail!("Unknown format '{format}', expected 'text' or 'json'"),
+    };
+
+    let mut manifests = crate::workspace_member_manifests(root_cargo_toml, root_manifest_path)?;
+    if root_cargo_toml.package.is_some() {
+        manifests.insert(0, root_manifest_path.to_path_buf());
+    }
+    let root_dir = root_manifest_path.parent().unwrap();
+    let mut members = Vec::new();
+    for manifest_path in manifests {
+        let package = crate::read_package(&manifest_path)?;
+        members.push((manifest_path, package));
+    }
+
+    let mut consumers: BTreeMap<String, Vec<Consumer>> = BTreeMap::new();
+    for (manifest_path, package) in &members {
+        let entries = package
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.feature_scope.as_ref());
+        for entry in entries.into_iter().flatten() {
+            let Some(library) = entry_library(manifest_path, package, entry) else {
+                continue;
+            };
+            consumers.entry(library).or_default().push(Consumer {
+                name: package.name.clone(),
+                features: entry.selection(),
+                default_features: entry.default_features.unwrap_or(true),
+                target: entry.target.clone(),
+                platform: entry.platform.clone(),
+                profile: entry.profile.clone(),
+            });
+        }
+    }
+
+    let mut listed = Vec::new();
+    for (manifest_path, package) in &members {
+        let Some(decl) = package
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.feature_scope_decl.as_ref())
+        else {
+            continue;
+        };
+
+        let mut names: Vec<&String> = decl.features.keys().collect();
+        names.sort();
+        let features = names
+            .into_iter()
+            .map(|name| {
+                let mut enabled = HashSet::new();
+                crate::resolve_requested_feature(name, decl, &mut enabled);
+                enabled.remove(name);
+                let mut enables: Vec<String> = enabled.into_iter().collect();
+                enables.sort();
+
+                let details = decl.details.get(name).cloned().unwrap_or_default();
+                ListedFeature {
+                    name: name.clone(),
+                    requires: decl.features[name].clone(),
+                    enables,
+                    doc: details.doc,
+                    conflicts: details.conflicts,
+                    deprecated: details.deprecated,
+                    platform: details.platform,
+                }
+            })
+            .collect();
+
+        let manifest = manifest_path
+            .strip_prefix(root_dir)
+            .unwrap_or(manifest_path);
+        listed.push(ListedPackage {
+            name: package.name.clone(),
+            manifest: manifest.display().to_string(),
+            default: decl.default.clone().unwrap_or_default(),
+            features,
+            exclusive: decl.exclusive.clone().unwrap_or_default(),
+            presets: decl.presets.clone().into_iter().collect(),
+            consumers: consumers.remove(&package.name).unwrap_or_default(),
+        });
+    }
+    listed.sort_by(|a, b| a.name.cmp(&b.name));
+
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&listed)?),
+        Format::Text if listed.is_empty() => println!("No package declares any scopes"),
+        Format::Text => print_text(&listed),
+    }
+    Ok(())
+}
+
+fn print_text(listed: &[ListedPackage]) {
+    for (i, package) in listed.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{} ({})", package.name, package.manifest);
+        println!("  default: {}", or_none(&package.default));
+
+        println!("  features:");
+        let width = package
+            .features
+            .iter()
+            .map(|feature| feature.name.chars().count())
+            .max()
+            .unwrap_or(0);
+        for feature in &package.features {
+            let mut line = format!("    {:width$}", feature.name);
+            if !feature.requires.is_empty() {
+                line.push_str(&format!(" -> {}", feature.requires.join(", ")));
+            }
+            // Only mention the transitive features when they are not all listed already
+            if feature.enables.len() > feature.requires.len() {
+                line.push_str(&format!(" (enables {})", feature.enables.join(", ")));
+            }
+            if !feature.conflicts.is_empty() {
+                line.push_str(&format!(
+                    ", conflicts with {}",
+                    feature.conflicts.join(", ")
+                ));
+            }
+            if feature.deprecated.is_some() {
+                line.push_str(", deprecated");
+            }
+            if let Some(platform) = &feature.platform {
+                line.push_str(&format!(", {platform} only"));
+            }
+            if let Some(doc) = &feature.doc {
+                line.push_str(&format!(": {doc}"));
+            }
+            println!("{}", line.trim_end());
+        }
+
+        for group in &package.exclusive {
+            println!("  exclusive: {}", group.join(" | "));
+        }
+        for (preset, features) in &package.presets {
+            println!("  preset: {preset} = {}", features.join(", "));
+        }
+
+        if package.consumers.is_empty() {
+            println!("  used by: none");
+            continue;
+        }
+        println!("  used by:");
+        for consumer in &package.consumers {
+            let defaults = if consumer.default_features {
+                "defaults"
+            } else {
+                "no defaults"
+            };
+            let target: String = consumer
+                .target
+                .iter()
+                .chain(&consumer.platform)
+                .map(|only| format!(", {only} only"))
+                .chain(
+                    consumer
+                        .profile
+                        .iter()
+                        .map(|profile| format!(", {profile} profile only")),
+                )
+                .collect();
+            println!(
+                "    {}: {} ({defaults}{target})",
+                consumer.name,
+                or_none(&consumer.features)
+            );
+        }
+    }
+}
+
+fn or_none(features: &[String]) -> String {
+    if features.is_empty() {
+        String::from("none")
+    } else {
+        features.join(", ")
+    }
+}
+
+/// Returns the name of the library a consumer's entry references, or `None` if its path does
+/// not lead to a package. Entries without `package` and `path` select the consumer's own scopes.
+pub fn entry_library(
+    consumer_manifest: &Path,
+    consumer: &Package,
+    entry: &FeatureScope,
+) -> Option<String> {
+    let path = match (&entry.package, &entry.path) {
+        (Some(library), _) => return Some(library.clone()),
+        (None, Some(path)) => path,
+        (None, None) => return Some(consumer.name.clone()),
+    };
+
+    // Entry paths name the package directory or its manifest, relative to the consumer's manifest
+    let path = consumer_manifest.parent().unwrap().join(path);
+    let manifest = if path.is_dir() {
+        path.join("Cargo.toml")
+    } else {
+        path
+    };
+    crate::read_package(&manifest)
+        .ok()
+        .map(|library| library.name)
+}
@@ -0,0 +1,205 @@
+//! The scope lock, a snapshot of the resolved scopes of every workspace member.
+//!
+//! `feature-scope.lock` next to the root manifest records the enabled cfgs and the fingerprint
+//! of each package, with what enabled each scope, so that a build elsewhere, e.g. from vendored
+//! sources, can be compared with the configuration it was frozen with, and so that changes to
+//! the resolution show up in reviews. `cargo feature-scope update` writes it, and commands given
+//! cargo's `--locked` or `--frozen` fail when resolving anew would change it.
+
+use anyhow::{Context, Result};
+use feature_scope_core::Overrides;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{fingerprint, progress::Progress, CargoToml};
+
+pub const FILE_NAME: &str = "feature-scope.lock";
+
+const VERSION: u32 = 2;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Lock {
+    version: u32,
+    package: Vec<LockedPackage>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct LockedPackage {
+    name: String,
+    fingerprint: String,
+    cfgs: Vec<String>,
+    // The enabled features of the libraries, missing in version 1
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    scope: Vec<LockedScope>,
+}
+
+// An enabled feature, with what enabled it
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct LockedScope {
+    library: String,
+    feature: String,
+    #[serde(rename = "enabled-by")]
+    enabled_by: Vec<String>,
+}
+
+pub fn run(root_cargo_toml: &CargoToml, root_manifest_path: &Path) -> Result<()> {
+    let path = write(root_cargo_toml, root_manifest_path)?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// Resolves every package and writes the lock file, returning its path.
+pub fn write(root_cargo_toml: &CargoToml, root_manifest_path: &Path) -> Result<PathBuf> {
+    let lock = resolve(root_cargo_toml, root_manifest_path)?;
+    let content = format!(
+        "# This file is generated by cargo feature-scope, do not edit it by hand.\n{}",
+        toml::to_string(&lock).context("Failed to serialize the scope lock")?
+    );
+
+    let path = lock_path(root_manifest_path);
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Fails unless the lock file exists and resolving every package anew gives the same lock.
+pub fn check(root_cargo_toml: &CargoToml, root_manifest_path: &Path) -> Result<()> {
+    let path = lock_path(root_manifest_path);
+    let update = "run `cargo feature-scope update` to write it";
+    let Ok(content) = fs::read_to_string(&path) else {
+        anyhow::bail!("--locked needs {}, {update}", path.display());
+    };
+    let locked: Lock =
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))?;
+    if locked.version != VERSION {
+        anyhow::bail!(
+            "{} was written by another version of cargo feature-scope, {update} again",
+            path.display()
+        );
+    }
+
+    let resolved = resolve(root_cargo_toml, root_manifest_path)?;
+    if resolved == locked {
+        return Ok(());
+    }
+    anyhow::bail!(
+        "The scopes resolved now differ from {}, as --locked forbids; {update} again:\n{}",
+        path.display(),
+        differences(&locked, &resolved).join("\n")
+    )
+}
+
+fn lock_path(root_manifest_path: &Path) -> PathBuf {
+    root_manifest_path.parent().unwrap().join(FILE_NAME)
+}
+
+// Resolves every package the way a build without overrides does
+fn resolve(root_cargo_toml: &CargoToml, root_manifest_path: &Path) -> Result<Lock> {
+    let names = fingerprint::package_names(root_cargo_toml, root_manifest_path)?;
+    let mut packages = Vec::new();
+    let mut progress = Progress::new("Resolving", names.len());
+    for name in names {
+        progress.step(&name);
+        let resolved = crate::resolve_scope_with(
+            root_cargo_toml,
+            root_manifest_path,
+            &name,
+            &Overrides::default(),
+        )?;
+
+        let mut cfgs: Vec<String> = resolved.enabled_cfgs().map(String::from).collect();
+        cfgs.sort();
+        cfgs.dedup();
+
+        let scope = resolved
+            .origins
+            .iter()
+            .flat_map(|(library, features)| {
+                features.iter().map(move |(feature, origins)| LockedScope {
+                    library: library.clone(),
+                    feature: feature.clone(),
+                    enabled_by: origins.iter().map(ToString::to_string).collect(),
+                })
+            })
+            .collect();
+
+        packages.push(LockedPackage {
+            name,
+            fingerprint: format!(
+                "{:016x}",
+                fingerprint::fingerprint_cfg_args(&resolved.cfg_args, &resolved.check_cfg_args)
+            ),
+            cfgs,
+            scope,
+        });
+    }
+
+    Ok(Lock {
+        version: VERSION,
+        package: packages,
+    })
+}
+
+// One line per package whose resolution changed, naming the scopes that changed
+fn differences(locked: &Lock, resolved: &Lock) -> Vec<String> {
+    let by_name = |lock: &Lock| -> BTreeMap<String, (Vec<String>, String)> {
+        lock.package
+            .iter()
+            .map(|package| {
+                let scopes = package
+                    .scope
+                    .iter()
+                    .map(|scope| {
+                        format!(
+                            "{}/{} (enabled by {})",
+                            scope.library,
+                            scope.feature,
+                            scope.enabled_by.join(", ")
+                        )
+                    })
+                    .collect();
+                (package.name.clone(), (scopes, package.fingerprint.clone()))
+            })
+            .collect()
+    };
+    let locked = by_name(locked);
+    let resolved = by_name(resolved);
+
+    let mut lines = Vec::new();
+    for name in locked
+        .keys()
+        .chain(resolved.keys().filter(|name| !locked.contains_key(*name)))
+    {
+        let line = match (locked.get(name), resolved.get(name)) {
+            (Some(_), None) => format!("  {name}: no longer a package of the workspace"),
+            (None, Some(_)) => format!("  {name}: not in the lock"),
+            (Some(before), Some(after)) if before != after => {
+                let changes: Vec<String> = before
+                    .0
+                    .iter()
+                    .filter(|scope| !after.0.contains(scope))
+                    .map(|scope| format!("-{scope}"))
+                    .chain(
+                        after
+                            .0
+                            .iter()
+                            .filter(|scope| !before.0.contains(scope))
+                            .map(|scope| format!("+{scope}")),
+                    )
+                    .collect();
+                if changes.is_empty() {
+                    // The same scopes with other check-cfgs, e.g. after declaring a feature
+                    format!("  {name}: fingerprint {} -> {}", before.1, after.1)
+                } else {
+                    format!("  {name}: {}", changes.join(", "))
+                }
+            }
+            _ => continue,
+        };
+        lines.push(line);
+    }
+    lines
+}
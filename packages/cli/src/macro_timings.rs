@@ -0,0 +1,141 @@
+//! Viewer of the macro expansion timings.
+//!
+//! Building with `FEATURE_SCOPE_MACRO_TIMING=1` makes the attribute macros record how long each
+//! expansion takes, in one file per crate, crate type and target, like `app-bin-host.tsv`.
+//! `cargo feature-scope macro-timings` adds them up per macro and prints the totals, the most
+//! expensive crates first.
+
+use anyhow::{Context, Result};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    bench::format_duration,
+    env_vars::{MACRO_TIMING, MACRO_TIMING_DIR},
+    target_dir,
+};
+
+struct Row {
+    crate_name: String,
+    macro_name: String,
+    count: u64,
+    total_ns: f64,
+    max_ns: f64,
+}
+
+/// Returns the directory the macros write their timings to for this workspace.
+pub fn timings_dir(root_manifest_path: &Path) -> PathBuf {
+    env::var_os(MACRO_TIMING_DIR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            target_dir::base_target_dir(root_manifest_path.parent().unwrap(), None)
+                .join("feature-scope")
+                .join("macro-timings")
+        })
+}
+
+pub fn run(root_manifest_path: &Path) -> Result<()> {
+    let dir = timings_dir(root_manifest_path);
+
+    let mut rows = Vec::new();
+    if let Ok(entries) = fs::read_dir(&dir) {
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "tsv") {
+                rows.extend(read_timings(&path)?);
+            }
+        }
+    }
+
+    if rows.is_empty() {
+        println!(
+            "No macro timings found in {}, build with {MACRO_TIMING}=1 to record them",
+            dir.display()
+        );
+        return Ok(());
+    }
+
+    rows.sort_by(|a, b| {
+        b.total_ns
+            .total_cmp(&a.total_ns)
+            .then_with(|| a.crate_name.cmp(&b.crate_name))
+    });
+
+    let mut table = vec![["Crate", "Macro", "Count", "Total", "Mean", "Max"].map(String::from)];
+    for row in &rows {
+        table.push([
+            row.crate_name.clone(),
+            row.macro_name.clone(),
+            row.count.to_string(),
+            format_duration(row.total_ns),
+            format_duration(row.total_ns / row.count.max(1) as f64),
+            format_duration(row.max_ns),
+        ]);
+    }
+
+    let widths: Vec<usize> = (0..6)
+        .map(|column| {
+            table
+                .iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    for row in &table {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+
+    let count: u64 = rows.iter().map(|row| row.count).sum();
+    let total: f64 = rows.iter().map(|row| row.total_ns).sum();
+    println!();
+    println!(
+        "{count} expansions took {} in total",
+        format_duration(total)
+    );
+
+    Ok(())
+}
+
+fn read_timings(path: &Path) -> Result<Vec<Row>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    // Crate names have no `-`, the crate types written by the macros neither
+    let crate_name = match stem.splitn(3, '-').collect::<Vec<_>>().as_slice() {
+        [name, crate_type, "host"] => format!("{name} ({crate_type})"),
+        [name, crate_type, target] => format!("{name} ({crate_type}, {target})"),
+        _ => stem.into_owned(),
+    };
+
+    let mut rows: Vec<Row> = Vec::new();
+    for line in content.lines().skip(1) {
+        let parse_error = || format!("Unexpected line in {}: {line}", path.display());
+        let (macro_name, ns) = line.split_once('\t').with_context(parse_error)?;
+        let ns: f64 = ns.parse().with_context(parse_error)?;
+
+        match rows.iter_mut().find(|row| row.macro_name == macro_name) {
+            Some(row) => {
+                row.count += 1;
+                row.total_ns += ns;
+                row.max_ns = row.max_ns.max(ns);
+            }
+            None => rows.push(Row {
+                crate_name: crate_name.clone(),
+                macro_name: macro_name.to_string(),
+                count: 1,
+                total_ns: ns,
+                max_ns: ns,
+            }),
+        }
+    }
+
+    Ok(rows)
+}
@@ -23,6 +23,15 @@
 //!
 //! # Run tests
 //! cargo feature-scope test
+//!
+//! # Cargo flags go before `--`, program arguments after it
+//! cargo feature-scope run --release -- --port 8080
+//!
+//! # Lint with the resolved scopes, other cargo commands work the same way
+//! cargo feature-scope clippy -- -D warnings
+//!
+//! # Also list every declared scope, or show the raw cfg flags with -vv
+//! cargo feature-scope build -v
 //! ```
 //!
 //! ## Installation
@@ -31,6 +40,16 @@
 //! cargo install cargo-feature-scope
 //! ```
 //!
+//! Cargo finds the installed binary for `cargo feature-scope`. It can also run on its own, as
+//! `cargo-feature-scope build`, with the same commands and options.
+//!
+//! Built with the `in-process` feature, `build` and `check` run through the cargo library inside
+//! the CLI instead of spawning the `cargo` binary:
+//!
+//! ```bash
+//! cargo install cargo-feature-scope --features in-process
+//! ```
+//!
 //! ## Configuration
 //!
 //! This tool works with a two-step configuration in your `Cargo.toml`:
@@ -50,110 +69,658 @@
 //! features = ["b"]
 //! default-features = false
 //! ```
+//!
+//! The README describes the rest of the configuration, from entries limited to one target,
+//! platform or profile to strict mode and variants, and every other command.
+//! `cargo feature-scope help <COMMAND>` lists the options of a command, in English or Simplified
+//! Chinese like the diagnostics, following `FEATURE_SCOPE_LANG` (`en` or `zh`) or else the locale.
+
+mod artifacts;
+mod audit;
+mod bench;
+mod cargo_args;
+mod cbindgen;
+mod conflict;
+mod diff;
+mod display;
+mod e2e;
+mod edit;
+mod explain;
+mod export;
+mod fingerprint;
+mod fuzz;
+mod history;
+mod i18n;
+mod ide_setup;
+#[cfg(feature = "in-process")]
+mod in_process;
+mod index;
+mod init;
+mod items;
+mod lints;
+mod list;
+mod lock;
+mod macro_timings;
+mod matrix;
+mod migrate;
+mod multi_package;
+mod nextest;
+mod pipeline;
+mod progress;
+mod rename;
+mod runner;
+mod rustflags;
+mod script;
+mod target_dir;
+mod template;
+mod unused;
+mod vcs;
+mod vendor;
+mod verify;
 
 use anyhow::{Context, Result};
-use clap::{Arg, ArgMatches, Command};
-use serde::Deserialize;
+use cargo_args::ForwardedArgs;
+use clap::{Arg, ArgGroup, ArgMatches, Command};
+use feature_scope_core::{
+    cfg_name, default_packages, determine_default_package, env_vars,
+    manifest::{
+        extract_package_name_from_manifest, find_root_manifest, load_manifest, read_package,
+        CargoToml, ConsumerConflicts, Package,
+    },
+    resolve_feature_selection, resolve_requested_feature,
+    workspace::workspace_member_manifests,
+    ConflictError, Diagnostic, Origin, Overrides, ResolvedScope, StrictError,
+};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::BTreeSet,
     env,
+    ffi::OsString,
+    iter,
     path::{Path, PathBuf},
     process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
 };
 
-#[derive(Debug, Deserialize)]
-struct CargoToml {
-    package: Option<Package>,
-    workspace: Option<Workspace>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Workspace {
-    members: Option<Vec<String>>,
-    #[serde(rename = "default-members")]
-    default_members: Option<Vec<String>>,
+// Everything resolved for one invocation, for commands that spawn cargo several times
+struct Invocation<'a> {
+    root_cargo_toml: &'a CargoToml,
+    root_manifest_path: &'a Path,
+    package: Option<&'a String>,
+    target_package: &'a str,
+    cfg_args: Vec<String>,
+    check_cfg_args: Vec<String>,
+    config_rustflags: Vec<String>,
+    additional_args: ForwardedArgs,
+    scope_names: display::ScopeNames,
+    verbose: u8,
 }
 
-#[derive(Debug, Deserialize)]
-struct Package {
-    name: String,
-    metadata: Option<Metadata>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Metadata {
-    #[serde(rename = "feature-scope-decl")]
-    feature_scope_decl: Option<FeatureScopeDecl>,
-    #[serde(rename = "feature-scope")]
-    feature_scope: Option<Vec<FeatureScope>>,
-}
+// The cargo commands with a subcommand of their own, other cargo commands still work unlisted
+const CARGO_COMMANDS: [&str; 7] = ["build", "check", "run", "test", "clippy", "doc", "bench"];
 
-#[derive(Debug, Deserialize)]
-struct FeatureScopeDecl {
-    default: Option<Vec<String>>,
-    #[serde(flatten)]
-    features: HashMap<String, Vec<String>>,
+fn main() -> process::ExitCode {
+    match run() {
+        Ok(()) => process::ExitCode::SUCCESS,
+        Err(err) => {
+            i18n::fail(&err);
+            process::ExitCode::FAILURE
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct FeatureScope {
-    package: String,
-    features: Vec<String>,
-    #[serde(rename = "default-features")]
-    default_features: Option<bool>,
-}
+fn run() -> Result<()> {
+    let tools = [
+        ("add", i18n::Help::Add),
+        ("audit", i18n::Help::Audit),
+        ("cbindgen", i18n::Help::Cbindgen),
+        ("diff", i18n::Help::Diff),
+        ("e2e", i18n::Help::E2e),
+        ("env", i18n::Help::Env),
+        ("exec", i18n::Help::Exec),
+        ("explain", i18n::Help::Explain),
+        ("export", i18n::Help::Export),
+        ("fingerprint", i18n::Help::Fingerprint),
+        ("fuzz", i18n::Help::Fuzz),
+        ("history", i18n::Help::History),
+        ("ide-setup", i18n::Help::IdeSetup),
+        ("index", i18n::Help::Index),
+        ("init", i18n::Help::Init),
+        ("items", i18n::Help::Items),
+        ("lints", i18n::Help::Lints),
+        ("list", i18n::Help::List),
+        ("macro-timings", i18n::Help::MacroTimings),
+        ("migrate", i18n::Help::Migrate),
+        ("nextest", i18n::Help::Nextest),
+        ("pipeline", i18n::Help::Pipeline),
+        ("profile", i18n::Help::Profile),
+        ("remove", i18n::Help::Remove),
+        ("rename-feature", i18n::Help::RenameFeature),
+        ("template", i18n::Help::Template),
+        ("unused", i18n::Help::Unused),
+        ("update", i18n::Help::Update),
+        ("vendor", i18n::Help::Vendor),
+        ("verify", i18n::Help::Verify),
+    ];
 
-fn main() -> Result<()> {
     let app = Command::new("cargo-feature-scope")
         .bin_name("cargo")
         .subcommand_required(true)
         .subcommand(
             Command::new("feature-scope")
-                .about("Cargo feature scope helper")
-                .arg(
-                    Arg::new("command")
-                        .help("Cargo command to run (build, check, run, test, etc.)")
-                        .required(true)
-                        .value_name("COMMAND"),
-                )
-                .arg(
-                    Arg::new("package")
-                        .short('p')
-                        .long("package")
-                        .help("Package to build")
-                        .value_name("SPEC"),
+                .about(i18n::Help::About.text())
+                .subcommand_required(true)
+                .allow_external_subcommands(true)
+                .subcommands(
+                    CARGO_COMMANDS
+                        .map(|name| scope_command(name).about(i18n::cargo_command_help(name))),
                 )
-                .arg(
-                    Arg::new("args")
-                        .help("Additional arguments to pass to cargo")
-                        .action(clap::ArgAction::Append)
-                        .trailing_var_arg(true),
-                ),
+                .subcommands(tools.map(|(name, help)| scope_command(name).about(help.text()))),
         );
 
-    let matches = app.get_matches();
+    let matches = app.get_matches_from(normalized_args());
 
     if let Some(feature_scope_matches) = matches.subcommand_matches("feature-scope") {
-        run_feature_scope(feature_scope_matches)?;
+        let (command, sub_matches) = feature_scope_matches.subcommand().unwrap();
+        let is_external =
+            !CARGO_COMMANDS.contains(&command) && !tools.iter().any(|(name, _)| *name == command);
+        // clap leaves the arguments of other cargo commands untouched, so parse them the same way
+        let external_matches;
+        let sub_matches = if is_external {
+            let args = sub_matches
+                .get_many::<OsString>("")
+                .unwrap_or_default()
+                .cloned();
+            external_matches = scope_command(command)
+                .get_matches_from(iter::once(OsString::from(command)).chain(args));
+            &external_matches
+        } else {
+            sub_matches
+        };
+
+        let args = forwarded_args(command, sub_matches);
+        run_feature_scope(command, sub_matches, args)?;
     }
 
     Ok(())
 }
 
-fn run_feature_scope(matches: &ArgMatches) -> Result<()> {
-    let command = matches.get_one::<String>("command").unwrap();
-    let package = matches.get_one::<String>("package");
-    let additional_args: Vec<&String> = matches.get_many("args").unwrap_or_default().collect();
+// A subcommand taking the options of feature-scope, followed by the arguments it forwards
+fn scope_command(name: &str) -> Command {
+    Command::new(name.to_string())
+        .arg(
+            Arg::new("package")
+                .short('p')
+                .long("package")
+                .help(i18n::Help::Package.text())
+                .value_name("SPEC")
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("features")
+                .short('F')
+                .long("features")
+                .help(i18n::Help::Features.text())
+                .value_name("FEATURES")
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("no-default-features")
+                .long("no-default-features")
+                .help(i18n::Help::NoDefaultFeatures.text())
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("exclude-host")
+                .long("exclude-host")
+                .help(i18n::Help::ExcludeHost.text())
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("infer")
+                .long("infer")
+                .help(i18n::Help::Infer.text())
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-probes")
+                .long("no-probes")
+                .help(i18n::Help::NoProbes.text())
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("variant")
+                .long("variant")
+                .help(i18n::Help::Variant.text())
+                .value_name("NAME"),
+        )
+        .arg(
+            Arg::new("artifact-dir")
+                .long("artifact-dir")
+                .help(i18n::Help::ArtifactDir.text())
+                .value_name("DIR")
+                .requires("variant"),
+        )
+        .arg(
+            Arg::new("vcs-check")
+                .long("vcs-check")
+                .help(i18n::Help::VcsCheck.text())
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("deny-warnings")
+                .long("deny-warnings")
+                .help(i18n::Help::DenyWarnings.text())
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("interactive")
+                .long("interactive")
+                .help(i18n::Help::Interactive.text())
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("resolution")
+                .long("resolution")
+                .help(i18n::Help::Resolution.text())
+                .value_name("POLICY")
+                .value_parser(["prefer-consumer", "prefer-library"])
+                .conflicts_with("interactive"),
+        )
+        .arg(
+            Arg::new("isolate-target-dir")
+                .long("isolate-target-dir")
+                .help(i18n::Help::IsolateTargetDir.text())
+                .conflicts_with_all(["variant", "compare-scopes"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("reuse-deps")
+                .long("reuse-deps")
+                .help(i18n::Help::ReuseDeps.text())
+                .requires("target-variant")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .group(ArgGroup::new("target-variant").args(["variant", "isolate-target-dir"]))
+        .arg(
+            Arg::new("compare-scopes")
+                .long("compare-scopes")
+                .help(i18n::Help::CompareScopes.text())
+                .value_name("SETS")
+                .conflicts_with("variant"),
+        )
+        .arg(
+            Arg::new("all-scopes")
+                .long("all-scopes")
+                .help(i18n::Help::AllScopes.text())
+                .conflicts_with_all([
+                    "variant",
+                    "compare-scopes",
+                    "features",
+                    "no-default-features",
+                ])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("feature-powerset")
+                .long("feature-powerset")
+                .help(i18n::Help::FeaturePowerset.text())
+                .requires("all-scopes")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("depth")
+                .long("depth")
+                .help(i18n::Help::Depth.text())
+                .value_name("NUM")
+                .value_parser(clap::value_parser!(usize))
+                .requires("feature-powerset"),
+        )
+        .arg(
+            Arg::new("no-run-cargo")
+                .long("no-run-cargo")
+                .help(i18n::Help::NoRunCargo.text())
+                .conflicts_with_all(["compare-scopes", "all-scopes"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("script-format")
+                .long("script-format")
+                .help(i18n::Help::ScriptFormat.text())
+                .value_name("FORMAT")
+                .value_parser(["sh", "bat"]),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help(i18n::Help::Verbose.text())
+                .action(clap::ArgAction::Count),
+        )
+        .arg(
+            Arg::new("args")
+                .help(i18n::Help::Args.text())
+                .action(clap::ArgAction::Append)
+                .num_args(0..)
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            Arg::new("program")
+                .help(i18n::Help::ProgramArgs.text())
+                .action(clap::ArgAction::Append)
+                .num_args(0..)
+                .allow_hyphen_values(true)
+                .last(true),
+        )
+}
 
-    // Get current directory and root Cargo.toml
-    let current_dir = env::current_dir()?;
-    let root_manifest_path = find_root_manifest(&current_dir)?;
+// clap puts everything after a `--` that directly follows the command or its options into
+// `program`, while a later `--` stays among `args`. Either way the separator is passed on, as
+// cargo needs it to tell its own flags from the ones of the program, except to `exec`, which
+// runs the program itself
+fn forwarded_args(command: &str, matches: &ArgMatches) -> Vec<String> {
+    let mut args: Vec<String> = matches
+        .get_many::<String>("args")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    if let Some(program) = matches.get_many::<String>("program") {
+        if command != "exec" {
+            args.push(String::from("--"));
+        }
+        args.extend(program.cloned());
+    }
+    args
+}
 
-    // Parse root Cargo.toml
-    let root_content = std::fs::read_to_string(&root_manifest_path)
-        .with_context(|| format!("Failed to read {}", root_manifest_path.display()))?;
-    let root_cargo_toml: CargoToml =
-        toml::from_str(&root_content).with_context(|| "Failed to parse root Cargo.toml")?;
+// Cargo runs the binary as `cargo-feature-scope feature-scope <COMMAND>`, while running it
+// directly gives `cargo-feature-scope <COMMAND>`, so the name of the subcommand is added back
+fn normalized_args() -> Vec<OsString> {
+    let mut args: Vec<OsString> = env::args_os().collect();
+    if args.get(1).is_none_or(|arg| arg != "feature-scope") {
+        args.insert(1.min(args.len()), OsString::from("feature-scope"));
+    }
+    args
+}
+
+fn run_feature_scope(command: &str, matches: &ArgMatches, args: Vec<String>) -> Result<()> {
+    let packages: Vec<String> = matches
+        .get_many::<String>("package")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let package = packages.first().cloned();
+    let variant = matches.get_one::<String>("variant");
+    let artifact_dir = matches.get_one::<String>("artifact-dir").map(PathBuf::from);
+    let reuse_deps = matches.get_flag("reuse-deps");
+    let compare_scopes = matches.get_one::<String>("compare-scopes");
+    let verbose = matches.get_count("verbose");
+
+    // Templates are usually emitted before there is any manifest, fuzzing brings its own
+    match command {
+        "fuzz" => return fuzz::run(&args),
+        "template" => return template::run(&args),
+        _ => {}
+    }
+    progress::hide_for_message_format(&args);
+
+    // Inference changes how every manifest reads, so it is turned on before the first one
+    let infer = matches.get_flag("infer")
+        || (command != "exec"
+            && args
+                .iter()
+                .take_while(|arg| *arg != "--")
+                .any(|arg| arg == "--infer"));
+    if infer {
+        env::set_var(env_vars::INFER, "1");
+        i18n::warn(i18n::Message::InferenceMode);
+    }
+
+    // Get root Cargo.toml
+    let (root_manifest_path, root_cargo_toml) = read_root_manifest()?;
+
+    // Let the macros write their timings next to the build output, for every spawned process
+    if env::var_os(env_vars::MACRO_TIMING).is_some()
+        && env::var_os(env_vars::MACRO_TIMING_DIR).is_none()
+    {
+        env::set_var(
+            env_vars::MACRO_TIMING_DIR,
+            macro_timings::timings_dir(&root_manifest_path),
+        );
+    }
+
+    match command {
+        "add" => {
+            return edit::add(
+                &root_cargo_toml,
+                &root_manifest_path,
+                package.as_ref(),
+                &args,
+            )
+        }
+        "audit" => return audit::run(&root_cargo_toml, &root_manifest_path),
+        "e2e" => return e2e::run(&root_cargo_toml, &root_manifest_path),
+        "export" => {
+            return export::run(
+                &root_cargo_toml,
+                &root_manifest_path,
+                package.as_ref(),
+                &args,
+            )
+        }
+        "fingerprint" => return fingerprint::run(&root_cargo_toml, &root_manifest_path, &packages),
+        "history" => return history::run(&root_manifest_path, package.as_ref(), &args),
+        "ide-setup" => {
+            return ide_setup::run(&root_cargo_toml, &root_manifest_path, &packages, &args)
+        }
+        "index" => return index::run(&root_manifest_path, &args),
+        "init" => {
+            return init::run(
+                &root_cargo_toml,
+                &root_manifest_path,
+                package.as_ref(),
+                &args,
+            )
+        }
+        "items" => return items::run(&root_cargo_toml, &root_manifest_path, &args),
+        "lints" => return lints::run(&root_cargo_toml, &root_manifest_path, &args),
+        "list" => return list::run(&root_cargo_toml, &root_manifest_path, &args),
+        "macro-timings" => return macro_timings::run(&root_manifest_path),
+        "migrate" => {
+            return migrate::run(
+                &root_cargo_toml,
+                &root_manifest_path,
+                package.as_ref(),
+                &args,
+            )
+        }
+        "remove" => {
+            return edit::remove(
+                &root_cargo_toml,
+                &root_manifest_path,
+                package.as_ref(),
+                &args,
+            )
+        }
+        "rename-feature" => return rename::run(&root_cargo_toml, &root_manifest_path, &args),
+        "unused" => return unused::run(&root_cargo_toml, &root_manifest_path, &packages),
+        "update" => return lock::run(&root_cargo_toml, &root_manifest_path),
+        "vendor" => return vendor::run(&root_cargo_toml, &root_manifest_path, &args),
+        "verify" => return verify::run(&root_cargo_toml, &root_manifest_path, &args),
+        _ => {}
+    }
+
+    // `exec` hands all of its arguments to the program, other commands forward them to cargo
+    let (program_args, mut additional_args) = if command == "exec" {
+        (args, ForwardedArgs::default())
+    } else {
+        (Vec::new(), ForwardedArgs::parse(args))
+    };
+    // Further packages go to cargo, which builds them together with the first one
+    for other in packages.iter().skip(1).cloned() {
+        additional_args
+            .cargo
+            .extend([String::from("--package"), other]);
+    }
+    let package = package.or_else(|| additional_args.take_package());
+    let package = package.as_ref();
+
+    // Cargo keeps the rustflags off build scripts and proc macros when given a `--target`
+    if matches.get_flag("exclude-host") | additional_args.take_flag("--exclude-host") {
+        if matches!(command, "exec" | "env" | "cbindgen") {
+            anyhow::bail!("--exclude-host needs a cargo command, which {command} does not run");
+        }
+        if additional_args.option_values(None, "--target").is_empty() {
+            additional_args
+                .cargo
+                .splice(0..0, [String::from("--target"), runner::host_triple()?]);
+        }
+    }
+
+    // `profile` is `run` with a profiler as the runner, `nextest` takes its subcommand along
+    let nextest_command;
+    let mut runner = additional_args.take_option(None, "--runner");
+    let command = if command == "profile" {
+        if runner.is_some() {
+            anyhow::bail!("profile takes its runner from --profiler, not --runner");
+        }
+        let profiler = additional_args
+            .take_option(None, "--profiler")
+            .unwrap_or_else(|| String::from("perf"));
+        runner = Some(runner::profiler_runner(&profiler));
+        let has_profile = additional_args.cargo.iter().any(|arg| {
+            arg == "--release" || arg == "-r" || arg == "--profile" || arg.starts_with("--profile=")
+        });
+        if !has_profile {
+            additional_args.cargo.insert(0, String::from("--release"));
+        }
+        "run"
+    } else if command == "nextest" {
+        nextest_command = nextest::take_command(&mut additional_args);
+        nextest_command.as_str()
+    } else {
+        command
+    };
+    if let Some(runner) = runner {
+        if !matches!(command, "run" | "test" | "bench") {
+            anyhow::bail!("--runner is only supported by the run, test and bench commands");
+        }
+        let config_args = runner::config_args(&runner, &additional_args)?;
+        additional_args.cargo.splice(0..0, config_args);
+    }
+    if artifact_dir.is_some() && command != "build" {
+        anyhow::bail!("--artifact-dir is only supported by the build command");
+    }
+
+    // Like cargo, several features may be given at once, separated by commas or spaces
+    let mut features: Vec<String> = matches
+        .get_many::<String>("features")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    while let Some(value) = additional_args.take_option(Some('F'), "--features") {
+        features.push(value);
+    }
+    let options = root_cargo_toml.options();
+    let variant_options = variant.and_then(|variant| options.variants.get(variant));
+    if let Some(variant_options) = variant_options {
+        features.extend(variant_options.features.iter().cloned());
+    }
+    let overrides = Overrides {
+        features: features
+            .iter()
+            .flat_map(|value| value.split([',', ' ']))
+            .filter(|feature| !feature.is_empty())
+            .map(String::from)
+            .collect(),
+        no_default_features: matches.get_flag("no-default-features")
+            | additional_args.take_flag("--no-default-features"),
+        targets: additional_args.selected_targets(),
+        // nextest builds the targets of `cargo test`
+        command: Some(String::from(if command.starts_with("nextest ") {
+            "test"
+        } else {
+            command
+        })),
+        no_probes: matches.get_flag("no-probes") | additional_args.take_flag("--no-probes"),
+        target: additional_args
+            .option_values(None, "--target")
+            .into_iter()
+            .next(),
+        profiles: build_profiles(&root_cargo_toml, command, &additional_args),
+    };
+    // The profile settings of the variant apply to the cargo command being run
+    if let Some(variant_options) = variant_options {
+        if !variant_options.profile.is_empty() && !matches!(command, "env" | "exec") {
+            let config_args =
+                target_dir::profile_config_args(&overrides.profiles[0], &variant_options.profile);
+            additional_args.cargo.splice(0..0, config_args);
+        }
+    }
+
+    // Already turned on before reading the manifests
+    additional_args.take_flag("--infer");
+
+    let no_run_cargo =
+        matches.get_flag("no-run-cargo") | additional_args.take_flag("--no-run-cargo");
+    let script_format = matches
+        .get_one::<String>("script-format")
+        .cloned()
+        .or_else(|| additional_args.take_option(None, "--script-format"));
+    let format = match script_format.as_deref() {
+        Some("sh") => script::Format::Sh,
+        Some("bat") => script::Format::Bat,
+        Some(other) => anyhow::bail!("Unknown script format '{other}', expected `sh` or `bat`"),
+        None => script::Format::native(),
+    };
+    // `env` prints the environment in the format whether or not it runs cargo
+    let script_format = no_run_cargo.then_some(format);
+
+    if matches.get_flag("deny-warnings") | additional_args.take_flag("--deny-warnings") {
+        DENY_WARNINGS.store(true, Ordering::Relaxed);
+    }
+
+    let interactive = matches.get_flag("interactive") | additional_args.take_flag("--interactive");
+    let resolution = matches
+        .get_one::<String>("resolution")
+        .cloned()
+        .or_else(|| additional_args.take_option(None, "--resolution"));
+    let conflict_mode = match resolution {
+        Some(policy) => Some(conflict::Mode::parse(&policy)?),
+        None => interactive.then_some(conflict::Mode::Interactive),
+    };
+    if let Some(mode) = conflict_mode {
+        let _ = CONFLICT_MODE.set(mode);
+    }
+
+    if matches.get_flag("vcs-check") | additional_args.take_flag("--vcs-check") {
+        vcs::check(&root_cargo_toml, &root_manifest_path)?;
+    }
+    // Cargo takes these flags too, for its own lock file
+    let locked = additional_args
+        .cargo
+        .iter()
+        .any(|arg| arg == "--locked" || arg == "--frozen");
+    if locked {
+        lock::check(&root_cargo_toml, &root_manifest_path)?;
+    }
+
+    if command == "diff" {
+        let mut selected = packages.clone();
+        selected.extend(
+            package
+                .filter(|package| !packages.contains(package))
+                .cloned(),
+        );
+        return diff::run(
+            &root_manifest_path,
+            &selected,
+            &overrides,
+            &additional_args.cargo,
+        );
+    }
 
     // Determine target package
     let target_package_name = if let Some(pkg) = package {
@@ -163,394 +730,657 @@ fn run_feature_scope(matches: &ArgMatches) -> Result<()> {
         determine_default_package(&root_cargo_toml, &root_manifest_path)?
     };
 
-    // Check if it's a workspace
-    let (cfg_args, check_cfg_args) = if root_cargo_toml.workspace.is_some() {
-        // Workspace mode
-        handle_workspace_package(&root_cargo_toml, &root_manifest_path, &target_package_name)?
+    if command == "explain" {
+        if packages.len() > 1 {
+            anyhow::bail!("explain resolves a single consumer, but several -p were given");
+        }
+        return explain::run(
+            &root_cargo_toml,
+            &root_manifest_path,
+            &target_package_name,
+            &overrides,
+            &additional_args.cargo,
+        );
+    }
+
+    // Cargo builds every package of `--workspace`, several `-p` or its default members with the
+    // same RUSTFLAGS
+    let targets = multi_package::targets(
+        &root_cargo_toml,
+        &root_manifest_path,
+        package,
+        &additional_args,
+    )?;
+    if command == "cbindgen" && targets.len() > 1 {
+        anyhow::bail!(
+            "cbindgen generates the header of a single package, but {} were selected, select one with -p",
+            targets.join(", ")
+        );
+    }
+    let target_package_name = match targets.as_slice() {
+        [only] => only.clone(),
+        _ => target_package_name,
+    };
+    let policy = root_cargo_toml.options().consumer_conflicts;
+    let merged = match targets.len() {
+        0 | 1 => None,
+        _ if policy == ConsumerConflicts::PerTargetIsolation => None,
+        _ => multi_package::merge(
+            &root_cargo_toml,
+            &root_manifest_path,
+            &targets,
+            &overrides,
+            policy,
+        )?,
+    };
+    if targets.len() > 1 && merged.is_none() {
+        // The program or the steps of the pipeline run once, with one set of scopes
+        if command == "exec" || command == "env" || command == "pipeline" {
+            anyhow::bail!(
+                "{command} needs one configuration for all packages, but {} resolve different scopes, select one with -p",
+                targets.join(", ")
+            );
+        }
+        if script_format.is_some() {
+            anyhow::bail!(
+                "--no-run-cargo needs a single build, but the packages are built one by one"
+            );
+        }
+        if artifact_dir.is_some() {
+            anyhow::bail!(
+                "--artifact-dir needs a single build, but the packages are built one by one"
+            );
+        }
+        if command == "nextest archive" && nextest::archive_file(&additional_args).is_some() {
+            anyhow::bail!(
+                "--archive-file needs a single build, but the packages are built one by one"
+            );
+        }
+        return multi_package::run_isolated(
+            &root_cargo_toml,
+            &root_manifest_path,
+            command,
+            &targets,
+            &overrides,
+            additional_args,
+            verbose,
+        );
+    }
+
+    let (cfg_args, check_cfg_args, cargo_features) = match merged {
+        Some(merged) => merged,
+        None => {
+            let resolved = resolve_scope_with(
+                &root_cargo_toml,
+                &root_manifest_path,
+                &target_package_name,
+                &overrides,
+            )?;
+            (
+                resolved.cfg_args,
+                resolved.check_cfg_args,
+                resolved.cargo_features,
+            )
+        }
+    };
+    // The optional dependencies of the scopes only exist for cargo, not for programs run directly
+    if !matches!(command, "env" | "exec" | "cbindgen") {
+        additional_args
+            .cargo
+            .extend(cargo_feature_args(&cargo_features));
+    }
+
+    // Give the variant, or the resolved cfg set with --isolate-target-dir, its own target
+    // directory so it does not clobber other scope sets
+    let isolate_target_dir =
+        matches.get_flag("isolate-target-dir") | additional_args.take_flag("--isolate-target-dir");
+    let variants_base = if variant.is_some() || isolate_target_dir {
+        let explicit_target_dir = target_dir::take_target_dir_arg(&mut additional_args.cargo);
+        Some(target_dir::base_target_dir(
+            root_manifest_path.parent().unwrap(),
+            explicit_target_dir,
+        ))
     } else {
-        // Single package mode
-        handle_single_package(&root_cargo_toml)?
+        None
+    };
+    let target_dir = match (variant, &variants_base) {
+        (Some(variant), Some(base)) => Some(target_dir::prepare_variant_target_dir(
+            base, variant, reuse_deps,
+        )?),
+        (None, Some(base)) => Some(target_dir::prepare_isolated_target_dir(
+            base,
+            &cfg_args,
+            &check_cfg_args,
+            reuse_deps,
+        )?),
+        _ => None,
+    };
+
+    let scope_names = display::ScopeNames::from_workspace(&root_cargo_toml, &root_manifest_path);
+
+    if command == "env" {
+        if !additional_args.cargo.is_empty() || additional_args.program.is_some() {
+            anyhow::bail!(
+                "Usage: cargo feature-scope env [-p <PACKAGE>] [--script-format sh|bat], \
+                 unexpected arguments: {}",
+                additional_args
+                    .cargo
+                    .iter()
+                    .chain(additional_args.program.iter().flatten())
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+        }
+        let mut cmd = process::Command::new("env");
+        apply_program_env(&mut cmd, target_dir.as_deref(), &cfg_args, &check_cfg_args);
+        cmd.env(env_vars::TARGET_PACKAGE, &target_package_name);
+
+        let comments = [display::describe_scopes(&scope_names, &cfg_args)];
+        print!("{}", script::render_env(&cmd, &comments, format));
+        return Ok(());
+    }
+
+    if command == "exec" || command == "cbindgen" {
+        let program_args = if command == "cbindgen" {
+            cbindgen::program_args(
+                &root_cargo_toml,
+                &root_manifest_path,
+                &target_package_name,
+                &additional_args,
+            )?
+        } else {
+            program_args
+        };
+        let mut cmd = build_program_command(
+            target_dir.as_deref(),
+            &cfg_args,
+            &check_cfg_args,
+            &program_args,
+        )?;
+        cmd.env(env_vars::TARGET_PACKAGE, &target_package_name);
+
+        let description =
+            display::describe_invocation(&cmd, &scope_names, &cfg_args, &check_cfg_args, verbose);
+        if let Some(format) = script_format {
+            print!("{}", script::render(&cmd, &description, format));
+            return Ok(());
+        }
+
+        // Keep stdout clean for the program, whose output is often piped
+        for line in description {
+            eprintln!("{line}");
+        }
+
+        let entry = history::Entry {
+            root_manifest_path: &root_manifest_path,
+            package: &target_package_name,
+            scope_names: &scope_names,
+            cfg_args: &cfg_args,
+            check_cfg_args: &check_cfg_args,
+        };
+        return run_to_completion(cmd, entry);
+    }
+
+    // Merge rustflags from forwarded `--config` arguments, which the environment would otherwise
+    // shadow
+    let config_rustflags = rustflags::take_config_rustflags(&mut additional_args)?;
+
+    if command == "pipeline" {
+        if script_format.is_some() {
+            anyhow::bail!(
+                "--no-run-cargo is not supported by pipelines, which run several commands"
+            );
+        }
+        let invocation = Invocation {
+            root_cargo_toml: &root_cargo_toml,
+            root_manifest_path: &root_manifest_path,
+            package,
+            target_package: &target_package_name,
+            cfg_args,
+            check_cfg_args,
+            config_rustflags,
+            additional_args,
+            scope_names,
+            verbose,
+        };
+        return pipeline::run(&invocation, target_dir.as_deref());
+    }
+
+    let all_scopes = matches.get_flag("all-scopes") | additional_args.take_flag("--all-scopes");
+    let feature_powerset =
+        matches.get_flag("feature-powerset") | additional_args.take_flag("--feature-powerset");
+    let depth = match matches.get_one::<usize>("depth") {
+        Some(depth) => Some(*depth),
+        None => additional_args
+            .take_option(None, "--depth")
+            .map(|depth| depth.parse().context("--depth takes a number"))
+            .transpose()?,
+    };
+    if all_scopes {
+        if command == "nextest archive" && nextest::archive_file(&additional_args).is_some() {
+            anyhow::bail!("--all-scopes would write every scope set to the same --archive-file");
+        }
+        let invocation = Invocation {
+            root_cargo_toml: &root_cargo_toml,
+            root_manifest_path: &root_manifest_path,
+            package,
+            target_package: &target_package_name,
+            cfg_args,
+            check_cfg_args,
+            config_rustflags,
+            additional_args,
+            scope_names,
+            verbose,
+        };
+        let powerset_depth = feature_powerset.then(|| depth.unwrap_or(usize::MAX));
+        return matrix::run_all_scopes(
+            &invocation,
+            command,
+            powerset_depth,
+            variants_base.as_deref(),
+        );
+    }
+
+    if let Some(scope_sets) = compare_scopes {
+        if command != "bench" {
+            anyhow::bail!("--compare-scopes is only supported by the bench command");
+        }
+
+        let invocation = Invocation {
+            root_cargo_toml: &root_cargo_toml,
+            root_manifest_path: &root_manifest_path,
+            package,
+            target_package: &target_package_name,
+            cfg_args,
+            check_cfg_args,
+            config_rustflags,
+            additional_args,
+            scope_names,
+            verbose,
+        };
+        return bench::compare_scopes(&invocation, scope_sets);
+    }
+
+    // Running or listing the tests of a nextest archive builds nothing, the tests have the scopes
+    // they were built with, and nextest takes no packages then
+    let archive = nextest::archive_file(&additional_args);
+    let from_archive = archive.is_some() && command != "nextest archive";
+    let (cfg_args, check_cfg_args) = match &archive {
+        Some(archive) if from_archive => {
+            nextest::archived_scopes(archive, &scope_names, cfg_args, check_cfg_args)?
+        }
+        _ => (cfg_args, check_cfg_args),
     };
 
     // Build and execute cargo command
-    execute_cargo_command(
+    let mut cargo_cmd = build_cargo_command(
         command,
-        package,
+        package.filter(|_| !from_archive),
+        target_dir.as_deref(),
+        &config_rustflags,
         &cfg_args,
         &check_cfg_args,
         &additional_args,
-    )
-}
+    );
+    cargo_cmd.env(env_vars::TARGET_PACKAGE, &target_package_name);
 
-fn find_root_manifest(start_dir: &Path) -> Result<PathBuf> {
-    let mut current_dir = start_dir.to_path_buf();
+    let description = display::describe_invocation(
+        &cargo_cmd,
+        &scope_names,
+        &cfg_args,
+        &check_cfg_args,
+        verbose,
+    );
+    if let Some(format) = script_format {
+        if artifact_dir.is_some() {
+            anyhow::bail!("--no-run-cargo cannot copy the binaries of a build it does not run");
+        }
+        print!("{}", script::render(&cargo_cmd, &description, format));
+        return Ok(());
+    }
 
-    loop {
-        let cargo_toml = current_dir.join("Cargo.toml");
-        if cargo_toml.exists() {
-            return Ok(cargo_toml);
+    // Cargo's JSON messages on stdout would be broken up by the summary
+    if !progress::is_hidden() {
+        for line in description {
+            println!("{line}");
         }
+    }
 
-        if let Some(parent) = current_dir.parent() {
-            current_dir = parent.to_path_buf();
-        } else {
-            break;
+    #[cfg(feature = "in-process")]
+    if in_process::supports(command) && artifact_dir.is_none() {
+        return in_process::run(&cargo_cmd);
+    }
+
+    let entry = history::Entry {
+        root_manifest_path: &root_manifest_path,
+        package: &target_package_name,
+        scope_names: &scope_names,
+        cfg_args: &cfg_args,
+        check_cfg_args: &check_cfg_args,
+    };
+    if let Some(archive) = archive.filter(|_| !from_archive) {
+        run_to_completion(cargo_cmd, entry)?;
+        return nextest::record_archive(&archive, &target_package_name, &cfg_args, &check_cfg_args);
+    }
+    let (Some(artifact_dir), Some(variant)) = (artifact_dir, variant) else {
+        return run_to_completion(cargo_cmd, entry);
+    };
+    run_to_completion(cargo_cmd, entry)?;
+
+    // The same build again, only for the messages naming the binaries
+    let mut replay_args = additional_args;
+    while replay_args.take_option(None, "--message-format").is_some() {}
+    replay_args.cargo.extend([
+        String::from("--quiet"),
+        String::from("--message-format=json"),
+    ]);
+    let mut replay = build_cargo_command(
+        command,
+        package,
+        target_dir.as_deref(),
+        &config_rustflags,
+        &cfg_args,
+        &check_cfg_args,
+        &replay_args,
+    );
+    replay.env(env_vars::TARGET_PACKAGE, &target_package_name);
+    let executables = artifacts::executables(replay)?;
+
+    let pattern = root_cargo_toml
+        .options()
+        .artifact_name
+        .unwrap_or_else(|| String::from(artifacts::DEFAULT_PATTERN));
+    let copies = artifacts::copy(&executables, &artifact_dir, &pattern, variant)?;
+    if !progress::is_hidden() {
+        if copies.is_empty() {
+            println!("The build produced no binaries to copy");
+        }
+        for (executable, copy) in executables.iter().zip(&copies) {
+            println!("Copied {} to {}", executable.display(), copy.display());
         }
     }
+    Ok(())
+}
+
+fn read_root_manifest() -> Result<(PathBuf, CargoToml)> {
+    // Get current directory and root Cargo.toml
+    let current_dir = env::current_dir()?;
+    let root_manifest_path = find_root_manifest(&current_dir)?;
 
-    anyhow::bail!("Could not find Cargo.toml in current directory or parent directories")
+    // Parse root Cargo.toml
+    let root_cargo_toml = load_manifest(&root_manifest_path)?;
+
+    Ok((root_manifest_path, root_cargo_toml))
 }
 
-fn determine_default_package(
+// Set by --deny-warnings, which makes every resolution of the run strict
+static DENY_WARNINGS: AtomicBool = AtomicBool::new(false);
+
+// Set by `--interactive` and `--resolution`, to fix conflicts instead of failing on them
+static CONFLICT_MODE: OnceLock<conflict::Mode> = OnceLock::new();
+
+// Resolves the scope flags of a package and reports the problems found on the way
+fn resolve_cfg_args(
     root_cargo_toml: &CargoToml,
     root_manifest_path: &Path,
-) -> Result<String> {
-    if let Some(workspace) = &root_cargo_toml.workspace {
-        // Workspace mode: use the first default-members or first members
-        if let Some(default_members) = &workspace.default_members {
-            if let Some(first_default) = default_members.first() {
-                return extract_package_name_from_path(first_default, root_manifest_path);
-            }
-        }
+    target_package_name: &str,
+) -> Result<(Vec<String>, Vec<String>)> {
+    resolve_cfg_args_with(
+        root_cargo_toml,
+        root_manifest_path,
+        target_package_name,
+        &Overrides::default(),
+    )
+}
 
-        if let Some(members) = &workspace.members {
-            if let Some(first_member) = members.first() {
-                return extract_package_name_from_path(first_member, root_manifest_path);
-            }
-        }
+// Like `resolve_cfg_args`, with command line overrides applied to the entries of the package
+fn resolve_cfg_args_with(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    target_package_name: &str,
+    overrides: &Overrides,
+) -> Result<(Vec<String>, Vec<String>)> {
+    let resolved = resolve_scope_with(
+        root_cargo_toml,
+        root_manifest_path,
+        target_package_name,
+        overrides,
+    )?;
+    Ok((resolved.cfg_args, resolved.check_cfg_args))
+}
 
-        anyhow::bail!("No members found in workspace")
-    } else {
-        // Single package mode: use current package name
-        if let Some(package) = &root_cargo_toml.package {
-            Ok(package.name.clone())
-        } else {
-            anyhow::bail!("No package found in root Cargo.toml")
+// Like `resolve_cfg_args_with`, keeping what enabled each scope
+fn resolve_scope_with(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    target_package_name: &str,
+    overrides: &Overrides,
+) -> Result<ResolvedScope> {
+    // Fixed conflicts change the manifests, so the root one is read again before resolving anew
+    let mut reloaded: Option<CargoToml> = None;
+    let mut fixed = Vec::new();
+    let resolved = loop {
+        let result = feature_scope_core::resolve_cfg_args_with(
+            reloaded.as_ref().unwrap_or(root_cargo_toml),
+            root_manifest_path,
+            target_package_name,
+            overrides,
+        );
+        let err = match result {
+            Ok(resolved) => break resolved,
+            Err(err) => err,
+        };
+
+        // Strict manifests fail in the resolution already, --deny-warnings only below
+        let err = match err.downcast::<StrictError>() {
+            Ok(strict) => return Err(deny_diagnostics(&strict.package, &strict.diagnostics)),
+            Err(err) => err,
+        };
+        let conflict = err.downcast::<ConflictError>()?;
+        let Some(mode) = CONFLICT_MODE.get() else {
+            return Err(conflict.into());
+        };
+        // A conflict coming back means the fix did not reach it
+        let description = conflict.to_string();
+        if fixed.contains(&description) {
+            return Err(
+                anyhow::Error::new(conflict).context("The fix did not resolve the conflict")
+            );
         }
+        conflict::resolve(&conflict, *mode, root_cargo_toml, root_manifest_path)?;
+        fixed.push(description);
+        reloaded = Some(load_manifest(root_manifest_path)?);
+    };
+    if DENY_WARNINGS.load(Ordering::Relaxed) && resolved.diagnostics.iter().any(|d| !d.is_note()) {
+        return Err(deny_diagnostics(&resolved.package, &resolved.diagnostics));
     }
-}
-
-fn extract_package_name_from_path(member_path: &str, root_manifest_path: &Path) -> Result<String> {
-    let root_dir = root_manifest_path.parent().unwrap();
-    let member_manifest = root_dir.join(member_path).join("Cargo.toml");
-
-    let content = std::fs::read_to_string(&member_manifest)
-        .with_context(|| format!("Failed to read {}", member_manifest.display()))?;
-    let cargo_toml: CargoToml =
-        toml::from_str(&content).with_context(|| "Failed to parse member Cargo.toml")?;
-
-    if let Some(package) = cargo_toml.package {
-        Ok(package.name)
-    } else {
-        anyhow::bail!("No package found in {}", member_manifest.display())
+    for diagnostic in &resolved.diagnostics {
+        i18n::report(diagnostic);
     }
+    // Probes fire for each consumer alike, so one note per scope is enough
+    let probes: BTreeSet<(String, String)> = resolved
+        .origins
+        .values()
+        .flat_map(|features| features.values().flatten())
+        .filter_map(|origin| match origin {
+            Origin::Probe {
+                library,
+                feature,
+                probe,
+            } => Some((format!("{library}/{feature}"), probe.to_string())),
+            _ => None,
+        })
+        .collect();
+    for (scope, probe) in &probes {
+        i18n::note(i18n::Message::ProbeFired { scope, probe });
+    }
+
+    Ok(resolved)
 }
 
-fn handle_single_package(cargo_toml: &CargoToml) -> Result<(Vec<String>, Vec<String>)> {
-    let mut cfg_args = vec![String::from("--cfg"), String::from("__scope_default")];
-    let mut all_scope_features = HashSet::new();
-
-    // Always add default scope
-    all_scope_features.insert("__scope_default".to_string());
-
-    if let Some(package) = &cargo_toml.package {
-        if let Some(metadata) = &package.metadata {
-            // Single package mode: feature-scope-decl and feature-scope are in the same file
-            if let Some(feature_scope_decl) = &metadata.feature_scope_decl {
-                // Collect all declared feature scopes
-                for feature_name in feature_scope_decl.features.keys() {
-                    all_scope_features.insert(format!("__scope_{feature_name}"));
-                }
-
-                // Iteratively parse default features and their dependencies
-                let mut enabled_features = HashSet::new();
-                if let Some(defaults) = &feature_scope_decl.default {
-                    for default_feature in defaults {
-                        resolve_feature_dependencies(
-                            default_feature,
-                            &feature_scope_decl.features,
-                            &mut enabled_features,
-                        );
-                    }
-                }
-
-                // Add cfg parameters for enabled features
-                for feature in &enabled_features {
-                    all_scope_features.insert(format!("__scope_{feature}"));
-                    cfg_args.push(String::from("--cfg"));
-                    cfg_args.push(format!("__scope_{feature}"));
-                }
-
-                if let Some(feature_scope) = &metadata.feature_scope {
-                    // Cross-validate and apply feature-scope configuration
-                    for scope in feature_scope {
-                        for feature in &scope.features {
-                            if feature_scope_decl.features.contains_key(feature) {
-                                // Parse dependencies of this feature
-                                let mut scope_enabled_features = HashSet::new();
-                                resolve_feature_dependencies(
-                                    feature,
-                                    &feature_scope_decl.features,
-                                    &mut scope_enabled_features,
-                                );
-
-                                for enabled_feature in scope_enabled_features {
-                                    cfg_args.push(String::from("--cfg"));
-                                    cfg_args.push(format!("__scope_{enabled_feature}"));
-                                }
-                            } else {
-                                eprintln!(
-                                    "Warning: feature '{feature}' not declared in feature-scope-decl"
-                                );
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    // Build check-cfg parameters
-    let mut check_cfg_args = Vec::new();
-    for scope_feature in all_scope_features {
-        check_cfg_args.push(String::from("--check-cfg"));
-        check_cfg_args.push(format!("cfg({scope_feature})"));
-    }
-
-    Ok((cfg_args, check_cfg_args))
+// `--features` with the cargo features activating the optional dependencies of enabled scopes
+fn cargo_feature_args(cargo_features: &[String]) -> Vec<String> {
+    if cargo_features.is_empty() {
+        return Vec::new();
+    }
+    vec![String::from("--features"), cargo_features.join(",")]
 }
 
-// Helper function to iteratively parse feature dependencies
-fn resolve_feature_dependencies(
-    feature: &str,
-    feature_map: &HashMap<String, Vec<String>>,
-    enabled_features: &mut HashSet<String>,
-) {
-    // Avoid circular dependencies
-    if enabled_features.contains(feature) {
-        return;
+// Reports the warnings of a strict resolution as errors, returning the error that fails it
+fn deny_diagnostics(package: &str, diagnostics: &[Diagnostic]) -> anyhow::Error {
+    let mut count = 0;
+    for diagnostic in diagnostics {
+        if diagnostic.is_note() {
+            i18n::note(i18n::Message::Resolution(diagnostic));
+        } else {
+            i18n::error(i18n::Message::Resolution(diagnostic));
+            count += 1;
+        }
     }
+    anyhow::anyhow!("{}", i18n::Message::StrictModeDenied { package, count })
+}
 
-    enabled_features.insert(feature.to_string());
+// Runs a command, records it in the history and exits with its status code if it fails
+fn run_to_completion(mut cmd: process::Command, entry: history::Entry) -> Result<()> {
+    let status = history::status(&mut cmd, entry)?;
 
-    // Recursively parse dependencies
-    if let Some(dependencies) = feature_map.get(feature) {
-        for dep in dependencies {
-            resolve_feature_dependencies(dep, feature_map, enabled_features);
-        }
+    if !status.success() {
+        process::exit(status.code().unwrap_or(1));
     }
+
+    Ok(())
 }
 
-fn handle_workspace_package(
+// The profile cargo builds with and the profiles it inherits from, by the flags it is given
+fn build_profiles(
     root_cargo_toml: &CargoToml,
-    root_manifest_path: &Path,
-    target_package: &str,
-) -> Result<(Vec<String>, Vec<String>)> {
-    let mut cfg_args = Vec::new();
-    let mut all_scope_features = HashSet::new();
-    let root_dir = root_manifest_path.parent().unwrap();
-
-    // Always add default scope to check-cfg
-    all_scope_features.insert("__scope_default".to_string());
-
-    // Default enable __scope_default
-    let mut enable_scope_default = true;
-
-    // First collect information of all packages in the workspace
-    let mut workspace_packages = HashMap::new();
-
-    if let Some(workspace) = &root_cargo_toml.workspace {
-        if let Some(members) = &workspace.members {
-            for member_path in members {
-                let member_manifest = root_dir.join(member_path).join("Cargo.toml");
-                if member_manifest.exists() {
-                    let content = std::fs::read_to_string(&member_manifest)?;
-                    let member_cargo_toml: CargoToml = toml::from_str(&content)?;
-
-                    if let Some(package) = member_cargo_toml.package {
-                        workspace_packages.insert(package.name.clone(), (member_manifest, package));
-                    }
-                }
-            }
-        }
-    }
-
-    // Collect feature scopes defined in feature-scope-decl of all packages
-    for (_, package) in workspace_packages.values() {
-        if let Some(metadata) = &package.metadata {
-            if let Some(feature_scope_decl) = &metadata.feature_scope_decl {
-                // Collect all declared feature scopes
-                for feature_name in feature_scope_decl.features.keys() {
-                    all_scope_features.insert(format!("__scope_{feature_name}"));
-                }
-
-                if let Some(defaults) = &feature_scope_decl.default {
-                    for feature in defaults {
-                        all_scope_features.insert(format!("__scope_{feature}"));
-                    }
-                }
-            }
-        }
-    }
-
-    // Find target package
-    let (_target_manifest_path, target_package_info) = workspace_packages
-        .get(target_package)
-        .ok_or_else(|| anyhow::anyhow!("Package '{}' not found in workspace", target_package))?;
-
-    // Process feature-scope configuration of target package
-    if let Some(metadata) = &target_package_info.metadata {
-        if let Some(feature_scope) = &metadata.feature_scope {
-            for scope in feature_scope {
-                // Find feature-scope-decl of dependency package
-                if let Some((_, dep_package)) = workspace_packages.get(&scope.package) {
-                    if let Some(dep_metadata) = &dep_package.metadata {
-                        if let Some(dep_feature_scope_decl) = &dep_metadata.feature_scope_decl {
-                            // Check if default features are disabled
-                            let scope_enable_default_features =
-                                scope.default_features.unwrap_or(true);
-                            if !scope_enable_default_features {
-                                enable_scope_default = false;
-                            }
-
-                            // Cross-validate and parse explicitly specified feature dependencies
-                            for feature in &scope.features {
-                                if dep_feature_scope_decl.features.contains_key(feature)
-                                    || dep_feature_scope_decl
-                                        .default
-                                        .as_ref()
-                                        .is_some_and(|d| d.contains(feature))
-                                {
-                                    // Iteratively parse feature dependencies
-                                    let mut enabled_features = HashSet::new();
-                                    resolve_feature_dependencies(
-                                        feature,
-                                        &dep_feature_scope_decl.features,
-                                        &mut enabled_features,
-                                    );
-
-                                    for enabled_feature in enabled_features {
-                                        cfg_args.push(String::from("--cfg"));
-                                        cfg_args.push(format!("__scope_{enabled_feature}"));
-                                    }
-                                } else {
-                                    eprintln!(
-                                        "Warning: feature '{}' not declared in package '{}'",
-                                        feature, scope.package
-                                    );
-                                }
-                            }
-
-                            // If default features are enabled and no features are explicitly specified, handle default features
-                            if scope_enable_default_features && scope.features.is_empty() {
-                                if let Some(defaults) = &dep_feature_scope_decl.default {
-                                    for default_feature in defaults {
-                                        let mut enabled_features = HashSet::new();
-                                        resolve_feature_dependencies(
-                                            default_feature,
-                                            &dep_feature_scope_decl.features,
-                                            &mut enabled_features,
-                                        );
-
-                                        for enabled_feature in enabled_features {
-                                            cfg_args.push(String::from("--cfg"));
-                                            cfg_args.push(format!("__scope_{enabled_feature}"));
-                                        }
-                                    }
-                                }
-                            }
-                        } else {
-                            eprintln!(
-                                "Warning: package '{}' does not have feature-scope-decl",
-                                scope.package
-                            );
-                        }
-                    }
-                } else {
-                    eprintln!(
-                        "Warning: dependency package '{}' not found in workspace",
-                        scope.package
-                    );
-                }
-            }
-        }
-    }
-
-    // Finally decide whether to add __scope_default
-    if enable_scope_default {
-        cfg_args.insert(0, String::from("__scope_default"));
-        cfg_args.insert(0, String::from("--cfg"));
-    }
-
-    // Build check-cfg parameters
-    let mut check_cfg_args = Vec::new();
-    for scope_feature in all_scope_features {
-        check_cfg_args.push(String::from("--check-cfg"));
-        check_cfg_args.push(format!("cfg({scope_feature})"));
-    }
-
-    Ok((cfg_args, check_cfg_args))
+    command: &str,
+    additional_args: &ForwardedArgs,
+) -> Vec<String> {
+    let release = additional_args
+        .cargo
+        .iter()
+        .any(|arg| arg == "--release" || arg == "-r");
+    // Nextest has profiles of its own, and builds the tests like `cargo test`
+    let is_nextest = command.starts_with("nextest ");
+    let profile_option = if is_nextest {
+        "--cargo-profile"
+    } else {
+        "--profile"
+    };
+    let profile = match additional_args
+        .option_values(None, profile_option)
+        .into_iter()
+        .next()
+    {
+        Some(profile) => profile,
+        None if release => String::from("release"),
+        None if command == "test" || is_nextest => String::from("test"),
+        None if command == "bench" => String::from("bench"),
+        None => String::from("dev"),
+    };
+    root_cargo_toml.profile_chain(&profile)
 }
 
-fn execute_cargo_command(
+fn build_cargo_command(
     command: &str,
     package: Option<&String>,
+    target_dir: Option<&Path>,
+    config_rustflags: &[String],
     cfg_args: &[String],
     check_cfg_args: &[String],
-    additional_args: &[&String],
-) -> Result<()> {
+    additional_args: &ForwardedArgs,
+) -> process::Command {
     let mut cargo_cmd = process::Command::new("cargo");
-    cargo_cmd.arg(command);
+    // Like `nextest run`, with its subcommand
+    cargo_cmd.args(command.split(' '));
 
     // Add package arguments
     if let Some(pkg) = package {
         cargo_cmd.arg("-p").arg(pkg);
     }
 
-    // Pass cfg and check-cfg parameters through RUSTFLAGS environment variable
-    if !cfg_args.is_empty() || !check_cfg_args.is_empty() {
-        let mut rustflags = env::var("RUSTFLAGS").unwrap_or_default();
+    // Add variant target directory
+    if let Some(target_dir) = target_dir {
+        cargo_cmd.arg("--target-dir").arg(target_dir);
+    }
 
-        // Add cfg parameters
-        for cfg_arg in cfg_args {
-            if !rustflags.is_empty() {
-                rustflags.push(' ');
-            }
-            rustflags.push_str(cfg_arg);
-        }
+    // With `--target`, the flags only reach the artifacts of the target, not the host ones
+    let triples = additional_args.option_values(None, "--target");
+    if triples.is_empty() {
+        apply_scope_env(&mut cargo_cmd, config_rustflags, cfg_args, check_cfg_args);
+    } else {
+        rustflags::apply_to_targets(
+            &mut cargo_cmd,
+            &triples,
+            config_rustflags,
+            cfg_args,
+            check_cfg_args,
+        );
+        export_cfgs(&mut cargo_cmd, cfg_args);
+    }
 
-        // Add check-cfg parameters
-        for check_cfg_arg in check_cfg_args {
-            if !rustflags.is_empty() {
-                rustflags.push(' ');
-            }
-            rustflags.push_str(check_cfg_arg);
-        }
+    // Add additional arguments, keeping the ones after `--` last
+    additional_args.apply(&mut cargo_cmd);
 
-        cargo_cmd.env("RUSTFLAGS", rustflags);
-    }
+    cargo_cmd
+}
 
-    // Add additional arguments
-    for arg in additional_args {
-        cargo_cmd.arg(arg);
+fn apply_scope_env(
+    cmd: &mut process::Command,
+    config_rustflags: &[String],
+    cfg_args: &[String],
+    check_cfg_args: &[String],
+) {
+    // Pass cfg and check-cfg parameters through the rustflags environment variables
+    if !cfg_args.is_empty() || !check_cfg_args.is_empty() || !config_rustflags.is_empty() {
+        let rustflags = rustflags::compose(config_rustflags, cfg_args, check_cfg_args);
+        rustflags::apply(cmd, &rustflags);
     }
 
-    println!("Running: {cargo_cmd:?}");
-    if !cfg_args.is_empty() {
-        println!("cfg_args: {cfg_args:?}");
-    }
-    if !check_cfg_args.is_empty() {
-        println!("check_cfg_args: {check_cfg_args:?}");
-    }
+    export_cfgs(cmd, cfg_args);
+}
+
+// Exports the enabled scope cfgs, so `feature_scope_setup!()` knows the CLI is in use
+fn export_cfgs(cmd: &mut process::Command, cfg_args: &[String]) {
+    let enabled_cfgs: Vec<&str> = cfg_args
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .map(String::as_str)
+        .collect();
+    cmd.env(env_vars::CFGS, enabled_cfgs.join(" "));
+}
 
-    // Execute cargo command
-    let status = cargo_cmd
-        .status()
-        .with_context(|| "Failed to execute cargo command")?;
+// Builds an arbitrary command, e.g. `cargo nextest run`, under the resolved scope environment
+fn build_program_command(
+    target_dir: Option<&Path>,
+    cfg_args: &[String],
+    check_cfg_args: &[String],
+    program_args: &[String],
+) -> Result<process::Command> {
+    let Some((program, args)) = program_args.split_first() else {
+        anyhow::bail!("No command given, usage: cargo feature-scope exec -- <COMMAND> [ARGS]...");
+    };
 
-    if !status.success() {
-        process::exit(status.code().unwrap_or(1));
-    }
+    let mut cmd = process::Command::new(program);
+    cmd.args(args);
+    apply_program_env(&mut cmd, target_dir, cfg_args, check_cfg_args);
+    Ok(cmd)
+}
 
-    Ok(())
+// The scope environment of a program other than cargo
+fn apply_program_env(
+    cmd: &mut process::Command,
+    target_dir: Option<&Path>,
+    cfg_args: &[String],
+    check_cfg_args: &[String],
+) {
+    apply_scope_env(cmd, &[], cfg_args, check_cfg_args);
+
+    // Nested cargo invocations pick up the variant target directory from the environment
+    if let Some(target_dir) = target_dir {
+        cmd.env("CARGO_TARGET_DIR", target_dir);
+    }
 }
@@ -0,0 +1,222 @@
+//! Running a cargo command once per scope combination.
+//!
+//! `cargo feature-scope test --all-scopes` runs the command with the resolved configuration,
+//! with no default scopes, and with every declared feature of the workspace on its own, then
+//! prints which scope sets passed. `--feature-powerset` runs every combination of the declared
+//! features instead of single ones, up to `--depth` features per combination, like cargo-hack.
+//! Apart from the resolved configuration, every set starts from no default scopes.
+
+use anyhow::Result;
+use feature_scope_core::Overrides;
+use std::path::Path;
+
+use crate::{
+    display, history,
+    progress::{self, Progress},
+    target_dir, Invocation,
+};
+
+// A scope combination, with the label the summary shows for it
+struct ScopeSet {
+    label: String,
+    overrides: Overrides,
+}
+
+/// Runs the command for every scope set, combining up to `powerset_depth` features per set when
+/// it is given and taking the features one by one otherwise. With `isolated_base`, every set
+/// builds into its own target directory below it.
+pub fn run_all_scopes(
+    invocation: &Invocation,
+    command: &str,
+    powerset_depth: Option<usize>,
+    isolated_base: Option<&Path>,
+) -> Result<()> {
+    let features = declared_features(invocation)?;
+    let scope_sets = scope_sets(&features, powerset_depth);
+
+    let mut results = Vec::new();
+    let mut progress = Progress::new("Scope sets", scope_sets.len());
+    for scope_set in &scope_sets {
+        // Every set builds the targets cargo is asked for, with the entries for them
+        let overrides = Overrides {
+            targets: invocation.additional_args.selected_targets(),
+            command: Some(command.to_string()),
+            profiles: crate::build_profiles(
+                invocation.root_cargo_toml,
+                command,
+                &invocation.additional_args,
+            ),
+            ..scope_set.overrides.clone()
+        };
+        let (cfg_args, check_cfg_args) = crate::resolve_cfg_args_with(
+            invocation.root_cargo_toml,
+            invocation.root_manifest_path,
+            invocation.target_package,
+            &overrides,
+        )?;
+        let target_dir = isolated_base
+            .map(|base| {
+                target_dir::prepare_isolated_target_dir(base, &cfg_args, &check_cfg_args, false)
+            })
+            .transpose()?;
+
+        let mut cargo_cmd = crate::build_cargo_command(
+            command,
+            invocation.package,
+            target_dir.as_deref(),
+            &invocation.config_rustflags,
+            &cfg_args,
+            &check_cfg_args,
+            &invocation.additional_args,
+        );
+        cargo_cmd.env(crate::env_vars::TARGET_PACKAGE, invocation.target_package);
+
+        progress.header(&scope_set.label);
+        if !progress::is_hidden() {
+            for line in display::describe_invocation(
+                &cargo_cmd,
+                &invocation.scope_names,
+                &cfg_args,
+                &check_cfg_args,
+                invocation.verbose,
+            ) {
+                println!("{line}");
+            }
+        }
+
+        let entry = history::Entry {
+            root_manifest_path: invocation.root_manifest_path,
+            package: invocation.target_package,
+            scope_names: &invocation.scope_names,
+            cfg_args: &cfg_args,
+            check_cfg_args: &check_cfg_args,
+        };
+        let status = history::status(&mut cargo_cmd, entry)?;
+        results.push(status.success());
+    }
+
+    let failed = results.iter().filter(|passed| !**passed).count();
+    let summary = || {
+        anyhow::anyhow!(
+            "cargo {command} failed for {failed} of {} scope sets",
+            scope_sets.len()
+        )
+    };
+    // The summary would end up between cargo's JSON messages
+    if progress::is_hidden() {
+        return if failed > 0 { Err(summary()) } else { Ok(()) };
+    }
+
+    let width = scope_sets
+        .iter()
+        .map(|scope_set| scope_set.label.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(9);
+    println!();
+    println!("{:width$}  Result", "Scope set");
+    for (scope_set, passed) in scope_sets.iter().zip(&results) {
+        let result = if *passed { "✅ passed" } else { "❌ failed" };
+        println!("{:width$}  {result}", scope_set.label);
+    }
+
+    if failed > 0 {
+        println!();
+        return Err(summary());
+    }
+    Ok(())
+}
+
+// Every `package/feature` declared in the workspace, sorted
+fn declared_features(invocation: &Invocation) -> Result<Vec<String>> {
+    let mut manifests = crate::workspace_member_manifests(
+        invocation.root_cargo_toml,
+        invocation.root_manifest_path,
+    )?;
+    if invocation.root_cargo_toml.package.is_some() {
+        manifests.insert(0, invocation.root_manifest_path.to_path_buf());
+    }
+
+    let mut features = Vec::new();
+    for manifest_path in manifests {
+        let package = crate::read_package(&manifest_path)?;
+        let Some(decl) = package
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.feature_scope_decl.as_ref())
+        else {
+            continue;
+        };
+        for feature in decl.features.keys() {
+            features.push(format!("{}/{feature}", package.name));
+        }
+    }
+    features.sort();
+
+    Ok(features)
+}
+
+fn scope_sets(features: &[String], powerset_depth: Option<usize>) -> Vec<ScopeSet> {
+    let mut scope_sets = vec![
+        ScopeSet {
+            label: String::from("default"),
+            overrides: Overrides::default(),
+        },
+        ScopeSet {
+            label: String::from("no-default"),
+            overrides: Overrides {
+                features: Vec::new(),
+                no_default_features: true,
+                targets: Vec::new(),
+                ..Overrides::default()
+            },
+        },
+    ];
+
+    let combinations = match powerset_depth {
+        Some(depth) => powerset(features, depth),
+        None => features.iter().map(|feature| vec![feature]).collect(),
+    };
+    for combination in combinations {
+        scope_sets.push(ScopeSet {
+            label: combination
+                .iter()
+                .map(|feature| feature.as_str())
+                .collect::<Vec<_>>()
+                .join("+"),
+            overrides: Overrides {
+                features: combination.into_iter().cloned().collect(),
+                no_default_features: true,
+                targets: Vec::new(),
+                ..Overrides::default()
+            },
+        });
+    }
+
+    scope_sets
+}
+
+// The non-empty combinations of at most `depth` features, smaller ones first
+fn powerset(features: &[String], depth: usize) -> Vec<Vec<&String>> {
+    let mut result = Vec::new();
+
+    // Combinations as indices, only extended with later features so each comes up once
+    let mut combinations: Vec<Vec<usize>> = vec![Vec::new()];
+    for _ in 0..depth.min(features.len()) {
+        combinations = combinations
+            .iter()
+            .flat_map(|combination| {
+                let start = combination.last().map_or(0, |last| last + 1);
+                (start..features.len())
+                    .map(move |index| [combination.as_slice(), &[index]].concat())
+            })
+            .collect();
+        result.extend(
+            combinations
+                .iter()
+                .map(|combination| combination.iter().map(|&index| &features[index]).collect()),
+        );
+    }
+
+    result
+}
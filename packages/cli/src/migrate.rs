@@ -0,0 +1,344 @@
+//! Migration of a crate from cargo features to scopes.
+//!
+//! `cargo feature-scope migrate [<PACKAGE>]` moves a package that gates code with cargo features
+//! onto scopes. It does what `init --import-features` does, declaring the features of
+//! `[features]` with the features they depend on, and then rewrites the gates in the sources of
+//! the package: `#[cfg(feature = "x")]` on items becomes `#[feature_scope::feature_scope(x)]`,
+//! with `all`, `any` and `not` carried over, and `cfg!(feature = "x")` becomes
+//! `feature_scope::cfg_scope!(x)`. Everything it leaves alone is reported: entries of
+//! `[features]` naming optional dependencies without `dep:`, and the gates that name
+//! other features, mix features with other cfgs, sit on something other than an item or are
+//! `cfg_attr`s. `[features]` itself stays, for the consumers that still select cargo features.
+
+use anyhow::{Context, Result};
+use proc_macro2::Span;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs,
+    path::Path,
+};
+use syn::{
+    punctuated::Punctuated,
+    visit::{self, Visit},
+    AttrStyle, Attribute, ImplItem, Item, Macro, MacroDelimiter, Meta, Token, TraitItem,
+};
+use toml_edit::Item as TomlItem;
+
+use crate::{
+    init::{self, RESERVED_KEYS},
+    items,
+    lints::read_document,
+    rename::{apply_edits, offset},
+    CargoToml,
+};
+
+const USAGE: &str = "Usage: cargo feature-scope migrate [<PACKAGE>]";
+
+// A cfg predicate as a scope predicate
+enum Predicate {
+    // Names no feature, like `unix`, and is none of this migration's business
+    NoFeature,
+    // The scope predicate
+    Scope(String),
+    // Names features but cannot be a scope predicate, for the reason given
+    Unsupported(String),
+}
+
+pub fn run(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    package: Option<&String>,
+    args: &[String],
+) -> Result<()> {
+    let package = match args {
+        [] => match package {
+            Some(package) => package.clone(),
+            None => {
+                feature_scope_core::determine_default_package(root_cargo_toml, root_manifest_path)?
+            }
+        },
+        [package] if !package.starts_with('-') => package.clone(),
+        _ => anyhow::bail!(USAGE),
+    };
+    let (manifest_path, declared) =
+        items::find_package(root_cargo_toml, root_manifest_path, &package)?;
+    if declared
+        .metadata
+        .is_some_and(|metadata| metadata.feature_scope_decl.is_some())
+    {
+        anyhow::bail!(
+            "Package '{package}' already declares scopes, migrate only starts them from [features]"
+        );
+    }
+
+    let document = read_document(&manifest_path)?;
+    let features = document
+        .get("features")
+        .and_then(TomlItem::as_table_like)
+        .with_context(|| format!("Package '{package}' has no [features] to migrate"))?;
+    let names: Vec<&str> = features.iter().map(|(name, _)| name).collect();
+    let mut notes = Vec::new();
+    for (name, value) in features.iter() {
+        for entry in value
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|v| v.as_str())
+        {
+            if let Some(note) = entry_note(entry, &names) {
+                notes.push(format!("[features] {name}: {note}"));
+            }
+        }
+    }
+    let scopes: BTreeSet<String> = names
+        .iter()
+        .filter(|name| **name != "default" && !RESERVED_KEYS.contains(name))
+        .map(|name| name.to_string())
+        .collect();
+
+    init::scaffold(root_manifest_path, &manifest_path, &package, true)?;
+
+    let root_dir = root_manifest_path.parent().unwrap();
+    let package_dir = manifest_path.parent().unwrap();
+    let mut files = Vec::new();
+    items::collect_sources(package_dir, package_dir, &mut files)?;
+    files.sort();
+    let mut migrated = 0;
+    for file in files {
+        let content = fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let parsed = syn::parse_file(&content)
+            .with_context(|| format!("Failed to parse {}", file.display()))?;
+
+        let mut migration = Migration {
+            content: &content,
+            scopes: &scopes,
+            item_attrs: HashSet::new(),
+            edits: BTreeMap::new(),
+            notes: Vec::new(),
+        };
+        migration.visit_file(&parsed);
+        let shown = file.strip_prefix(root_dir).unwrap_or(&file);
+        for (line, note) in migration.notes {
+            notes.push(format!("{}:{line}: {note}", shown.display()));
+        }
+        if !migration.edits.is_empty() {
+            migrated += migration.edits.len();
+            fs::write(&file, apply_edits(&content, &migration.edits))
+                .with_context(|| format!("Failed to write {}", file.display()))?;
+            println!("Updated {}", file.display());
+        }
+    }
+
+    println!(
+        "Migrated {} feature(s) and {migrated} gate(s) of '{package}', [features] is kept for cargo",
+        scopes.len()
+    );
+    for note in &notes {
+        println!("⚠️  {note}");
+    }
+    Ok(())
+}
+
+// Why an entry of a feature in `[features]` is left out of the declaration, if it is
+fn entry_note(entry: &str, names: &[&str]) -> Option<String> {
+    // Imported, scopes enable them through the cargo feature of the same name
+    if entry.starts_with("dep:") || entry.contains('/') {
+        return None;
+    }
+    (!names.contains(&entry)).then(|| {
+        format!("`{entry}` enables the optional dependency '{entry}', which scopes cannot do")
+    })
+}
+
+// Collects the edits of a source file, with notes on the gates it leaves alone
+struct Migration<'a> {
+    content: &'a str,
+    scopes: &'a BTreeSet<String>,
+    // The start of the attributes of items, which attribute macros can replace
+    item_attrs: HashSet<(usize, usize)>,
+    edits: BTreeMap<usize, (usize, String)>,
+    notes: Vec<(usize, String)>,
+}
+
+impl Migration<'_> {
+    fn mark(&mut self, attrs: &[Attribute]) {
+        for attr in attrs {
+            let start = attr.pound_token.span.start();
+            self.item_attrs.insert((start.line, start.column));
+        }
+    }
+
+    fn replace(&mut self, start: Span, end: Span, text: String) {
+        let start = offset(self.content, start.start());
+        self.edits
+            .insert(start, (offset(self.content, end.end()), text));
+    }
+
+    // Translates a cfg predicate, `nested` inside `all`, `any` or `not`
+    fn predicate(&self, meta: &Meta, nested: bool) -> Predicate {
+        match meta {
+            Meta::NameValue(name_value) if name_value.path.is_ident("feature") => {
+                let syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(feature),
+                    ..
+                }) = &name_value.value
+                else {
+                    return Predicate::NoFeature;
+                };
+                let feature = feature.value();
+                if !self.scopes.contains(&feature) {
+                    return Predicate::Unsupported(format!(
+                        "'{feature}' is not a feature of [features], possibly an optional dependency"
+                    ));
+                }
+                if syn::parse_str::<syn::Ident>(&feature).is_ok() {
+                    Predicate::Scope(feature)
+                } else if nested {
+                    Predicate::Unsupported(format!(
+                        "'{feature}' is not an identifier, which scope predicates combine"
+                    ))
+                } else {
+                    Predicate::Scope(format!("feature = {feature:?}"))
+                }
+            }
+            Meta::List(list)
+                if ["all", "any", "not"]
+                    .iter()
+                    .any(|op| list.path.is_ident(op)) =>
+            {
+                let Ok(operands) =
+                    list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                else {
+                    return Predicate::NoFeature;
+                };
+                let mut translated = Vec::new();
+                let mut other_cfgs = false;
+                for operand in &operands {
+                    match self.predicate(operand, true) {
+                        Predicate::NoFeature => other_cfgs = true,
+                        Predicate::Scope(scope) => translated.push(scope),
+                        unsupported => return unsupported,
+                    }
+                }
+                match (translated.is_empty(), other_cfgs) {
+                    (true, _) => Predicate::NoFeature,
+                    (false, true) => {
+                        Predicate::Unsupported(String::from("features are mixed with other cfgs"))
+                    }
+                    (false, false) => Predicate::Scope(format!(
+                        "{}({})",
+                        list.path.get_ident().unwrap(),
+                        translated.join(", ")
+                    )),
+                }
+            }
+            _ => Predicate::NoFeature,
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Migration<'_> {
+    fn visit_item(&mut self, item: &'ast Item) {
+        self.mark(items::item_attrs(item));
+        visit::visit_item(self, item);
+    }
+
+    fn visit_impl_item(&mut self, item: &'ast ImplItem) {
+        match item {
+            ImplItem::Const(item) => self.mark(&item.attrs),
+            ImplItem::Fn(item) => self.mark(&item.attrs),
+            ImplItem::Type(item) => self.mark(&item.attrs),
+            ImplItem::Macro(item) => self.mark(&item.attrs),
+            _ => {}
+        }
+        visit::visit_impl_item(self, item);
+    }
+
+    fn visit_trait_item(&mut self, item: &'ast TraitItem) {
+        match item {
+            TraitItem::Const(item) => self.mark(&item.attrs),
+            TraitItem::Fn(item) => self.mark(&item.attrs),
+            TraitItem::Type(item) => self.mark(&item.attrs),
+            TraitItem::Macro(item) => self.mark(&item.attrs),
+            _ => {}
+        }
+        visit::visit_trait_item(self, item);
+    }
+
+    fn visit_attribute(&mut self, attr: &'ast Attribute) {
+        let start = attr.pound_token.span.start();
+        let line = start.line;
+        if attr.path().is_ident("cfg_attr") {
+            let condition = attr
+                .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                .ok()
+                .and_then(|metas| metas.first().map(|meta| self.predicate(meta, false)));
+            if matches!(
+                condition,
+                Some(Predicate::Scope(_) | Predicate::Unsupported(_))
+            ) {
+                self.notes.push((
+                    line,
+                    String::from("`cfg_attr` on a feature is kept, scopes have no counterpart"),
+                ));
+            }
+            return;
+        }
+        if !attr.path().is_ident("cfg") {
+            return;
+        }
+        let Ok(meta) = attr.parse_args::<Meta>() else {
+            return;
+        };
+
+        match self.predicate(&meta, false) {
+            Predicate::NoFeature => {}
+            Predicate::Unsupported(reason) => {
+                self.notes.push((line, format!("`cfg` is kept, {reason}")));
+            }
+            Predicate::Scope(_) if matches!(attr.style, AttrStyle::Inner(_)) => {
+                self.notes.push((
+                    line,
+                    String::from(
+                        "inner `cfg` is kept, attribute macros cannot be inner attributes",
+                    ),
+                ));
+            }
+            Predicate::Scope(_) if !self.item_attrs.contains(&(start.line, start.column)) => {
+                self.notes.push((
+                    line,
+                    String::from("`cfg` is kept, `#[feature_scope]` only applies to items"),
+                ));
+            }
+            Predicate::Scope(scope) => self.replace(
+                attr.pound_token.span,
+                attr.bracket_token.span.close(),
+                format!("#[feature_scope::feature_scope({scope})]"),
+            ),
+        }
+    }
+
+    fn visit_macro(&mut self, mac: &'ast Macro) {
+        if mac.path.is_ident("cfg") {
+            let line = mac.path.segments[0].ident.span().start().line;
+            let close = match &mac.delimiter {
+                MacroDelimiter::Paren(paren) => paren.span.close(),
+                MacroDelimiter::Brace(brace) => brace.span.close(),
+                MacroDelimiter::Bracket(bracket) => bracket.span.close(),
+            };
+            match syn::parse2::<Meta>(mac.tokens.clone()).map(|meta| self.predicate(&meta, false)) {
+                Ok(Predicate::Scope(scope)) => self.replace(
+                    mac.path.segments[0].ident.span(),
+                    close,
+                    format!("feature_scope::cfg_scope!({scope})"),
+                ),
+                Ok(Predicate::Unsupported(reason)) => {
+                    self.notes.push((line, format!("`cfg!` is kept, {reason}")));
+                }
+                _ => {}
+            }
+        }
+        visit::visit_macro(self, mac);
+    }
+}
@@ -0,0 +1,441 @@
+//! Building several packages with one command.
+//!
+//! Cargo applies one set of RUSTFLAGS to every package of `--workspace` or of several `-p`, so
+//! their scopes have to agree. With `--workspace`, the scopes are resolved for the top-level
+//! members, the ones no other member depends on, whose builds include the other members.
+//! Without either, cargo builds the package in the current directory, or the `default-members`
+//! when run in the root of the workspace, and the scopes are resolved for the top-level ones of
+//! those the same way.
+//!
+//! `consumer-conflicts` in the `feature-scope-options` of the root manifest decides what happens
+//! when the packages do not agree: `auto` (the default) builds them one by one, `union` builds
+//! them with every scope one of them enables, unless that enables mutually exclusive features,
+//! `error` fails listing which packages disagree and on what, and `per-target-isolation` always
+//! builds the packages one by one, each with its own scopes in a target directory of its own.
+
+use anyhow::Result;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::Path,
+};
+
+use feature_scope_core::{
+    manifest::{ConsumerConflicts, Dependency},
+    Overrides, ResolvedScope,
+};
+
+use crate::{
+    cargo_args::ForwardedArgs,
+    display, fingerprint, history, items,
+    progress::{self, Progress},
+    rustflags, target_dir, CargoToml,
+};
+
+/// Returns the packages a cargo command resolves scopes for: the top-level members with
+/// `--workspace`, the selected packages with several `-p`, and the top-level packages of the
+/// ones cargo builds by default without either. `package` is the one taken from the arguments
+/// already.
+pub fn targets(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    package: Option<&String>,
+    args: &ForwardedArgs,
+) -> Result<Vec<String>> {
+    let is_workspace = args
+        .cargo
+        .iter()
+        .any(|arg| arg == "--workspace" || arg == "--all");
+    let mut targets: Vec<String> = if is_workspace {
+        let excluded = args.option_values(None, "--exclude");
+        top_level_members(root_cargo_toml, root_manifest_path, None)?
+            .into_iter()
+            .filter(|name| !excluded.contains(name))
+            .collect()
+    } else {
+        package
+            .into_iter()
+            .cloned()
+            .chain(args.option_values(Some('p'), "--package"))
+            .collect()
+    };
+    if targets.is_empty() && root_cargo_toml.workspace.is_some() {
+        let defaults = crate::default_packages(
+            root_cargo_toml,
+            root_manifest_path,
+            &std::env::current_dir()?,
+        )?;
+        targets = match defaults.len() {
+            0 | 1 => defaults,
+            _ => top_level_members(root_cargo_toml, root_manifest_path, Some(&defaults))?,
+        };
+    }
+    targets.sort();
+    targets.dedup();
+
+    Ok(targets)
+}
+
+// The members no other member depends on, other than through dev-dependencies, counting only
+// the members `within` if given
+fn top_level_members(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    within: Option<&[String]>,
+) -> Result<Vec<String>> {
+    let mut manifests = crate::workspace_member_manifests(root_cargo_toml, root_manifest_path)?;
+    if root_cargo_toml.package.is_some() {
+        manifests.insert(0, root_manifest_path.to_path_buf());
+    }
+
+    let workspace_dependencies = root_cargo_toml
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.dependencies.as_ref());
+    let mut members = Vec::new();
+    let mut depended_on = HashSet::new();
+    for manifest_path in manifests {
+        let cargo_toml = crate::load_manifest(&manifest_path)?;
+        let Some(package) = &cargo_toml.package else {
+            continue;
+        };
+        if within.is_some_and(|within| !within.contains(&package.name)) {
+            continue;
+        }
+        members.push(package.name.clone());
+
+        let mut tables = vec![&cargo_toml.dependencies, &cargo_toml.build_dependencies];
+        for target in cargo_toml
+            .target
+            .iter()
+            .flat_map(|targets| targets.values())
+        {
+            tables.extend([&target.dependencies, &target.build_dependencies]);
+        }
+        for (name, dependency) in tables.into_iter().flatten().flatten() {
+            // Renamed dependencies name the package in `package`, inherited ones in the root
+            let dependency = match dependency {
+                Dependency::Detailed(detail) if detail.workspace => {
+                    workspace_dependencies.and_then(|dependencies| dependencies.get(name))
+                }
+                dependency => Some(dependency),
+            };
+            let package = match dependency {
+                Some(Dependency::Detailed(detail)) => detail.package.as_ref().unwrap_or(name),
+                _ => name,
+            };
+            depended_on.insert(package.clone());
+        }
+    }
+
+    let top_level: Vec<String> = members
+        .iter()
+        .filter(|member| !depended_on.contains(*member))
+        .cloned()
+        .collect();
+    if top_level.is_empty() {
+        return match within {
+            Some(within) => Ok(within.to_vec()),
+            None => fingerprint::package_names(root_cargo_toml, root_manifest_path),
+        };
+    }
+    Ok(top_level)
+}
+
+/// The cfg flags, check-cfg flags and cargo features several targets are built with together.
+pub type Merged = (Vec<String>, Vec<String>, Vec<String>);
+
+/// Resolves every target and merges their scopes as `policy` allows, returning the cfg and
+/// check-cfg flags and the cargo features to build all of them with, or `None` if they are to
+/// be built one by one.
+pub fn merge(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    targets: &[String],
+    overrides: &Overrides,
+    policy: ConsumerConflicts,
+) -> Result<Option<Merged>> {
+    let mut resolved = Vec::new();
+    for target in targets {
+        resolved.push(crate::resolve_scope_with(
+            root_cargo_toml,
+            root_manifest_path,
+            target,
+            overrides,
+        )?);
+    }
+
+    let agree = resolved
+        .windows(2)
+        .all(|pair| pair[0].cfg_args == pair[1].cfg_args);
+    if policy == ConsumerConflicts::Auto && !agree {
+        return Ok(None);
+    }
+
+    let conflicts = union_conflicts(root_cargo_toml, root_manifest_path, &resolved);
+    if !conflicts.is_empty() {
+        anyhow::bail!(
+            "The packages built together enable mutually exclusive features:\n  {}\n\
+             Build them one by one, or set `consumer-conflicts = \"per-target-isolation\"` in the \
+             feature-scope-options of the root manifest",
+            conflicts.join("\n  ")
+        );
+    }
+    let disagreements = disagreements(&resolved);
+    if policy == ConsumerConflicts::Error && !disagreements.is_empty() {
+        anyhow::bail!(
+            "The packages built together disagree on their scopes:\n  {}\n\
+             Build them one by one, or set `consumer-conflicts` to \"union\" or \
+             \"per-target-isolation\" in the feature-scope-options of the root manifest",
+            disagreements.join("\n  ")
+        );
+    }
+
+    let union = |flags: fn(&ResolvedScope) -> &Vec<String>| -> Vec<String> {
+        let pairs: BTreeSet<(&String, &String)> = resolved
+            .iter()
+            .flat_map(|scope| {
+                let flags = flags(scope);
+                flags.iter().step_by(2).zip(flags.iter().skip(1).step_by(2))
+            })
+            .collect();
+        pairs
+            .into_iter()
+            .flat_map(|(flag, value)| [flag.clone(), value.clone()])
+            .collect()
+    };
+    let cargo_features: BTreeSet<&String> = resolved
+        .iter()
+        .flat_map(|scope| &scope.cargo_features)
+        .collect();
+    Ok(Some((
+        union(|scope| &scope.cfg_args),
+        union(|scope| &scope.check_cfg_args),
+        cargo_features.into_iter().cloned().collect(),
+    )))
+}
+
+// What the targets disagree on: the default scope, and the features of each library they use
+fn disagreements(resolved: &[ResolvedScope]) -> Vec<String> {
+    let mut disagreements = Vec::new();
+
+    let (keeping, dropping): (Vec<&ResolvedScope>, Vec<&ResolvedScope>) = resolved
+        .iter()
+        .partition(|scope| scope.enabled_cfgs().any(|cfg| cfg == "__scope_default"));
+    if !keeping.is_empty() && !dropping.is_empty() {
+        disagreements.push(format!(
+            "default scope: kept by {}, dropped by {}",
+            names(&keeping),
+            names(&dropping)
+        ));
+    }
+
+    // Only the targets using a library take part, the others have no say in its features
+    let mut libraries: BTreeMap<&String, BTreeMap<BTreeSet<&String>, Vec<&ResolvedScope>>> =
+        BTreeMap::new();
+    for scope in resolved {
+        for (library, features) in &scope.origins {
+            libraries
+                .entry(library)
+                .or_default()
+                .entry(features.keys().collect())
+                .or_default()
+                .push(scope);
+        }
+    }
+    for (library, selections) in libraries {
+        if selections.len() < 2 {
+            continue;
+        }
+        let selections: Vec<String> = selections
+            .iter()
+            .map(|(features, scopes)| {
+                let features: Vec<&str> = features.iter().map(|f| f.as_str()).collect();
+                let verb = if scopes.len() == 1 {
+                    "enables"
+                } else {
+                    "enable"
+                };
+                format!("{} {verb} {}", names(scopes), features.join(", "))
+            })
+            .collect();
+        disagreements.push(format!("{library}: {}", selections.join("; ")));
+    }
+
+    disagreements
+}
+
+// The exclusive groups and conflicts of a library that the union of the targets' features breaks
+fn union_conflicts(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    resolved: &[ResolvedScope],
+) -> Vec<String> {
+    let mut enabled: BTreeMap<&String, BTreeMap<&String, Vec<&ResolvedScope>>> = BTreeMap::new();
+    for scope in resolved {
+        for (library, features) in &scope.origins {
+            for feature in features.keys() {
+                enabled
+                    .entry(library)
+                    .or_default()
+                    .entry(feature)
+                    .or_default()
+                    .push(scope);
+            }
+        }
+    }
+
+    let mut conflicts = Vec::new();
+    for (library, features) in enabled {
+        // Path dependencies outside the workspace cannot be looked up by name
+        let Ok((_, package)) = items::find_package(root_cargo_toml, root_manifest_path, library)
+        else {
+            continue;
+        };
+        let Some(decl) = package
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.feature_scope_decl.as_ref())
+        else {
+            continue;
+        };
+
+        let mut groups = decl.exclusive.clone().unwrap_or_default();
+        let mut pairs: Vec<Vec<String>> = decl
+            .details
+            .iter()
+            .flat_map(|(feature, details)| {
+                details
+                    .conflicts
+                    .iter()
+                    .map(|conflict| vec![feature.clone(), conflict.clone()])
+            })
+            .collect();
+        pairs.sort();
+        groups.extend(pairs);
+
+        for group in groups {
+            let members: Vec<String> = group
+                .iter()
+                .filter_map(|feature| {
+                    let scopes = features.get(feature)?;
+                    Some(format!("'{feature}' (enabled by {})", names(scopes)))
+                })
+                .collect();
+            if members.len() >= 2 {
+                conflicts.push(format!("{library}: {}", members.join(" and ")));
+            }
+        }
+    }
+
+    conflicts
+}
+
+fn names(scopes: &[&ResolvedScope]) -> String {
+    let names: Vec<&str> = scopes.iter().map(|scope| scope.package.as_str()).collect();
+    names.join(", ")
+}
+
+/// Runs the command once per target with its own scopes, each scope set building into its own
+/// directory below the target directory, then prints which packages passed.
+pub fn run_isolated(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    command: &str,
+    targets: &[String],
+    overrides: &Overrides,
+    mut args: ForwardedArgs,
+    verbose: u8,
+) -> Result<()> {
+    // Every run selects its package alone
+    while args.take_package().is_some() {}
+    while args.take_option(None, "--exclude").is_some() {}
+    args.take_flag("--workspace");
+    args.take_flag("--all");
+    let config_rustflags = rustflags::take_config_rustflags(&mut args)?;
+    let explicit_target_dir = target_dir::take_target_dir_arg(&mut args.cargo);
+    let isolated_base =
+        target_dir::base_target_dir(root_manifest_path.parent().unwrap(), explicit_target_dir);
+    let scope_names = display::ScopeNames::from_workspace(root_cargo_toml, root_manifest_path);
+
+    let mut results = Vec::new();
+    let mut progress = Progress::new("Packages", targets.len());
+    for target in targets {
+        let resolved =
+            crate::resolve_scope_with(root_cargo_toml, root_manifest_path, target, overrides)?;
+        let (cfg_args, check_cfg_args) = (resolved.cfg_args, resolved.check_cfg_args);
+        let mut args = args.clone();
+        args.cargo
+            .extend(crate::cargo_feature_args(&resolved.cargo_features));
+        let target_dir = target_dir::prepare_isolated_target_dir(
+            &isolated_base,
+            &cfg_args,
+            &check_cfg_args,
+            false,
+        )?;
+
+        let mut cargo_cmd = crate::build_cargo_command(
+            command,
+            Some(target),
+            Some(&target_dir),
+            &config_rustflags,
+            &cfg_args,
+            &check_cfg_args,
+            &args,
+        );
+        cargo_cmd.env(crate::env_vars::TARGET_PACKAGE, target);
+
+        progress.header(target);
+        if !progress::is_hidden() {
+            for line in display::describe_invocation(
+                &cargo_cmd,
+                &scope_names,
+                &cfg_args,
+                &check_cfg_args,
+                verbose,
+            ) {
+                println!("{line}");
+            }
+        }
+
+        let entry = history::Entry {
+            root_manifest_path,
+            package: target,
+            scope_names: &scope_names,
+            cfg_args: &cfg_args,
+            check_cfg_args: &check_cfg_args,
+        };
+        let status = history::status(&mut cargo_cmd, entry)?;
+        results.push(status.success());
+    }
+
+    let failed = results.iter().filter(|passed| !**passed).count();
+    let summary = || {
+        anyhow::anyhow!(
+            "cargo {command} failed for {failed} of {} packages",
+            targets.len()
+        )
+    };
+    // The summary would end up between cargo's JSON messages
+    if progress::is_hidden() {
+        return if failed > 0 { Err(summary()) } else { Ok(()) };
+    }
+
+    let width = targets
+        .iter()
+        .map(|target| target.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(7);
+    println!();
+    println!("{:width$}  Result", "Package");
+    for (target, passed) in targets.iter().zip(&results) {
+        let result = if *passed { "✅ passed" } else { "❌ failed" };
+        println!("{target:width$}  {result}");
+    }
+
+    if failed > 0 {
+        println!();
+        return Err(summary());
+    }
+    Ok(())
+}
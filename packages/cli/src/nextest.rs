@@ -0,0 +1,101 @@
+//! cargo-nextest under the resolved scopes.
+//!
+//! `cargo feature-scope nextest [run|list|archive] [ARGS]...` runs `cargo nextest` with the
+//! subcommand ahead of the forwarded arguments, `run` when none is given. Nextest builds the
+//! tests through cargo, which takes the scope flags from the environment like for `test`.
+//!
+//! CI often splits the build from the run: `archive` builds the tests into an archive, and
+//! `run --archive-file` runs them elsewhere without building, so scopes resolved at that point
+//! change nothing in the binaries. An archive therefore gets the scopes it was built with next to
+//! it, in `<ARCHIVE>.feature-scope.json`, and a run from it exports those instead, with a warning
+//! when they differ from the scopes resolved for the run.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cargo_args::ForwardedArgs, display::ScopeNames, fingerprint::fingerprint_cfg_args, i18n,
+};
+
+// The scopes an archive was built with
+#[derive(Serialize, Deserialize)]
+struct ArchivedScopes {
+    package: String,
+    cfg_args: Vec<String>,
+    check_cfg_args: Vec<String>,
+}
+
+/// Removes the nextest subcommand from the front of the cargo arguments and returns
+/// `nextest <SUBCOMMAND>`, the command cargo runs.
+pub fn take_command(args: &mut ForwardedArgs) -> String {
+    let subcommand = match args.cargo.first() {
+        Some(first) if !first.starts_with('-') => args.cargo.remove(0),
+        _ => String::from("run"),
+    };
+    format!("nextest {subcommand}")
+}
+
+/// The `--archive-file` of the nextest arguments, if there is one.
+pub fn archive_file(args: &ForwardedArgs) -> Option<PathBuf> {
+    args.option_values(None, "--archive-file")
+        .into_iter()
+        .next()
+        .map(PathBuf::from)
+}
+
+/// Writes the scopes of an archive next to it.
+pub fn record_archive(
+    archive: &Path,
+    package: &str,
+    cfg_args: &[String],
+    check_cfg_args: &[String],
+) -> Result<()> {
+    let scopes = ArchivedScopes {
+        package: package.to_string(),
+        cfg_args: cfg_args.to_vec(),
+        check_cfg_args: check_cfg_args.to_vec(),
+    };
+    let path = scopes_path(archive);
+    fs::write(&path, serde_json::to_string_pretty(&scopes)? + "\n")
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Returns the scopes a run or listing from `archive` exports, the recorded ones if the
+/// archive has them and else the resolved ones.
+pub fn archived_scopes(
+    archive: &Path,
+    scope_names: &ScopeNames,
+    cfg_args: Vec<String>,
+    check_cfg_args: Vec<String>,
+) -> Result<(Vec<String>, Vec<String>)> {
+    let path = scopes_path(archive);
+    // Archives built without feature-scope carry no scopes
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return Ok((cfg_args, check_cfg_args));
+    };
+    let archived: ArchivedScopes = serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    if fingerprint_cfg_args(&archived.cfg_args, &archived.check_cfg_args)
+        != fingerprint_cfg_args(&cfg_args, &check_cfg_args)
+    {
+        i18n::warn(i18n::Message::ArchiveScopesDiffer {
+            archive: &archive.display().to_string(),
+            package: &archived.package,
+            archived: &scope_names.pretty_args(&archived.cfg_args).join(", "),
+            resolved: &scope_names.pretty_args(&cfg_args).join(", "),
+        });
+    }
+    Ok((archived.cfg_args, archived.check_cfg_args))
+}
+
+// The file holding the scopes of an archive
+fn scopes_path(archive: &Path) -> PathBuf {
+    let mut path = archive.as_os_str().to_owned();
+    path.push(".feature-scope.json");
+    PathBuf::from(path)
+}
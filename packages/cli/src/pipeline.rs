@@ -0,0 +1,97 @@
+//! Several cargo commands under one resolved configuration.
+//!
+//! `cargo feature-scope pipeline <NAME>` runs the steps of a pipeline declared in the
+//! `feature-scope-options` of the root manifest one after the other, like
+//! `ci = ["check --all-targets", "clippy -- -D warnings", "test"]`. The scopes are resolved once
+//! for all steps, `-p`, `--features` and `--no-default-features` included, and arguments given
+//! after the name are added to every step. The first failing step stops the pipeline.
+
+use anyhow::Result;
+use std::path::Path;
+
+use crate::{
+    cargo_args::ForwardedArgs,
+    display, history,
+    progress::{self, Progress},
+    Invocation,
+};
+
+pub fn run(invocation: &Invocation, target_dir: Option<&Path>) -> Result<()> {
+    let mut extra_args = invocation.additional_args.clone();
+    if extra_args.cargo.is_empty() {
+        anyhow::bail!("Usage: cargo feature-scope pipeline <NAME> [CARGO ARGS]...");
+    }
+    let name = extra_args.cargo.remove(0);
+
+    let pipelines = invocation.root_cargo_toml.options().pipeline;
+    let Some(steps) = pipelines.get(&name) else {
+        if pipelines.is_empty() {
+            anyhow::bail!(
+                "No pipelines declared, add them to the `pipeline` table of the feature-scope-options in the root manifest"
+            );
+        }
+        let names: Vec<&str> = pipelines.keys().map(String::as_str).collect();
+        anyhow::bail!(
+            "Unknown pipeline '{name}', declared pipelines are: {}",
+            names.join(", ")
+        );
+    };
+    if steps.is_empty() {
+        anyhow::bail!("Pipeline '{name}' has no steps");
+    }
+
+    let mut progress = Progress::new("Steps", steps.len());
+    for step in steps {
+        let mut words = step.split_whitespace().map(String::from);
+        let Some(command) = words.next() else {
+            anyhow::bail!("Pipeline '{name}' has an empty step");
+        };
+
+        // The extra arguments go before the step's own separator, to cargo itself
+        let mut args = ForwardedArgs::parse(words.collect());
+        args.cargo.extend(extra_args.cargo.iter().cloned());
+        if let Some(program) = &extra_args.program {
+            args.program
+                .get_or_insert_with(Vec::new)
+                .extend(program.iter().cloned());
+        }
+
+        let mut cargo_cmd = crate::build_cargo_command(
+            &command,
+            invocation.package,
+            target_dir,
+            &invocation.config_rustflags,
+            &invocation.cfg_args,
+            &invocation.check_cfg_args,
+            &args,
+        );
+        cargo_cmd.env(crate::env_vars::TARGET_PACKAGE, invocation.target_package);
+
+        progress.header(step);
+        if !progress::is_hidden() {
+            for line in display::describe_invocation(
+                &cargo_cmd,
+                &invocation.scope_names,
+                &invocation.cfg_args,
+                &invocation.check_cfg_args,
+                invocation.verbose,
+            ) {
+                println!("{line}");
+            }
+        }
+
+        let entry = history::Entry {
+            root_manifest_path: invocation.root_manifest_path,
+            package: invocation.target_package,
+            scope_names: &invocation.scope_names,
+            cfg_args: &invocation.cfg_args,
+            check_cfg_args: &invocation.check_cfg_args,
+        };
+        let status = history::status(&mut cargo_cmd, entry)?;
+        if !status.success() {
+            anyhow::bail!("Pipeline '{name}' failed at `{step}` with {status}");
+        }
+    }
+
+    Ok(())
+}
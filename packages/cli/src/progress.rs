@@ -0,0 +1,132 @@
+//! Progress of commands working through many packages or scope sets.
+//!
+//! Steps running cargo, like the scope sets of a matrix, get a header line with their position
+//! and, on a terminal, an estimate of the time left. Steps only resolving scopes are shown as a
+//! bar on stderr, redrawn in place on a terminal and logged as plain lines otherwise. With
+//! `--message-format json`, nothing is printed, so the output stays machine-readable.
+
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+// Set when cargo is asked for JSON messages, which any other output would break up
+static HIDDEN: AtomicBool = AtomicBool::new(false);
+
+// Set while a bar is drawn that other output on stderr has to clear first
+static DRAWN: AtomicBool = AtomicBool::new(false);
+
+const BAR_WIDTH: usize = 20;
+
+/// Hides all progress if the forwarded arguments ask cargo for JSON messages.
+pub fn hide_for_message_format(args: &[String]) {
+    let mut args = args.iter().take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        let format = match arg.strip_prefix("--message-format") {
+            Some("") => args.next().map(String::as_str),
+            Some(value) => value.strip_prefix('='),
+            None => None,
+        };
+        if format.is_some_and(|format| format.starts_with("json")) {
+            HIDDEN.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Whether progress and the other human-readable output around cargo is hidden.
+pub fn is_hidden() -> bool {
+    HIDDEN.load(Ordering::Relaxed)
+}
+
+/// Clears the bar, if one is drawn, so a message printed to stderr gets a line of its own.
+pub fn clear_bar() {
+    if DRAWN.swap(false, Ordering::Relaxed) {
+        let _ = write!(io::stderr(), "\r\x1b[2K");
+    }
+}
+
+pub struct Progress {
+    what: &'static str,
+    total: usize,
+    done: usize,
+    started: Instant,
+    terminal: bool,
+}
+
+impl Progress {
+    pub fn new(what: &'static str, total: usize) -> Self {
+        Progress {
+            what,
+            total,
+            done: 0,
+            started: Instant::now(),
+            terminal: io::stderr().is_terminal(),
+        }
+    }
+
+    /// Prints the header of a step whose output follows it, like `=== [2/5] label`.
+    pub fn header(&mut self, label: &str) {
+        self.done += 1;
+        if is_hidden() {
+            return;
+        }
+        let left = match self.estimate() {
+            Some(left) if self.terminal => format!(" ({} left)", format_duration(left)),
+            _ => String::new(),
+        };
+        println!("=== [{}/{}] {label}{left}", self.done, self.total);
+    }
+
+    /// Shows a step printing nothing itself, replaced by the next one on a terminal.
+    pub fn step(&mut self, label: &str) {
+        self.done += 1;
+        if is_hidden() || self.total < 2 {
+            return;
+        }
+        let mut stderr = io::stderr();
+        if !self.terminal {
+            let _ = writeln!(
+                stderr,
+                "{} [{}/{}] {label}",
+                self.what, self.done, self.total
+            );
+            return;
+        }
+
+        let filled = BAR_WIDTH * (self.done - 1) / self.total;
+        let _ = write!(
+            stderr,
+            "\r\x1b[2K{} [{}{}] {}/{} {label}",
+            self.what,
+            "=".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            self.done,
+            self.total
+        );
+        let _ = stderr.flush();
+        DRAWN.store(true, Ordering::Relaxed);
+    }
+
+    // The time the remaining steps will take if they take as long as the finished ones
+    fn estimate(&self) -> Option<Duration> {
+        let finished = self.done.checked_sub(1).filter(|finished| *finished > 0)?;
+        let per_step = self.started.elapsed() / finished as u32;
+        Some(per_step * (self.total - finished) as u32)
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        clear_bar();
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{seconds}s"),
+        60..=3599 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
@@ -0,0 +1,550 @@
+//! Renaming of a declared feature across the workspace.
+//!
+//! `cargo feature-scope rename-feature <PACKAGE> <OLD> <NEW> [--dry-run]` renames a feature or
+//! preset of a library everywhere the workspace names it: its key in `feature-scope-decl` and
+//! the features, presets, defaults, conflicts and exclusive groups listing it, the selections of
+//! every entry for the library, and in the sources of the library the predicates of the scope
+//! attributes and macros, `declare_feature_scopes!` and the scope cfgs of plain `cfg`s.
+//! Manifests are edited with their formatting and comments kept and sources token by token, so
+//! nothing else changes. Everything is checked before anything is written, and `--dry-run`
+//! prints the changes as a diff instead of writing them.
+
+use anyhow::{Context, Result};
+use proc_macro2::{Delimiter, LineColumn, Spacing, Span, TokenStream, TokenTree};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use toml_edit::{DocumentMut, Formatted, Item, Key, TableLike, Value};
+
+use crate::{
+    cfg_name,
+    conflict::entry_refers,
+    edit::{for_each_entry, own_name, Outcome},
+    init::RESERVED_KEYS,
+    items, lock, CargoToml,
+};
+
+const USAGE: &str = "Usage: cargo feature-scope rename-feature <PACKAGE> <OLD> <NEW> [--dry-run]";
+
+// The attributes whose arguments start with a scope predicate
+const PREDICATE_ATTRIBUTES: [&str; 4] = [
+    "feature_scope",
+    "feature_scope_bench",
+    "feature_scope_default",
+    "feature_scope_serde",
+];
+
+// The macros of `predicate => ...` arms
+const ARM_MACROS: [&str; 2] = ["feature_scope_select", "feature_scope_switch"];
+
+// The keys of a feature's table listing other features
+const FEATURE_LISTS: [&str; 3] = ["deps", "requires", "conflicts"];
+
+// A file with its content before and after the rename
+struct Change {
+    path: PathBuf,
+    before: String,
+    after: String,
+}
+
+pub fn run(root_cargo_toml: &CargoToml, root_manifest_path: &Path, args: &[String]) -> Result<()> {
+    let mut dry_run = false;
+    let mut positional = Vec::new();
+    for arg in args {
+        if arg == "--dry-run" {
+            dry_run = true;
+        } else if arg.starts_with('-') {
+            anyhow::bail!(USAGE);
+        } else {
+            positional.push(arg.as_str());
+        }
+    }
+    let [library, old, new] = positional[..] else {
+        anyhow::bail!(USAGE);
+    };
+
+    if old == "default" {
+        anyhow::bail!("The defaults cannot be renamed");
+    }
+    if RESERVED_KEYS.contains(&new) {
+        anyhow::bail!("'{new}' is reserved in feature-scope-decl");
+    }
+    cfg_name::validate_feature_name(library, new)?;
+    let (library_manifest, package) =
+        items::find_package(root_cargo_toml, root_manifest_path, library)?;
+    let decl = package
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.feature_scope_decl.as_ref())
+        .with_context(|| format!("Package '{library}' declares no scopes"))?;
+    if !decl.declares(old) {
+        anyhow::bail!("Package '{library}' does not declare '{old}'");
+    }
+    if decl.declares(new) {
+        anyhow::bail!("Package '{library}' already declares '{new}'");
+    }
+
+    let mut changes = Vec::new();
+    let mut manifests = crate::workspace_member_manifests(root_cargo_toml, root_manifest_path)?;
+    if root_cargo_toml.package.is_some() {
+        manifests.insert(0, root_manifest_path.to_path_buf());
+    }
+    for manifest_path in manifests {
+        let before = fs::read_to_string(&manifest_path)
+            .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+        let mut document: DocumentMut = before
+            .parse()
+            .with_context(|| format!("Failed to parse {}", manifest_path.display()))?;
+        if manifest_path == library_manifest {
+            rename_in_decl(&mut document, old, new);
+        }
+        rename_in_entries(&mut document, &manifest_path, library, old, new)?;
+        let after = document.to_string();
+        if after != before {
+            changes.push(Change {
+                path: manifest_path,
+                before,
+                after,
+            });
+        }
+    }
+
+    let root_dir = root_manifest_path.parent().unwrap();
+    let package_dir = library_manifest.parent().unwrap();
+    let mut files = Vec::new();
+    items::collect_sources(package_dir, package_dir, &mut files)?;
+    files.sort();
+    let mut unrepresentable = Vec::new();
+    for file in files {
+        let before = fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let tokens: TokenStream = before
+            .parse()
+            .map_err(|err| anyhow::anyhow!("Failed to parse {}: {err}", file.display()))?;
+
+        let mut renamer = Renamer::new(&before, old, new);
+        renamer.walk(&tokens.into_iter().collect::<Vec<_>>());
+        let shown = file.strip_prefix(root_dir).unwrap_or(&file);
+        unrepresentable.extend(
+            renamer
+                .unrepresentable
+                .iter()
+                .map(|line| format!("{}:{line}", shown.display())),
+        );
+        let after = apply_edits(&before, &renamer.edits);
+        if after != before {
+            changes.push(Change {
+                path: file,
+                before,
+                after,
+            });
+        }
+    }
+    if !unrepresentable.is_empty() {
+        anyhow::bail!(
+            "'{new}' is not an identifier, so it cannot replace '{old}' in the predicates at {}; \
+             rewrite those with `feature = \"{new}\"` by hand or choose an identifier",
+            unrepresentable.join(", ")
+        );
+    }
+
+    for change in &changes {
+        let shown = change.path.strip_prefix(root_dir).unwrap_or(&change.path);
+        if dry_run {
+            print_diff(shown, change);
+        } else {
+            fs::write(&change.path, &change.after)
+                .with_context(|| format!("Failed to write {}", change.path.display()))?;
+            println!("Updated {}", change.path.display());
+        }
+    }
+    if !dry_run && root_dir.join(lock::FILE_NAME).is_file() {
+        println!(
+            "⚠️  {} still records '{old}', run `cargo feature-scope update`",
+            lock::FILE_NAME
+        );
+    }
+    Ok(())
+}
+
+// Renames the key of the feature or preset and the lists of features naming it
+fn rename_in_decl(document: &mut DocumentMut, old: &str, new: &str) {
+    let Some(decl) = document
+        .get_mut("package")
+        .and_then(Item::as_table_like_mut)
+        .and_then(|package| package.get_mut("metadata"))
+        .and_then(Item::as_table_like_mut)
+        .and_then(|metadata| metadata.get_mut("feature-scope-decl"))
+        .and_then(Item::as_table_like_mut)
+    else {
+        return;
+    };
+
+    let rename = |feature: &str| (feature == old).then(|| new.to_string());
+    rename_key(decl, old, new);
+    for (key, item) in decl.iter_mut() {
+        match (key.get(), item.as_table_like_mut()) {
+            ("stub-disabled-items", _) => {}
+            ("presets", Some(presets)) => {
+                rename_key(presets, old, new);
+                for (_, members) in presets.iter_mut() {
+                    rename_strings(members, &rename);
+                }
+            }
+            (_, Some(details)) => {
+                for list in FEATURE_LISTS {
+                    if let Some(features) = details.get_mut(list) {
+                        rename_strings(features, &rename);
+                    }
+                }
+            }
+            // `default`, `exclusive` and the arrays of dependencies
+            (_, None) => rename_strings(item, &rename),
+        }
+    }
+}
+
+// Renames the feature in the selections of a consumer's entries for the library
+fn rename_in_entries(
+    document: &mut DocumentMut,
+    manifest_path: &Path,
+    library: &str,
+    old: &str,
+    new: &str,
+) -> Result<()> {
+    let own_name = own_name(document);
+    let Some(entries) = document
+        .get_mut("package")
+        .and_then(Item::as_table_like_mut)
+        .and_then(|package| package.get_mut("metadata"))
+        .and_then(Item::as_table_like_mut)
+        .and_then(|metadata| metadata.get_mut("feature-scope"))
+    else {
+        return Ok(());
+    };
+
+    for_each_entry(entries, |entry| {
+        if entry_refers(entry, manifest_path, own_name.as_deref(), library)? {
+            if let Some(features) = entry.get_mut("features") {
+                rename_strings(features, &|selection| rename_terms(selection, old, new));
+            }
+        }
+        Ok(Outcome::Kept)
+    })
+}
+
+// A selection with the feature renamed in its terms, like `old`, `-old` or `tls + old`
+fn rename_terms(selection: &str, old: &str, new: &str) -> Option<String> {
+    let mut renamed = false;
+    let terms: Vec<String> = selection
+        .split(' ')
+        .map(|term| {
+            let (sign, feature) = term
+                .strip_prefix('-')
+                .map_or(("", term), |feature| ("-", feature));
+            if feature == old {
+                renamed = true;
+                format!("{sign}{new}")
+            } else {
+                term.to_string()
+            }
+        })
+        .collect();
+    renamed.then(|| terms.join(" "))
+}
+
+// Renames a key in place, keeping the order of the table and the formatting of the key
+fn rename_key(table: &mut dyn TableLike, old: &str, new: &str) {
+    if !table.contains_key(old) {
+        return;
+    }
+    let keys: Vec<Key> = table
+        .iter()
+        .map(|(key, _)| table.key(key).unwrap().clone())
+        .collect();
+    for key in keys {
+        let item = table.remove(key.get()).unwrap();
+        let key = if key.get() == old {
+            Key::new(new)
+                .with_leaf_decor(key.leaf_decor().clone())
+                .with_dotted_decor(key.dotted_decor().clone())
+        } else {
+            key
+        };
+        table.entry_format(&key).or_insert(item);
+    }
+}
+
+// Renames the strings of an array, also in nested arrays and inline tables like `{ prefer = [...] }`
+fn rename_strings(item: &mut Item, rename: &dyn Fn(&str) -> Option<String>) {
+    if let Some(value) = item.as_value_mut() {
+        rename_value(value, rename);
+    }
+}
+
+fn rename_value(value: &mut Value, rename: &dyn Fn(&str) -> Option<String>) {
+    match value {
+        Value::String(string) => {
+            if let Some(renamed) = rename(string.value()) {
+                let decor = string.decor().clone();
+                *string = Formatted::new(renamed);
+                *string.decor_mut() = decor;
+            }
+        }
+        Value::Array(array) => {
+            for value in array.iter_mut() {
+                rename_value(value, rename);
+            }
+        }
+        Value::InlineTable(table) => {
+            for (_, value) in table.iter_mut() {
+                rename_value(value, rename);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Collects the edits renaming the feature in a source file, by the byte ranges they replace
+struct Renamer<'a> {
+    content: &'a str,
+    old: &'a str,
+    new: &'a str,
+    new_is_ident: bool,
+    old_cfg: String,
+    new_cfg: String,
+    edits: BTreeMap<usize, (usize, String)>,
+    // The lines of predicates the new name cannot be written in, as it is no identifier
+    unrepresentable: Vec<usize>,
+}
+
+impl<'a> Renamer<'a> {
+    fn new(content: &'a str, old: &'a str, new: &'a str) -> Self {
+        Renamer {
+            content,
+            old,
+            new,
+            new_is_ident: syn::parse_str::<syn::Ident>(new).is_ok(),
+            old_cfg: cfg_name::scope_cfg(old),
+            new_cfg: cfg_name::scope_cfg(new),
+            edits: BTreeMap::new(),
+            unrepresentable: Vec::new(),
+        }
+    }
+
+    fn walk(&mut self, tokens: &[TokenTree]) {
+        for (index, token) in tokens.iter().enumerate() {
+            match token {
+                // `#[...]` and `#![...]`
+                TokenTree::Punct(punct) if punct.as_char() == '#' => {
+                    let next = match tokens.get(index + 1) {
+                        Some(TokenTree::Punct(bang)) if bang.as_char() == '!' => {
+                            tokens.get(index + 2)
+                        }
+                        next => next,
+                    };
+                    if let Some(TokenTree::Group(group)) = next {
+                        if group.delimiter() == Delimiter::Bracket {
+                            self.attribute(group.stream());
+                        }
+                    }
+                }
+                TokenTree::Ident(ident) => {
+                    if *ident == self.old_cfg {
+                        self.replace(ident.span(), self.new_cfg.clone());
+                    }
+                    if let (Some(TokenTree::Punct(bang)), Some(TokenTree::Group(group))) =
+                        (tokens.get(index + 1), tokens.get(index + 2))
+                    {
+                        if bang.as_char() == '!' {
+                            self.invocation(&ident.to_string(), group.stream());
+                        }
+                    }
+                }
+                TokenTree::Group(group) => {
+                    self.walk(&group.stream().into_iter().collect::<Vec<_>>());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // The scope attributes, also when written with a path like `feature_scope::feature_scope`
+    fn attribute(&mut self, stream: TokenStream) {
+        let tokens: Vec<TokenTree> = stream.into_iter().collect();
+        let Some(TokenTree::Group(arguments)) = tokens.last() else {
+            return;
+        };
+        let name = tokens.iter().rev().find_map(|token| match token {
+            TokenTree::Ident(ident) => Some(ident.to_string()),
+            _ => None,
+        });
+        if arguments.delimiter() == Delimiter::Parenthesis
+            && name.is_some_and(|name| PREDICATE_ATTRIBUTES.contains(&name.as_str()))
+        {
+            self.arguments(arguments.stream(), true);
+        }
+    }
+
+    fn invocation(&mut self, name: &str, stream: TokenStream) {
+        let tokens: Vec<TokenTree> = stream.clone().into_iter().collect();
+        if name == "cfg_scope" {
+            self.arguments(stream, false);
+        } else if name == "declare_feature_scopes" {
+            self.declarations(&tokens);
+        } else if ARM_MACROS.contains(&name) {
+            // A predicate runs from the start of its arm to the `=>`, and has no comma outside
+            // of parentheses, unlike the items of the arm before it
+            let mut start = 0;
+            for (index, token) in tokens.iter().enumerate() {
+                let TokenTree::Punct(punct) = token else {
+                    continue;
+                };
+                let is_arrow = punct.as_char() == '='
+                    && punct.spacing() == Spacing::Joint
+                    && matches!(tokens.get(index + 1), Some(TokenTree::Punct(next)) if next.as_char() == '>');
+                if punct.as_char() == ',' {
+                    start = index + 1;
+                } else if is_arrow {
+                    self.predicate(&tokens[start..index], false);
+                }
+            }
+        }
+    }
+
+    // Arguments starting with a predicate, followed by options like `feature = "old"`
+    fn arguments(&mut self, stream: TokenStream, in_attribute: bool) {
+        let tokens: Vec<TokenTree> = stream.into_iter().collect();
+        let segments = tokens
+            .split(|token| matches!(token, TokenTree::Punct(punct) if punct.as_char() == ','));
+        for (index, segment) in segments.enumerate() {
+            match segment {
+                [TokenTree::Ident(key), TokenTree::Punct(eq), TokenTree::Literal(literal)]
+                    if eq.as_char() == '=' =>
+                {
+                    let value =
+                        syn::parse2::<syn::LitStr>(TokenTree::Literal(literal.clone()).into());
+                    if key == "feature" && value.is_ok_and(|value| value.value() == self.old) {
+                        self.replace(literal.span(), format!("{:?}", self.new));
+                    }
+                }
+                _ if index == 0 => self.predicate(segment, in_attribute && segment.len() == 1),
+                _ => {}
+            }
+        }
+    }
+
+    // A predicate like `all(old, not(b))`, `alone` when it is the feature itself in an
+    // attribute, which can take the `feature = "..."` form
+    fn predicate(&mut self, tokens: &[TokenTree], alone: bool) {
+        for (index, token) in tokens.iter().enumerate() {
+            match token {
+                TokenTree::Ident(ident)
+                    if ident.to_string().trim_start_matches("r#") == self.old
+                        && !matches!(tokens.get(index + 1), Some(TokenTree::Group(_))) =>
+                {
+                    if self.new_is_ident {
+                        self.replace(ident.span(), self.new.to_string());
+                    } else if alone {
+                        self.replace(ident.span(), format!("feature = {:?}", self.new));
+                    } else {
+                        self.unrepresentable.push(ident.span().start().line);
+                    }
+                }
+                TokenTree::Group(group) => {
+                    self.predicate(&group.stream().into_iter().collect::<Vec<_>>(), false);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // `declare_feature_scopes!` names nothing but features
+    fn declarations(&mut self, tokens: &[TokenTree]) {
+        for token in tokens {
+            match token {
+                TokenTree::Ident(ident)
+                    if ident.to_string().trim_start_matches("r#") == self.old =>
+                {
+                    if self.new_is_ident {
+                        self.replace(ident.span(), self.new.to_string());
+                    } else {
+                        self.unrepresentable.push(ident.span().start().line);
+                    }
+                }
+                TokenTree::Group(group) => {
+                    self.declarations(&group.stream().into_iter().collect::<Vec<_>>());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn replace(&mut self, span: Span, text: String) {
+        let start = offset(self.content, span.start());
+        self.edits
+            .insert(start, (offset(self.content, span.end()), text));
+    }
+}
+
+/// The byte offset of a position of the spans in a source, whose columns count characters.
+pub fn offset(content: &str, position: LineColumn) -> usize {
+    let line_start: usize = content
+        .split_inclusive('\n')
+        .take(position.line - 1)
+        .map(str::len)
+        .sum();
+    content[line_start..]
+        .char_indices()
+        .nth(position.column)
+        .map_or(content.len(), |(index, _)| line_start + index)
+}
+
+/// Replaces byte ranges of a source, given by their start with their end and replacement.
+pub fn apply_edits(content: &str, edits: &BTreeMap<usize, (usize, String)>) -> String {
+    let mut edited = String::with_capacity(content.len());
+    let mut position = 0;
+    for (start, (end, text)) in edits {
+        edited.push_str(&content[position..*start]);
+        edited.push_str(text);
+        position = *end;
+    }
+    edited.push_str(&content[position..]);
+    edited
+}
+
+// Prints the lines a change replaces, which keeps the number of lines
+fn print_diff(path: &Path, change: &Change) {
+    println!("--- a/{0}\n+++ b/{0}", path.display());
+    let before: Vec<&str> = change.before.lines().collect();
+    let after: Vec<&str> = change.after.lines().collect();
+    let len = before.len().max(after.len());
+    let mut line = 0;
+    while line < len {
+        if before.get(line) == after.get(line) {
+            line += 1;
+            continue;
+        }
+        let start = line;
+        while line < len && before.get(line) != after.get(line) {
+            line += 1;
+        }
+        let removed = &before[start.min(before.len())..line.min(before.len())];
+        let added = &after[start.min(after.len())..line.min(after.len())];
+        println!(
+            "@@ -{},{} +{},{} @@",
+            start + 1,
+            removed.len(),
+            start + 1,
+            added.len()
+        );
+        for removed in removed {
+            println!("-{removed}");
+        }
+        for added in added {
+            println!("+{added}");
+        }
+    }
+}
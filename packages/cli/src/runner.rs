@@ -0,0 +1,76 @@
+//! Running the built binaries under a debugger or profiler.
+//!
+//! `--runner "gdb --args"` on `run`, `test` or `bench` sets cargo's `target.<triple>.runner` for
+//! the build target through `--config`: cargo builds the binary with the resolved scopes and
+//! runs it as `gdb --args <binary> [ARGS]...`, so the debugged binary is never a stale artifact
+//! of a plain `cargo build`. Like cargo's setting, the runner is split at whitespace, and the
+//! `<triple>` form takes precedence over runners configured for a `cfg(...)`.
+//!
+//! `cargo feature-scope profile [--profiler perf|samply] [CARGO ARGS]... [-- ARGS]...` is `run`
+//! under `perf record -g` or `samply record`, in release mode unless `--profile` says otherwise.
+
+use anyhow::{Context, Result};
+use std::{env, path::Path, process};
+
+use crate::cargo_args::ForwardedArgs;
+
+/// Returns the runner of a profiler, `perf record -g` for `perf` and `samply record` for
+/// `samply`, taking anything else as the runner itself.
+pub fn profiler_runner(profiler: &str) -> String {
+    match profiler {
+        "perf" => String::from("perf record -g --"),
+        "samply" => String::from("samply record --"),
+        other => other.to_string(),
+    }
+}
+
+/// Returns the `--config` arguments setting the runner for each target the command builds.
+pub fn config_args(runner: &str, args: &ForwardedArgs) -> Result<Vec<String>> {
+    let words: Vec<&str> = runner.split_whitespace().collect();
+    if words.is_empty() {
+        anyhow::bail!("--runner takes a command, like --runner \"gdb --args\"");
+    }
+    let words: toml_edit::Array = words.into_iter().collect();
+
+    let mut triples = args.option_values(None, "--target");
+    if triples.is_empty() {
+        triples.push(host_triple()?);
+    }
+    Ok(triples
+        .into_iter()
+        .flat_map(|triple| {
+            let key = toml_edit::Key::new(target_name(&triple));
+            [
+                String::from("--config"),
+                format!("target.{key}.runner = {words}"),
+            ]
+        })
+        .collect())
+}
+
+/// Returns the name of a `--target` in the `target.<name>` tables of the cargo config.
+pub fn target_name(triple: &str) -> String {
+    // Cargo names custom targets given as a JSON file by the file's stem
+    match triple.strip_suffix(".json") {
+        Some(path) => Path::new(path)
+            .file_name()
+            .map_or(triple.to_string(), |name| {
+                name.to_string_lossy().into_owned()
+            }),
+        None => triple.to_string(),
+    }
+}
+
+/// Returns the triple cargo builds for without `--target`, from `rustc -vV`.
+pub fn host_triple() -> Result<String> {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    let output = process::Command::new(&rustc)
+        .arg("-vV")
+        .output()
+        .with_context(|| format!("Failed to execute {rustc}"))?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(String::from)
+        .with_context(|| format!("{rustc} -vV does not name the host triple"))
+}
@@ -0,0 +1,351 @@
+//! Composition of the rustflags passed to cargo.
+//!
+//! Cargo reads rustflags from the environment in preference to any `build.rustflags` of its
+//! config files or of `--config`, so setting our environment variable would silently drop them.
+//! Instead, they are merged in front of the scope flags: the ones of the config files cargo
+//! would read, then the ones found in `--config` arguments, which are taken out of the forwarded
+//! arguments, like cargo joining the lists with the closest ones last.
+//!
+//! The flags are passed in `CARGO_ENCODED_RUSTFLAGS`, separated by `0x1f`, so flags containing
+//! spaces survive. `RUSTFLAGS` gets the same flags separated by spaces for cargo before 1.55,
+//! which newer ones ignore in favor of the encoded variable.
+//!
+//! Without `--target`, cargo passes these variables to every rustc it runs, including the ones
+//! for build scripts and proc macros, which therefore see the scope cfgs too. With `--target`,
+//! the flags go to a `--config target.<triple>.rustflags` array instead, one quoted string per
+//! flag so spaces survive there too. Cargo joins it with the `target.<triple>.rustflags` and
+//! matching `target.'cfg(...)'.rustflags` of its config files and keeps it off the host
+//! artifacts, so these are compiled the same whatever the scopes and can be shared between scope
+//! sets.
+
+use anyhow::{Context, Result};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process,
+};
+
+use feature_scope_core::platform;
+
+use crate::{cargo_args::ForwardedArgs, i18n, runner};
+
+/// Extracts `build.rustflags` from the `--config` arguments forwarded to cargo.
+///
+/// Inline `--config build.rustflags=...` arguments are removed, since the merged `RUSTFLAGS`
+/// replaces them. `--config <file>` arguments are kept for their other settings, and their
+/// rustflags are merged as well.
+pub fn take_config_rustflags(forwarded: &mut ForwardedArgs) -> Result<Vec<String>> {
+    // The rustflags of the `--target` triples are joined with the scope flags, not overridden
+    let triples = forwarded.option_values(None, "--target");
+    let args = &mut forwarded.cargo;
+    let mut rustflags = Vec::new();
+    let mut index = 0;
+
+    while index < args.len() {
+        if args[index] == "--" {
+            break;
+        }
+
+        let (value, width) = if args[index] == "--config" && index + 1 < args.len() {
+            (args[index + 1].clone(), 2)
+        } else if let Some(value) = args[index].strip_prefix("--config=") {
+            (value.to_string(), 1)
+        } else {
+            index += 1;
+            continue;
+        };
+
+        if let Ok(table) = value.parse::<toml::Table>() {
+            // Inline `KEY=VALUE` form
+            warn_target_rustflags(&table, &value, &triples);
+            if let Some(flags) = build_rustflags(&table, &value)? {
+                rustflags.extend(flags);
+                if table.len() == 1 && table["build"].as_table().is_some_and(|b| b.len() == 1) {
+                    args.drain(index..index + width);
+                    continue;
+                }
+            }
+        } else if Path::new(&value).is_file() {
+            // Config file form
+            let content = std::fs::read_to_string(&value)
+                .with_context(|| format!("Failed to read {value}"))?;
+            let table: toml::Table =
+                toml::from_str(&content).with_context(|| format!("Failed to parse {value}"))?;
+            warn_target_rustflags(&table, &value, &triples);
+            if let Some(flags) = build_rustflags(&table, &value)? {
+                rustflags.extend(flags);
+            }
+        }
+
+        index += width;
+    }
+
+    Ok(rustflags)
+}
+
+/// Joins the user's rustflags and the scope flags into the final list of rustflags.
+///
+/// `CARGO_ENCODED_RUSTFLAGS` or `RUSTFLAGS` already present in the environment take precedence
+/// over the rustflags of the cargo config, mirroring cargo's own precedence rules. Scope flags
+/// in them, as left by an outer `cargo feature-scope` run, are dropped so that every scope cfg
+/// is passed exactly once.
+pub fn compose(
+    config_rustflags: &[String],
+    cfg_args: &[String],
+    check_cfg_args: &[String],
+) -> Vec<String> {
+    let mut flags = Vec::new();
+
+    if let Some(env_rustflags) = env_rustflags() {
+        if !config_rustflags.is_empty() {
+            i18n::warn(i18n::Message::ConfigRustflagsShadowed);
+        }
+        let (env_rustflags, had_scope_flags) = strip_scope_flags(env_rustflags);
+        if had_scope_flags {
+            i18n::warn(i18n::Message::EnvScopeFlagsIgnored);
+        }
+        flags.extend(env_rustflags);
+    } else {
+        // Cargo reports broken config files itself
+        if let Ok(current_dir) = env::current_dir() {
+            flags.extend(config_file_rustflags(&current_dir).unwrap_or_default());
+        }
+        flags.extend(config_rustflags.iter().cloned());
+    }
+
+    flags.extend(cfg_args.iter().cloned());
+    flags.extend(check_cfg_args.iter().cloned());
+
+    flags
+}
+
+/// Gives `cmd` the rustflags, in `CARGO_ENCODED_RUSTFLAGS` and as a fallback in `RUSTFLAGS`.
+pub fn apply(cmd: &mut process::Command, flags: &[String]) {
+    cmd.env("CARGO_ENCODED_RUSTFLAGS", flags.join("\x1f"));
+    cmd.env("RUSTFLAGS", flags.join(" "));
+}
+
+/// Gives `cmd` the rustflags for the artifacts of the `--target` triples only.
+///
+/// Every triple gets its flags in a `--config target.<triple>.rustflags` argument, after the ones
+/// of `CARGO_TARGET_<TRIPLE>_RUSTFLAGS`, and the variables cargo would prefer to them are
+/// removed. Their flags are moved to each triple, or else the `build.rustflags` cargo would have
+/// used, which it ignores as soon as a triple has rustflags of its own.
+pub fn apply_to_targets(
+    cmd: &mut process::Command,
+    triples: &[String],
+    config_rustflags: &[String],
+    cfg_args: &[String],
+    check_cfg_args: &[String],
+) {
+    let env_rustflags = env_rustflags().map(|env_rustflags| {
+        if !config_rustflags.is_empty() {
+            i18n::warn(i18n::Message::ConfigRustflagsShadowed);
+        }
+        let (env_rustflags, had_scope_flags) = strip_scope_flags(env_rustflags);
+        if had_scope_flags {
+            i18n::warn(i18n::Message::EnvScopeFlagsIgnored);
+        }
+        env_rustflags
+    });
+    cmd.env_remove("CARGO_ENCODED_RUSTFLAGS");
+    cmd.env_remove("RUSTFLAGS");
+
+    // Cargo reports broken config files itself
+    let tables = env::current_dir()
+        .ok()
+        .and_then(|current_dir| config_tables(&current_dir).ok())
+        .unwrap_or_default();
+
+    for triple in triples {
+        let name = runner::target_name(triple);
+        let var = format!(
+            "CARGO_TARGET_{}_RUSTFLAGS",
+            name.to_uppercase().replace(['-', '.'], "_")
+        );
+        let (target_env_rustflags, _) = strip_scope_flags(
+            env::var(&var)
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(String::from)
+                .collect(),
+        );
+        cmd.env_remove(&var);
+        let has_target_rustflags = !target_env_rustflags.is_empty()
+            || tables
+                .iter()
+                .any(|(_, table)| has_target_rustflags(table, triple, &name));
+
+        let mut flags = match &env_rustflags {
+            Some(env_rustflags) => env_rustflags.clone(),
+            None if has_target_rustflags => Vec::new(),
+            None => {
+                let mut build_flags = Vec::new();
+                for (origin, table) in &tables {
+                    if let Ok(Some(table_flags)) = build_rustflags(table, origin) {
+                        build_flags.extend(table_flags);
+                    }
+                }
+                build_flags.extend(config_rustflags.iter().cloned());
+                build_flags
+            }
+        };
+        flags.extend(target_env_rustflags);
+        flags.extend(cfg_args.iter().cloned());
+        flags.extend(check_cfg_args.iter().cloned());
+        let flags: toml_edit::Array = flags.iter().map(String::as_str).collect();
+        cmd.arg("--config").arg(format!(
+            "target.{}.rustflags = {flags}",
+            toml_edit::Key::new(name.as_str())
+        ));
+    }
+}
+
+// Whether a config file gives the triple rustflags of its own, in its table or in a
+// `cfg(...)` table the triple matches, which makes cargo ignore `build.rustflags`
+fn has_target_rustflags(table: &toml::Table, triple: &str, name: &str) -> bool {
+    let Some(targets) = table.get("target").and_then(|targets| targets.as_table()) else {
+        return false;
+    };
+
+    targets.iter().any(|(key, target)| {
+        target.get("rustflags").is_some()
+            && (key == name
+                || (key.starts_with("cfg(")
+                    && platform::matches(key, Some(triple)).unwrap_or_default()))
+    })
+}
+
+// The rustflags of the environment, in cargo's order of precedence
+fn env_rustflags() -> Option<Vec<String>> {
+    if let Ok(encoded) = env::var("CARGO_ENCODED_RUSTFLAGS") {
+        if !encoded.is_empty() {
+            return Some(encoded.split('\x1f').map(String::from).collect());
+        }
+    }
+    let rustflags = env::var("RUSTFLAGS").unwrap_or_default();
+    if rustflags.trim().is_empty() {
+        return None;
+    }
+    Some(rustflags.split_whitespace().map(String::from).collect())
+}
+
+/// Returns the config files cargo reads for builds started in `dir`, closest first, ending with
+/// the one in the cargo home.
+pub fn cargo_config_files(dir: &Path) -> Vec<PathBuf> {
+    let cargo_home = env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::home_dir().map(|home| home.join(".cargo")));
+    let mut files: Vec<PathBuf> = dir
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(cargo_home)
+        .flat_map(|dir| ["config.toml", "config"].map(|name| dir.join(name)))
+        .filter(|path| path.is_file())
+        .collect();
+    let mut seen = Vec::new();
+    files.retain(|path| {
+        let path = path.canonicalize().unwrap_or_else(|_| path.clone());
+        let is_new = !seen.contains(&path);
+        seen.push(path);
+        is_new
+    });
+    files
+}
+
+// The `build.rustflags` of the config files, joined with the closest ones last like cargo does
+fn config_file_rustflags(dir: &Path) -> Result<Vec<String>> {
+    let mut rustflags = Vec::new();
+    for (origin, table) in config_tables(dir)? {
+        warn_target_rustflags(&table, &origin, &[]);
+        if let Some(flags) = build_rustflags(&table, &origin)? {
+            rustflags.extend(flags);
+        }
+    }
+    Ok(rustflags)
+}
+
+// The config files cargo reads in `dir` by their paths, the closest ones last
+fn config_tables(dir: &Path) -> Result<Vec<(String, toml::Table)>> {
+    let mut tables = Vec::new();
+    for path in cargo_config_files(dir).into_iter().rev() {
+        let origin = path.display().to_string();
+        let content =
+            std::fs::read_to_string(&path).with_context(|| format!("Failed to read {origin}"))?;
+        let table: toml::Table =
+            toml::from_str(&content).with_context(|| format!("Failed to parse {origin}"))?;
+        tables.push((origin, table));
+    }
+    Ok(tables)
+}
+
+// Removes `--cfg __scope_*` and `--check-cfg cfg(__scope_*)` flags, in both spellings
+fn strip_scope_flags(rustflags: Vec<String>) -> (Vec<String>, bool) {
+    let is_scope_value =
+        |value: &str| value.starts_with("__scope_") || value.starts_with("cfg(__scope_");
+
+    let mut kept = Vec::new();
+    let mut stripped = false;
+    let mut words = rustflags.into_iter().peekable();
+    while let Some(word) = words.next() {
+        if word == "--cfg" || word == "--check-cfg" {
+            if words.peek().is_some_and(|value| is_scope_value(value)) {
+                words.next();
+                stripped = true;
+                continue;
+            }
+        } else if let Some((_, value)) = word
+            .split_once('=')
+            .filter(|(flag, _)| *flag == "--cfg" || *flag == "--check-cfg")
+        {
+            if is_scope_value(value) {
+                stripped = true;
+                continue;
+            }
+        }
+
+        kept.push(word);
+    }
+
+    (kept, stripped)
+}
+
+fn build_rustflags(table: &toml::Table, origin: &str) -> Result<Option<Vec<String>>> {
+    let Some(value) = table
+        .get("build")
+        .and_then(|build| build.as_table())
+        .and_then(|build| build.get("rustflags"))
+    else {
+        return Ok(None);
+    };
+
+    let flags = match value {
+        toml::Value::String(flags) => flags.split_whitespace().map(String::from).collect(),
+        toml::Value::Array(flags) => flags
+            .iter()
+            .map(|flag| {
+                flag.as_str().map(String::from).ok_or_else(|| {
+                    anyhow::anyhow!("build.rustflags in '{origin}' must contain only strings")
+                })
+            })
+            .collect::<Result<Vec<_>>>()?,
+        _ => anyhow::bail!("build.rustflags in '{origin}' must be a string or an array of strings"),
+    };
+
+    Ok(Some(flags))
+}
+
+fn warn_target_rustflags(table: &toml::Table, origin: &str, triples: &[String]) {
+    let Some(targets) = table.get("target").and_then(|target| target.as_table()) else {
+        return;
+    };
+
+    for (target, config) in targets {
+        let built = triples
+            .iter()
+            .any(|triple| runner::target_name(triple) == *target);
+        if config.get("rustflags").is_some() && !built {
+            i18n::warn(i18n::Message::TargetRustflagsOverridden { target, origin });
+        }
+    }
+}
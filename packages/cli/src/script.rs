@@ -0,0 +1,124 @@
+//! Script emission for hosts without `cargo-feature-scope`.
+//!
+//! With `--no-run-cargo`, the command that would be spawned is printed as a self-contained
+//! shell or batch script instead. The script sets the computed environment and runs the command,
+//! so build farms that pre-approve exact commands can review it and run it without installing
+//! this tool. Scopes are resolved when the script is generated, so it has to be regenerated
+//! whenever the scope configuration changes.
+//!
+//! `cargo feature-scope env` prints only the environment, as lines to `eval` in a shell or to
+//! run in a batch file, so other tools can be run with the scopes without going through `exec`.
+
+use std::{ffi::OsStr, process};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    Sh,
+    Bat,
+}
+
+impl Format {
+    /// The format matching the current platform.
+    pub fn native() -> Self {
+        if cfg!(windows) {
+            Format::Bat
+        } else {
+            Format::Sh
+        }
+    }
+}
+
+/// Renders `cmd` as a script, with `comments` (such as the resolved scopes) in its header.
+pub fn render(cmd: &process::Command, comments: &[String], format: Format) -> String {
+    let command_line = std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| quote(&arg.to_string_lossy(), format))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut lines = Vec::new();
+    match format {
+        Format::Sh => {
+            lines.push(String::from("#!/bin/sh"));
+            lines.push(String::from("# Generated by cargo-feature-scope"));
+            lines.extend(comments.iter().map(|comment| format!("# {comment}")));
+            lines.push(String::from("set -e"));
+            lines.extend(env_lines(cmd, format));
+            lines.push(format!("exec {command_line}"));
+        }
+        Format::Bat => {
+            lines.push(String::from("@echo off"));
+            lines.push(String::from("rem Generated by cargo-feature-scope"));
+            lines.extend(comments.iter().map(|comment| format!("rem {comment}")));
+            lines.extend(env_lines(cmd, format));
+            lines.push(command_line);
+            lines.push(String::from("exit /b %ERRORLEVEL%"));
+        }
+    }
+
+    join_lines(lines, format)
+}
+
+/// Renders the environment of `cmd` alone, as `export` lines or `set` lines for a batch file,
+/// with `comments` before them.
+pub fn render_env(cmd: &process::Command, comments: &[String], format: Format) -> String {
+    let mut lines: Vec<String> = match format {
+        Format::Sh => comments
+            .iter()
+            .map(|comment| format!("# {comment}"))
+            .collect(),
+        Format::Bat => comments
+            .iter()
+            .map(|comment| format!("rem {comment}"))
+            .collect(),
+    };
+    lines.extend(env_lines(cmd, format));
+    join_lines(lines, format)
+}
+
+// The lines setting the environment of `cmd`, sorted by variable
+fn env_lines(cmd: &process::Command, format: Format) -> Vec<String> {
+    let mut envs: Vec<(&OsStr, Option<&OsStr>)> = cmd.get_envs().collect();
+    envs.sort();
+
+    envs.into_iter()
+        .map(|(key, value)| {
+            let key = key.to_string_lossy();
+            match (format, value) {
+                (Format::Sh, Some(value)) => {
+                    format!("export {key}={}", quote(&value.to_string_lossy(), format))
+                }
+                (Format::Sh, None) => format!("unset {key}"),
+                (Format::Bat, value) => {
+                    let value = value.map(OsStr::to_string_lossy).unwrap_or_default();
+                    format!("set \"{key}={}\"", value.replace('%', "%%"))
+                }
+            }
+        })
+        .collect()
+}
+
+fn join_lines(lines: Vec<String>, format: Format) -> String {
+    let newline = match format {
+        Format::Sh => "\n",
+        Format::Bat => "\r\n",
+    };
+    let mut script = lines.join(newline);
+    script.push_str(newline);
+    script
+}
+
+fn quote(value: &str, format: Format) -> String {
+    let is_plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@".contains(c));
+    if is_plain {
+        return value.to_string();
+    }
+
+    match format {
+        Format::Sh => format!("'{}'", value.replace('\'', r"'\''")),
+        Format::Bat => format!("\"{}\"", value.replace('%', "%%").replace('"', "\"\"")),
+    }
+}
@@ -0,0 +1,176 @@
+//! Per-variant target directory management.
+//!
+//! Every scope variant is compiled with its own set of `--cfg` flags, which changes cargo's
+//! fingerprints. Building several variants into the same target directory makes them clobber
+//! each other, so each variant gets a dedicated directory under
+//! `<target-dir>/feature-scope/<variant>`.
+//!
+//! The download cache lives in `CARGO_HOME` and is never overridden here, so all variants share
+//! the registry and git checkouts and only compilation is duplicated. Optionally, a fresh variant
+//! directory can be seeded with the artifacts of the main target directory, letting cargo's
+//! fingerprinting reuse everything that is not affected by the scope flags. The compiled
+//! libraries are hard-linked and everything else is copied, as cargo rewrites it in place.
+//!
+//! With `--isolate-target-dir`, the variant is named after the fingerprint of the resolved cfg
+//! set instead, so switching between packages built with different scopes keeps every build warm.
+//!
+//! A variant declared under `variants` in the `feature-scope-options` also adds its feature
+//! selections to the build and overrides settings of the cargo profile, passed with `--config`,
+//! so a size-focused variant gets `opt-level = "z"` and `panic = "abort"` along with its scopes.
+
+use anyhow::{Context, Result};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Name of the directory inside the base target directory that holds all variant directories.
+const VARIANTS_DIR: &str = "feature-scope";
+
+/// Returns the `--config` arguments overriding settings of a cargo profile, nested tables as
+/// dotted keys.
+pub fn profile_config_args(profile: &str, settings: &BTreeMap<String, toml::Value>) -> Vec<String> {
+    fn flatten(prefix: String, settings: &BTreeMap<String, toml::Value>, args: &mut Vec<String>) {
+        for (name, value) in settings {
+            let key = format!("{prefix}.{}", toml_edit::Key::new(name.as_str()));
+            match value {
+                toml::Value::Table(table) => {
+                    let table: BTreeMap<String, toml::Value> =
+                        table.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+                    flatten(key, &table, args);
+                }
+                value => args.extend([String::from("--config"), format!("{key} = {value}")]),
+            }
+        }
+    }
+
+    let mut args = Vec::new();
+    flatten(
+        format!("profile.{}", toml_edit::Key::new(profile)),
+        settings,
+        &mut args,
+    );
+    args
+}
+
+/// Removes `--target-dir` from the arguments forwarded to cargo and returns its value.
+pub fn take_target_dir_arg(args: &mut Vec<String>) -> Option<PathBuf> {
+    let mut target_dir = None;
+    let mut index = 0;
+
+    while index < args.len() {
+        if args[index] == "--" {
+            break;
+        }
+
+        if args[index] == "--target-dir" && index + 1 < args.len() {
+            target_dir = Some(PathBuf::from(args.remove(index + 1)));
+            args.remove(index);
+        } else if let Some(value) = args[index].strip_prefix("--target-dir=") {
+            target_dir = Some(PathBuf::from(value));
+            args.remove(index);
+        } else {
+            index += 1;
+        }
+    }
+
+    target_dir
+}
+
+/// Determines the target directory cargo would use if we did not interfere.
+pub fn base_target_dir(workspace_root: &Path, explicit: Option<PathBuf>) -> PathBuf {
+    let target_dir = explicit
+        .or_else(|| env::var_os("CARGO_TARGET_DIR").map(PathBuf::from))
+        .or_else(|| env::var_os("CARGO_BUILD_TARGET_DIR").map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("target"));
+
+    if target_dir.is_absolute() {
+        target_dir
+    } else {
+        workspace_root.join(target_dir)
+    }
+}
+
+/// Creates (if needed) and returns the target directory of the given variant.
+///
+/// When `reuse_deps` is set and the directory does not exist yet, it is seeded with the artifacts
+/// already present in `base`.
+pub fn prepare_variant_target_dir(base: &Path, variant: &str, reuse_deps: bool) -> Result<PathBuf> {
+    validate_variant_name(variant)?;
+
+    let variant_dir = base.join(VARIANTS_DIR).join(variant);
+    if variant_dir.exists() {
+        return Ok(variant_dir);
+    }
+
+    fs::create_dir_all(&variant_dir)
+        .with_context(|| format!("Failed to create {}", variant_dir.display()))?;
+
+    if reuse_deps && base.exists() {
+        link_artifacts(base, &variant_dir, true)
+            .with_context(|| format!("Failed to seed {}", variant_dir.display()))?;
+    }
+
+    Ok(variant_dir)
+}
+
+/// Creates (if needed) and returns the target directory of a resolved cfg set, named after its
+/// fingerprint.
+pub fn prepare_isolated_target_dir(
+    base: &Path,
+    cfg_args: &[String],
+    check_cfg_args: &[String],
+    reuse_deps: bool,
+) -> Result<PathBuf> {
+    let fingerprint = crate::fingerprint::fingerprint_cfg_args(cfg_args, check_cfg_args);
+    prepare_variant_target_dir(base, &format!("{fingerprint:016x}"), reuse_deps)
+}
+
+fn validate_variant_name(variant: &str) -> Result<()> {
+    if variant.is_empty()
+        || variant == "."
+        || variant == ".."
+        || variant.contains(['/', '\\'])
+        || variant.chars().any(char::is_whitespace)
+    {
+        anyhow::bail!("Invalid variant name '{variant}': it must be a single path component");
+    }
+
+    Ok(())
+}
+
+// Mirrors `from` into `to`, leaving the files `to` already has alone. Cargo writes the libraries
+// in `deps` once, under a name with a hash of their inputs, so they are hard-linked, falling back
+// to copies when linking is not possible (e.g. across file systems). Everything else, like the
+// fingerprints and the outputs of build scripts, is rewritten in place and copied, so a variant
+// build never changes the files of another one. Incremental caches and cargo's lock files are
+// never shared.
+fn link_artifacts(from: &Path, to: &Path, is_root: bool) -> Result<()> {
+    let in_deps = from.file_name().is_some_and(|name| name == "deps");
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let source = entry.path();
+        let destination = to.join(&name);
+        let file_type = entry.file_type()?;
+
+        if (is_root && name == VARIANTS_DIR) || name == "incremental" || name == ".cargo-lock" {
+            continue;
+        }
+
+        if file_type.is_dir() {
+            fs::create_dir_all(&destination)?;
+            link_artifacts(&source, &destination, false)?;
+        } else if file_type.is_file() && !destination.exists() {
+            let is_library = source
+                .extension()
+                .is_some_and(|extension| extension == "rlib" || extension == "rmeta");
+            if !(in_deps && is_library) || fs::hard_link(&source, &destination).is_err() {
+                fs::copy(&source, &destination)?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! Project templates bundled with the binary.
+//!
+//! `cargo feature-scope template list` shows the templates and `template emit <NAME> [DIR]`
+//! writes one into a directory, the current one by default. The templates are embedded at build
+//! time, so they work offline and always match the version of the CLI. Together they make a
+//! workspace with a library declaring scopes and an app selecting them:
+//!
+//! ```bash
+//! cargo feature-scope template emit workspace
+//! cargo feature-scope template emit library my-library
+//! cargo feature-scope template emit app my-app
+//! ```
+
+use anyhow::{Context, Result};
+use include_dir::{include_dir, Dir, DirEntry};
+use std::{fs, path::Path};
+
+static TEMPLATES: Dir = include_dir!("$CARGO_MANIFEST_DIR/templates");
+
+const TEMPLATES_INFO: [(&str, &str); 3] = [
+    ("app", "A binary selecting the scopes of my-library"),
+    (
+        "library",
+        "A library declaring scopes and gating items with them",
+    ),
+    (
+        "workspace",
+        "The root manifest of a workspace with my-library and my-app",
+    ),
+];
+
+// Package manifests are stored with this suffix, or cargo would not package the templates
+const SUFFIX: &str = ".tmpl";
+
+pub fn run(args: &[String]) -> Result<()> {
+    match args {
+        [command] if command == "list" => {
+            for (name, description) in TEMPLATES_INFO {
+                println!("{name:10} {description}");
+            }
+            Ok(())
+        }
+        [command, name, dir @ ..] if command == "emit" && dir.len() <= 1 => {
+            let dir = dir.first().map(String::as_str).unwrap_or(".");
+            emit(name, Path::new(dir))
+        }
+        _ => anyhow::bail!("Usage: cargo feature-scope template list | emit <NAME> [DIR]"),
+    }
+}
+
+fn emit(name: &str, dir: &Path) -> Result<()> {
+    let Some(template) = TEMPLATES.get_dir(name) else {
+        let names: Vec<&str> = TEMPLATES_INFO.iter().map(|(name, _)| *name).collect();
+        anyhow::bail!(
+            "Unknown template '{name}', expected one of: {}",
+            names.join(", ")
+        );
+    };
+
+    let mut files = Vec::new();
+    collect_files(template, &mut files);
+
+    // Check everything first, so a conflict leaves no half-written template behind
+    let mut outputs = Vec::new();
+    for file in files {
+        let relative = file.path().strip_prefix(name).unwrap();
+        let relative = relative.to_string_lossy();
+        let output = dir.join(relative.strip_suffix(SUFFIX).unwrap_or(&relative));
+        if output.exists() {
+            anyhow::bail!("{} already exists", output.display());
+        }
+
+        let contents = file
+            .contents_utf8()
+            .with_context(|| format!("Template file {} is not UTF-8", file.path().display()))?
+            .replace("{{version}}", env!("CARGO_PKG_VERSION"));
+        outputs.push((output, contents));
+    }
+
+    for (output, contents) in outputs {
+        fs::create_dir_all(output.parent().unwrap())?;
+        fs::write(&output, contents)
+            .with_context(|| format!("Failed to write {}", output.display()))?;
+        println!("Created {}", output.display());
+    }
+
+    Ok(())
+}
+
+fn collect_files<'a>(dir: &'a Dir<'a>, files: &mut Vec<&'a include_dir::File<'a>>) {
+    for entry in dir.entries() {
+        match entry {
+            DirEntry::Dir(dir) => collect_files(dir, files),
+            DirEntry::File(file) => files.push(file),
+        }
+    }
+}
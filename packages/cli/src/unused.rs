@@ -0,0 +1,74 @@
+//! Cross-check of the scopes gating code against the declared ones.
+//!
+//! `cargo feature-scope unused [-p <PACKAGE>]...` scans the sources of every package, or of the
+//! selected ones, for their `#[feature_scope(...)]` and `#[feature_scope_default(...)]`
+//! attributes. It reports features the attributes name but the package does not declare, whose
+//! items never compile, typically because of a typo, and declared features that gate no item
+//! at all. Features depending on others are bundles and count as used.
+
+use anyhow::Result;
+use std::path::Path;
+
+use crate::{items, CargoToml};
+
+pub fn run(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    requested: &[String],
+) -> Result<()> {
+    let mut packages = Vec::new();
+    if requested.is_empty() {
+        let mut manifests = crate::workspace_member_manifests(root_cargo_toml, root_manifest_path)?;
+        if root_cargo_toml.package.is_some() {
+            manifests.insert(0, root_manifest_path.to_path_buf());
+        }
+        for manifest_path in manifests {
+            let package = crate::read_package(&manifest_path)?;
+            packages.push((manifest_path, package));
+        }
+    } else {
+        for name in requested {
+            packages.push(items::find_package(
+                root_cargo_toml,
+                root_manifest_path,
+                name,
+            )?);
+        }
+    }
+
+    let root_dir = root_manifest_path.parent().unwrap();
+    let (mut undeclared, mut unused) = (0, 0);
+    for (manifest_path, package) in &packages {
+        let decl = package
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.feature_scope_decl.as_ref());
+        let sources = items::scan_sources(manifest_path.parent().unwrap())?;
+
+        for usage in sources.undeclared(decl) {
+            let file = usage.file.strip_prefix(root_dir).unwrap_or(&usage.file);
+            println!(
+                "❌ {}: {}:{}: the attribute names scope '{}', which is not declared",
+                package.name,
+                file.display(),
+                usage.line,
+                usage.feature
+            );
+            undeclared += 1;
+        }
+        for feature in decl.map(|decl| sources.unused(decl)).unwrap_or_default() {
+            println!(
+                "⚠️  {}: scope '{feature}' is declared but gates no item",
+                package.name
+            );
+            unused += 1;
+        }
+    }
+
+    if undeclared + unused == 0 {
+        println!("✅ Every scope gating code is declared, and every declared scope gates code");
+        return Ok(());
+    }
+    println!();
+    anyhow::bail!("{undeclared} undeclared and {unused} unused scope(s) found")
+}
@@ -0,0 +1,155 @@
+//! Detection of uncommitted scope configuration changes.
+//!
+//! With `--vcs-check`, every manifest of the workspace is compared with its committed version
+//! before the command runs, and a warning lists the manifests whose scope declarations,
+//! feature-scope entries or workspace members differ. Other edits of the manifests are not
+//! reported. Git and Mercurial checkouts are supported, other systems implement [`Vcs`].
+
+use anyhow::{Context, Result};
+use std::{collections::BTreeSet, fs, path::Path, process};
+
+use crate::{i18n, CargoToml};
+
+/// A version control system the workspace is checked out from.
+pub trait Vcs {
+    /// Returns the committed content of a file, or `None` if it is not committed.
+    fn committed(&self, path: &Path) -> Result<Option<String>>;
+}
+
+struct Git;
+
+impl Vcs for Git {
+    fn committed(&self, path: &Path) -> Result<Option<String>> {
+        // `HEAD:./<file>` is resolved relative to the directory git runs in
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        let output = process::Command::new("git")
+            .arg("show")
+            .arg(format!("HEAD:./{file_name}"))
+            .current_dir(path.parent().unwrap())
+            .stderr(process::Stdio::null())
+            .output()
+            .context("Failed to run git")?;
+        Ok(output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).into_owned()))
+    }
+}
+
+struct Mercurial;
+
+impl Vcs for Mercurial {
+    fn committed(&self, path: &Path) -> Result<Option<String>> {
+        let output = process::Command::new("hg")
+            .args(["cat", "-r", "."])
+            .arg(path)
+            .current_dir(path.parent().unwrap())
+            .stderr(process::Stdio::null())
+            .output()
+            .context("Failed to run hg")?;
+        Ok(output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).into_owned()))
+    }
+}
+
+/// Returns the version control system of the checkout containing `dir`, if any.
+pub fn detect(dir: &Path) -> Option<Box<dyn Vcs>> {
+    dir.ancestors().find_map(|dir| -> Option<Box<dyn Vcs>> {
+        if dir.join(".git").exists() {
+            Some(Box::new(Git))
+        } else if dir.join(".hg").exists() {
+            Some(Box::new(Mercurial))
+        } else {
+            None
+        }
+    })
+}
+
+/// Warns about the manifests whose uncommitted changes affect the scope resolution.
+pub fn check(root_cargo_toml: &CargoToml, root_manifest_path: &Path) -> Result<()> {
+    let root_dir = root_manifest_path.parent().unwrap();
+    let Some(vcs) = detect(root_dir) else {
+        i18n::warn(i18n::Message::NoVcs);
+        return Ok(());
+    };
+
+    let mut manifests = vec![root_manifest_path.to_path_buf()];
+    manifests.extend(crate::workspace_member_manifests(
+        root_cargo_toml,
+        root_manifest_path,
+    )?);
+
+    for manifest_path in manifests {
+        let changes = scope_changes(vcs.as_ref(), &manifest_path)?;
+        if !changes.is_empty() {
+            let manifest = manifest_path
+                .strip_prefix(root_dir)
+                .unwrap_or(&manifest_path);
+            i18n::warn(i18n::Message::UncommittedScopeChanges {
+                manifest: &manifest.display().to_string(),
+                changes: &changes.join(", "),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+// Names the scope-relevant parts of a manifest that differ from the committed version
+fn scope_changes(vcs: &dyn Vcs, manifest_path: &Path) -> Result<Vec<String>> {
+    let current = fs::read_to_string(manifest_path)
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+    let committed = vcs.committed(manifest_path)?;
+    if committed.as_deref() == Some(current.as_str()) {
+        return Ok(Vec::new());
+    }
+
+    let parse = |content: &str| content.parse::<toml::Table>().unwrap_or_default();
+    let current = parse(&current);
+    let committed = committed.as_deref().map(parse).unwrap_or_default();
+
+    let mut changes = Vec::new();
+    let current_decl = declared_features(&current);
+    let committed_decl = declared_features(&committed);
+    let changed_features: BTreeSet<&String> = current_decl
+        .symmetric_difference(&committed_decl)
+        .map(|(feature, _)| feature)
+        .collect();
+    if !changed_features.is_empty() {
+        let features: Vec<&str> = changed_features.iter().map(|f| f.as_str()).collect();
+        changes.push(format!("feature-scope-decl ({})", features.join(", ")));
+    }
+
+    for (key, path) in [
+        (
+            "feature-scope",
+            &["package", "metadata", "feature-scope"][..],
+        ),
+        ("workspace.members", &["workspace", "members"][..]),
+        ("workspace.exclude", &["workspace", "exclude"][..]),
+    ] {
+        if lookup(&current, path) != lookup(&committed, path) {
+            changes.push(key.to_string());
+        }
+    }
+
+    Ok(changes)
+}
+
+// The declared features with their dependencies, `default` included
+fn declared_features(manifest: &toml::Table) -> BTreeSet<(String, String)> {
+    lookup(manifest, &["package", "metadata", "feature-scope-decl"])
+        .and_then(|decl| decl.as_table())
+        .into_iter()
+        .flatten()
+        .map(|(feature, dependencies)| (feature.clone(), dependencies.to_string()))
+        .collect()
+}
+
+fn lookup<'a>(manifest: &'a toml::Table, path: &[&str]) -> Option<&'a toml::Value> {
+    let (first, rest) = path.split_first()?;
+    rest.iter()
+        .try_fold(manifest.get(*first)?, |value, key| value.get(key))
+}
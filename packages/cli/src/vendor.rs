@@ -0,0 +1,230 @@
+//! Vendoring of dependencies together with the scope configuration.
+//!
+//! `cargo feature-scope vendor [DIR] [OPTIONS]` runs `cargo vendor` from the workspace root, adds
+//! the source replacement it prints to `.cargo/config.toml` and freezes the resolved scopes in
+//! `feature-scope.lock`, so offline builds from the vendored sources get the same scopes. Scope
+//! gated code commonly relies on optional dependencies, so the optional dependencies of every
+//! package declaring scopes must have been vendored as well.
+
+use anyhow::{Context, Result};
+use std::{
+    collections::HashSet,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::{lock, CargoToml};
+
+// Options of `cargo vendor` that take a value, which is then not the vendor directory
+const OPTIONS_WITH_VALUE: [&str; 5] = ["-s", "--sync", "--color", "--config", "-Z"];
+
+pub fn run(root_cargo_toml: &CargoToml, root_manifest_path: &Path, args: &[String]) -> Result<()> {
+    let root_dir = root_manifest_path.parent().unwrap();
+    let vendor_dir = root_dir.join(vendor_dir_arg(args).unwrap_or("vendor"));
+
+    let output = process::Command::new("cargo")
+        .arg("vendor")
+        .arg("--manifest-path")
+        .arg(root_manifest_path)
+        .args(args)
+        .current_dir(root_dir)
+        .stderr(process::Stdio::inherit())
+        .output()
+        .context("Failed to run cargo vendor")?;
+    if !output.status.success() {
+        process::exit(output.status.code().unwrap_or(1));
+    }
+
+    let config_path = root_dir.join(".cargo").join("config.toml");
+    let snippet = String::from_utf8_lossy(&output.stdout);
+    add_source_replacement(&config_path, &snippet)?;
+    println!("Updated {}", config_path.display());
+
+    let lock_path = lock::write(root_cargo_toml, root_manifest_path)?;
+    println!("Wrote {}", lock_path.display());
+
+    let missing = missing_optional_dependencies(root_cargo_toml, root_manifest_path, &vendor_dir)?;
+    if missing.is_empty() {
+        println!("✅ Every optional dependency of the scoped packages is vendored");
+        return Ok(());
+    }
+
+    for (package, dependency) in &missing {
+        println!("⚠️  Optional dependency '{dependency}' of '{package}' is not vendored");
+    }
+    println!();
+    anyhow::bail!(
+        "{} optional dependencies are missing from {}",
+        missing.len(),
+        vendor_dir.display()
+    )
+}
+
+fn vendor_dir_arg(args: &[String]) -> Option<&str> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if OPTIONS_WITH_VALUE.contains(&arg.as_str()) {
+            args.next();
+        } else if !arg.starts_with('-') {
+            return Some(arg);
+        }
+    }
+    None
+}
+
+// Appends the `[source.*]` tables `cargo vendor` prints, keeping the rest of the file untouched
+fn add_source_replacement(config_path: &Path, snippet: &str) -> Result<()> {
+    let snippet: toml::Table = toml::from_str(snippet)
+        .context("Failed to parse the configuration cargo vendor printed")?;
+    let existing: toml::Table = match fs::read_to_string(config_path) {
+        Ok(content) => toml::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", config_path.display()))?,
+        Err(_) => toml::Table::new(),
+    };
+
+    let existing_sources = existing.get("source").and_then(|source| source.as_table());
+    let mut missing = toml::Table::new();
+    for (name, source) in snippet
+        .get("source")
+        .and_then(|source| source.as_table())
+        .into_iter()
+        .flatten()
+    {
+        match existing_sources.and_then(|sources| sources.get(name)) {
+            Some(existing) if existing == source => {}
+            Some(_) => anyhow::bail!(
+                "{} already configures source '{name}' differently, update it by hand",
+                config_path.display()
+            ),
+            None => {
+                missing.insert(name.clone(), source.clone());
+            }
+        }
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let mut addition = toml::Table::new();
+    addition.insert(String::from("source"), toml::Value::Table(missing));
+
+    fs::create_dir_all(config_path.parent().unwrap())?;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(config_path)
+        .with_context(|| format!("Failed to open {}", config_path.display()))?;
+    if !existing.is_empty() {
+        writeln!(file)?;
+    }
+    write!(file, "{}", toml::to_string(&addition)?)
+        .with_context(|| format!("Failed to write {}", config_path.display()))
+}
+
+// Lists the optional registry dependencies of scoped packages that `vendor_dir` lacks
+fn missing_optional_dependencies(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    vendor_dir: &Path,
+) -> Result<Vec<(String, String)>> {
+    let vendored = vendored_packages(vendor_dir)?;
+
+    let mut manifests = crate::workspace_member_manifests(root_cargo_toml, root_manifest_path)?;
+    if root_cargo_toml.package.is_some() {
+        manifests.insert(0, root_manifest_path.to_path_buf());
+    }
+
+    let workspace_dependencies = read_table(root_manifest_path)?
+        .get("workspace")
+        .and_then(|workspace| workspace.get("dependencies"))
+        .and_then(|dependencies| dependencies.as_table())
+        .cloned()
+        .unwrap_or_default();
+
+    let mut missing = Vec::new();
+    for manifest_path in manifests {
+        let package = crate::read_package(&manifest_path)?;
+        let declares_scopes = package
+            .metadata
+            .as_ref()
+            .is_some_and(|metadata| metadata.feature_scope_decl.is_some());
+        if !declares_scopes {
+            continue;
+        }
+
+        let manifest = read_table(&manifest_path)?;
+        for (name, dependency) in optional_dependencies(&manifest) {
+            // Inherited dependencies take their source from the workspace
+            let source = if dependency.get("workspace").and_then(|w| w.as_bool()) == Some(true) {
+                workspace_dependencies.get(&name).unwrap_or(&dependency)
+            } else {
+                &dependency
+            };
+            if source.get("path").is_some() {
+                continue;
+            }
+
+            let real_name = source
+                .get("package")
+                .and_then(|package| package.as_str())
+                .unwrap_or(&name);
+            if !vendored.contains(real_name) {
+                missing.push((package.name.clone(), real_name.to_string()));
+            }
+        }
+    }
+
+    Ok(missing)
+}
+
+fn optional_dependencies(manifest: &toml::Table) -> Vec<(String, toml::Value)> {
+    let mut tables = Vec::new();
+    for key in ["dependencies", "build-dependencies"] {
+        tables.extend(manifest.get(key));
+    }
+    let targets = manifest.get("target").and_then(|target| target.as_table());
+    for config in targets.into_iter().flatten().map(|(_, config)| config) {
+        for key in ["dependencies", "build-dependencies"] {
+            tables.extend(config.get(key));
+        }
+    }
+
+    tables
+        .into_iter()
+        .filter_map(|table| table.as_table())
+        .flatten()
+        .filter(|(_, dependency)| {
+            dependency.get("optional").and_then(|o| o.as_bool()) == Some(true)
+        })
+        .map(|(name, dependency)| (name.clone(), dependency.clone()))
+        .collect()
+}
+
+// The names of the packages in a vendor directory, whose subdirectories may carry versions
+fn vendored_packages(vendor_dir: &Path) -> Result<HashSet<String>> {
+    let mut packages = HashSet::new();
+    let entries = fs::read_dir(vendor_dir)
+        .with_context(|| format!("Failed to read {}", vendor_dir.display()))?;
+    for entry in entries {
+        let manifest_path: PathBuf = entry?.path().join("Cargo.toml");
+        let Ok(manifest) = read_table(&manifest_path) else {
+            continue;
+        };
+        if let Some(name) = manifest
+            .get("package")
+            .and_then(|package| package.get("name"))
+            .and_then(|name| name.as_str())
+        {
+            packages.insert(name.to_string());
+        }
+    }
+    Ok(packages)
+}
+
+fn read_table(manifest_path: &Path) -> Result<toml::Table> {
+    let content = fs::read_to_string(manifest_path)
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+    toml::from_str(&content).with_context(|| format!("Failed to parse {}", manifest_path.display()))
+}
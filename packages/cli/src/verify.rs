@@ -0,0 +1,779 @@
+//! Checks of the scope configuration for CI, without building.
+//!
+//! `cargo feature-scope verify` resolves every workspace member and fails if a resolution fails,
+//! e.g. with features of an exclusive group selected together, if an entry references a package
+//! that does not exist or a feature its library does not declare, if the dependencies between
+//! the features of a package form a cycle, or if a `#[feature_scope(...)]` attribute gates code
+//! by a feature its package does not declare. `--format json` prints the report as JSON, with
+//! the other warnings of the resolutions apart from the errors. `--gated-references` adds
+//! warnings for ungated code referring to items that the default scopes leave out, which
+//! compiles only with the right scopes; see [`items::gated_references`] for its limits.
+//! `--format sarif` prints the findings as a SARIF 2.1.0 log for code scanning tools, located
+//! at the manifest, and its line where known, or at the source lines they are about.
+//!
+//! `cargo feature-scope verify --against-git <REF>` checks `REF` out into a temporary worktree
+//! and compares the scopes every package declares and gates its code with, and the scopes the
+//! consumers select, with the working tree. It lists the scopes that were added, removed or
+//! renamed, and fails if a change was not carried through, like a scope renamed in the code but
+//! still declared, or selected by a consumer, under its old name.
+
+use anyhow::{Context, Result};
+use feature_scope_core::{
+    diagnostic::Span, manifest::FeatureScopeDecl, ConflictError, DeclError, Diagnostic, StrictError,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::{
+    items::{self, SourceScopes},
+    CargoToml,
+};
+
+const USAGE: &str =
+    "Usage: cargo feature-scope verify [--format text|json|sarif] [--gated-references] [--against-git <REF>]";
+
+// What the checks of the workspace found
+#[derive(Debug, Default, Serialize)]
+struct Report {
+    packages: Vec<String>,
+    errors: Vec<Finding>,
+    warnings: Vec<Finding>,
+    // Locations are relative to it
+    #[serde(skip)]
+    root_dir: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+struct Finding {
+    package: String,
+    kind: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<Location>,
+}
+
+// The file a finding is about, relative to the workspace root, and the place in it if known
+#[derive(Debug, Clone, Serialize)]
+struct Location {
+    file: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<usize>,
+}
+
+impl Location {
+    fn new(file: &Path, line: Option<usize>) -> Self {
+        Location {
+            file: file.to_path_buf(),
+            line,
+            column: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Json,
+    Sarif,
+}
+
+impl Report {
+    // Every consumer resolving a library sees its problems, the first one reports them
+    fn push(
+        &mut self,
+        is_error: bool,
+        package: &str,
+        kind: &'static str,
+        message: String,
+        location: Location,
+    ) {
+        let mut findings = self.errors.iter().chain(&self.warnings);
+        if findings.any(|finding| finding.message == message) {
+            return;
+        }
+        let file = location
+            .file
+            .strip_prefix(&self.root_dir)
+            .unwrap_or(&location.file);
+        let finding = Finding {
+            package: package.to_string(),
+            kind,
+            message,
+            location: Some(Location {
+                file: file.to_path_buf(),
+                ..location
+            }),
+        };
+        if is_error {
+            self.errors.push(finding);
+        } else {
+            self.warnings.push(finding);
+        }
+    }
+
+    // Diagnostics that locate themselves override the manifest of the resolved package
+    fn push_diagnostic(
+        &mut self,
+        package: &str,
+        manifest_path: &Path,
+        diagnostic: &Diagnostic,
+        strict: bool,
+    ) {
+        let message = diagnostic.to_string();
+        let (is_error, kind) = match diagnostic {
+            Diagnostic::FeatureNotDeclared { .. }
+            | Diagnostic::FeatureNotDeclaredInPackage { .. } => (true, "undeclared-feature"),
+            // Entries outside the dependency graph are ignored by builds, but stale all the same
+            Diagnostic::PackageNotFound { .. } | Diagnostic::NotADependency { .. } => {
+                (true, "unknown-package")
+            }
+            // Reported with the members themselves
+            Diagnostic::UnreadableMember { .. } => return,
+            _ if diagnostic.is_note() => return,
+            _ => (strict, "warning"),
+        };
+        let location = match diagnostic {
+            Diagnostic::NotADependency {
+                manifest: Some(manifest),
+                line,
+                ..
+            } => Location::new(manifest, *line),
+            Diagnostic::DuplicateEntry {
+                manifest, lines, ..
+            } => Location::new(manifest, lines.first().copied()),
+            Diagnostic::UnknownKey { manifest, .. } => Location::new(manifest, None),
+            _ => Location::new(manifest_path, None),
+        };
+        self.push(is_error, package, kind, message, location);
+    }
+
+    // An error of reading or resolving a package, located by the span of a parse error
+    fn push_error(&mut self, package: &str, manifest_path: &Path, err: &anyhow::Error) {
+        let kind = if err.downcast_ref::<ConflictError>().is_some() {
+            "exclusive-conflict"
+        } else if err.downcast_ref::<DeclError>().is_some() {
+            "invalid-declaration"
+        } else {
+            "resolution"
+        };
+        let location = match err.downcast_ref::<DeclError>() {
+            Some(DeclError::InvalidManifest {
+                manifest,
+                span: Some(span),
+                ..
+            }) => Location {
+                column: Some(span.column),
+                ..Location::new(manifest, Some(span.line))
+            },
+            Some(DeclError::InvalidManifest { manifest, .. })
+            | Some(DeclError::MissingCargoFeature { manifest, .. }) => {
+                Location::new(manifest, None)
+            }
+            _ => Location::new(manifest_path, None),
+        };
+        self.push(true, package, kind, format!("{err:#}"), location);
+    }
+}
+
+// The scopes of a workspace, by package
+#[derive(Debug, Default)]
+struct Snapshot {
+    declared: BTreeMap<String, BTreeSet<String>>,
+    gated: BTreeMap<String, BTreeSet<String>>,
+    // Consumer to the `(package, feature)` pairs its entries select
+    selected: BTreeMap<String, BTreeSet<(String, String)>>,
+}
+
+// The changes of one set of scopes between two snapshots
+struct Changes {
+    added: BTreeSet<String>,
+    removed: BTreeSet<String>,
+}
+
+impl Changes {
+    fn between(before: Option<&BTreeSet<String>>, after: Option<&BTreeSet<String>>) -> Self {
+        let empty = BTreeSet::new();
+        let (before, after) = (before.unwrap_or(&empty), after.unwrap_or(&empty));
+        Changes {
+            added: after.difference(before).cloned().collect(),
+            removed: before.difference(after).cloned().collect(),
+        }
+    }
+
+    fn contains(&self, feature: &str) -> bool {
+        self.added.contains(feature) || self.removed.contains(feature)
+    }
+
+    // A single scope replaced by a single other one is taken as a rename
+    fn rename(&self) -> Option<(&String, &String)> {
+        match (self.removed.len(), self.added.len()) {
+            (1, 1) => Some((self.removed.first()?, self.added.first()?)),
+            _ => None,
+        }
+    }
+
+    fn renamed_to(&self, feature: &str) -> Option<&String> {
+        self.rename()
+            .and_then(|(from, to)| (from == feature).then_some(to))
+    }
+
+    fn renamed_from(&self, feature: &str) -> Option<&String> {
+        self.rename()
+            .and_then(|(from, to)| (to == feature).then_some(from))
+    }
+
+    fn describe(&self, package: &str, what: &str, lines: &mut Vec<String>) {
+        if let Some((from, to)) = self.rename() {
+            lines.push(format!("{package}: {what} '{from}' renamed to '{to}'"));
+            return;
+        }
+        for feature in &self.added {
+            lines.push(format!("{package}: {what} '{feature}' added"));
+        }
+        for feature in &self.removed {
+            lines.push(format!("{package}: {what} '{feature}' removed"));
+        }
+    }
+}
+
+/// A detached worktree of a revision, removed again when dropped.
+pub struct Worktree {
+    dir: PathBuf,
+    repo_dir: PathBuf,
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let _ = process::Command::new("git")
+            .args(["worktree", "remove", "--force"])
+            .arg(&self.dir)
+            .current_dir(&self.repo_dir)
+            .output();
+    }
+}
+
+pub fn run(root_cargo_toml: &CargoToml, root_manifest_path: &Path, args: &[String]) -> Result<()> {
+    let mut format = Format::Text;
+    let mut gated_references = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let name = match arg.as_str() {
+            "--against-git" => {
+                let git_ref = args.next().context(USAGE)?;
+                return against_git(root_manifest_path, git_ref);
+            }
+            "--gated-references" => {
+                gated_references = true;
+                continue;
+            }
+            "--format" => args.next().context(USAGE)?.as_str(),
+            _ => {
+                if let Some(git_ref) = arg.strip_prefix("--against-git=") {
+                    return against_git(root_manifest_path, git_ref);
+                }
+                arg.strip_prefix("--format=").context(USAGE)?
+            }
+        };
+        format = match name {
+            "text" => Format::Text,
+            "json" => Format::Json,
+            "sarif" => Format::Sarif,
+            _ => anyhow::bail!("Unknown format '{name}', expected 'text', 'json' or 'sarif'"),
+        };
+    }
+
+    let report = check_workspace(root_cargo_toml, root_manifest_path, gated_references)?;
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if format == Format::Sarif {
+        println!("{}", serde_json::to_string_pretty(&sarif(&report))?);
+    } else {
+        for warning in &report.warnings {
+            println!("⚠️  {}: {}", warning.package, warning.message);
+        }
+        for error in &report.errors {
+            println!("❌ {}: {}", error.package, error.message);
+        }
+        if report.errors.is_empty() {
+            println!(
+                "✅ The scopes of all {} packages resolve and are declared",
+                report.packages.len()
+            );
+        }
+    }
+
+    if !report.errors.is_empty() {
+        anyhow::bail!("{} problem(s) found", report.errors.len());
+    }
+    Ok(())
+}
+
+// Resolves every member and checks the declarations and attributes the resolutions do not see
+fn check_workspace(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    gated_references: bool,
+) -> Result<Report> {
+    let mut manifests = crate::workspace_member_manifests(root_cargo_toml, root_manifest_path)?;
+    if root_cargo_toml.package.is_some() {
+        manifests.insert(0, root_manifest_path.to_path_buf());
+    }
+
+    let root_dir = root_manifest_path.parent().unwrap();
+    let mut report = Report {
+        root_dir: root_dir.to_path_buf(),
+        ..Report::default()
+    };
+    for manifest_path in manifests {
+        let package = match crate::read_package(&manifest_path) {
+            Ok(package) => package,
+            Err(err) => {
+                let name = manifest_path.display().to_string();
+                report.push_error(&name, &manifest_path, &err);
+                continue;
+            }
+        };
+        let name = package.name.clone();
+        report.packages.push(name.clone());
+
+        let decl = package
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.feature_scope_decl.as_ref());
+        if let Some(decl) = decl {
+            for (feature, dependencies) in &decl.features {
+                for dependency in dependencies.iter().filter(|d| !decl.declares(d)) {
+                    report.push(
+                        true,
+                        &name,
+                        "undeclared-feature",
+                        format!(
+                            "feature '{feature}' depends on '{dependency}', which is not declared"
+                        ),
+                        Location::new(&manifest_path, decl_line(&manifest_path, feature)),
+                    );
+                }
+            }
+            for cycle in dependency_cycles(decl) {
+                report.push(
+                    true,
+                    &name,
+                    "dependency-cycle",
+                    format!(
+                        "the feature dependencies form a cycle: {}",
+                        cycle.join(" -> ")
+                    ),
+                    Location::new(&manifest_path, decl_line(&manifest_path, &cycle[0])),
+                );
+            }
+        }
+
+        let sources = match items::scan_sources(manifest_path.parent().unwrap()) {
+            Ok(sources) => sources,
+            Err(err) => {
+                report.push(
+                    true,
+                    &name,
+                    "unreadable-source",
+                    format!("{err:#}"),
+                    Location::new(&manifest_path, None),
+                );
+                SourceScopes::default()
+            }
+        };
+        for usage in sources.undeclared(decl) {
+            let file = usage.file.strip_prefix(root_dir).unwrap_or(&usage.file);
+            report.push(
+                true,
+                &name,
+                "undeclared-gate",
+                format!(
+                    "{}:{}: code is gated by scope '{}', which feature-scope-decl does not declare",
+                    file.display(),
+                    usage.line,
+                    usage.feature
+                ),
+                Location::new(&usage.file, Some(usage.line)),
+            );
+        }
+
+        // Approximate, so the findings are warnings
+        let references = match decl {
+            // Sources that do not parse are reported above already
+            Some(decl) if gated_references => {
+                items::gated_references(manifest_path.parent().unwrap(), decl).unwrap_or_default()
+            }
+            _ => Vec::new(),
+        };
+        for reference in references {
+            let file = reference
+                .file
+                .strip_prefix(root_dir)
+                .unwrap_or(&reference.file);
+            let item_file = reference
+                .item_file
+                .strip_prefix(root_dir)
+                .unwrap_or(&reference.item_file);
+            report.push(
+                false,
+                &name,
+                "gated-reference",
+                format!(
+                    "{}:{}: `{}` is used by code every build compiles, but only compiles with scope '{}' ({}:{})",
+                    file.display(),
+                    reference.line,
+                    reference.name,
+                    reference.scopes.join("', '"),
+                    item_file.display(),
+                    reference.item_line
+                ),
+                Location::new(&reference.file, Some(reference.line)),
+            );
+        }
+
+        match feature_scope_core::resolve_cfg_args(root_cargo_toml, root_manifest_path, &name) {
+            Ok(scope) => {
+                for diagnostic in &scope.diagnostics {
+                    report.push_diagnostic(&name, &manifest_path, diagnostic, false);
+                }
+            }
+            Err(err) => {
+                if let Some(strict) = err.downcast_ref::<StrictError>() {
+                    for diagnostic in &strict.diagnostics {
+                        report.push_diagnostic(&name, &manifest_path, diagnostic, true);
+                    }
+                    continue;
+                }
+                report.push_error(&name, &manifest_path, &err);
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+// The line declaring a feature in the feature-scope-decl of a manifest
+fn decl_line(manifest_path: &Path, feature: &str) -> Option<usize> {
+    let content = fs::read_to_string(manifest_path).ok()?;
+    let document = toml_edit::Document::parse(content.as_str()).ok()?;
+    let decl = document
+        .get("package")?
+        .get("metadata")?
+        .get("feature-scope-decl")?
+        .as_table_like()?;
+    let (key, _) = decl.get_key_value(feature)?;
+    Some(Span::new(&content, key.span()?).line)
+}
+
+// The report as a SARIF 2.1.0 log, with a rule for every kind of finding
+fn sarif(report: &Report) -> serde_json::Value {
+    let findings = report
+        .errors
+        .iter()
+        .map(|finding| (finding, "error"))
+        .chain(report.warnings.iter().map(|finding| (finding, "warning")));
+    let mut kinds = BTreeSet::new();
+    let mut results = Vec::new();
+    for (finding, level) in findings {
+        kinds.insert(finding.kind);
+        let locations: Vec<serde_json::Value> = finding
+            .location
+            .iter()
+            .map(|location| {
+                let mut region = serde_json::Map::new();
+                if let Some(line) = location.line {
+                    region.insert(String::from("startLine"), json!(line));
+                }
+                if let Some(column) = location.column {
+                    region.insert(String::from("startColumn"), json!(column));
+                }
+                let mut physical = json!({
+                    "artifactLocation": {
+                        "uri": location.file.to_string_lossy().replace('\\', "/"),
+                        "uriBaseId": "%SRCROOT%",
+                    },
+                });
+                if !region.is_empty() {
+                    physical["region"] = serde_json::Value::Object(region);
+                }
+                json!({ "physicalLocation": physical })
+            })
+            .collect();
+        results.push(json!({
+            "ruleId": finding.kind,
+            "level": level,
+            "message": { "text": format!("{}: {}", finding.package, finding.message) },
+            "locations": locations,
+        }));
+    }
+
+    let rules: Vec<serde_json::Value> = kinds
+        .into_iter()
+        .map(|kind| json!({ "id": kind, "shortDescription": { "text": rule_description(kind) } }))
+        .collect();
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "cargo-feature-scope",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+fn rule_description(kind: &str) -> &'static str {
+    match kind {
+        "undeclared-feature" => "A feature is selected or depended on but not declared",
+        "unknown-package" => "An entry references a package outside the dependency graph",
+        "dependency-cycle" => "The dependencies between features form a cycle",
+        "undeclared-gate" => "Code is gated by a scope its package does not declare",
+        "gated-reference" => "Code every build compiles uses an item only some scopes compile",
+        "exclusive-conflict" => {
+            "Features of an exclusive group or conflicting ones are enabled together"
+        }
+        "invalid-declaration" => "A manifest or its scope declarations cannot be read",
+        "unreadable-source" => "A source file cannot be read or parsed",
+        "resolution" => "The scopes of a package do not resolve",
+        _ => "A warning of the scope resolution",
+    }
+}
+
+// The cycles among the dependencies of the features, each starting and ending with one feature
+fn dependency_cycles(decl: &FeatureScopeDecl) -> Vec<Vec<String>> {
+    fn visit(
+        feature: &str,
+        decl: &FeatureScopeDecl,
+        path: &mut Vec<String>,
+        done: &mut BTreeSet<String>,
+        cycles: &mut Vec<Vec<String>>,
+    ) {
+        if let Some(start) = path.iter().position(|f| f == feature) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(feature.to_string());
+            cycles.push(cycle);
+            return;
+        }
+        if !done.insert(feature.to_string()) {
+            return;
+        }
+        path.push(feature.to_string());
+        for dependency in decl.features.get(feature).into_iter().flatten() {
+            visit(dependency, decl, path, done, cycles);
+        }
+        path.pop();
+    }
+
+    let mut features: Vec<&String> = decl.features.keys().collect();
+    features.sort();
+    let mut done = BTreeSet::new();
+    let mut cycles = Vec::new();
+    for feature in features {
+        visit(feature, decl, &mut Vec::new(), &mut done, &mut cycles);
+    }
+    cycles
+}
+
+fn against_git(root_manifest_path: &Path, git_ref: &str) -> Result<()> {
+    let (worktree, ref_manifest_path) = checkout(root_manifest_path, git_ref)?;
+    let before = snapshot(&ref_manifest_path)
+        .with_context(|| format!("Failed to read the scopes at {git_ref}"))?;
+    let after = snapshot(root_manifest_path)?;
+    drop(worktree);
+
+    let mut changes = Vec::new();
+    let mut problems = Vec::new();
+    let packages: BTreeSet<&String> = [&before, &after]
+        .iter()
+        .flat_map(|snapshot| snapshot.declared.keys().chain(snapshot.gated.keys()))
+        .collect();
+    let mut changed = BTreeMap::new();
+    for package in packages {
+        let declared = Changes::between(before.declared.get(package), after.declared.get(package));
+        let gated = Changes::between(before.gated.get(package), after.gated.get(package));
+        declared.describe(package, "declared scope", &mut changes);
+        gated.describe(package, "scope gating its code", &mut changes);
+
+        let now_declared = after.declared.get(package);
+        let is_declared = |feature: &String| now_declared.is_some_and(|d| d.contains(feature));
+        for feature in after.gated.get(package).into_iter().flatten() {
+            if !is_declared(feature) && (declared.contains(feature) || gated.contains(feature)) {
+                let hint = match (declared.renamed_to(feature), gated.renamed_from(feature)) {
+                    (Some(to), _) => format!(" (renamed to '{to}' in feature-scope-decl only)"),
+                    (None, Some(from)) => format!(" (renamed from '{from}' in the code only)"),
+                    (None, None) => String::new(),
+                };
+                problems.push(format!(
+                    "{package}: code is gated by scope '{feature}', which feature-scope-decl does not declare{hint}"
+                ));
+            }
+        }
+        for feature in &gated.removed {
+            if is_declared(feature) && !declared.contains(feature) {
+                let hint = match gated.renamed_to(feature) {
+                    Some(to) => format!(" (the code uses '{to}' instead)"),
+                    None => String::new(),
+                };
+                problems.push(format!(
+                    "{package}: scope '{feature}' is still declared, but no code is gated by it anymore{hint}"
+                ));
+            }
+        }
+        changed.insert(package, (declared, now_declared));
+    }
+
+    for (consumer, selections) in &after.selected {
+        for (package, feature) in selections {
+            let Some((declared, Some(now_declared))) = changed.get(package) else {
+                continue;
+            };
+            if declared.removed.contains(feature) && !now_declared.contains(feature) {
+                let hint = match declared.renamed_to(feature) {
+                    Some(to) => format!(" (renamed to '{to}')"),
+                    None => String::new(),
+                };
+                problems.push(format!(
+                    "{consumer}: selects '{package}/{feature}', which '{package}' no longer declares{hint}"
+                ));
+            }
+        }
+    }
+
+    if changes.is_empty() {
+        println!("No scope changes since {git_ref}");
+    } else {
+        println!("Scope changes since {git_ref}:");
+        for change in &changes {
+            println!("  {change}");
+        }
+    }
+    println!();
+
+    if problems.is_empty() {
+        println!("✅ Every scope change is carried through the declarations, code and entries");
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("⚠️  {problem}");
+    }
+    println!();
+    anyhow::bail!("{} problem(s) found", problems.len())
+}
+
+/// Checks `git_ref` out into a temporary worktree, returning it with the path of the root
+/// manifest in it.
+pub fn checkout(root_manifest_path: &Path, git_ref: &str) -> Result<(Worktree, PathBuf)> {
+    let root_dir = root_manifest_path.parent().unwrap();
+    let worktree = add_worktree(root_dir, git_ref)?;
+    let prefix = git(root_dir, &["rev-parse", "--show-prefix"])?;
+    let ref_manifest_path = worktree
+        .dir
+        .join(prefix.trim())
+        .join(root_manifest_path.file_name().unwrap());
+    Ok((worktree, ref_manifest_path))
+}
+
+fn add_worktree(root_dir: &Path, git_ref: &str) -> Result<Worktree> {
+    let repo_dir = PathBuf::from(git(root_dir, &["rev-parse", "--show-toplevel"])?.trim());
+    let dir = env::temp_dir().join(format!("feature-scope-worktree-{}", process::id()));
+
+    let output = process::Command::new("git")
+        .args(["worktree", "add", "--detach", "--quiet"])
+        .arg(&dir)
+        .arg(git_ref)
+        .current_dir(&repo_dir)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to check out {git_ref}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(Worktree { dir, repo_dir })
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = process::Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn snapshot(root_manifest_path: &Path) -> Result<Snapshot> {
+    let root_cargo_toml = crate::load_manifest(root_manifest_path)?;
+    let mut manifests = crate::workspace_member_manifests(&root_cargo_toml, root_manifest_path)?;
+    if root_cargo_toml.package.is_some() {
+        manifests.insert(0, root_manifest_path.to_path_buf());
+    }
+
+    let mut snapshot = Snapshot::default();
+    for manifest_path in manifests {
+        let package = crate::read_package(&manifest_path)?;
+        let package_dir = manifest_path.parent().unwrap();
+        let Some(metadata) = &package.metadata else {
+            continue;
+        };
+
+        if let Some(decl) = &metadata.feature_scope_decl {
+            snapshot.declared.insert(
+                package.name.clone(),
+                decl.features.keys().cloned().collect(),
+            );
+            snapshot
+                .gated
+                .insert(package.name.clone(), items::gated_features(package_dir)?);
+        }
+
+        for entry in metadata.feature_scope.iter().flatten() {
+            let library = match (&entry.package, &entry.path) {
+                (Some(library), _) => library.clone(),
+                // Entries with only a path name the package through its manifest
+                (None, Some(path)) => {
+                    let path = package_dir.join(path);
+                    let manifest = if path.is_dir() {
+                        path.join("Cargo.toml")
+                    } else {
+                        path
+                    };
+                    match crate::read_package(&manifest) {
+                        Ok(library) => library.name,
+                        Err(_) => continue,
+                    }
+                }
+                (None, None) => package.name.clone(),
+            };
+            let selected = snapshot.selected.entry(package.name.clone()).or_default();
+            for feature in entry.features.iter().filter(|f| f.as_str() != "default") {
+                selected.insert((library.clone(), feature.clone()));
+            }
+        }
+    }
+
+    Ok(snapshot)
+}
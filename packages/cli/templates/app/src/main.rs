@@ -0,0 +1,4 @@
+fn main() {
+    println!("{}", my_library::greeting());
+    println!("{}", my_library::farewell());
+}
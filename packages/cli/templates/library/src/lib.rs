@@ -0,0 +1,14 @@
+use feature_scope::{feature_scope, feature_scope_default};
+
+// Warns when the crate is built with plain `cargo` instead of `cargo feature-scope`
+feature_scope::feature_scope_setup!();
+
+#[feature_scope_default(basic)]
+pub fn greeting() -> &'static str {
+    "Hello"
+}
+
+#[feature_scope(extended)]
+pub fn farewell() -> &'static str {
+    "Goodbye"
+}
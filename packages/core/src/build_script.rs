@@ -0,0 +1,214 @@
+//! Resolution from build scripts, and from other tools with explicit paths.
+//!
+//! [`load`] is the convenience for a `build.rs` that makes plain `cargo build` see the scopes: it
+//! resolves the package cargo builds the script for and prints the `cargo:` directives for its
+//! cfgs, check-cfgs and rerun triggers, among them the variables its `env:` probes read. Under
+//! `cargo feature-scope`, which passes the cfgs in the rustflags and exports `FEATURE_SCOPE_CFGS`,
+//! it leaves the cfgs out so the crate does not see them twice.
+//!
+//! With `FEATURE_SCOPE_TARGET_PACKAGE` naming the final consumer, as in
+//! `FEATURE_SCOPE_TARGET_PACKAGE=app cargo build -p app`, the build script of a library resolves
+//! that package instead and prints the cfgs of the features it enables in the library.
+//!
+//! ```no_run
+//! // In the `main` of build.rs
+//! feature_scope_core::load()?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! [`load_with`] does the resolution alone, for xtask tools and tests, taking the package from
+//! its options instead of `CARGO_MANIFEST_DIR` and printing nothing. Called from a build script,
+//! where cargo sets `HOST`, it still takes the profile from `PROFILE` and the target from
+//! `TARGET`, with the cfgs of the `CARGO_CFG_*` variables, where the overrides leave them out:
+//!
+//! ```no_run
+//! use feature_scope_core::LoadOptions;
+//!
+//! let mut options = LoadOptions::new("crates/app");
+//! options.overrides.features.push(String::from("tls"));
+//! let scope = feature_scope_core::load_with(&options)?;
+//! println!("{:?}", scope.cfg_args);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use anyhow::{Context, Result};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cfg_name, env_vars,
+    manifest::{extract_package_name_from_manifest, find_root_manifest},
+    Overrides, ResolvedScope, Resolver,
+};
+
+/// What [`load_with`] resolves.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct LoadOptions {
+    /// The directory of the package's manifest, like the `CARGO_MANIFEST_DIR` of its build
+    /// script. The workspace is the one this directory belongs to.
+    pub manifest_dir: PathBuf,
+    /// The package to resolve, by default the one of the manifest in `manifest_dir`.
+    pub package: Option<String>,
+    /// Selections on top of the entries, as given on the command line of the CLI.
+    pub overrides: Overrides,
+}
+
+impl LoadOptions {
+    pub fn new(manifest_dir: impl Into<PathBuf>) -> Self {
+        LoadOptions {
+            manifest_dir: manifest_dir.into(),
+            package: None,
+            overrides: Overrides::default(),
+        }
+    }
+}
+
+/// Resolves the scopes of a package for a build script, printing the `cargo:` directives that
+/// pass them to the compilation of the package.
+///
+/// The package resolved is the one of `FEATURE_SCOPE_TARGET_PACKAGE` when it is set, and
+/// otherwise the one cargo builds the script for. Fails outside of build scripts, where cargo
+/// does not set `CARGO_MANIFEST_DIR`.
+pub fn load() -> Result<ResolvedScope> {
+    let manifest_dir = env::var_os("CARGO_MANIFEST_DIR").context(
+        "CARGO_MANIFEST_DIR is not set, load() reads it in build scripts; use load_with elsewhere",
+    )?;
+    let mut options = LoadOptions::new(manifest_dir);
+    let built = env::var("CARGO_PKG_NAME").ok();
+    options.package = env::var(env_vars::TARGET_PACKAGE)
+        .ok()
+        .filter(|package| !package.is_empty())
+        .or_else(|| built.clone());
+
+    let scope = load_with(&options)?;
+    let root_manifest_path = find_root_manifest(&options.manifest_dir)?;
+    // Another consumer enables scopes in more libraries than the one being built
+    let library = built.filter(|built| options.package.as_ref() != Some(built));
+    let cfgs = env::var_os(env_vars::CFGS)
+        .is_none()
+        .then(|| match &library {
+            Some(library) => library_cfgs(&scope, library),
+            None => scope.enabled_cfgs().map(String::from).collect(),
+        });
+    let directives = directives(
+        &scope,
+        &[
+            &root_manifest_path,
+            &options.manifest_dir.join("Cargo.toml"),
+        ],
+        cfgs.as_deref(),
+    );
+    for directive in directives {
+        println!("{directive}");
+    }
+    Ok(scope)
+}
+
+/// Resolves the scopes of a package without printing anything. Only in a build script does it
+/// read cargo's variables, `PROFILE`, `HOST`, `TARGET` and `CARGO_CFG_*`, for the profile and
+/// the target the overrides leave out.
+pub fn load_with(options: &LoadOptions) -> Result<ResolvedScope> {
+    let resolver = Resolver::from_workspace(&options.manifest_dir)?;
+    let package = match &options.package {
+        Some(package) => package.clone(),
+        None => extract_package_name_from_manifest(&options.manifest_dir.join("Cargo.toml"))?,
+    };
+    resolver.resolve_with(&package, &options.overrides)
+}
+
+// The enabled cfgs of one library of a resolution, with the default scope if it is enabled
+fn library_cfgs(scope: &ResolvedScope, library: &str) -> Vec<String> {
+    let default = scope
+        .enabled_cfgs()
+        .find(|cfg| *cfg == cfg_name::scope_cfg("default"))
+        .map(String::from);
+    let features = scope
+        .origins
+        .get(library)
+        .into_iter()
+        .flat_map(|features| features.keys())
+        .map(|feature| cfg_name::scope_cfg(feature));
+    default.into_iter().chain(features).collect()
+}
+
+// The build script output for a resolved scope, with the cfgs unless the CLI passes them. The
+// variables of the `env:` probes are rerun triggers too, as plain cargo builds would otherwise
+// keep what the probes found when the script first ran.
+fn directives(scope: &ResolvedScope, manifests: &[&Path], cfgs: Option<&[String]>) -> Vec<String> {
+    let mut directives: Vec<String> = env_vars::ALL
+        .iter()
+        .copied()
+        .chain(scope.probe_vars.iter().map(String::as_str))
+        .map(|var| format!("cargo:rerun-if-env-changed={var}"))
+        .chain(
+            manifests
+                .iter()
+                .map(|manifest| format!("cargo:rerun-if-changed={}", manifest.display())),
+        )
+        .collect();
+
+    for diagnostic in &scope.diagnostics {
+        directives.push(format!("cargo:warning={diagnostic}"));
+    }
+    if let Some(cfgs) = cfgs {
+        directives.extend(cfgs.iter().map(|cfg| format!("cargo:rustc-cfg={cfg}")));
+        // Like under the CLI, so `feature_scope_setup!()` sees the scopes are passed
+        directives.push(format!(
+            "cargo:rustc-env={}={}",
+            env_vars::CFGS,
+            cfgs.join(" ")
+        ));
+    }
+    directives.extend(
+        scope
+            .check_cfg_args
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .map(|check_cfg| format!("cargo:rustc-check-cfg={check_cfg}")),
+    );
+    directives
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn directives_rerun_on_env_probes() {
+        let dir = env::temp_dir().join(format!("feature-scope-probe-rerun-{}", std::process::id()));
+        let files = [
+            ("Cargo.toml", "[workspace]\nmembers = [\"lib\", \"app\"]\n"),
+            (
+                "lib/Cargo.toml",
+                "[package]\nname = \"lib\"\nversion = \"0.1.0\"\n\n\
+                 [package.metadata.feature-scope-decl]\ncuda = { probe = \"env:MY_CUDA_PATH\" }\n",
+            ),
+            ("lib/src/lib.rs", ""),
+            (
+                "app/Cargo.toml",
+                "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n\
+                 [dependencies]\nlib = { path = \"../lib\" }\n",
+            ),
+            ("app/src/main.rs", "fn main() {}\n"),
+        ];
+        for (path, content) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let scope = load_with(&LoadOptions::new(dir.join("app"))).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let directives = directives(&scope, &[], None);
+        assert!(directives.contains(&String::from("cargo:rerun-if-env-changed=MY_CUDA_PATH")));
+        for var in env_vars::ALL {
+            assert!(directives.contains(&format!("cargo:rerun-if-env-changed={var}")));
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Mapping of feature names to cfg names.
+//!
+//! Feature names are TOML keys and may contain anything, while cfg names must be identifiers.
+//! Names made of ASCII letters, digits and underscores map to `__scope_<name>` unchanged. Any
+//! other name has every other character replaced by `_` and gets a hash of the original name
+//! appended, so `log-level` becomes `__scope_log_level_<hash>` rather than the
+//! `__scope_log_level` of `log_level`. Two names can still map to the same cfg name, like
+//! `log_level_<hash>` next to `log-level`, which a package declaring both fails with
+//! [`DeclError::CfgNameCollision`]. Names are hashed exactly as written, so a non-ASCII name has
+//! to be spelled with the same Unicode normalization (NFC, what editors produce) in `Cargo.toml`
+//! and in code.
+//!
+//! The macros use this module too, so both sides always agree on the mapping.
+
+use crate::{diagnostic::DeclError, stable_hash};
+
+/// Returns the cfg name of a feature.
+pub fn scope_cfg(feature: &str) -> String {
+    if is_plain(feature) {
+        return format!("__scope_{feature}");
+    }
+
+    let sanitized: String = feature
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!(
+        "__scope_{sanitized}_{:08x}",
+        stable_hash(feature.as_bytes()) as u32
+    )
+}
+
+/// Whether a cfg name and the selection syntax can represent a feature name: it is not empty and
+/// contains neither `/` nor control characters.
+pub fn is_valid_feature_name(feature: &str) -> bool {
+    !feature.is_empty() && !feature.contains('/') && !feature.chars().any(char::is_control)
+}
+
+/// Rejects feature names that no cfg name or selection syntax can represent.
+pub fn validate_feature_name(package: &str, feature: &str) -> Result<(), DeclError> {
+    if !is_valid_feature_name(feature) {
+        return Err(DeclError::ReservedName {
+            package: package.to_string(),
+            name: feature.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+fn is_plain(feature: &str) -> bool {
+    !feature.is_empty()
+        && feature
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,185 @@
+//! Scope declarations written in code with `declare_feature_scopes!`.
+//!
+//! The macro itself expands to nothing: the declarations are read here from the library's crate
+//! root and merged with `[package.metadata.feature-scope-decl]`. Features may be declared on
+//! either side, but a feature declared on both sides must have the same dependencies, and the
+//! `default` lists must match if both give one.
+
+use anyhow::{Context, Result};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+use syn::{
+    bracketed,
+    ext::IdentExt,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    Ident, Item, Token,
+};
+
+use crate::{diagnostic::DeclError, manifest::FeatureScopeDecl};
+
+const MACRO_NAME: &str = "declare_feature_scopes";
+
+struct CodeDecl {
+    default: Option<Vec<String>>,
+    features: Vec<(String, Vec<String>)>,
+}
+
+impl Parse for CodeDecl {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut default = None;
+        let mut features = Vec::new();
+        let mut seen = HashSet::new();
+
+        while !input.is_empty() {
+            let ident = input.call(Ident::parse_any)?.unraw();
+            let name = ident.to_string();
+            let deps = if input.peek(Token![=]) {
+                input.parse::<Token![=]>()?;
+                let content;
+                bracketed!(content in input);
+                Punctuated::<Ident, Token![,]>::parse_terminated_with(&content, Ident::parse_any)?
+                    .into_iter()
+                    .map(|ident| ident.unraw().to_string())
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+            // Same check as the macro, a second declaration would silently replace the first
+            if !seen.insert(name.clone()) {
+                return Err(syn::Error::new(
+                    ident.span(),
+                    format!("`{name}` is declared more than once"),
+                ));
+            }
+
+            if name == "default" {
+                default = Some(deps);
+            } else {
+                features.push((name, deps));
+            }
+
+            if !input.is_empty() {
+                input.parse::<Token![;]>()?;
+            }
+        }
+
+        Ok(CodeDecl { default, features })
+    }
+}
+
+/// Reads the `declare_feature_scopes!` invocation of a crate root, if there is one.
+pub fn read(crate_root: &Path) -> Result<Option<FeatureScopeDecl>> {
+    let Ok(content) = std::fs::read_to_string(crate_root) else {
+        return Ok(None);
+    };
+
+    // Avoid parsing every crate root with syn
+    if !content.contains(MACRO_NAME) {
+        return Ok(None);
+    }
+
+    let file = syn::parse_file(&content)
+        .with_context(|| format!("Failed to parse {}", crate_root.display()))?;
+
+    let mut found = None;
+    for item in file.items {
+        let Item::Macro(item) = item else {
+            continue;
+        };
+        if item
+            .mac
+            .path
+            .segments
+            .last()
+            .is_none_or(|s| s.ident != MACRO_NAME)
+        {
+            continue;
+        }
+
+        if found.is_some() {
+            anyhow::bail!(
+                "{} contains more than one {MACRO_NAME}! invocation",
+                crate_root.display()
+            );
+        }
+
+        let decl: CodeDecl = item.mac.parse_body().with_context(|| {
+            format!("Failed to parse {MACRO_NAME}! in {}", crate_root.display())
+        })?;
+        found = Some(FeatureScopeDecl {
+            default: decl.default,
+            exclusive: None,
+            features: decl.features.into_iter().collect(),
+            optional_dependencies: HashMap::new(),
+            dependency_features: HashMap::new(),
+            details: HashMap::new(),
+            presets: HashMap::new(),
+            stub_disabled_items: false,
+        });
+    }
+
+    Ok(found)
+}
+
+/// Merges the declarations found in code into the TOML ones.
+pub fn merge(
+    package: &str,
+    toml_decl: Option<FeatureScopeDecl>,
+    code_decl: FeatureScopeDecl,
+) -> Result<FeatureScopeDecl> {
+    let Some(mut merged) = toml_decl else {
+        return Ok(code_decl);
+    };
+
+    match (&merged.default, code_decl.default) {
+        (Some(toml_default), Some(code_default)) => {
+            if !same_set(toml_default, &code_default) {
+                return Err(DeclError::CodeMismatch {
+                    package: package.to_string(),
+                    key: String::from("default"),
+                    toml: toml_default.clone(),
+                    code: code_default,
+                }
+                .into());
+            }
+        }
+        (None, code_default) => merged.default = code_default,
+        (Some(_), None) => {}
+    }
+
+    let mut features: HashMap<String, Vec<String>> = std::mem::take(&mut merged.features);
+    for (feature, code_deps) in code_decl.features {
+        match features.get(&feature) {
+            Some(toml_deps) if !same_set(toml_deps, &code_deps) => {
+                return Err(DeclError::CodeMismatch {
+                    package: package.to_string(),
+                    key: feature,
+                    toml: toml_deps.clone(),
+                    code: code_deps,
+                }
+                .into());
+            }
+            Some(_) => {}
+            None => {
+                features.insert(feature, code_deps);
+            }
+        }
+    }
+    merged.features = features;
+
+    Ok(merged)
+}
+
+fn same_set(a: &[String], b: &[String]) -> bool {
+    let mut a: Vec<&String> = a.iter().collect();
+    let mut b: Vec<&String> = b.iter().collect();
+    a.sort();
+    a.dedup();
+    b.sort();
+    b.dedup();
+    a == b
+}
@@ -0,0 +1,541 @@
+//! Problems found while resolving scopes that don't stop the resolution, unless strict mode
+//! turns the warnings among them into a [`StrictError`], the [`ConflictError`] of resolutions
+//! enabling features that exclude each other and the [`DeclError`] of scope declarations that
+//! cannot be used at all.
+//!
+//! The errors come wrapped in [`anyhow::Error`], and can be matched by downcasting:
+//!
+//! ```no_run
+//! use feature_scope_core::{manifest::load_manifest, DeclError};
+//!
+//! if let Err(err) = load_manifest("Cargo.toml".as_ref()) {
+//!     match err.downcast_ref::<DeclError>() {
+//!         Some(DeclError::UnknownFeature { feature, .. }) => eprintln!("declare '{feature}'"),
+//!         _ => eprintln!("{err:#}"),
+//!     }
+//! }
+//! ```
+
+use std::{
+    fmt,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use crate::probe::Probe;
+
+/// A warning or note about the scope configuration of a workspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// A single package requests a feature it does not declare.
+    FeatureNotDeclared { feature: String },
+    /// A consumer requests a feature its library does not declare.
+    FeatureNotDeclaredInPackage { feature: String, package: String },
+    /// A default feature is not declared on its own, which is deprecated.
+    UndeclaredDefault { feature: String, package: String },
+    /// A consumer selects a feature its library declares as deprecated.
+    DeprecatedFeature {
+        feature: String,
+        package: String,
+        note: String,
+    },
+    /// A consumer references a package without scope declarations.
+    PackageWithoutDecl { package: String },
+    /// A consumer references a package that is neither a member nor a path dependency.
+    PackageNotFound { package: String },
+    /// A consumer's entry references a package outside its dependency graph, and is ignored.
+    /// `manifest` and `line` locate the entry, a selection from `--features` has neither.
+    NotADependency {
+        consumer: String,
+        package: String,
+        manifest: Option<PathBuf>,
+        line: Option<usize>,
+    },
+    /// A consumer has several entries selecting scopes of one package, for the same target,
+    /// platform and profile. They add up, each enabling the scopes it would enable alone.
+    /// `lines` locate them in `manifest`, unless they are written inline.
+    DuplicateEntry {
+        consumer: String,
+        package: String,
+        manifest: PathBuf,
+        lines: Vec<usize>,
+        count: usize,
+    },
+    /// None of the features of a consumer's `prefer` chain is available in its library.
+    NoPreferredFeature {
+        consumer: String,
+        package: String,
+        features: Vec<String>,
+    },
+    /// A consumer removes a feature of its library that another feature it enables depends on,
+    /// so it stays enabled.
+    RemovedFeatureEnabled {
+        consumer: String,
+        package: String,
+        feature: String,
+    },
+    /// A consumer selects `default` of its library, which enabled `features` too when it meant
+    /// every declared feature. Reported with `check-default-migration = true`.
+    DefaultMeaningChanged {
+        consumer: String,
+        package: String,
+        features: Vec<String>,
+    },
+    /// A workspace member's manifest cannot be read, and the package is left out of a
+    /// resolution that does not need it.
+    UnreadableMember { manifest: PathBuf, message: String },
+    /// A feature-scope table has a key this version does not know, reported with
+    /// `strict-keys = true`.
+    UnknownKey {
+        manifest: PathBuf,
+        table: String,
+        key: String,
+    },
+    /// A consumer references a path dependency that is not a workspace member.
+    PathDependencyFallback {
+        package: String,
+        manifest: PathBuf,
+        member: PathBuf,
+    },
+}
+
+impl Diagnostic {
+    /// Whether this is merely informational, as opposed to a warning.
+    pub fn is_note(&self) -> bool {
+        matches!(self, Diagnostic::PathDependencyFallback { .. })
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::FeatureNotDeclared { feature } => {
+                write!(
+                    f,
+                    "feature '{feature}' not declared in feature-scope-decl; \
+                     declare it there or remove it from the selection"
+                )
+            }
+            Diagnostic::FeatureNotDeclaredInPackage { feature, package } => {
+                write!(
+                    f,
+                    "feature '{feature}' not declared in package '{package}'; \
+                     check the feature-scope-decl of '{package}' for its name"
+                )
+            }
+            Diagnostic::UndeclaredDefault { feature, package } => write!(
+                f,
+                "default feature '{feature}' of package '{package}' is not declared in feature-scope-decl; \
+                 add `{feature} = []` (undeclared default features are deprecated)"
+            ),
+            Diagnostic::DeprecatedFeature {
+                feature,
+                package,
+                note,
+            } => write!(
+                f,
+                "feature '{feature}' of package '{package}' is deprecated: {note}"
+            ),
+            Diagnostic::PackageWithoutDecl { package } => {
+                write!(
+                    f,
+                    "package '{package}' does not have feature-scope-decl; \
+                     declare its features or remove the entry referencing it"
+                )
+            }
+            Diagnostic::PackageNotFound { package } => write!(
+                f,
+                "dependency package '{package}' not found in workspace; \
+                 add it to workspace.members or depend on it through a `path` dependency"
+            ),
+            Diagnostic::NotADependency {
+                consumer,
+                package,
+                manifest: None,
+                ..
+            } => write!(
+                f,
+                "--features selects scopes of '{package}', which package '{consumer}' does not depend on, \
+                 so the selection is ignored"
+            ),
+            Diagnostic::NotADependency {
+                consumer,
+                package,
+                manifest: Some(manifest),
+                line,
+            } => write!(
+                f,
+                "{}: the feature-scope entry of package '{consumer}' selects scopes of '{package}', \
+                 which it does not depend on, so the entry is ignored; \
+                 add '{package}' to its dependencies or remove the entry",
+                location(manifest, *line)
+            ),
+            Diagnostic::DuplicateEntry {
+                consumer,
+                package,
+                manifest,
+                lines,
+                count,
+            } => write!(
+                f,
+                "{}: package '{consumer}' has {count} feature-scope entries for '{package}'{}, \
+                 which add up as if each was alone; merge them into one entry",
+                location(manifest, lines.first().copied()),
+                entry_lines(lines)
+            ),
+            Diagnostic::NoPreferredFeature {
+                consumer,
+                package,
+                features,
+            } => write!(
+                f,
+                "package '{consumer}' prefers one of {} of package '{package}', but none is declared, \
+                 available on the target and free of conflicts, so none is enabled",
+                quoted(features)
+            ),
+            Diagnostic::RemovedFeatureEnabled {
+                consumer,
+                package,
+                feature,
+            } => write!(
+                f,
+                "package '{consumer}' removes feature '{feature}' of package '{package}', \
+                 but a feature it still enables depends on it, so it stays enabled"
+            ),
+            Diagnostic::DefaultMeaningChanged {
+                consumer,
+                package,
+                features,
+            } => write!(
+                f,
+                "package '{consumer}' selects 'default' of package '{package}', which enabled {} too \
+                 before it meant the declared default set; select them to keep them",
+                quoted(features)
+            ),
+            Diagnostic::UnreadableMember { manifest, message } => write!(
+                f,
+                "workspace member {} cannot be read and is left out, \
+                 as the package being resolved does not need it: {message}",
+                manifest.display()
+            ),
+            Diagnostic::UnknownKey {
+                manifest,
+                table,
+                key,
+            } => write!(
+                f,
+                "{}: unknown key '{key}' in {table}, which feature-scope ignores; \
+                 check its spelling, or leave strict-keys off for keys of other tools",
+                manifest.display()
+            ),
+            Diagnostic::PathDependencyFallback {
+                package,
+                manifest,
+                member,
+            } => write!(
+                f,
+                "package '{package}' is not a workspace member, using its path dependency at {}; \
+                 add \"{}\" to workspace.members to make this explicit",
+                manifest.display(),
+                member.display()
+            ),
+        }
+    }
+}
+
+// The features as `'a', 'b'`
+fn quoted(features: &[String]) -> String {
+    features
+        .iter()
+        .map(|feature| format!("'{feature}'"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// ` (lines 3, 9)`, or nothing when the lines are unknown
+fn entry_lines(lines: &[usize]) -> String {
+    if lines.is_empty() {
+        return String::new();
+    }
+    let lines: Vec<String> = lines.iter().map(usize::to_string).collect();
+    format!(" (lines {})", lines.join(", "))
+}
+
+// `path:line`, or only the path when the line is unknown
+fn location(manifest: &Path, line: Option<usize>) -> String {
+    match line {
+        Some(line) => format!("{}:{line}", manifest.display()),
+        None => manifest.display().to_string(),
+    }
+}
+
+/// Fails a resolution in strict mode, which does not accept warnings.
+#[derive(Debug, Clone)]
+pub struct StrictError {
+    /// The package the scopes were resolved for.
+    pub package: String,
+    /// The warnings of the resolution, without the notes.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for StrictError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "strict mode denies the scope configuration of '{}':",
+            self.package
+        )?;
+        for diagnostic in &self.diagnostics {
+            write!(f, "\n  - {diagnostic}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for StrictError {}
+
+/// Fails a resolution enabling two features of an exclusive group, or two features declaring a
+/// conflict, of one library.
+#[derive(Debug, Clone)]
+pub struct ConflictError {
+    /// The package the scopes were resolved for.
+    pub consumer: String,
+    /// The library declaring the features.
+    pub library: String,
+    /// The enabled features of the group, in the order the group lists them.
+    pub features: Vec<ConflictingFeature>,
+}
+
+/// An enabled feature of a [`ConflictError`], with everything that enabled it.
+#[derive(Debug, Clone)]
+pub struct ConflictingFeature {
+    pub feature: String,
+    pub origins: Vec<Origin>,
+}
+
+/// Why a feature of a library is enabled.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Origin {
+    /// A consumer's entry selects the feature, or a feature depending on it.
+    Selection { consumer: String, feature: String },
+    /// The library's `default` set enables the feature.
+    Defaults { library: String },
+    /// The probe of a feature of the library found what it looks for.
+    Probe {
+        library: String,
+        feature: String,
+        probe: Probe,
+    },
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Selection { consumer, feature } => {
+                write!(f, "'{consumer}' selecting '{feature}'")
+            }
+            Origin::Defaults { library } => write!(f, "the defaults of '{library}'"),
+            Origin::Probe {
+                library,
+                feature,
+                probe,
+            } => write!(f, "the probe '{probe}' of '{library}/{feature}'"),
+        }
+    }
+}
+
+impl fmt::Display for ConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self
+            .features
+            .iter()
+            .map(|feature| format!("'{}'", feature.feature))
+            .collect();
+        write!(
+            f,
+            "Package '{}' enables {} of package '{}', which are mutually exclusive",
+            self.consumer,
+            names.join(" and "),
+            self.library
+        )?;
+        for feature in &self.features {
+            let origins: Vec<String> = feature.origins.iter().map(Origin::to_string).collect();
+            write!(
+                f,
+                "\n  '{}' is enabled by {}",
+                feature.feature,
+                origins.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConflictError {}
+
+/// Fails loading a manifest with scope declarations no resolution could use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DeclError {
+    /// The manifest is not valid TOML, or a part feature-scope reads has the wrong type, like a
+    /// feature declared as `a = 3`.
+    InvalidManifest {
+        manifest: PathBuf,
+        message: String,
+        span: Option<Span>,
+    },
+    /// A declared name no cfg name or selection syntax can represent: an empty one, or one
+    /// containing `/` or control characters.
+    ReservedName { package: String, name: String },
+    /// A name declared both as a feature and as a preset.
+    DuplicateName { package: String, name: String },
+    /// Two declared features map to the same cfg name, see [`crate::cfg_name`].
+    CfgNameCollision {
+        package: String,
+        features: [String; 2],
+        cfg: String,
+    },
+    /// A preset, an exclusive group or the conflicts of a feature list an undeclared feature.
+    UnknownFeature {
+        package: String,
+        feature: String,
+        listed_in: Listing,
+    },
+    /// `Cargo.toml` and `declare_feature_scopes!` declare a feature, or `default`, differently.
+    CodeMismatch {
+        package: String,
+        key: String,
+        toml: Vec<String>,
+        code: Vec<String>,
+    },
+    /// A feature with `dep:` or `crate/feature` entries has no cargo feature of the same name in
+    /// the `[features]` of its manifest, which cargo needs to activate the dependencies or their
+    /// features.
+    MissingCargoFeature {
+        package: String,
+        manifest: PathBuf,
+        feature: String,
+    },
+}
+
+/// Where in a manifest an [`DeclError::InvalidManifest`] is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// The byte range in the manifest.
+    pub range: Range<usize>,
+    /// The line of the start of the range, from 1.
+    pub line: usize,
+    /// The column of the start of the range in characters, from 1.
+    pub column: usize,
+}
+
+impl Span {
+    /// Locates a byte range in the content of a manifest.
+    pub fn new(content: &str, range: Range<usize>) -> Self {
+        let before = &content[..range.start.min(content.len())];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        Span {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            range,
+        }
+    }
+}
+
+/// What lists the feature of a [`DeclError::UnknownFeature`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Listing {
+    Preset(String),
+    ExclusiveGroup,
+    /// The `conflicts` of the feature.
+    Conflicts(String),
+}
+
+impl fmt::Display for DeclError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeclError::InvalidManifest {
+                manifest,
+                message,
+                span: Some(span),
+            } => write!(
+                f,
+                "Failed to parse {}:{}: {message}",
+                location(manifest, Some(span.line)),
+                span.column
+            ),
+            DeclError::InvalidManifest {
+                manifest,
+                message,
+                span: None,
+            } => write!(f, "Failed to parse {}: {message}", manifest.display()),
+            DeclError::ReservedName { package, name } if name.is_empty() => {
+                write!(f, "Package '{package}' declares a scope with an empty name")
+            }
+            DeclError::ReservedName { package, name } if name.contains('/') => write!(
+                f,
+                "Package '{package}' declares scope '{name}', but scope names cannot contain '/', which separates packages from features"
+            ),
+            DeclError::ReservedName { package, name } => write!(
+                f,
+                "Package '{package}' declares scope {name:?}, but scope names cannot contain control characters"
+            ),
+            DeclError::DuplicateName { package, name } => write!(
+                f,
+                "Package '{package}' declares '{name}' both as a feature and as a preset"
+            ),
+            DeclError::CfgNameCollision {
+                package,
+                features: [first, second],
+                cfg,
+            } => write!(
+                f,
+                "Package '{package}' declares features '{first}' and '{second}', which both map to the cfg name '{cfg}'; rename one of them"
+            ),
+            DeclError::UnknownFeature {
+                package,
+                feature,
+                listed_in: Listing::Preset(preset),
+            } => write!(
+                f,
+                "Preset '{preset}' of package '{package}' lists '{feature}', which feature-scope-decl does not declare"
+            ),
+            DeclError::UnknownFeature {
+                package,
+                feature,
+                listed_in: Listing::ExclusiveGroup,
+            } => write!(
+                f,
+                "Package '{package}' lists '{feature}' in an exclusive group, but does not declare it in feature-scope-decl"
+            ),
+            DeclError::UnknownFeature {
+                package,
+                feature,
+                listed_in: Listing::Conflicts(conflicting),
+            } => write!(
+                f,
+                "Feature '{conflicting}' of package '{package}' conflicts with '{feature}', which feature-scope-decl does not declare"
+            ),
+            DeclError::CodeMismatch {
+                package,
+                key,
+                toml,
+                code,
+            } => write!(
+                f,
+                "Package '{package}' declares {key} = {toml:?} in Cargo.toml but {key} = {code:?} in declare_feature_scopes!"
+            ),
+            DeclError::MissingCargoFeature {
+                package,
+                manifest,
+                feature,
+            } => write!(
+                f,
+                "Feature '{feature}' of package '{package}' activates dependencies or their features, but {} has no '{feature}' key in [features], which cargo needs to activate them",
+                manifest.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DeclError {}
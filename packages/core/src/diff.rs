@@ -0,0 +1,204 @@
+//! Comparison of the scopes two resolutions enable.
+//!
+//! [`ResolvedScopes`] holds the resolutions of several packages, usually every member of a
+//! workspace as [`Resolver::resolve_all`](crate::Resolver::resolve_all) gives them, and
+//! [`ResolvedScopes::diff`] lists the features each package gains or loses between two of them,
+//! and the ones the defaults of their library stop or start enabling. A CI script can assert
+//! with it that a change leaves the release scopes alone:
+//!
+//! ```no_run
+//! use feature_scope_core::{Overrides, Resolver};
+//!
+//! let mut overrides = Overrides::default();
+//! overrides.profiles.push(String::from("release"));
+//! let before = Resolver::from_workspace("../main")?.resolve_all(&overrides)?;
+//! let after = Resolver::from_workspace(".")?.resolve_all(&overrides)?;
+//! let diff = before.diff(&after);
+//! assert!(diff.is_empty(), "The release scopes changed:\n{diff}");
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use crate::{diagnostic::Origin, ResolvedScope};
+
+/// The resolved scopes of several packages, by package.
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedScopes {
+    pub packages: BTreeMap<String, ResolvedScope>,
+}
+
+impl FromIterator<ResolvedScope> for ResolvedScopes {
+    fn from_iter<I: IntoIterator<Item = ResolvedScope>>(scopes: I) -> Self {
+        ResolvedScopes {
+            packages: scopes
+                .into_iter()
+                .map(|scope| (scope.package.clone(), scope))
+                .collect(),
+        }
+    }
+}
+
+impl ResolvedScopes {
+    /// Lists what changed from these scopes to `other`, which is taken as the newer one.
+    pub fn diff(&self, other: &ResolvedScopes) -> ScopeDiff {
+        let mut diff = ScopeDiff {
+            added_packages: other
+                .packages
+                .keys()
+                .filter(|package| !self.packages.contains_key(*package))
+                .cloned()
+                .collect(),
+            removed_packages: self
+                .packages
+                .keys()
+                .filter(|package| !other.packages.contains_key(*package))
+                .cloned()
+                .collect(),
+            ..ScopeDiff::default()
+        };
+        for (package, before) in &self.packages {
+            let Some(after) = other.packages.get(package) else {
+                continue;
+            };
+            let (enabled_before, enabled_after) = (enabled(before, false), enabled(after, false));
+            let (defaults_before, defaults_after) = (enabled(before, true), enabled(after, true));
+            let changes = PackageDiff {
+                added: difference(&enabled_after, &enabled_before),
+                removed: difference(&enabled_before, &enabled_after),
+                defaults_added: difference(&defaults_after, &defaults_before),
+                defaults_removed: difference(&defaults_before, &defaults_after),
+            };
+            if !changes.is_empty() {
+                diff.packages.insert(package.clone(), changes);
+            }
+        }
+        diff
+    }
+}
+
+/// What changed between two [`ResolvedScopes`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ScopeDiff {
+    /// Packages only the newer scopes resolve.
+    pub added_packages: BTreeSet<String>,
+    /// Packages only the older scopes resolve.
+    pub removed_packages: BTreeSet<String>,
+    /// The changes of the packages both resolve, leaving out the unchanged ones.
+    pub packages: BTreeMap<String, PackageDiff>,
+}
+
+impl ScopeDiff {
+    /// Whether both resolve the same packages with the same scopes.
+    pub fn is_empty(&self) -> bool {
+        self.added_packages.is_empty()
+            && self.removed_packages.is_empty()
+            && self.packages.is_empty()
+    }
+}
+
+/// What changed in the scopes of one package, as features by library.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PackageDiff {
+    /// Features enabled now but not before.
+    pub added: BTreeMap<String, BTreeSet<String>>,
+    /// Features enabled before but not now.
+    pub removed: BTreeMap<String, BTreeSet<String>>,
+    /// Features the defaults of their library enable now but did not before, whether another
+    /// origin enabled them already or not.
+    pub defaults_added: BTreeMap<String, BTreeSet<String>>,
+    /// Features the defaults of their library enabled before but do not now.
+    pub defaults_removed: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl PackageDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.defaults_added.is_empty()
+            && self.defaults_removed.is_empty()
+    }
+}
+
+impl fmt::Display for ScopeDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for package in &self.added_packages {
+            writeln!(f, "{package}: new package")?;
+        }
+        for package in &self.removed_packages {
+            writeln!(f, "{package}: no longer a package")?;
+        }
+        for (package, changes) in &self.packages {
+            let enabled = signed(&changes.added, &changes.removed);
+            let defaults = signed(&changes.defaults_added, &changes.defaults_removed);
+            match (enabled.is_empty(), defaults.is_empty()) {
+                (false, false) => writeln!(f, "{package}: {enabled}; defaults: {defaults}")?,
+                (false, true) => writeln!(f, "{package}: {enabled}")?,
+                _ => writeln!(f, "{package}: defaults: {defaults}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// `+library/feature` for the added features and `-library/feature` for the removed ones
+fn signed(
+    added: &BTreeMap<String, BTreeSet<String>>,
+    removed: &BTreeMap<String, BTreeSet<String>>,
+) -> String {
+    let signed = |sign: char, features: &BTreeMap<String, BTreeSet<String>>| {
+        features
+            .iter()
+            .flat_map(|(library, features)| {
+                features
+                    .iter()
+                    .map(move |feature| format!("{sign}{library}/{feature}"))
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut parts = signed('+', added);
+    parts.extend(signed('-', removed));
+    parts.join(", ")
+}
+
+// The enabled features of a resolution by library, or only the ones its defaults enable
+fn enabled(scope: &ResolvedScope, by_defaults: bool) -> BTreeMap<String, BTreeSet<String>> {
+    scope
+        .origins
+        .iter()
+        .map(|(library, features)| {
+            let features = features
+                .iter()
+                .filter(|(_, origins)| {
+                    !by_defaults
+                        || origins
+                            .iter()
+                            .any(|origin| matches!(origin, Origin::Defaults { .. }))
+                })
+                .map(|(feature, _)| feature.clone())
+                .collect();
+            (library.clone(), features)
+        })
+        .collect()
+}
+
+// The features of `a` that `b` does not have, leaving out the libraries without any
+fn difference(
+    a: &BTreeMap<String, BTreeSet<String>>,
+    b: &BTreeMap<String, BTreeSet<String>>,
+) -> BTreeMap<String, BTreeSet<String>> {
+    a.iter()
+        .filter_map(|(library, features)| {
+            let missing: BTreeSet<String> = match b.get(library) {
+                Some(other) => features.difference(other).cloned().collect(),
+                None => features.clone(),
+            };
+            (!missing.is_empty()).then(|| (library.clone(), missing))
+        })
+        .collect()
+}
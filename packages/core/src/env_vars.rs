@@ -0,0 +1,32 @@
+//! Environment variables feature-scope exports to the processes it spawns, and the ones it
+//! reads to resolve.
+//!
+//! The set is fixed: no name depends on a package, feature or variant, so a build script reading
+//! any of them can list each one in `cargo:rerun-if-env-changed` and gets a bounded rerun set
+//! that is the same for every workspace. `cargo feature-scope audit` checks that build scripts do,
+//! and [`load`](crate::load) lists all of them.
+
+/// The space-separated enabled scope cfgs.
+pub const CFGS: &str = "FEATURE_SCOPE_CFGS";
+/// The package the scopes were resolved for.
+pub const TARGET_PACKAGE: &str = "FEATURE_SCOPE_TARGET_PACKAGE";
+/// Enables the macro expansion timings.
+pub const MACRO_TIMING: &str = "FEATURE_SCOPE_MACRO_TIMING";
+/// The directory the macro expansion timings are written to.
+pub const MACRO_TIMING_DIR: &str = "FEATURE_SCOPE_MACRO_TIMING_DIR";
+/// Set to a non-empty value, makes [`load_manifest`](crate::manifest::load_manifest) infer the
+/// declarations of packages that declare no scopes from their `#[feature_scope]` attributes.
+/// `cargo feature-scope --infer` sets it.
+pub const INFER: &str = "FEATURE_SCOPE_INFER";
+/// Points to a workspace index, which then replaces `cargo metadata`.
+pub const INDEX: &str = "FEATURE_SCOPE_INDEX";
+
+/// Every variable above.
+pub const ALL: [&str; 6] = [
+    CFGS,
+    TARGET_PACKAGE,
+    MACRO_TIMING,
+    MACRO_TIMING_DIR,
+    INFER,
+    INDEX,
+];
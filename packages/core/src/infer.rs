@@ -0,0 +1,173 @@
+//! Scope declarations inferred from the `#[feature_scope]` attributes of a package.
+//!
+//! With [`INFER`](crate::env_vars::INFER) set, a package declaring no scopes, neither in
+//! `[package.metadata.feature-scope-decl]` nor with `declare_feature_scopes!`, declares every
+//! feature its `#[feature_scope(...)]` attributes name, negated ones included, without defaults
+//! or dependencies between them. This lets the macros be tried before writing any TOML.
+
+use anyhow::{Context, Result};
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use std::{
+    collections::{BTreeSet, HashMap},
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use crate::{env_vars, manifest::FeatureScopeDecl};
+
+const ATTRIBUTE_NAME: &str = "feature_scope";
+
+/// Whether inference is turned on for this process.
+pub fn is_enabled() -> bool {
+    env::var_os(env_vars::INFER).is_some_and(|value| !value.is_empty())
+}
+
+/// Infers the declarations of the package in `package_dir`, `None` if no attribute names a
+/// feature.
+pub fn read(package_dir: &Path) -> Result<Option<FeatureScopeDecl>> {
+    // Resolutions read the same manifests many times, the sources only change between runs
+    static CACHE: OnceLock<Mutex<HashMap<PathBuf, BTreeSet<String>>>> = OnceLock::new();
+    let cache = CACHE.get_or_init(Default::default);
+    let mut cache = cache
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let features = match cache.get(package_dir) {
+        Some(features) => features.clone(),
+        None => {
+            let features = scan_package(package_dir)?;
+            cache.insert(package_dir.to_path_buf(), features.clone());
+            features
+        }
+    };
+
+    if features.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(FeatureScopeDecl {
+        default: None,
+        exclusive: None,
+        features: features
+            .into_iter()
+            .map(|feature| (feature, Vec::new()))
+            .collect(),
+        optional_dependencies: HashMap::new(),
+        dependency_features: HashMap::new(),
+        details: HashMap::new(),
+        presets: HashMap::new(),
+        stub_disabled_items: false,
+    }))
+}
+
+// The features named by the attributes in the sources of a package
+fn scan_package(package_dir: &Path) -> Result<BTreeSet<String>> {
+    let mut files = Vec::new();
+    collect_sources(package_dir, package_dir, &mut files)?;
+
+    let mut features = BTreeSet::new();
+    for file in files {
+        let content = fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        // Avoid tokenizing every file
+        if !content.contains(ATTRIBUTE_NAME) {
+            continue;
+        }
+        let tokens: TokenStream = content
+            .parse()
+            .map_err(|err| anyhow::anyhow!("Failed to parse {}: {err}", file.display()))?;
+        scan_tokens(tokens, &mut features);
+    }
+    Ok(features)
+}
+
+// Collects the Rust sources of a package, leaving out build output and nested packages
+fn collect_sources(package_dir: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy();
+
+        if path.is_dir() {
+            let is_nested_package = path != package_dir && path.join("Cargo.toml").exists();
+            if name != "target" && !name.starts_with('.') && !is_nested_package {
+                collect_sources(package_dir, &path, files)?;
+            }
+        } else if name.ends_with(".rs") {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+// Finds the attributes anywhere in the tokens, including those of nested items and statements
+fn scan_tokens(tokens: TokenStream, features: &mut BTreeSet<String>) {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    for (index, token) in tokens.iter().enumerate() {
+        let TokenTree::Group(group) = token else {
+            continue;
+        };
+        let follows_hash = index
+            .checked_sub(1)
+            .is_some_and(|previous| is_punct(&tokens[previous], '#'));
+        if group.delimiter() == Delimiter::Bracket && follows_hash {
+            scan_attribute(group.stream(), features);
+        }
+        scan_tokens(group.stream(), features);
+    }
+}
+
+// Reads an attribute whose path ends with `feature_scope`, as in `#[feature_scope::feature_scope]`
+fn scan_attribute(tokens: TokenStream, features: &mut BTreeSet<String>) {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    let Some((TokenTree::Ident(name), TokenTree::Group(arguments))) = tokens
+        .windows(2)
+        .find(|window| matches!(window[1], TokenTree::Group(_)))
+        .map(|window| (&window[0], &window[1]))
+    else {
+        return;
+    };
+    if name != ATTRIBUTE_NAME || arguments.delimiter() != Delimiter::Parenthesis {
+        return;
+    }
+
+    // A predicate like `all(a, not(b))` comes first, then options like `feature = "a"` or `stub`
+    let arguments: Vec<TokenTree> = arguments.stream().into_iter().collect();
+    let options = arguments.split(|token| is_punct(token, ','));
+    for (position, option) in options.enumerate() {
+        match option {
+            [TokenTree::Ident(key), TokenTree::Punct(eq), TokenTree::Literal(value)]
+                if key == "feature" && eq.as_char() == '=' =>
+            {
+                if let Ok(value) = syn::parse_str::<syn::LitStr>(&value.to_string()) {
+                    features.insert(value.value());
+                }
+            }
+            [_, TokenTree::Punct(eq), ..] if eq.as_char() == '=' => {}
+            predicate if position == 0 => scan_predicate(predicate, features),
+            _ => {}
+        }
+    }
+}
+
+fn scan_predicate(tokens: &[TokenTree], features: &mut BTreeSet<String>) {
+    let mut index = 0;
+    while let Some(token) = tokens.get(index) {
+        match (token, tokens.get(index + 1)) {
+            // `all`, `any` and `not` with their operands
+            (TokenTree::Ident(_), Some(TokenTree::Group(group))) => {
+                let operands: Vec<TokenTree> = group.stream().into_iter().collect();
+                scan_predicate(&operands, features);
+                index += 2;
+            }
+            (TokenTree::Ident(ident), _) => {
+                features.insert(ident.to_string().trim_start_matches("r#").to_string());
+                index += 1;
+            }
+            _ => index += 1,
+        }
+    }
+}
+
+fn is_punct(token: &TokenTree, punct: char) -> bool {
+    matches!(token, TokenTree::Punct(found) if found.as_char() == punct)
+}
@@ -0,0 +1,60 @@
+//! # feature-scope-core
+//!
+//! The scope resolution shared by the `cargo feature-scope` CLI and other tools that need the
+//! exact same flags: reading the scope declarations of a workspace, mapping feature names to cfg
+//! names and resolving which scopes a package is built with.
+//!
+//! ```no_run
+//! use feature_scope_core::Resolver;
+//!
+//! let resolver = Resolver::from_workspace(".")?;
+//! let scope = resolver.resolve("your-package-name")?;
+//! for diagnostic in &scope.diagnostics {
+//!     eprintln!("Warning: {diagnostic}");
+//! }
+//! println!("{}", scope.enabled_cfgs().collect::<Vec<_>>().join(" "));
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Build scripts get the same scopes for plain `cargo` builds with [`load`], and xtask tools and
+//! tests resolve from explicit paths with [`load_with`].
+//! [`Resolver::resolve_all`] resolves every package of a workspace at once, and
+//! [`ResolvedScopes::diff`] compares two such resolutions.
+//!
+//! The [`manifest`] and [`workspace`] modules are public for tools that read the same manifests
+//! without resolving. Their items follow semver like the rest of the crate, and the types marked
+//! `#[non_exhaustive]` may gain fields in minor releases. [`env_vars`] names the variables the
+//! CLI exports to the processes it spawns.
+
+mod build_script;
+pub mod cfg_name;
+mod code_decl;
+pub mod diagnostic;
+mod diff;
+pub mod env_vars;
+mod infer;
+pub mod manifest;
+pub mod platform;
+pub mod probe;
+mod resolver;
+pub mod workspace;
+
+pub use build_script::{load, load_with, LoadOptions};
+pub use diagnostic::{
+    ConflictError, ConflictingFeature, DeclError, Diagnostic, Origin, StrictError,
+};
+pub use diff::{PackageDiff, ResolvedScopes, ScopeDiff};
+pub use resolver::{
+    default_packages, determine_default_package, resolve_cfg_args, resolve_cfg_args_with,
+    resolve_feature_selection, resolve_requested_feature, Overrides, ResolvedScope, Resolver,
+};
+
+/// Computes a 64-bit FNV-1a hash, which stays stable across Rust releases.
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+    })
+}
@@ -0,0 +1,741 @@
+//! The parts of `Cargo.toml` manifests feature-scope reads.
+//!
+//! Scopes are declared in `[package.metadata.feature-scope-decl]` or with
+//! `declare_feature_scopes!` in the library's crate root, and consumed through
+//! `[[package.metadata.feature-scope]]` entries.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cfg_name, code_decl,
+    diagnostic::{DeclError, Listing, Span},
+    infer, platform,
+    probe::Probe,
+};
+
+/// The parts of a `Cargo.toml` the resolution reads.
+#[derive(Debug, Deserialize)]
+pub struct CargoToml {
+    pub package: Option<Package>,
+    pub workspace: Option<Workspace>,
+    pub dependencies: Option<HashMap<String, Dependency>>,
+    #[serde(rename = "dev-dependencies")]
+    pub dev_dependencies: Option<HashMap<String, Dependency>>,
+    #[serde(rename = "build-dependencies")]
+    pub build_dependencies: Option<HashMap<String, Dependency>>,
+    /// Platform-specific dependencies, by `cfg(...)` expression or target triple.
+    pub target: Option<HashMap<String, TargetDependencies>>,
+    pub lib: Option<LibTarget>,
+    /// The `[features]` table, with the entries of each cargo feature.
+    pub features: Option<HashMap<String, Vec<String>>>,
+    /// The `[profile.*]` tables, by profile name.
+    pub profile: Option<HashMap<String, ProfileTable>>,
+}
+
+/// The dependency tables of a `[target.'cfg(...)']` section.
+#[derive(Debug, Deserialize)]
+pub struct TargetDependencies {
+    pub dependencies: Option<HashMap<String, Dependency>>,
+    #[serde(rename = "dev-dependencies")]
+    pub dev_dependencies: Option<HashMap<String, Dependency>>,
+    #[serde(rename = "build-dependencies")]
+    pub build_dependencies: Option<HashMap<String, Dependency>>,
+}
+
+/// The parts of a `[profile.*]` table the resolution reads.
+#[derive(Debug, Deserialize)]
+pub struct ProfileTable {
+    pub inherits: Option<String>,
+}
+
+impl CargoToml {
+    /// Returns `profile` followed by the profiles it inherits from, through the `inherits` of the
+    /// `[profile.*]` tables of a root manifest and the built-in `test` and `bench` profiles.
+    pub fn profile_chain(&self, profile: &str) -> Vec<String> {
+        let mut chain = vec![profile.to_string()];
+        loop {
+            let last = chain.last().unwrap().as_str();
+            let inherits = self
+                .profile
+                .as_ref()
+                .and_then(|profiles| profiles.get(last))
+                .and_then(|table| table.inherits.clone())
+                .or_else(|| match last {
+                    "test" => Some(String::from("dev")),
+                    "bench" => Some(String::from("release")),
+                    _ => None,
+                });
+            // Cargo rejects cycles, so stop at one rather than loop
+            match inherits {
+                Some(inherits) if !chain.contains(&inherits) => chain.push(inherits),
+                _ => return chain,
+            }
+        }
+    }
+
+    /// Returns the options of a root manifest, the defaults if it has none.
+    pub fn options(&self) -> FeatureScopeOptions {
+        let options = match &self.workspace {
+            Some(workspace) => workspace
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.feature_scope_options.as_ref()),
+            None => self
+                .package
+                .as_ref()
+                .and_then(|package| package.metadata.as_ref())
+                .and_then(|metadata| metadata.feature_scope_options.as_ref()),
+        };
+        options.cloned().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LibTarget {
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Workspace {
+    /// Member paths, possibly with glob patterns, relative to the root manifest.
+    pub members: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    #[serde(rename = "default-members")]
+    pub default_members: Option<Vec<String>>,
+    pub dependencies: Option<HashMap<String, Dependency>>,
+    pub metadata: Option<WorkspaceMetadata>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct WorkspaceMetadata {
+    #[serde(rename = "feature-scope-options")]
+    pub feature_scope_options: Option<FeatureScopeOptions>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Dependency {
+    Detailed(DependencyDetail),
+    // Plain version requirements never point to a path
+    Version(#[allow(dead_code)] String),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DependencyDetail {
+    pub path: Option<String>,
+    pub package: Option<String>,
+    #[serde(default)]
+    pub workspace: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Package {
+    pub name: String,
+    /// The directory of the workspace root, if the package names it.
+    pub workspace: Option<String>,
+    pub metadata: Option<Metadata>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Metadata {
+    #[serde(rename = "feature-scope-decl")]
+    pub feature_scope_decl: Option<FeatureScopeDecl>,
+    #[serde(rename = "feature-scope")]
+    pub feature_scope: Option<Vec<FeatureScope>>,
+    #[serde(rename = "feature-scope-options")]
+    pub feature_scope_options: Option<FeatureScopeOptions>,
+}
+
+/// The `feature-scope-options` of the root manifest, in `[workspace.metadata]` for workspaces
+/// and in `[package.metadata]` for single packages.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FeatureScopeOptions {
+    /// Fails the resolution on warnings, like `--deny-warnings`.
+    #[serde(default)]
+    pub strict: bool,
+    /// How the scopes of several packages built by one command are combined.
+    #[serde(default, rename = "consumer-conflicts")]
+    pub consumer_conflicts: ConsumerConflicts,
+    /// Named sequences of cargo commands for `cargo feature-scope pipeline`, like
+    /// `ci = ["check --all-targets", "test"]`.
+    #[serde(default)]
+    pub pipeline: BTreeMap<String, Vec<String>>,
+    /// The name of the copies `--artifact-dir` makes of the binaries of a variant build, with
+    /// `{name}` for the binary and `{variant}` for the variant, like `"{name}-{variant}"`.
+    #[serde(rename = "artifact-name")]
+    pub artifact_name: Option<String>,
+    /// What the builds with `--variant <NAME>` select and compile with, by variant name.
+    #[serde(default)]
+    pub variants: BTreeMap<String, VariantOptions>,
+    /// Reports the keys of the feature-scope tables this version does not know as warnings,
+    /// instead of keeping them as extensions silently.
+    #[serde(default, rename = "strict-keys")]
+    pub strict_keys: bool,
+    /// Warns about the entries selecting `default` that used to enable every declared feature
+    /// of their library, before it meant the declared default set.
+    #[serde(default, rename = "check-default-migration")]
+    pub check_default_migration: bool,
+    /// The keys this version does not know, left to other tools.
+    #[serde(flatten)]
+    pub extensions: HashMap<String, toml::Value>,
+}
+
+/// A variant of `feature-scope-options`, like a `tiny` build optimized for size:
+///
+/// ```toml
+/// [workspace.metadata.feature-scope-options.variants.tiny]
+/// features = ["codec/minimal"]
+/// profile = { opt-level = "z", panic = "abort" }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct VariantOptions {
+    /// `[package/]feature` selections added to the entries, like `--features`.
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Settings of the cargo profile the build uses, overridden with `--config`, like
+    /// `opt-level = "z"`. Tables give nested settings, like `package."*"`.
+    #[serde(default)]
+    pub profile: BTreeMap<String, toml::Value>,
+}
+
+/// How the CLI builds several packages at once, with `--workspace` or more than one `-p`, when
+/// they resolve to different scopes. Cargo applies one set of RUSTFLAGS to all of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConsumerConflicts {
+    /// Builds them together when they resolve to the same scopes, and one by one like
+    /// [`ConsumerConflicts::PerTargetIsolation`] otherwise.
+    #[default]
+    Auto,
+    /// Builds them with the union of their scopes, failing only if the union enables features
+    /// that are mutually exclusive.
+    Union,
+    /// Fails if they resolve to different scopes.
+    Error,
+    /// Builds them one by one, each with its own scopes in a target directory of its own.
+    PerTargetIsolation,
+}
+
+/// A library's `[package.metadata.feature-scope-decl]`, merged with its `declare_feature_scopes!`.
+///
+/// A feature is declared either with the array of the features it depends on, `a = ["b"]`, or
+/// with a table also giving its documentation, the features it conflicts with and a deprecation
+/// note: `a = { deps = ["b"], doc = "...", conflicts = ["c"], deprecated = "use b instead" }`,
+/// and a [`Probe`](crate::probe::Probe) enabling it: `avx2 = { probe = "target-feature:avx2" }`.
+/// `presets = { web = ["http", "json"] }` names bundles of features selected at once.
+/// `tls = ["dep:rustls"]` makes a feature activate an optional dependency, and
+/// `serde-support = ["serde/derive"]` a feature of a dependency, which the CLI does by passing
+/// the library's cargo feature of the same name to cargo.
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "RawFeatureScopeDecl")]
+pub struct FeatureScopeDecl {
+    pub default: Option<Vec<String>>,
+    /// Groups of features of which a resolution may enable at most one, e.g. database backends.
+    pub exclusive: Option<Vec<Vec<String>>>,
+    /// Every declared feature with the features it depends on.
+    pub features: HashMap<String, Vec<String>>,
+    /// The optional dependencies each feature activates, listed as `dep:name` among its
+    /// dependencies and left out of `features`.
+    pub optional_dependencies: HashMap<String, Vec<String>>,
+    /// The features of dependencies each feature enables, listed as `crate/feature` among its
+    /// dependencies and left out of `features`.
+    pub dependency_features: HashMap<String, Vec<String>>,
+    /// The details of the features declared with a table.
+    pub details: HashMap<String, FeatureDetails>,
+    /// Names selecting several features at once. A preset has no cfg of its own, it stands for
+    /// the features it lists.
+    pub presets: HashMap<String, Vec<String>>,
+    /// `stub-disabled-items = true`: the functions of disabled scopes are kept with
+    /// `unimplemented!()` bodies, like with `#[feature_scope(a, stub)]`. The macros read it
+    /// from the manifest themselves.
+    pub stub_disabled_items: bool,
+}
+
+impl FeatureScopeDecl {
+    /// Returns whether `feature` is a declared feature or a preset.
+    pub fn declares(&self, feature: &str) -> bool {
+        self.features.contains_key(feature) || self.presets.contains_key(feature)
+    }
+
+    /// Returns whether `feature` has `dep:` or `crate/feature` entries, which only the cargo
+    /// feature of the same name can enable.
+    pub fn needs_cargo_feature(&self, feature: &str) -> bool {
+        self.optional_dependencies.contains_key(feature)
+            || self.dependency_features.contains_key(feature)
+    }
+}
+
+/// What the table form of a feature declaration says besides its dependencies.
+#[derive(Debug, Clone, Default)]
+pub struct FeatureDetails {
+    pub doc: Option<String>,
+    /// Features that cannot be enabled together with this one.
+    pub conflicts: Vec<String>,
+    /// Why the feature should not be selected anymore, and what to select instead.
+    pub deprecated: Option<String>,
+    /// What enables the feature when found in the build environment.
+    pub probe: Option<Probe>,
+    /// The platforms the feature is enabled on, a triple or `cfg(...)`, elsewhere it is dropped.
+    pub platform: Option<String>,
+    /// The keys of the table this version does not know, left to other tools.
+    pub extensions: HashMap<String, toml::Value>,
+}
+
+#[derive(Deserialize)]
+struct RawFeatureScopeDecl {
+    default: Option<Vec<String>>,
+    exclusive: Option<Vec<Vec<String>>>,
+    #[serde(default)]
+    presets: HashMap<String, Vec<String>>,
+    #[serde(default, rename = "stub-disabled-items")]
+    stub_disabled_items: bool,
+    #[serde(flatten)]
+    features: HashMap<String, RawFeature>,
+}
+
+#[derive(Deserialize)]
+#[serde(
+    untagged,
+    expecting = "an array of features or a table of `deps`, `doc`, `conflicts`, `deprecated`, `probe` and `platform`"
+)]
+enum RawFeature {
+    Deps(Vec<String>),
+    Table(FeatureTable),
+}
+
+#[derive(Deserialize)]
+struct FeatureTable {
+    #[serde(default, alias = "requires")]
+    deps: Vec<String>,
+    doc: Option<String>,
+    #[serde(default)]
+    conflicts: Vec<String>,
+    deprecated: Option<String>,
+    probe: Option<String>,
+    platform: Option<String>,
+    #[serde(flatten)]
+    extensions: HashMap<String, toml::Value>,
+}
+
+impl TryFrom<RawFeatureScopeDecl> for FeatureScopeDecl {
+    type Error = String;
+
+    fn try_from(raw: RawFeatureScopeDecl) -> Result<Self, Self::Error> {
+        let mut features = HashMap::new();
+        let mut optional_dependencies = HashMap::new();
+        let mut dependency_features = HashMap::new();
+        let mut details = HashMap::new();
+        // `dep:` and `crate/feature` entries name what cargo enables rather than features
+        let mut split = |feature: &String, deps: Vec<String>| {
+            let (dependencies, deps): (Vec<String>, Vec<String>) =
+                deps.into_iter().partition(|dep| dep.starts_with("dep:"));
+            if !dependencies.is_empty() {
+                let dependencies = dependencies
+                    .into_iter()
+                    .map(|dep| dep["dep:".len()..].to_string())
+                    .collect();
+                optional_dependencies.insert(feature.clone(), dependencies);
+            }
+            let (external, deps): (Vec<String>, Vec<String>) =
+                deps.into_iter().partition(|dep| dep.contains('/'));
+            if !external.is_empty() {
+                dependency_features.insert(feature.clone(), external);
+            }
+            deps
+        };
+        for (feature, declaration) in raw.features {
+            match declaration {
+                RawFeature::Deps(deps) => {
+                    let deps = split(&feature, deps);
+                    features.insert(feature, deps);
+                }
+                RawFeature::Table(table) => {
+                    // Parsed here, an untagged enum would only say that no form matched
+                    let probe = table
+                        .probe
+                        .map(Probe::try_from)
+                        .transpose()
+                        .map_err(|err| format!("feature '{feature}': {err}"))?;
+                    if let Some(platform) = &table.platform {
+                        platform::validate(platform)
+                            .map_err(|err| format!("feature '{feature}': {err:#}"))?;
+                    }
+                    let deps = split(&feature, table.deps);
+                    features.insert(feature.clone(), deps);
+                    details.insert(
+                        feature,
+                        FeatureDetails {
+                            doc: table.doc,
+                            conflicts: table.conflicts,
+                            deprecated: table.deprecated,
+                            probe,
+                            platform: table.platform,
+                            extensions: table.extensions,
+                        },
+                    );
+                }
+            }
+        }
+
+        Ok(FeatureScopeDecl {
+            default: raw.default,
+            exclusive: raw.exclusive,
+            features,
+            optional_dependencies,
+            dependency_features,
+            details,
+            presets: raw.presets,
+            stub_disabled_items: raw.stub_disabled_items,
+        })
+    }
+}
+
+/// One `[[package.metadata.feature-scope]]` entry of a consumer.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "RawFeatureScope")]
+pub struct FeatureScope {
+    pub package: Option<String>,
+    // Directory or manifest of the package, relative to the consumer's manifest
+    pub path: Option<String>,
+    pub features: Vec<String>,
+    /// The features `features` removes with `-feature` or `a - b`, left out of what the entry
+    /// enables. In a workspace, an entry that only removes features starts from the defaults.
+    pub removed: Vec<String>,
+    /// The `{ prefer = [...] }` items of `features`, of which the first available feature is
+    /// enabled.
+    pub prefer: Vec<Vec<String>>,
+    #[serde(rename = "default-features")]
+    pub default_features: Option<bool>,
+    // `kind:name` of the only target the entry applies to, like `bin:server` or `example:demo`,
+    // or a platform like `platform`
+    pub target: Option<String>,
+    // Triple or `cfg(...)` expression of the platforms the entry applies to
+    pub platform: Option<String>,
+    // Cargo profile the entry applies to, like `release`, including the profiles inheriting it
+    pub profile: Option<String>,
+    /// The keys of the entry this version does not know, left to other tools.
+    pub extensions: HashMap<String, toml::Value>,
+}
+
+impl FeatureScope {
+    /// The selected features followed by the removed ones, written `-feature`.
+    pub fn selection(&self) -> Vec<String> {
+        let removed = self.removed.iter().map(|feature| format!("-{feature}"));
+        self.features.iter().cloned().chain(removed).collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct RawFeatureScope {
+    package: Option<String>,
+    path: Option<String>,
+    features: Vec<RawFeatureRequest>,
+    #[serde(rename = "default-features")]
+    default_features: Option<bool>,
+    target: Option<String>,
+    platform: Option<String>,
+    profile: Option<String>,
+    #[serde(flatten)]
+    extensions: HashMap<String, toml::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(
+    untagged,
+    expecting = "a feature name or a table with the `prefer` list of features to pick from"
+)]
+enum RawFeatureRequest {
+    Feature(String),
+    Prefer { prefer: Vec<String> },
+}
+
+impl From<RawFeatureScope> for FeatureScope {
+    fn from(raw: RawFeatureScope) -> Self {
+        let mut features = Vec::new();
+        let mut removed = Vec::new();
+        let mut prefer = Vec::new();
+        for request in raw.features {
+            match request {
+                RawFeatureRequest::Feature(request) => {
+                    for (is_removal, feature) in selection_terms(&request) {
+                        if is_removal {
+                            removed.push(feature);
+                        } else {
+                            features.push(feature);
+                        }
+                    }
+                }
+                RawFeatureRequest::Prefer { prefer: chain } => prefer.push(chain),
+            }
+        }
+        FeatureScope {
+            package: raw.package,
+            path: raw.path,
+            features,
+            removed,
+            prefer,
+            default_features: raw.default_features,
+            target: raw.target,
+            platform: raw.platform,
+            profile: raw.profile,
+            extensions: raw.extensions,
+        }
+    }
+}
+
+// Splits a request like `default - telemetry + tls` into its terms, `true` for the removed
+// ones. Feature names may contain `-`, so a binary `-` needs spaces around it.
+fn selection_terms(request: &str) -> Vec<(bool, String)> {
+    let mut terms = Vec::new();
+    let mut removal = false;
+    for token in request.split_whitespace() {
+        match token {
+            "+" => removal = false,
+            "-" => removal = true,
+            _ => {
+                let term = match token.strip_prefix('-') {
+                    Some(feature) => (true, feature),
+                    None => (removal, token.strip_prefix('+').unwrap_or(token)),
+                };
+                terms.push((term.0, term.1.to_string()));
+                removal = false;
+            }
+        }
+    }
+    terms
+}
+
+/// Reads a manifest and merges the `declare_feature_scopes!` declarations of its library into
+/// the TOML ones. With [`INFER`](crate::env_vars::INFER) set, a package declaring nothing
+/// declares the features its `#[feature_scope]` attributes name.
+pub fn load_manifest(manifest_path: &Path) -> Result<CargoToml> {
+    let content = std::fs::read_to_string(manifest_path)
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+    let mut cargo_toml: CargoToml =
+        toml::from_str(&content).map_err(|err| DeclError::InvalidManifest {
+            manifest: manifest_path.to_path_buf(),
+            message: err.message().trim_end().to_string(),
+            span: err.span().map(|range| Span::new(&content, range)),
+        })?;
+
+    let lib_path = cargo_toml
+        .lib
+        .as_ref()
+        .and_then(|lib| lib.path.as_deref())
+        .unwrap_or("src/lib.rs");
+    let crate_root = manifest_path.parent().unwrap().join(lib_path);
+
+    if let Some(package) = &mut cargo_toml.package {
+        if let Some(code_decl) = code_decl::read(&crate_root)? {
+            let metadata = package.metadata.get_or_insert_with(Metadata::default);
+            metadata.feature_scope_decl = Some(code_decl::merge(
+                &package.name,
+                metadata.feature_scope_decl.take(),
+                code_decl,
+            )?);
+        }
+
+        let declares = package
+            .metadata
+            .as_ref()
+            .is_some_and(|metadata| metadata.feature_scope_decl.is_some());
+        if !declares && infer::is_enabled() {
+            if let Some(inferred) = infer::read(manifest_path.parent().unwrap())? {
+                package
+                    .metadata
+                    .get_or_insert_with(Metadata::default)
+                    .feature_scope_decl = Some(inferred);
+            }
+        }
+    }
+
+    if let Some(package) = &cargo_toml.package {
+        let decl = package
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.feature_scope_decl.as_ref());
+        if let Some(decl) = decl {
+            let names = decl
+                .features
+                .iter()
+                .flat_map(|(feature, dependencies)| std::iter::once(feature).chain(dependencies));
+            for feature in names.chain(decl.default.iter().flatten()) {
+                cfg_name::validate_feature_name(&package.name, feature)?;
+            }
+            let mut features: Vec<&String> = decl.features.keys().collect();
+            features.sort();
+            let mut cfgs = HashMap::new();
+            for feature in features {
+                let cfg = cfg_name::scope_cfg(feature);
+                if let Some(other) = cfgs.insert(cfg.clone(), feature) {
+                    return Err(DeclError::CfgNameCollision {
+                        package: package.name.clone(),
+                        features: [other.clone(), feature.clone()],
+                        cfg,
+                    }
+                    .into());
+                }
+            }
+            for (preset, features) in &decl.presets {
+                cfg_name::validate_feature_name(&package.name, preset)?;
+                if decl.features.contains_key(preset) {
+                    return Err(DeclError::DuplicateName {
+                        package: package.name.clone(),
+                        name: preset.clone(),
+                    }
+                    .into());
+                }
+                if let Some(feature) = features.iter().find(|f| !decl.features.contains_key(*f)) {
+                    return Err(unknown_feature(
+                        package,
+                        feature,
+                        Listing::Preset(preset.clone()),
+                    ));
+                }
+            }
+            for feature in decl.exclusive.iter().flatten().flatten() {
+                if !decl.features.contains_key(feature) {
+                    return Err(unknown_feature(package, feature, Listing::ExclusiveGroup));
+                }
+            }
+            for (feature, details) in &decl.details {
+                for conflict in &details.conflicts {
+                    if !decl.features.contains_key(conflict) {
+                        let listing = Listing::Conflicts(feature.clone());
+                        return Err(unknown_feature(package, conflict, listing));
+                    }
+                }
+            }
+            // Only the cargo feature of the same name makes cargo activate the dependencies
+            let mut cargo_backed: Vec<&String> = decl
+                .features
+                .keys()
+                .filter(|feature| decl.needs_cargo_feature(feature))
+                .collect();
+            cargo_backed.sort();
+            let cargo_features = cargo_toml.features.as_ref();
+            for feature in cargo_backed {
+                if !cargo_features.is_some_and(|features| features.contains_key(feature)) {
+                    return Err(DeclError::MissingCargoFeature {
+                        package: package.name.clone(),
+                        manifest: manifest_path.to_path_buf(),
+                        feature: feature.clone(),
+                    }
+                    .into());
+                }
+            }
+        }
+    }
+
+    Ok(cargo_toml)
+}
+
+fn unknown_feature(package: &Package, feature: &str, listed_in: Listing) -> anyhow::Error {
+    DeclError::UnknownFeature {
+        package: package.name.clone(),
+        feature: feature.to_string(),
+        listed_in,
+    }
+    .into()
+}
+
+/// Finds the root manifest for `start_dir` the way cargo does: the closest `Cargo.toml` in
+/// `start_dir` or its ancestors, or the root of the workspace it belongs to. That is the one
+/// its `package.workspace` points to, or else the closest ancestor with a `[workspace]` table
+/// that does not `exclude` it.
+pub fn find_root_manifest(start_dir: &Path) -> Result<PathBuf> {
+    let mut current_dir = start_dir.to_path_buf();
+
+    loop {
+        let cargo_toml = current_dir.join("Cargo.toml");
+        if cargo_toml.exists() {
+            return Ok(workspace_root(&cargo_toml)?.unwrap_or(cargo_toml));
+        }
+
+        if let Some(parent) = current_dir.parent() {
+            current_dir = parent.to_path_buf();
+        } else {
+            break;
+        }
+    }
+
+    anyhow::bail!("Could not find Cargo.toml in current directory or parent directories")
+}
+
+// The root manifest of the workspace the package of `manifest_path` is a member of, if another
+fn workspace_root(manifest_path: &Path) -> Result<Option<PathBuf>> {
+    let cargo_toml = load_manifest(manifest_path)?;
+    if cargo_toml.workspace.is_some() {
+        return Ok(None);
+    }
+    let package_dir = normalize_path(manifest_path.parent().unwrap());
+    let explicit = cargo_toml
+        .package
+        .as_ref()
+        .and_then(|package| package.workspace.as_ref());
+    if let Some(root_dir) = explicit {
+        return Ok(Some(
+            normalize_path(&package_dir.join(root_dir)).join("Cargo.toml"),
+        ));
+    }
+
+    for dir in package_dir.ancestors().skip(1) {
+        let candidate = dir.join("Cargo.toml");
+        // Manifests above the workspace that are not ours to read are skipped, not errors
+        let Ok(root_cargo_toml) = load_manifest(&candidate) else {
+            continue;
+        };
+        let Some(workspace) = &root_cargo_toml.workspace else {
+            continue;
+        };
+        let excluded = workspace
+            .exclude
+            .iter()
+            .flatten()
+            .any(|exclude| package_dir.starts_with(normalize_path(&dir.join(exclude))));
+        return Ok((!excluded).then_some(candidate));
+    }
+    Ok(None)
+}
+
+/// Returns the name of the package a manifest declares.
+pub fn extract_package_name_from_manifest(member_manifest: &Path) -> Result<String> {
+    let cargo_toml = load_manifest(member_manifest)?;
+
+    if let Some(package) = cargo_toml.package {
+        Ok(package.name)
+    } else {
+        anyhow::bail!("No package found in {}", member_manifest.display())
+    }
+}
+
+/// Reads the package a manifest declares.
+pub fn read_package(manifest_path: &Path) -> Result<Package> {
+    let cargo_toml = load_manifest(manifest_path)?;
+
+    cargo_toml
+        .package
+        .ok_or_else(|| anyhow::anyhow!("No package found in {}", manifest_path.display()))
+}
+
+// Resolves `.` and `..` components without touching the file system
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
@@ -0,0 +1,285 @@
+//! Target platforms, for entries that only apply when building for some of them.
+//!
+//! A `platform` is written like the keys of cargo's `[target.'cfg(...)'.dependencies]`: a target
+//! triple, or a `cfg(...)` expression over the cfgs `rustc --print cfg` reports for the target:
+//!
+//! ```toml
+//! [[package.metadata.feature-scope]]
+//! package = "your-library-name"
+//! features = ["mmap"]
+//! platform = "cfg(all(unix, not(target_os = \"android\")))"
+//! ```
+//!
+//! The target is the one given in [`Overrides::target`](crate::Overrides::target). Without one,
+//! build scripts resolve for the `TARGET` cargo sets for them, and everything else for the host.
+//!
+//! Build scripts take the cfgs of their target from the `CARGO_CFG_*` variables cargo sets for
+//! them, and the host from `HOST`. Resolutions through a workspace index take them from the
+//! index, which `cargo feature-scope index` writes for the host and the `--target`s it is given.
+//! Only other resolutions spawn rustc to ask.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, iter,
+    path::Path,
+    process,
+    sync::{Mutex, OnceLock},
+};
+use syn::{punctuated::Punctuated, Expr, ExprLit, Lit, Meta, Token};
+
+use crate::{env_vars, workspace};
+
+// A cfg name with its value, if it has one
+type Cfg = (String, Option<String>);
+
+// The cfgs of the host and of other targets, by triple, as a workspace index stores them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct PlatformCfgs {
+    host: String,
+    targets: BTreeMap<String, Vec<Cfg>>,
+}
+
+// The cfgs of the index the workspace is resolved with, which then replace rustc
+static INDEXED: Mutex<Option<PlatformCfgs>> = Mutex::new(None);
+
+/// Whether `platform`, a triple or a `cfg(...)` expression, matches `target`.
+pub fn matches(platform: &str, target: Option<&str>) -> Result<bool> {
+    let target = build_target(target);
+    let Some(expression) = platform.strip_prefix("cfg(") else {
+        return Ok(match &target {
+            Some(target) => platform == target,
+            None => platform == host_triple()?,
+        });
+    };
+
+    let predicate = parse(platform, expression)?;
+    evaluate(&predicate, &target_cfgs(target.as_deref())?)
+        .with_context(|| format!("Invalid platform '{platform}'"))
+}
+
+// The predicate of a `cfg(...)` platform, given without its leading `cfg(`
+fn parse(platform: &str, expression: &str) -> Result<Meta> {
+    let meta: Meta = syn::parse_str(&format!("cfg({expression}"))
+        .with_context(|| format!("Invalid platform '{platform}'"))?;
+    let Meta::List(list) = meta else {
+        anyhow::bail!("Invalid platform '{platform}'");
+    };
+    list.parse_args()
+        .with_context(|| format!("Invalid platform '{platform}', expected one cfg predicate"))
+}
+
+/// Checks that `platform` is a triple or a well-formed `cfg(...)` expression.
+pub fn validate(platform: &str) -> Result<()> {
+    if let Some(expression) = platform.strip_prefix("cfg(") {
+        let predicate = parse(platform, expression)?;
+        evaluate(&predicate, &[]).with_context(|| format!("Invalid platform '{platform}'"))?;
+    }
+    Ok(())
+}
+
+/// Returns the cfgs of `target` as names and values, e.g. `("target_os", Some("linux"))`, none
+/// if they cannot be told.
+pub fn cfgs(target: Option<&str>) -> Vec<(String, Option<String>)> {
+    target_cfgs(build_target(target).as_deref()).unwrap_or_default()
+}
+
+// The cfgs of a target, `None` for the host, from cargo in the build script for that target,
+// from the index in use or else from rustc
+fn target_cfgs(target: Option<&str>) -> Result<Vec<Cfg>> {
+    if let Some(cfgs) = build_script_cfgs(target) {
+        return Ok(cfgs);
+    }
+    if let Some(indexed) = indexed()? {
+        let triple = target.unwrap_or(&indexed.host);
+        return indexed.targets.get(triple).cloned().with_context(|| {
+            format!("The workspace index has no cfgs for {triple}, regenerate it with `cargo feature-scope index --target {triple}`")
+        });
+    }
+
+    static CACHE: OnceLock<Mutex<HashMap<Option<String>, Vec<Cfg>>>> = OnceLock::new();
+    let cache = CACHE.get_or_init(Default::default);
+    let mut cache = cache
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    Ok(cache
+        .entry(target.map(String::from))
+        .or_insert_with(|| rustc_cfgs(target))
+        .clone())
+}
+
+// The cfgs cargo sets for a build script, when it runs for `target`. Cargo lists the values of a
+// cfg separated by commas and leaves the ones without a value empty.
+fn build_script_cfgs(target: Option<&str>) -> Option<Vec<Cfg>> {
+    if build_target(None).as_deref() != Some(target?) {
+        return None;
+    }
+
+    let mut cfgs = Vec::new();
+    for (var, value) in env::vars() {
+        let Some(name) = var.strip_prefix("CARGO_CFG_") else {
+            continue;
+        };
+        let name = name.to_lowercase();
+        if value.is_empty() {
+            cfgs.push((name, None));
+        } else {
+            cfgs.extend(
+                value
+                    .split(',')
+                    .map(|value| (name.clone(), Some(value.to_string()))),
+            );
+        }
+    }
+    Some(cfgs)
+}
+
+// The platform cfgs of the index in use, read from `FEATURE_SCOPE_INDEX` unless the workspace
+// already was
+fn indexed() -> Result<Option<PlatformCfgs>> {
+    let mut indexed = INDEXED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if indexed.is_none() {
+        if let Some(index_path) = env::var_os(env_vars::INDEX) {
+            *indexed = Some(workspace::index_platform(Path::new(&index_path))?);
+        }
+    }
+    Ok(indexed.clone())
+}
+
+// Makes the cfgs of an index answer for every platform from now on
+pub(crate) fn use_index(cfgs: PlatformCfgs) {
+    *INDEXED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(cfgs);
+}
+
+// Asks rustc for the cfgs of the host and of `targets`, for an index
+pub(crate) fn index_cfgs(targets: &[String]) -> Result<PlatformCfgs> {
+    let host = rustc_host_triple().context("rustc -vV does not name the host triple")?;
+    let targets = iter::once(&host)
+        .chain(targets)
+        .map(|triple| {
+            let cfgs = rustc_cfgs(Some(triple));
+            if cfgs.is_empty() {
+                anyhow::bail!("rustc --print cfg --target {triple} failed");
+            }
+            Ok((triple.clone(), cfgs))
+        })
+        .collect::<Result<_>>()?;
+    Ok(PlatformCfgs { host, targets })
+}
+
+// The target given, or else the one of the build script running, `None` for the host
+fn build_target(target: Option<&str>) -> Option<String> {
+    if let Some(target) = target {
+        return Some(target.to_string());
+    }
+    // Cargo sets both for build scripts, where `TARGET` alone could be anybody's variable
+    let build_target = env::var("TARGET").ok()?;
+    env::var_os("HOST")?;
+    Some(build_target)
+}
+
+fn evaluate(predicate: &Meta, cfgs: &[Cfg]) -> Result<bool> {
+    let name = |path: &syn::Path| {
+        path.get_ident()
+            .map(|ident| ident.to_string())
+            .context("cfg names are single identifiers")
+    };
+
+    match predicate {
+        Meta::Path(path) => {
+            let name = name(path)?;
+            Ok(cfgs
+                .iter()
+                .any(|(cfg, value)| *cfg == name && value.is_none()))
+        }
+        Meta::NameValue(name_value) => {
+            let Expr::Lit(ExprLit {
+                lit: Lit::Str(value),
+                ..
+            }) = &name_value.value
+            else {
+                anyhow::bail!("cfg values are string literals");
+            };
+            let name = name(&name_value.path)?;
+            let value = value.value();
+            Ok(cfgs
+                .iter()
+                .any(|(cfg, cfg_value)| *cfg == name && cfg_value.as_ref() == Some(&value)))
+        }
+        Meta::List(list) => {
+            let operands = list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+            let mut results = operands
+                .iter()
+                .map(|operand| evaluate(operand, cfgs))
+                .collect::<Result<Vec<_>>>()?
+                .into_iter();
+            match name(&list.path)?.as_str() {
+                "all" => Ok(results.all(|result| result)),
+                "any" => Ok(results.any(|result| result)),
+                "not" if operands.len() == 1 => Ok(!results.next().unwrap_or_default()),
+                "not" => anyhow::bail!("not() takes one predicate"),
+                other => anyhow::bail!("unknown cfg operator '{other}'"),
+            }
+        }
+    }
+}
+
+// The cfgs of `rustc --print cfg`, none if rustc cannot tell
+fn rustc_cfgs(target: Option<&str>) -> Vec<Cfg> {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    let mut cmd = process::Command::new(rustc);
+    cmd.args(["--print", "cfg"]);
+    if let Some(target) = target {
+        cmd.args(["--target", target]);
+    }
+    // Flags like `-C target-cpu=native` change the target features
+    match env::var("CARGO_ENCODED_RUSTFLAGS") {
+        Ok(flags) if !flags.is_empty() => {
+            cmd.args(flags.split('\x1f'));
+        }
+        _ => {
+            cmd.args(env::var("RUSTFLAGS").unwrap_or_default().split_whitespace());
+        }
+    }
+
+    let Ok(output) = cmd.stderr(process::Stdio::null()).output() else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| match line.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.trim_matches('"').to_string())),
+            None => (line.to_string(), None),
+        })
+        .collect()
+}
+
+// The triple of the host, which cargo tells build scripts and an index stores, or else rustc
+fn host_triple() -> Result<String> {
+    if let (Ok(host), Some(_)) = (env::var("HOST"), env::var_os("TARGET")) {
+        return Ok(host);
+    }
+    if let Some(indexed) = indexed()? {
+        return Ok(indexed.host);
+    }
+
+    static HOST: OnceLock<Option<String>> = OnceLock::new();
+    HOST.get_or_init(rustc_host_triple)
+        .clone()
+        .context("rustc -vV does not name the host triple")
+}
+
+// The triple of the host, from `rustc -vV`
+fn rustc_host_triple() -> Option<String> {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    let output = process::Command::new(rustc).arg("-vV").output().ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(String::from)
+}
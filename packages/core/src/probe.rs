@@ -0,0 +1,79 @@
+//! Probes enabling features from the build environment.
+//!
+//! A feature declared with a `probe` is enabled for every consumer of its library whose build
+//! environment the probe finds it in, on top of the features the consumer selects:
+//!
+//! ```toml
+//! [package.metadata.feature-scope-decl]
+//! simd-avx2 = { probe = "target-feature:avx2" }
+//! cuda = { probe = "env:CUDA_PATH" }
+//! ```
+//!
+//! `target-feature:<FEATURE>` fires when the build target has the target feature, as
+//! `rustc --print cfg` reports it for the rustflags in the environment, and `env:<VARIABLE>`
+//! when the variable is set and not empty. In build scripts, the target features come from
+//! `CARGO_CFG_TARGET_FEATURE`. [`Overrides::no_probes`](crate::Overrides::no_probes) turns the
+//! probes off, and [`Origin::Probe`](crate::Origin::Probe) records the ones that fired.
+
+use serde::Deserialize;
+use std::{env, fmt};
+
+use crate::platform;
+
+/// What a probe looks for, parsed from its `kind:value` form.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Probe {
+    /// `target-feature:<FEATURE>`, a target feature of the build target.
+    TargetFeature(String),
+    /// `env:<VARIABLE>`, an environment variable that is set and not empty.
+    Env(String),
+}
+
+impl Probe {
+    /// Whether the probe finds what it looks for in the build environment of `target`, the
+    /// host when `None`.
+    pub fn fires(&self, target: Option<&str>) -> bool {
+        match self {
+            Probe::TargetFeature(feature) => target_features(target).contains(feature),
+            Probe::Env(variable) => env::var_os(variable).is_some_and(|value| !value.is_empty()),
+        }
+    }
+}
+
+impl TryFrom<String> for Probe {
+    type Error = String;
+
+    fn try_from(probe: String) -> Result<Self, Self::Error> {
+        let parsed = match probe.split_once(':') {
+            Some(("target-feature", feature)) if !feature.is_empty() => {
+                Probe::TargetFeature(feature.to_string())
+            }
+            Some(("env", variable)) if !variable.is_empty() => Probe::Env(variable.to_string()),
+            _ => {
+                return Err(format!(
+                "invalid probe '{probe}', expected `target-feature:<FEATURE>` or `env:<VARIABLE>`"
+            ))
+            }
+        };
+        Ok(parsed)
+    }
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Probe::TargetFeature(feature) => write!(f, "target-feature:{feature}"),
+            Probe::Env(variable) => write!(f, "env:{variable}"),
+        }
+    }
+}
+
+// The target features of `target`, which cargo tells build scripts for their own
+fn target_features(target: Option<&str>) -> Vec<String> {
+    platform::cfgs(target)
+        .into_iter()
+        .filter(|(name, _)| name == "target_feature")
+        .filter_map(|(_, value)| value)
+        .collect()
+}
@@ -0,0 +1,2150 @@
+//! Resolution of the scope cfgs a package is built with.
+//!
+//! A consumer's `[[package.metadata.feature-scope]]` entries select features of the libraries it
+//! uses. Selected features pull in the features they depend on, the libraries' `default` sets
+//! apply unless the consumer opts out, and every declared scope becomes a `--check-cfg`. Only
+//! the entries for packages in the consumer's dependency graph, read from the manifests, apply.
+//! In a workspace, the entries of the local packages in that graph apply as well, adding to the
+//! consumer's selections like Cargo unifies the features of a dependency.
+//!
+//! Several entries of one consumer for the same library, target, platform and profile add up
+//! the same way: each enables what it would enable alone, whatever their order, and they are
+//! reported as a [`Diagnostic::DuplicateEntry`], which strict mode makes an error.
+
+use anyhow::{Context, Result};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cfg_name,
+    diagnostic::{ConflictError, ConflictingFeature, Diagnostic, Origin, StrictError},
+    diff::ResolvedScopes,
+    manifest::{
+        extract_package_name_from_manifest, find_root_manifest, load_manifest, normalize_path,
+        read_package, CargoToml, Dependency, FeatureDetails, FeatureScope, FeatureScopeDecl,
+        Package,
+    },
+    platform,
+    probe::Probe,
+    workspace::{self, is_root_manifest, workspace_member_manifests},
+};
+
+/// The resolved scope flags of one package.
+#[derive(Debug, Clone)]
+pub struct ResolvedScope {
+    /// The package the scopes were resolved for.
+    pub package: String,
+    /// `--cfg` flags, as pairs of arguments.
+    pub cfg_args: Vec<String>,
+    /// `--check-cfg` flags, as pairs of arguments.
+    pub check_cfg_args: Vec<String>,
+    /// Problems that did not stop the resolution.
+    pub diagnostics: Vec<Diagnostic>,
+    /// What enabled each feature, by library and feature. A feature enabled through the
+    /// dependencies of another one has the origins of that one.
+    pub origins: BTreeMap<String, BTreeMap<String, BTreeSet<Origin>>>,
+    /// The `library/feature` cargo features to build with, for the enabled features activating
+    /// optional dependencies or features of dependencies with `dep:` or `crate/feature` entries.
+    pub cargo_features: Vec<String>,
+    /// The variables the `env:` probes of the workspace members and of the other libraries the
+    /// resolution reaches read, whether they fired or not, so build scripts can rerun when one
+    /// changes.
+    pub probe_vars: BTreeSet<String>,
+}
+
+impl ResolvedScope {
+    /// Returns the enabled scope cfgs, e.g. `__scope_default`.
+    pub fn enabled_cfgs(&self) -> impl Iterator<Item = &str> {
+        self.cfg_args.iter().skip(1).step_by(2).map(String::as_str)
+    }
+}
+
+/// Selections given on the command line on top of a consumer's
+/// `[[package.metadata.feature-scope]]` entries.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    /// `[package/]feature` selections, added to the features the entries select.
+    pub features: Vec<String>,
+    /// Drops the default features of every library, so only the selected ones remain.
+    pub no_default_features: bool,
+    /// `kind:name` of the targets cargo is asked for, like `bin:server`, for the entries with a
+    /// `target`. `kind:*` stands for every target of the kind, as with `--examples`, with
+    /// `test:*` and `bench:*` being the targets `--tests` and `--benches` build, and `lib:*`
+    /// standing for the library.
+    pub targets: Vec<String>,
+    /// The cargo command being run, like `test`. When `targets` is empty, the entries with a
+    /// `target` are checked against the targets the command builds by default, such as the
+    /// tests and examples of `test`. Without a command, they only apply to selected targets.
+    pub command: Option<String>,
+    /// Leaves the features with a [`Probe`](crate::probe::Probe) to the selections, whatever
+    /// the probes would find.
+    pub no_probes: bool,
+    /// The target triple cargo builds for, which the entries with a `platform` and the
+    /// `target-feature:` probes are checked against. When `None`, it is the `TARGET` of the build
+    /// script resolving, or else the host.
+    pub target: Option<String>,
+    /// The cargo profile of the build followed by the profiles it inherits from, like
+    /// `["bench", "release"]`, for the entries with a `profile`. When empty, it is `release` or
+    /// `dev` after the `PROFILE` of the build script resolving, or else `dev`.
+    pub profiles: Vec<String>,
+}
+
+impl Overrides {
+    /// Whether cargo builds the `kind:name` target of an entry, by name or with its kind.
+    pub fn selects_target(&self, target: &str) -> bool {
+        let kind = target.split(':').next().unwrap_or_default();
+        self.targets
+            .iter()
+            .any(|selected| selected == target || selected.strip_suffix(":*") == Some(kind))
+    }
+}
+
+/// Resolves scopes for the packages of a workspace or a single package.
+///
+/// ```no_run
+/// let resolver = feature_scope_core::Resolver::from_workspace(".")?;
+/// let scope = resolver.resolve(&resolver.default_package()?)?;
+/// println!("{:?}", scope.cfg_args);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Resolver {
+    root_manifest_path: PathBuf,
+    root_cargo_toml: CargoToml,
+}
+
+impl Resolver {
+    /// Reads the root manifest of the workspace at or above `path`, which may be a directory, or
+    /// a manifest to read as the root.
+    pub fn from_workspace(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let root_manifest_path = if path.is_file() {
+            path.to_path_buf()
+        } else {
+            find_root_manifest(path)?
+        };
+        let root_cargo_toml = load_manifest(&root_manifest_path)?;
+
+        Ok(Resolver {
+            root_manifest_path,
+            root_cargo_toml,
+        })
+    }
+
+    /// Reads the workspace of an index written by `cargo feature-scope index`. The members and the
+    /// cfgs `platform`s are evaluated against come from the index, so resolving spawns no
+    /// process, e.g. in a build script:
+    ///
+    /// ```no_run
+    /// let index = concat!(env!("CARGO_MANIFEST_DIR"), "/../feature-scope-index.json");
+    /// let resolver = feature_scope_core::Resolver::from_index(index)?;
+    /// let scope = resolver.resolve(env!("CARGO_PKG_NAME"))?;
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn from_index(index_path: impl AsRef<Path>) -> Result<Self> {
+        let root_manifest_path = workspace::cache_index(index_path.as_ref())?;
+        let root_cargo_toml = load_manifest(&root_manifest_path)?;
+
+        Ok(Resolver {
+            root_manifest_path,
+            root_cargo_toml,
+        })
+    }
+
+    pub fn root_manifest_path(&self) -> &Path {
+        &self.root_manifest_path
+    }
+
+    pub fn root_cargo_toml(&self) -> &CargoToml {
+        &self.root_cargo_toml
+    }
+
+    /// Returns the package commands apply to when none is selected.
+    pub fn default_package(&self) -> Result<String> {
+        determine_default_package(&self.root_cargo_toml, &self.root_manifest_path)
+    }
+
+    /// Resolves the scope flags of a package.
+    pub fn resolve(&self, package: &str) -> Result<ResolvedScope> {
+        resolve_cfg_args(&self.root_cargo_toml, &self.root_manifest_path, package)
+    }
+
+    /// Resolves the scope flags of a package with command line overrides applied.
+    pub fn resolve_with(&self, package: &str, overrides: &Overrides) -> Result<ResolvedScope> {
+        resolve_cfg_args_with(
+            &self.root_cargo_toml,
+            &self.root_manifest_path,
+            package,
+            overrides,
+        )
+    }
+
+    /// Resolves every package of the workspace, or the root package outside of one, with the
+    /// same overrides.
+    pub fn resolve_all(&self, overrides: &Overrides) -> Result<ResolvedScopes> {
+        let mut manifests =
+            workspace_member_manifests(&self.root_cargo_toml, &self.root_manifest_path)?;
+        if self.root_cargo_toml.package.is_some() {
+            manifests.insert(0, self.root_manifest_path.clone());
+        }
+        manifests
+            .iter()
+            .map(|manifest| {
+                let package = extract_package_name_from_manifest(manifest)?;
+                self.resolve_with(&package, overrides)
+                    .with_context(|| format!("Failed to resolve the scopes of '{package}'"))
+            })
+            .collect()
+    }
+
+    /// Resolves an ad-hoc `[package/]feature` selection into cfg args.
+    pub fn resolve_selection(&self, selection: &str) -> Result<Vec<String>> {
+        resolve_feature_selection(&self.root_cargo_toml, &self.root_manifest_path, selection)
+    }
+}
+
+/// Resolves the cfg and check-cfg flags a package is built with.
+pub fn resolve_cfg_args(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    target_package_name: &str,
+) -> Result<ResolvedScope> {
+    resolve_cfg_args_with(
+        root_cargo_toml,
+        root_manifest_path,
+        target_package_name,
+        &Overrides::default(),
+    )
+}
+
+/// Resolves the cfg and check-cfg flags a package is built with, after applying overrides to
+/// its entries. With `strict = true` in the `feature-scope-options` of the root manifest,
+/// warnings fail the resolution with a [`StrictError`].
+pub fn resolve_cfg_args_with(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    target_package_name: &str,
+    overrides: &Overrides,
+) -> Result<ResolvedScope> {
+    let mut diagnostics = Vec::new();
+
+    // Check if it's a workspace
+    let (cfg_args, check_cfg_args, origins, cargo_features, probe_vars) =
+        if root_cargo_toml.workspace.is_some() {
+            // Workspace mode
+            handle_workspace_package(
+                root_cargo_toml,
+                root_manifest_path,
+                target_package_name,
+                overrides,
+                &mut diagnostics,
+            )?
+        } else {
+            // Single package mode
+            handle_single_package(
+                root_cargo_toml,
+                root_manifest_path,
+                overrides,
+                &mut diagnostics,
+            )?
+        };
+
+    if root_cargo_toml.options().strict_keys {
+        diagnostics.extend(unknown_keys(root_cargo_toml, root_manifest_path)?);
+    }
+
+    if root_cargo_toml.options().strict && diagnostics.iter().any(|d| !d.is_note()) {
+        return Err(StrictError {
+            package: target_package_name.to_string(),
+            diagnostics: diagnostics.into_iter().filter(|d| !d.is_note()).collect(),
+        }
+        .into());
+    }
+
+    // Cargo compares RUSTFLAGS literally, so the same set must always come out in the same order
+    Ok(ResolvedScope {
+        package: target_package_name.to_string(),
+        cfg_args: canonical_flags(cfg_args),
+        check_cfg_args: canonical_flags(check_cfg_args),
+        diagnostics,
+        origins,
+        cargo_features,
+        probe_vars,
+    })
+}
+
+// The keys of the feature-scope tables of the root and the members this version does not know
+fn unknown_keys(root_cargo_toml: &CargoToml, root_manifest_path: &Path) -> Result<Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+    let mut report = |manifest: &Path, table: String, extensions: &HashMap<String, toml::Value>| {
+        let mut keys: Vec<&String> = extensions.keys().collect();
+        keys.sort();
+        for key in keys {
+            diagnostics.push(Diagnostic::UnknownKey {
+                manifest: manifest.to_path_buf(),
+                table: table.clone(),
+                key: key.clone(),
+            });
+        }
+    };
+
+    let options_table = match root_cargo_toml.workspace {
+        Some(_) => "workspace.metadata.feature-scope-options",
+        None => "package.metadata.feature-scope-options",
+    };
+    report(
+        root_manifest_path,
+        options_table.to_string(),
+        &root_cargo_toml.options().extensions,
+    );
+
+    let mut manifests = workspace_member_manifests(root_cargo_toml, root_manifest_path)?;
+    if root_cargo_toml.package.is_some() {
+        manifests.insert(0, root_manifest_path.to_path_buf());
+    }
+    for manifest in manifests {
+        // The resolution reports members that cannot be read
+        let Ok(Package {
+            metadata: Some(metadata),
+            ..
+        }) = read_package(&manifest)
+        else {
+            continue;
+        };
+        if let Some(decl) = &metadata.feature_scope_decl {
+            let mut features: Vec<(&String, &FeatureDetails)> = decl.details.iter().collect();
+            features.sort_by_key(|(feature, _)| *feature);
+            for (feature, details) in features {
+                report(
+                    &manifest,
+                    format!("package.metadata.feature-scope-decl.{feature}"),
+                    &details.extensions,
+                );
+            }
+        }
+        for (index, entry) in metadata.feature_scope.iter().flatten().enumerate() {
+            report(
+                &manifest,
+                format!("entry {} of package.metadata.feature-scope", index + 1),
+                &entry.extensions,
+            );
+        }
+    }
+
+    Ok(diagnostics)
+}
+
+// What enabled each feature, by library and feature
+type Origins = BTreeMap<String, BTreeMap<String, BTreeSet<Origin>>>;
+
+// The cfg flags, the check-cfg flags, the origins and the cargo features of a resolution
+type Resolution = (
+    Vec<String>,
+    Vec<String>,
+    Origins,
+    Vec<String>,
+    BTreeSet<String>,
+);
+
+// The features enabled in each library with what enabled them, so the exclusive groups and
+// conflicts can be enforced once every entry is applied. A conflict is a group of two.
+#[derive(Default)]
+struct EnabledFeatures {
+    libraries: HashMap<String, LibraryFeatures>,
+    // The target triple, which the features with a `platform` must match to be enabled
+    target: Option<String>,
+    // The variables the `env:` probes read
+    probe_vars: BTreeSet<String>,
+}
+
+struct LibraryFeatures {
+    groups: Vec<Vec<String>>,
+    enabled: HashMap<String, BTreeSet<Origin>>,
+    // The features only a cargo feature can enable
+    cargo_backed: HashSet<String>,
+}
+
+impl EnabledFeatures {
+    // Records the features, dropping the ones whose `platform` the target does not match
+    fn record(
+        &mut self,
+        library: &str,
+        decl: &FeatureScopeDecl,
+        origin: Origin,
+        features: &mut HashSet<String>,
+    ) {
+        features.retain(|feature| {
+            let platform = decl
+                .details
+                .get(feature)
+                .and_then(|details| details.platform.as_deref());
+            // Declarations are validated while parsing, so only rustc can fail here
+            platform.is_none_or(|platform| {
+                platform::matches(platform, self.target.as_deref()).unwrap_or(false)
+            })
+        });
+        let library = self
+            .libraries
+            .entry(library.to_string())
+            .or_insert_with(|| LibraryFeatures {
+                groups: exclusive_groups(decl),
+                enabled: HashMap::new(),
+                cargo_backed: decl
+                    .features
+                    .keys()
+                    .filter(|feature| decl.needs_cargo_feature(feature))
+                    .cloned()
+                    .collect(),
+            });
+        for feature in features.iter() {
+            library
+                .enabled
+                .entry(feature.clone())
+                .or_default()
+                .insert(origin.clone());
+        }
+    }
+
+    // Whether the features would be in a group with another one, enabled so far or among them
+    fn conflicts_with(
+        &self,
+        library: &str,
+        decl: &FeatureScopeDecl,
+        features: &HashSet<String>,
+    ) -> bool {
+        let enabled = self.libraries.get(library).map(|library| &library.enabled);
+        exclusive_groups(decl).iter().any(|group| {
+            let is_enabled = |feature: &&String| {
+                features.contains(*feature)
+                    || enabled.is_some_and(|enabled| enabled.contains_key(*feature))
+            };
+            group.iter().any(|feature| features.contains(feature))
+                && group.iter().filter(is_enabled).count() >= 2
+        })
+    }
+
+    // Fails with a `ConflictError` if the consumer ends up with two features of the same group
+    fn check(&self, consumer: &str) -> Result<()> {
+        let mut libraries: Vec<_> = self.libraries.iter().collect();
+        libraries.sort_by_key(|(library, _)| *library);
+
+        for (
+            library,
+            LibraryFeatures {
+                groups, enabled, ..
+            },
+        ) in libraries
+        {
+            for group in groups {
+                let features: Vec<ConflictingFeature> = group
+                    .iter()
+                    .filter_map(|feature| {
+                        Some(ConflictingFeature {
+                            feature: feature.clone(),
+                            origins: enabled.get(feature)?.iter().cloned().collect(),
+                        })
+                    })
+                    .collect();
+                if features.len() >= 2 {
+                    return Err(ConflictError {
+                        consumer: consumer.to_string(),
+                        library: library.clone(),
+                        features,
+                    }
+                    .into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // The cargo features of the enabled features activating optional dependencies or features
+    // of dependencies, which the libraries declare in `[features]` under the same names
+    fn cargo_features(&self) -> Vec<String> {
+        let mut features: Vec<String> = self
+            .libraries
+            .iter()
+            .flat_map(|(library, features)| {
+                features
+                    .enabled
+                    .keys()
+                    .filter(|feature| features.cargo_backed.contains(*feature))
+                    .map(move |feature| format!("{library}/{feature}"))
+            })
+            .collect();
+        features.sort();
+        features
+    }
+
+    fn into_origins(self) -> Origins {
+        self.libraries
+            .into_iter()
+            .map(|(library, features)| (library, features.enabled.into_iter().collect()))
+            .collect()
+    }
+}
+
+// The exclusive groups of a library, followed by its conflicts as groups of two
+fn exclusive_groups(decl: &FeatureScopeDecl) -> Vec<Vec<String>> {
+    let mut groups = decl.exclusive.clone().unwrap_or_default();
+    let mut conflicts: Vec<Vec<String>> = decl
+        .details
+        .iter()
+        .flat_map(|(feature, details)| {
+            details
+                .conflicts
+                .iter()
+                .map(|conflict| vec![feature.clone(), conflict.clone()])
+        })
+        .collect();
+    conflicts.sort();
+    groups.extend(conflicts);
+    groups
+}
+
+// Sorts flags given as pairs of arguments by their value, dropping duplicates
+fn canonical_flags(args: Vec<String>) -> Vec<String> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    let mut args = args.into_iter();
+    while let (Some(flag), Some(value)) = (args.next(), args.next()) {
+        pairs.push((flag, value));
+    }
+    pairs.sort_by(|a, b| a.1.cmp(&b.1));
+    pairs.dedup();
+
+    pairs
+        .into_iter()
+        .flat_map(|(flag, value)| [flag, value])
+        .collect()
+}
+
+/// Returns the package commands apply to when none is selected: the first of
+/// [`default_packages`] for the current directory.
+pub fn determine_default_package(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+) -> Result<String> {
+    let current_dir = std::env::current_dir()?;
+    match default_packages(root_cargo_toml, root_manifest_path, &current_dir)?
+        .into_iter()
+        .next()
+    {
+        Some(package) => Ok(package),
+        None => anyhow::bail!("No members found in workspace"),
+    }
+}
+
+/// Returns the packages `cargo build` without `-p` builds when run in `current_dir`, by
+/// cargo's rules: the member whose directory contains `current_dir`, unless that is the root
+/// package, and otherwise the `default-members`, which default to the root package or, in a
+/// virtual workspace, to every member.
+pub fn default_packages(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    current_dir: &Path,
+) -> Result<Vec<String>> {
+    if root_cargo_toml.workspace.is_none() {
+        // Single package mode: use current package name
+        return match &root_cargo_toml.package {
+            Some(package) => Ok(vec![package.name.clone()]),
+            None => anyhow::bail!("No package found in root Cargo.toml"),
+        };
+    }
+
+    // The member paths from cargo are canonical, and so must the directory be
+    let members = workspace::members(root_manifest_path)?;
+    let current_dir = fs::canonicalize(current_dir).unwrap_or_else(|_| current_dir.to_path_buf());
+    let containing = members
+        .all
+        .iter()
+        .filter(|manifest| !is_root_manifest(manifest, root_manifest_path))
+        .filter(|manifest| {
+            manifest
+                .parent()
+                .is_some_and(|dir| current_dir.starts_with(dir))
+        })
+        .max_by_key(|manifest| manifest.components().count());
+    if let Some(member_manifest) = containing {
+        return Ok(vec![extract_package_name_from_manifest(member_manifest)?]);
+    }
+
+    members
+        .default
+        .iter()
+        .map(|manifest| extract_package_name_from_manifest(manifest))
+        .collect()
+}
+
+fn handle_single_package(
+    cargo_toml: &CargoToml,
+    manifest_path: &Path,
+    overrides: &Overrides,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Resolution> {
+    let check_default_migration = cargo_toml.options().check_default_migration;
+    let mut cfg_args = Vec::new();
+    if !overrides.no_default_features {
+        cfg_args.extend([String::from("--cfg"), String::from("__scope_default")]);
+    }
+    let mut all_scope_features = HashSet::new();
+    let mut library_features = EnabledFeatures {
+        target: overrides.target.clone(),
+        ..EnabledFeatures::default()
+    };
+
+    // Always add default scope
+    all_scope_features.insert("__scope_default".to_string());
+
+    if let Some(package) = &cargo_toml.package {
+        if let Some(metadata) = &package.metadata {
+            // Single package mode: feature-scope-decl and feature-scope are in the same file
+            if let Some(feature_scope_decl) = &metadata.feature_scope_decl {
+                warn_undeclared_defaults(&package.name, feature_scope_decl, diagnostics);
+
+                // Collect all declared feature scopes
+                for feature_name in feature_scope_decl.features.keys() {
+                    all_scope_features.insert(cfg_name::scope_cfg(feature_name));
+                }
+
+                // Iteratively parse default features and their dependencies
+                let mut enabled_features = HashSet::new();
+                if let Some(defaults) = feature_scope_decl
+                    .default
+                    .as_ref()
+                    .filter(|_| !overrides.no_default_features)
+                {
+                    for default_feature in defaults {
+                        resolve_feature_dependencies(
+                            default_feature,
+                            feature_scope_decl,
+                            &mut enabled_features,
+                        );
+                    }
+                }
+
+                library_features.record(
+                    &package.name,
+                    feature_scope_decl,
+                    Origin::Defaults {
+                        library: package.name.clone(),
+                    },
+                    &mut enabled_features,
+                );
+
+                // Add cfg parameters for enabled features
+                for feature in &enabled_features {
+                    all_scope_features.insert(cfg_name::scope_cfg(feature));
+                    cfg_args.push(String::from("--cfg"));
+                    cfg_args.push(cfg_name::scope_cfg(feature));
+                }
+                apply_probes(
+                    &package.name,
+                    feature_scope_decl,
+                    overrides,
+                    &mut library_features,
+                    &mut cfg_args,
+                );
+
+                // Selections of the package's own features become entries of their own
+                let feature_scope = apply_overrides(
+                    metadata.feature_scope.as_deref().unwrap_or_default(),
+                    overrides,
+                    |selected_package, feature| {
+                        let is_own = selected_package.is_none_or(|name| name == package.name);
+                        if is_own && feature_scope_decl.declares(feature) {
+                            vec![package.name.clone()]
+                        } else {
+                            Vec::new()
+                        }
+                    },
+                    diagnostics,
+                );
+
+                let dependencies = dependency_graph(
+                    manifest_path,
+                    &HashMap::new(),
+                    cargo_toml,
+                    manifest_path,
+                    true,
+                )?;
+                let source = EntrySource {
+                    name: package.name.clone(),
+                    manifest: manifest_path.to_path_buf(),
+                    entries: feature_scope,
+                    from_manifest: metadata.feature_scope.as_ref().map_or(0, Vec::len),
+                    dependencies,
+                };
+                diagnostics.extend(duplicate_entries(&source)?);
+                let built = built_targets(&source, overrides)?;
+
+                // Cross-validate and apply feature-scope configuration
+                for (index, scope) in source.entries.iter().enumerate() {
+                    // Entries with a `path` use the declarations of the package found there
+                    let referenced_package = read_referenced_package(manifest_path, scope)?;
+                    if let Some(referenced) = &referenced_package {
+                        if referenced.name != package.name
+                            && !source.dependencies.contains_key(&referenced.name)
+                        {
+                            diagnostics.push(not_a_dependency(
+                                &source,
+                                index,
+                                referenced.name.clone(),
+                            ));
+                            continue;
+                        }
+                        collect_declared_scopes(referenced, &mut all_scope_features, diagnostics);
+                    }
+                    if !entry_applies(&source, index, Some(&built), overrides)? {
+                        continue;
+                    }
+                    let scope_decl = referenced_package
+                        .as_ref()
+                        .and_then(|package| package.metadata.as_ref())
+                        .and_then(|metadata| metadata.feature_scope_decl.as_ref())
+                        .unwrap_or(feature_scope_decl);
+                    let library = referenced_package
+                        .as_ref()
+                        .map_or(&package.name, |package| &package.name);
+                    if referenced_package.is_some() {
+                        apply_probes(
+                            library,
+                            scope_decl,
+                            overrides,
+                            &mut library_features,
+                            &mut cfg_args,
+                        );
+                    }
+
+                    let (removed, undeclared) = removed_features(&scope.removed, scope_decl);
+                    for feature in undeclared {
+                        diagnostics.push(Diagnostic::FeatureNotDeclared { feature });
+                    }
+                    let mut kept = BTreeSet::new();
+                    let mut entry_features = HashSet::new();
+                    for feature in &scope.features {
+                        // Parse dependencies of this feature
+                        let mut scope_enabled_features = HashSet::new();
+                        if resolve_selection(
+                            feature,
+                            &removed,
+                            scope_decl,
+                            &mut scope_enabled_features,
+                            &mut kept,
+                        ) {
+                            warn_deprecated(library, feature, scope_decl, diagnostics);
+                            entry_features.extend(scope_enabled_features.iter().cloned());
+                            library_features.record(
+                                library,
+                                scope_decl,
+                                Origin::Selection {
+                                    consumer: package.name.clone(),
+                                    feature: feature.clone(),
+                                },
+                                &mut scope_enabled_features,
+                            );
+                            for enabled_feature in scope_enabled_features {
+                                cfg_args.push(String::from("--cfg"));
+                                cfg_args.push(cfg_name::scope_cfg(&enabled_feature));
+                            }
+                        } else {
+                            diagnostics.push(Diagnostic::FeatureNotDeclared {
+                                feature: feature.clone(),
+                            });
+                        }
+                    }
+                    if check_default_migration {
+                        check_default_meaning(
+                            &package.name,
+                            library,
+                            scope,
+                            &removed,
+                            scope_decl,
+                            &entry_features,
+                            diagnostics,
+                        );
+                    }
+                    for feature in kept {
+                        diagnostics.push(Diagnostic::RemovedFeatureEnabled {
+                            consumer: package.name.clone(),
+                            package: library.clone(),
+                            feature,
+                        });
+                    }
+
+                    let preferred_features = apply_preferences(
+                        &package.name,
+                        library,
+                        scope_decl,
+                        &scope.prefer,
+                        overrides,
+                        &mut library_features,
+                        diagnostics,
+                    )?;
+                    for enabled_feature in preferred_features {
+                        cfg_args.push(String::from("--cfg"));
+                        cfg_args.push(cfg_name::scope_cfg(&enabled_feature));
+                    }
+                }
+            }
+            library_features.check(&package.name)?;
+        }
+    }
+
+    // Build check-cfg parameters
+    let mut check_cfg_args = Vec::new();
+    for scope_feature in all_scope_features {
+        check_cfg_args.push(String::from("--check-cfg"));
+        check_cfg_args.push(format!("cfg({scope_feature})"));
+    }
+
+    let cargo_features = library_features.cargo_features();
+    let probe_vars = std::mem::take(&mut library_features.probe_vars);
+    Ok((
+        cfg_args,
+        check_cfg_args,
+        library_features.into_origins(),
+        cargo_features,
+        probe_vars,
+    ))
+}
+
+/// Resolves an ad-hoc `[package/]feature` selection into cfg args.
+///
+/// Without a package qualifier, every package declaring the feature contributes its dependencies.
+pub fn resolve_feature_selection(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    selection: &str,
+) -> Result<Vec<String>> {
+    let (package_name, feature) = match selection.split_once('/') {
+        Some((package_name, feature)) => (Some(package_name), feature),
+        None => (None, selection),
+    };
+
+    let mut packages = Vec::new();
+    if root_cargo_toml.package.is_some() {
+        packages.push(read_package(root_manifest_path)?);
+    }
+    for member_manifest in workspace_member_manifests(root_cargo_toml, root_manifest_path)? {
+        packages.push(read_package(&member_manifest)?);
+    }
+
+    let mut enabled_features = HashSet::new();
+    let mut found_package = false;
+    let mut found_feature = false;
+    for package in &packages {
+        if package_name.is_some_and(|name| name != package.name) {
+            continue;
+        }
+        found_package = true;
+
+        let Some(feature_scope_decl) = package
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.feature_scope_decl.as_ref())
+        else {
+            continue;
+        };
+        if resolve_requested_feature(feature, feature_scope_decl, &mut enabled_features) {
+            found_feature = true;
+        }
+    }
+
+    match package_name {
+        Some(package_name) if !found_package => {
+            anyhow::bail!("Package '{package_name}' not found in workspace")
+        }
+        Some(package_name) if !found_feature => {
+            anyhow::bail!("Feature '{feature}' not declared in package '{package_name}'")
+        }
+        None if !found_feature => {
+            anyhow::bail!("Feature '{feature}' is not declared by any package")
+        }
+        _ => {}
+    }
+
+    let mut cfg_args = Vec::new();
+    for enabled_feature in enabled_features {
+        cfg_args.push(String::from("--cfg"));
+        cfg_args.push(cfg_name::scope_cfg(&enabled_feature));
+    }
+    Ok(cfg_args)
+}
+
+/// Resolves a feature requested by a consumer into the features it enables.
+///
+/// The `default` pseudo-feature stands for the library's declared default set, and nothing else.
+/// Returns `false` if the library does not declare the feature.
+pub fn resolve_requested_feature(
+    feature: &str,
+    feature_scope_decl: &FeatureScopeDecl,
+    enabled_features: &mut HashSet<String>,
+) -> bool {
+    if feature == "default" {
+        for default_feature in feature_scope_decl.default.iter().flatten() {
+            resolve_feature_dependencies(default_feature, feature_scope_decl, enabled_features);
+        }
+        return true;
+    }
+
+    let is_declared = feature_scope_decl.declares(feature)
+        || feature_scope_decl
+            .default
+            .as_ref()
+            .is_some_and(|d| d.iter().any(|f| f == feature));
+    if !is_declared {
+        return false;
+    }
+
+    resolve_feature_dependencies(feature, feature_scope_decl, enabled_features);
+    true
+}
+
+// Helper function to expand the features an entry removes, `default` and presets included,
+// into the features they stand for. Returns the removed names the library does not declare too.
+fn removed_features(
+    removed: &[String],
+    feature_scope_decl: &FeatureScopeDecl,
+) -> (HashSet<String>, Vec<String>) {
+    let mut features = HashSet::new();
+    let mut undeclared = Vec::new();
+    for feature in removed {
+        if feature == "default" {
+            features.extend(feature_scope_decl.default.iter().flatten().cloned());
+        } else if !expand_preset(feature, feature_scope_decl, &mut features) {
+            if feature_scope_decl.declares(feature) {
+                features.insert(feature.clone());
+            } else {
+                undeclared.push(feature.clone());
+            }
+        }
+    }
+    (features, undeclared)
+}
+
+// Adds the members of a preset, returning `false` if the feature is no preset
+fn expand_preset(
+    feature: &str,
+    feature_scope_decl: &FeatureScopeDecl,
+    features: &mut HashSet<String>,
+) -> bool {
+    let Some(members) = feature_scope_decl.presets.get(feature) else {
+        return false;
+    };
+    for member in members {
+        if !expand_preset(member, feature_scope_decl, features) {
+            features.insert(member.clone());
+        }
+    }
+    true
+}
+
+// Helper function to resolve a feature an entry selects without the features it removes
+//
+// A removed feature stays enabled when a remaining one depends on it, and is added to `kept`.
+fn resolve_selection(
+    feature: &str,
+    removed: &HashSet<String>,
+    feature_scope_decl: &FeatureScopeDecl,
+    enabled_features: &mut HashSet<String>,
+    kept: &mut BTreeSet<String>,
+) -> bool {
+    // Seeding the removed features keeps the resolution from walking into them
+    let mut resolved = removed.clone();
+    if !resolve_requested_feature(feature, feature_scope_decl, &mut resolved) {
+        return false;
+    }
+    resolved.retain(|feature| !removed.contains(feature));
+
+    loop {
+        let needed: Vec<String> = resolved
+            .iter()
+            .filter_map(|feature| feature_scope_decl.features.get(feature))
+            .flatten()
+            .filter(|dependency| removed.contains(*dependency) && !resolved.contains(*dependency))
+            .cloned()
+            .collect();
+        if needed.is_empty() {
+            break;
+        }
+        for feature in needed {
+            resolve_feature_dependencies(&feature, feature_scope_decl, &mut resolved);
+            kept.insert(feature);
+        }
+    }
+    enabled_features.extend(resolved);
+    true
+}
+
+// Helper function to warn about default features that are not declared on their own
+//
+// Such features are still accepted for compatibility, but this is deprecated.
+fn warn_undeclared_defaults(
+    package: &str,
+    feature_scope_decl: &FeatureScopeDecl,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    for default_feature in feature_scope_decl.default.iter().flatten() {
+        if !feature_scope_decl.declares(default_feature) {
+            diagnostics.push(Diagnostic::UndeclaredDefault {
+                feature: default_feature.clone(),
+                package: package.to_string(),
+            });
+        }
+    }
+}
+
+// Helper function to warn about a consumer selecting a deprecated feature
+// Helper function to report the features an entry selecting `default` enabled when it stood for
+// every declared feature of the library, for `check-default-migration = true`
+fn check_default_meaning(
+    consumer: &str,
+    package: &str,
+    scope: &FeatureScope,
+    removed: &HashSet<String>,
+    feature_scope_decl: &FeatureScopeDecl,
+    entry_features: &HashSet<String>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if !scope.features.iter().any(|feature| feature == "default") {
+        return;
+    }
+    let mut legacy_features = HashSet::new();
+    for declared in feature_scope_decl.features.keys() {
+        resolve_feature_dependencies(declared, feature_scope_decl, &mut legacy_features);
+    }
+    let mut features: Vec<String> = legacy_features
+        .into_iter()
+        .filter(|feature| !entry_features.contains(feature) && !removed.contains(feature))
+        .collect();
+    if features.is_empty() {
+        return;
+    }
+    features.sort();
+    diagnostics.push(Diagnostic::DefaultMeaningChanged {
+        consumer: consumer.to_string(),
+        package: package.to_string(),
+        features,
+    });
+}
+
+fn warn_deprecated(
+    package: &str,
+    feature: &str,
+    feature_scope_decl: &FeatureScopeDecl,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let details = feature_scope_decl.details.get(feature);
+    if let Some(note) = details.and_then(|details| details.deprecated.as_ref()) {
+        diagnostics.push(Diagnostic::DeprecatedFeature {
+            feature: feature.to_string(),
+            package: package.to_string(),
+            note: note.clone(),
+        });
+    }
+}
+
+// Helper function to iteratively parse feature dependencies
+//
+// Presets enable the features they list instead of themselves.
+// Enables the features of a library whose probes fire, with the features they depend on
+fn apply_probes(
+    library: &str,
+    decl: &FeatureScopeDecl,
+    overrides: &Overrides,
+    library_features: &mut EnabledFeatures,
+    cfg_args: &mut Vec<String>,
+) {
+    if overrides.no_probes {
+        return;
+    }
+    let mut probed: Vec<(&String, &Probe)> = decl
+        .details
+        .iter()
+        .filter_map(|(feature, details)| Some((feature, details.probe.as_ref()?)))
+        .collect();
+    probed.sort();
+
+    for (feature, probe) in probed {
+        if let Probe::Env(variable) = probe {
+            library_features.probe_vars.insert(variable.clone());
+        }
+        if !probe.fires(overrides.target.as_deref()) {
+            continue;
+        }
+        let mut enabled_features = HashSet::new();
+        resolve_feature_dependencies(feature, decl, &mut enabled_features);
+        library_features.record(
+            library,
+            decl,
+            Origin::Probe {
+                library: library.to_string(),
+                feature: feature.clone(),
+                probe: probe.clone(),
+            },
+            &mut enabled_features,
+        );
+        for enabled_feature in enabled_features {
+            cfg_args.push(String::from("--cfg"));
+            cfg_args.push(cfg_name::scope_cfg(&enabled_feature));
+        }
+    }
+}
+
+// Enables the first feature of each `prefer` chain of an entry that the library declares, that
+// is available on the target and that conflicts with none of the features enabled so far,
+// returning the features enabled with them
+fn apply_preferences(
+    consumer: &str,
+    library: &str,
+    decl: &FeatureScopeDecl,
+    chains: &[Vec<String>],
+    overrides: &Overrides,
+    library_features: &mut EnabledFeatures,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<HashSet<String>> {
+    let mut all_enabled = HashSet::new();
+    for chain in chains {
+        let mut preferred = None;
+        for feature in chain {
+            if !decl.declares(feature) {
+                continue;
+            }
+            let platform = decl
+                .details
+                .get(feature)
+                .and_then(|details| details.platform.as_ref());
+            if let Some(platform) = platform {
+                let available = platform::matches(platform, overrides.target.as_deref())
+                    .with_context(|| {
+                        format!("Feature '{feature}' of '{library}' has an invalid platform")
+                    })?;
+                if !available {
+                    continue;
+                }
+            }
+            let mut enabled_features = HashSet::new();
+            resolve_feature_dependencies(feature, decl, &mut enabled_features);
+            if !library_features.conflicts_with(library, decl, &enabled_features) {
+                preferred = Some((feature, enabled_features));
+                break;
+            }
+        }
+
+        let Some((feature, mut enabled_features)) = preferred else {
+            diagnostics.push(Diagnostic::NoPreferredFeature {
+                consumer: consumer.to_string(),
+                package: library.to_string(),
+                features: chain.clone(),
+            });
+            continue;
+        };
+        warn_deprecated(library, feature, decl, diagnostics);
+        library_features.record(
+            library,
+            decl,
+            Origin::Selection {
+                consumer: consumer.to_string(),
+                feature: feature.clone(),
+            },
+            &mut enabled_features,
+        );
+        all_enabled.extend(enabled_features);
+    }
+    Ok(all_enabled)
+}
+
+fn resolve_feature_dependencies(
+    feature: &str,
+    feature_scope_decl: &FeatureScopeDecl,
+    enabled_features: &mut HashSet<String>,
+) {
+    if let Some(features) = feature_scope_decl.presets.get(feature) {
+        for feature in features {
+            resolve_feature_dependencies(feature, feature_scope_decl, enabled_features);
+        }
+        return;
+    }
+
+    // Avoid circular dependencies
+    if enabled_features.contains(feature) {
+        return;
+    }
+
+    enabled_features.insert(feature.to_string());
+
+    // Recursively parse dependencies
+    if let Some(dependencies) = feature_scope_decl.features.get(feature) {
+        for dep in dependencies {
+            resolve_feature_dependencies(dep, feature_scope_decl, enabled_features);
+        }
+    }
+}
+
+// Helper function to collect the check-cfg names of every scope a package declares
+fn collect_declared_scopes(
+    package: &Package,
+    all_scope_features: &mut HashSet<String>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if let Some(metadata) = &package.metadata {
+        if let Some(feature_scope_decl) = &metadata.feature_scope_decl {
+            warn_undeclared_defaults(&package.name, feature_scope_decl, diagnostics);
+
+            // Collect all declared feature scopes
+            for feature_name in feature_scope_decl.features.keys() {
+                all_scope_features.insert(cfg_name::scope_cfg(feature_name));
+            }
+
+            if let Some(defaults) = &feature_scope_decl.default {
+                let is_preset =
+                    |feature: &&String| feature_scope_decl.presets.contains_key(*feature);
+                for feature in defaults.iter().filter(|feature| !is_preset(feature)) {
+                    all_scope_features.insert(cfg_name::scope_cfg(feature));
+                }
+            }
+        }
+    }
+}
+
+// The variables the `env:` probes declared by a package read
+fn env_probe_vars(package: &Package) -> impl Iterator<Item = String> + '_ {
+    package
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.feature_scope_decl.as_ref())
+        .into_iter()
+        .flat_map(|decl| decl.details.values())
+        .filter_map(|details| match &details.probe {
+            Some(Probe::Env(variable)) => Some(variable.clone()),
+            _ => None,
+        })
+}
+
+// Helper function to tell whether an entry applies to the build
+//
+// Entries with a `target` only apply when cargo builds that target of the package being built,
+// and never for its dependencies, whose binaries, examples and tests are not built. Entries with
+// a `platform`, or a `target` naming a platform like the keys of cargo's `[target]` tables, only
+// apply when building for it, and entries with a `profile` when building with it.
+fn entry_applies(
+    source: &EntrySource,
+    index: usize,
+    built: Option<&[String]>,
+    overrides: &Overrides,
+) -> Result<bool> {
+    let entry = &source.entries[index];
+    let (target_platform, target) = match &entry.target {
+        Some(target) if target.starts_with("cfg(") || !target.contains(':') => (Some(target), None),
+        target => (None, target.as_ref()),
+    };
+    for platform in entry.platform.iter().chain(target_platform) {
+        let applies =
+            platform::matches(platform, overrides.target.as_deref()).with_context(|| {
+                format!(
+                    "Entry {} of the feature-scope entries of '{}' has an invalid platform",
+                    index + 1,
+                    source.name
+                )
+            })?;
+        if !applies {
+            return Ok(false);
+        }
+    }
+    if let Some(profile) = &entry.profile {
+        if !build_profiles(overrides).contains(profile) {
+            return Ok(false);
+        }
+    }
+
+    let Some(target) = target else {
+        return Ok(true);
+    };
+    let is_valid = matches!(
+        target.split_once(':'),
+        Some(("bin" | "example" | "test" | "bench", name)) if !name.is_empty()
+    );
+    if !is_valid {
+        anyhow::bail!(
+            "Entry {} of the feature-scope entries of '{}' has target '{target}', expected `bin:`, `example:`, `test:` or `bench:` followed by the name of a target",
+            index + 1,
+            source.name
+        );
+    }
+
+    Ok(built.is_some_and(|built| built.iter().any(|built| built == target)))
+}
+
+// Helper function to find the targets of the package being built that cargo builds, as
+// `kind:name`, for the entries with a `target`
+//
+// Cargo builds every target of one invocation with the same flags, so an entry for one of them
+// would reach the others too. That fails instead, the targets have to be built one by one.
+fn built_targets(source: &EntrySource, overrides: &Overrides) -> Result<Vec<String>> {
+    let is_limited = |scope: &FeatureScope| {
+        scope.target.as_ref().is_some_and(|target| {
+            matches!(
+                target.split_once(':'),
+                Some(("bin" | "example" | "test" | "bench", _))
+            )
+        })
+    };
+    if !source.entries.iter().any(is_limited) {
+        return Ok(Vec::new());
+    }
+
+    let (targets, default_run) = workspace::package_targets(&source.manifest)?;
+    let selected: Vec<String> = if !overrides.targets.is_empty() {
+        overrides.targets.clone()
+    } else {
+        let defaults = match (overrides.command.as_deref(), default_run) {
+            (Some("run"), Some(bin)) => return Ok(vec![format!("bin:{bin}")]),
+            (Some("run"), None) => vec!["bin:*"],
+            (Some("test"), _) => vec!["test:*", "example:*"],
+            (Some("bench"), _) => vec!["bench:*"],
+            (Some("build" | "check" | "clippy" | "doc" | "rustc" | "fix"), _) => {
+                vec!["lib:*", "bin:*"]
+            }
+            _ => return Ok(Vec::new()),
+        };
+        defaults.into_iter().map(String::from).collect()
+    };
+
+    let mut built: Vec<String> = Vec::new();
+    for selection in &selected {
+        let expanded: Vec<String> = match selection.split_once(':') {
+            Some((kind, "*")) => targets
+                .iter()
+                .filter(|target| match kind {
+                    "test" => target.test,
+                    "bench" => target.bench,
+                    kind => target.kind == kind,
+                })
+                .map(|target| format!("{}:{}", target.kind, target.name))
+                .collect(),
+            _ => vec![selection.clone()],
+        };
+        for target in expanded {
+            if !built.contains(&target) {
+                built.push(target);
+            }
+        }
+    }
+
+    for (index, scope) in source.entries.iter().enumerate() {
+        let Some(target) = scope.target.as_ref().filter(|_| is_limited(scope)) else {
+            continue;
+        };
+        if built.len() < 2 || !entry_applies(source, index, Some(&built), overrides)? {
+            continue;
+        }
+        let others: Vec<&str> = built
+            .iter()
+            .filter(|built| *built != target)
+            .map(String::as_str)
+            .collect();
+        let (kind, name) = target.split_once(':').unwrap_or_default();
+        anyhow::bail!(
+            "Entry {} of the feature-scope entries of '{}' is limited to target '{target}', but cargo also builds {} along with it, which would get its scopes too. Build the target on its own, like with `--{kind} {name}`",
+            index + 1,
+            source.name,
+            others.join(", ")
+        );
+    }
+
+    Ok(built)
+}
+
+// The profile of the build and the profiles it inherits from
+fn build_profiles(overrides: &Overrides) -> Vec<String> {
+    if !overrides.profiles.is_empty() {
+        return overrides.profiles.clone();
+    }
+    // Cargo sets both for build scripts, with `PROFILE` only telling `release` from the rest
+    let profile = match (std::env::var("PROFILE"), std::env::var_os("HOST")) {
+        (Ok(profile), Some(_)) if profile == "release" => "release",
+        _ => "dev",
+    };
+    vec![profile.to_string()]
+}
+
+// Helper function to apply command line overrides to a consumer's entries
+//
+// Each selection becomes an entry of its own for the packages `packages_for` returns, which adds
+// its feature to the ones existing entries of the same package select.
+fn apply_overrides(
+    entries: &[FeatureScope],
+    overrides: &Overrides,
+    packages_for: impl Fn(Option<&str>, &str) -> Vec<String>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<FeatureScope> {
+    let mut entries = entries.to_vec();
+    if overrides.no_default_features {
+        for entry in &mut entries {
+            entry.default_features = Some(false);
+        }
+    }
+
+    for selection in &overrides.features {
+        let (selected_package, feature) = match selection.split_once('/') {
+            Some((package, feature)) => (Some(package), feature),
+            None => (None, selection.as_str()),
+        };
+
+        let packages = packages_for(selected_package, feature);
+        if packages.is_empty() {
+            diagnostics.push(match selected_package {
+                Some(package) => Diagnostic::FeatureNotDeclaredInPackage {
+                    feature: feature.to_string(),
+                    package: package.to_string(),
+                },
+                None => Diagnostic::FeatureNotDeclared {
+                    feature: feature.to_string(),
+                },
+            });
+        }
+        for package in packages {
+            entries.push(FeatureScope {
+                package: Some(package),
+                path: None,
+                features: vec![feature.to_string()],
+                removed: Vec::new(),
+                prefer: Vec::new(),
+                default_features: overrides.no_default_features.then_some(false),
+                target: None,
+                platform: None,
+                profile: None,
+                extensions: HashMap::new(),
+            });
+        }
+    }
+
+    entries
+}
+
+// Helper function to find the manifest of a path dependency, including the ones inherited from
+// `[workspace.dependencies]`
+fn find_path_dependency(
+    manifest_path: &Path,
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    package_name: &str,
+) -> Result<Option<PathBuf>> {
+    let dependencies =
+        manifest_dependencies(manifest_path, root_cargo_toml, root_manifest_path, true)?;
+    Ok(dependencies
+        .into_iter()
+        .find(|(name, _)| name == package_name)
+        .and_then(|(_, manifest)| manifest))
+}
+
+// Helper function to list the packages a manifest depends on by name, with the manifests of the
+// path dependencies among them
+//
+// Dev-dependencies only count for the package being built, not for its dependencies.
+fn manifest_dependencies(
+    manifest_path: &Path,
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    include_dev: bool,
+) -> Result<Vec<(String, Option<PathBuf>)>> {
+    let cargo_toml = load_manifest(manifest_path)?;
+
+    let manifest_dir = manifest_path.parent().unwrap();
+    let root_dir = root_manifest_path.parent().unwrap();
+    let workspace_dependencies = root_cargo_toml
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.dependencies.as_ref());
+
+    let mut dependency_tables = vec![&cargo_toml.dependencies, &cargo_toml.build_dependencies];
+    for target in cargo_toml
+        .target
+        .iter()
+        .flat_map(|targets| targets.values())
+    {
+        dependency_tables.extend([&target.dependencies, &target.build_dependencies]);
+    }
+    if include_dev {
+        dependency_tables.push(&cargo_toml.dev_dependencies);
+        for target in cargo_toml
+            .target
+            .iter()
+            .flat_map(|targets| targets.values())
+        {
+            dependency_tables.push(&target.dev_dependencies);
+        }
+    }
+
+    let mut dependencies = Vec::new();
+    for (name, dependency) in dependency_tables.into_iter().flatten().flatten() {
+        let Dependency::Detailed(detail) = dependency else {
+            dependencies.push((name.clone(), None));
+            continue;
+        };
+
+        // For inherited dependencies, the path lives in the workspace root
+        let (detail, base_dir) = if detail.workspace {
+            match workspace_dependencies.and_then(|deps| deps.get(name)) {
+                Some(Dependency::Detailed(workspace_detail)) => (workspace_detail, root_dir),
+                _ => {
+                    dependencies.push((name.clone(), None));
+                    continue;
+                }
+            }
+        } else {
+            (detail, manifest_dir)
+        };
+
+        let real_name = detail.package.as_deref().unwrap_or(name);
+        let dep_manifest = detail
+            .path
+            .as_ref()
+            .map(|path| normalize_path(&base_dir.join(path).join("Cargo.toml")))
+            .filter(|dep_manifest| dep_manifest.exists());
+        dependencies.push((real_name.to_string(), dep_manifest));
+    }
+
+    Ok(dependencies)
+}
+
+// Helper function to collect the packages in the build graph of a package, which are the only
+// ones its entries may select scopes of, with the manifests of the local ones
+//
+// `include_dev` is set for the package being built, whose dev-dependencies are part of the graph.
+fn dependency_graph(
+    manifest_path: &Path,
+    workspace_packages: &HashMap<String, (PathBuf, Package)>,
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    include_dev: bool,
+) -> Result<HashMap<String, Option<PathBuf>>> {
+    let mut packages = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut queue = vec![(manifest_path.to_path_buf(), include_dev)];
+    while let Some((manifest_path, is_built_package)) = queue.pop() {
+        if !visited.insert(manifest_path.clone()) {
+            continue;
+        }
+
+        for (name, dep_manifest) in manifest_dependencies(
+            &manifest_path,
+            root_cargo_toml,
+            root_manifest_path,
+            is_built_package,
+        )? {
+            let dep_manifest = dep_manifest.or_else(|| {
+                workspace_packages
+                    .get(&name)
+                    .map(|(member_manifest, _)| member_manifest.clone())
+            });
+            if let Some(dep_manifest) = &dep_manifest {
+                queue.push((dep_manifest.clone(), false));
+            }
+            let known = packages.entry(name).or_insert(None);
+            if known.is_none() {
+                *known = dep_manifest;
+            }
+        }
+    }
+
+    Ok(packages)
+}
+
+// A workspace member whose manifest does not load
+struct UnreadableMember {
+    // Known if the TOML itself parses
+    name: Option<String>,
+    manifest: PathBuf,
+    error: anyhow::Error,
+}
+
+// Helper function to read the name of a package from a manifest that does not load
+fn raw_package_name(manifest_path: &Path) -> Option<String> {
+    let content = std::fs::read_to_string(manifest_path).ok()?;
+    let manifest: toml::Table = content.parse().ok()?;
+    manifest
+        .get("package")?
+        .get("name")?
+        .as_str()
+        .map(String::from)
+}
+
+// Helper function to describe the unreadable members failing a resolution, as the context of
+// the error of the one it needs, which keeps a `DeclError` downcastable
+fn unreadable_members_context(target_package: &str, unreadable: &[UnreadableMember]) -> String {
+    let manifests: Vec<String> = unreadable
+        .iter()
+        .map(|member| member.manifest.display().to_string())
+        .collect();
+    format!(
+        "Cannot resolve '{target_package}', {} workspace member(s) cannot be read: {}",
+        manifests.len(),
+        manifests.join(", ")
+    )
+}
+
+// A package whose entries apply, with the packages they may select scopes of
+struct EntrySource {
+    name: String,
+    manifest: PathBuf,
+    entries: Vec<FeatureScope>,
+    // The entries past these are selections from the command line
+    from_manifest: usize,
+    dependencies: HashMap<String, Option<PathBuf>>,
+}
+
+// Helper function to report an entry selecting scopes of a package its consumer does not
+// depend on, located in the consumer's manifest unless it comes from the command line
+fn not_a_dependency(source: &EntrySource, index: usize, package: String) -> Diagnostic {
+    let is_from_manifest = index < source.from_manifest;
+    Diagnostic::NotADependency {
+        consumer: source.name.clone(),
+        package,
+        manifest: is_from_manifest.then(|| source.manifest.clone()),
+        line: is_from_manifest
+            .then(|| entry_line(&source.manifest, index))
+            .flatten(),
+    }
+}
+
+// Helper function to report the entries of a consumer's manifest selecting scopes of one package
+// under the same target, platform and profile. They add up, each enabling what it would alone.
+fn duplicate_entries(source: &EntrySource) -> Result<Vec<Diagnostic>> {
+    let mut groups: BTreeMap<_, Vec<usize>> = BTreeMap::new();
+    for (index, scope) in source.entries[..source.from_manifest].iter().enumerate() {
+        let package = match read_referenced_package(&source.manifest, scope)? {
+            Some(package) => package.name,
+            None => match &scope.package {
+                Some(package) => package.clone(),
+                None => continue,
+            },
+        };
+        groups
+            .entry((package, &scope.target, &scope.platform, &scope.profile))
+            .or_default()
+            .push(index);
+    }
+
+    Ok(groups
+        .into_iter()
+        .filter(|(_, indices)| indices.len() > 1)
+        .map(|((package, ..), indices)| Diagnostic::DuplicateEntry {
+            consumer: source.name.clone(),
+            package,
+            manifest: source.manifest.clone(),
+            lines: indices
+                .iter()
+                .filter_map(|index| entry_line(&source.manifest, *index))
+                .collect(),
+            count: indices.len(),
+        })
+        .collect())
+}
+
+// Helper function to find the line of the header of the `index`-th entry in a manifest, when
+// the entries are written as `[[package.metadata.feature-scope]]` tables
+fn entry_line(manifest_path: &Path, index: usize) -> Option<usize> {
+    let content = std::fs::read_to_string(manifest_path).ok()?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let header = line.trim().replace(' ', "");
+            header.starts_with("[[package.metadata.feature-scope]]")
+        })
+        .nth(index)
+        .map(|(number, _)| number + 1)
+}
+
+// Helper function to read the package a consumer references by `path` instead of by name
+//
+// The path is relative to the consumer's manifest and may name the package directory or its
+// manifest. A `package` given alongside it must match the name found there.
+fn read_referenced_package(
+    consumer_manifest: &Path,
+    scope: &FeatureScope,
+) -> Result<Option<Package>> {
+    let Some(path) = &scope.path else {
+        return Ok(None);
+    };
+
+    let path = consumer_manifest.parent().unwrap().join(path);
+    let manifest_path = if path
+        .extension()
+        .is_some_and(|extension| extension == "toml")
+    {
+        normalize_path(&path)
+    } else {
+        normalize_path(&path.join("Cargo.toml"))
+    };
+    let package = read_package(&manifest_path).with_context(|| {
+        format!(
+            "Failed to read the package referenced by path \"{}\" in {}",
+            scope.path.as_deref().unwrap_or_default(),
+            consumer_manifest.display()
+        )
+    })?;
+
+    if let Some(name) = &scope.package {
+        if *name != package.name {
+            anyhow::bail!(
+                "The feature-scope entry with path \"{}\" in {} names package '{name}', but {} is package '{}'",
+                scope.path.as_deref().unwrap_or_default(),
+                consumer_manifest.display(),
+                manifest_path.display(),
+                package.name
+            );
+        }
+    }
+
+    Ok(Some(package))
+}
+
+fn handle_workspace_package(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+    target_package: &str,
+    overrides: &Overrides,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Resolution> {
+    let check_default_migration = root_cargo_toml.options().check_default_migration;
+    let mut cfg_args = Vec::new();
+    let mut all_scope_features = HashSet::new();
+
+    // Always add default scope to check-cfg
+    all_scope_features.insert("__scope_default".to_string());
+
+    // Default enable __scope_default
+    let mut enable_scope_default = !overrides.no_default_features;
+    let mut library_features = EnabledFeatures {
+        target: overrides.target.clone(),
+        ..EnabledFeatures::default()
+    };
+
+    // First collect information of all packages in the workspace. Members that cannot be read
+    // only fail the resolution if the target package needs them.
+    let mut workspace_packages = HashMap::new();
+    let mut unreadable = Vec::new();
+
+    for member_manifest in workspace_member_manifests(root_cargo_toml, root_manifest_path)? {
+        match load_manifest(&member_manifest) {
+            Ok(member_cargo_toml) => {
+                if let Some(package) = member_cargo_toml.package {
+                    workspace_packages.insert(package.name.clone(), (member_manifest, package));
+                }
+            }
+            Err(error) => unreadable.push(UnreadableMember {
+                name: raw_package_name(&member_manifest),
+                manifest: member_manifest,
+                error,
+            }),
+        }
+    }
+    // The root package is a member too, and the one cargo builds by default in its directory
+    if root_cargo_toml.package.is_some() {
+        if let Some(package) = load_manifest(root_manifest_path)?.package {
+            workspace_packages.insert(
+                package.name.clone(),
+                (root_manifest_path.to_path_buf(), package),
+            );
+        }
+    }
+
+    // Collect feature scopes defined in feature-scope-decl of all packages, with the variables
+    // of their probes, which build scripts rerun on whether the probes are applied or not
+    for (_, package) in workspace_packages.values() {
+        collect_declared_scopes(package, &mut all_scope_features, diagnostics);
+        library_features.probe_vars.extend(env_probe_vars(package));
+    }
+
+    // Find target package, which may be an unreadable member whose name is unknown
+    let is_target = |member: &UnreadableMember| {
+        member
+            .name
+            .as_deref()
+            .is_none_or(|name| name == target_package)
+    };
+    if let Some(index) = unreadable.iter().position(is_target) {
+        let context = unreadable_members_context(target_package, &unreadable);
+        return Err(unreadable.swap_remove(index).error.context(context));
+    }
+    let (target_manifest_path, target_package_info) =
+        workspace_packages.get(target_package).ok_or_else(|| {
+            let mut members: Vec<&String> = workspace_packages.keys().collect();
+            members.sort();
+            anyhow::anyhow!(
+                "Package '{}' not found in workspace, members are: {}",
+                target_package,
+                members
+                    .iter()
+                    .map(|m| m.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })?;
+
+    let dependencies = match dependency_graph(
+        target_manifest_path,
+        &workspace_packages,
+        root_cargo_toml,
+        root_manifest_path,
+        true,
+    ) {
+        Ok(dependencies) => dependencies,
+        // A path dependency on an unreadable member fails to read here
+        Err(err) if !unreadable.is_empty() => {
+            return Err(err.context(unreadable_members_context(target_package, &unreadable)));
+        }
+        Err(err) => return Err(err),
+    };
+    let is_needed = |member: &UnreadableMember| {
+        member
+            .name
+            .as_ref()
+            .is_some_and(|name| dependencies.contains_key(name))
+    };
+    if let Some(index) = unreadable.iter().position(is_needed) {
+        let context = unreadable_members_context(target_package, &unreadable);
+        return Err(unreadable.swap_remove(index).error.context(context));
+    }
+    for member in unreadable {
+        diagnostics.push(Diagnostic::UnreadableMember {
+            manifest: member.manifest,
+            message: format!("{:#}", member.error),
+        });
+    }
+
+    // Unqualified selections apply to every dependency declaring the feature
+    let feature_scope = apply_overrides(
+        target_package_info
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.feature_scope.as_deref())
+            .unwrap_or_default(),
+        overrides,
+        |selected_package, feature| match selected_package {
+            Some(name) => vec![name.to_string()],
+            None => {
+                let mut packages: Vec<String> = workspace_packages
+                    .values()
+                    .filter(|(_, package)| {
+                        package.name == target_package || dependencies.contains_key(&package.name)
+                    })
+                    .filter(|(_, package)| {
+                        package
+                            .metadata
+                            .as_ref()
+                            .and_then(|metadata| metadata.feature_scope_decl.as_ref())
+                            .is_some_and(|decl| decl.declares(feature))
+                    })
+                    .map(|(_, package)| package.name.clone())
+                    .collect();
+                packages.sort();
+                packages
+            }
+        },
+        diagnostics,
+    );
+
+    // The local packages the target depends on select scopes of their own dependencies, which
+    // add to the target's selections like Cargo unifies the features of a dependency
+    let from_manifest = target_package_info
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.feature_scope.as_ref())
+        .map_or(0, Vec::len);
+    let mut consumers = vec![EntrySource {
+        name: target_package.to_string(),
+        manifest: target_manifest_path.clone(),
+        entries: feature_scope,
+        from_manifest,
+        dependencies: dependencies.clone(),
+    }];
+    let mut intermediates: Vec<(&String, &PathBuf)> = dependencies
+        .iter()
+        .filter(|(name, _)| *name != target_package)
+        .filter_map(|(name, manifest)| Some((name, manifest.as_ref()?)))
+        .collect();
+    intermediates.sort();
+    for (name, manifest) in intermediates {
+        let path_package;
+        let package = match workspace_packages.get(name) {
+            Some((_, package)) => package,
+            None => {
+                path_package = read_package(manifest)?;
+                &path_package
+            }
+        };
+        let entries = package
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.feature_scope.clone())
+            .unwrap_or_default();
+        if entries.is_empty() {
+            continue;
+        }
+        let dependencies = dependency_graph(
+            manifest,
+            &workspace_packages,
+            root_cargo_toml,
+            root_manifest_path,
+            false,
+        )?;
+        consumers.push(EntrySource {
+            name: name.clone(),
+            manifest: manifest.clone(),
+            from_manifest: entries.len(),
+            entries,
+            dependencies,
+        });
+    }
+
+    // Process feature-scope configuration of the target package and its dependencies
+    for source in &consumers {
+        diagnostics.extend(duplicate_entries(source)?);
+        let (consumer, consumer_manifest) = (&source.name, &source.manifest);
+        let is_target = consumer == target_package;
+        let built = is_target
+            .then(|| built_targets(source, overrides))
+            .transpose()?;
+        for (index, scope) in source.entries.iter().enumerate() {
+            // Find feature-scope-decl of dependency package, either at the path the
+            // consumer gives or by name, falling back to path dependencies that are not
+            // workspace members
+            let referenced_package = read_referenced_package(consumer_manifest, scope)?;
+            let scope_package = match (&referenced_package, &scope.package) {
+                (Some(package), _) => package.name.clone(),
+                (None, Some(package)) => package.clone(),
+                (None, None) => anyhow::bail!(
+                    "A feature-scope entry of package '{consumer}' needs either `package` or `path`"
+                ),
+            };
+
+            // The manifests only lead to local packages, cargo knows the registry and git ones
+            let mut registry_manifest = None;
+            if scope_package != *consumer && !source.dependencies.contains_key(&scope_package) {
+                registry_manifest =
+                    workspace::resolved_dependencies(root_manifest_path, consumer_manifest)
+                        .and_then(|mut resolved| resolved.remove(&scope_package));
+                if registry_manifest.is_none() {
+                    diagnostics.push(not_a_dependency(source, index, scope_package));
+                    continue;
+                }
+            }
+
+            let path_dep_package;
+            let registry_package;
+            let dep_package = if let Some(package) = &referenced_package {
+                collect_declared_scopes(package, &mut all_scope_features, diagnostics);
+                Some(package)
+            } else if let Some((_, dep_package)) = workspace_packages.get(&scope_package) {
+                Some(dep_package)
+            } else if let Some(manifest) = &registry_manifest {
+                registry_package = read_package(manifest)?;
+                collect_declared_scopes(&registry_package, &mut all_scope_features, diagnostics);
+                Some(&registry_package)
+            } else if let Some(dep_manifest) = find_path_dependency(
+                consumer_manifest,
+                root_cargo_toml,
+                root_manifest_path,
+                &scope_package,
+            )? {
+                let root_dir = root_manifest_path.parent().unwrap();
+                let dep_dir = dep_manifest.parent().unwrap();
+                diagnostics.push(Diagnostic::PathDependencyFallback {
+                    package: scope_package.clone(),
+                    manifest: dep_manifest.clone(),
+                    member: dep_dir
+                        .strip_prefix(root_dir)
+                        .unwrap_or(dep_dir)
+                        .to_path_buf(),
+                });
+
+                path_dep_package = read_package(&dep_manifest)?;
+                collect_declared_scopes(&path_dep_package, &mut all_scope_features, diagnostics);
+                Some(&path_dep_package)
+            } else {
+                diagnostics.push(Diagnostic::PackageNotFound {
+                    package: scope_package.clone(),
+                });
+                None
+            };
+
+            if !entry_applies(source, index, built.as_deref(), overrides)? {
+                continue;
+            }
+
+            if let Some(dep_package) = dep_package {
+                if let Some(dep_metadata) = &dep_package.metadata {
+                    if let Some(dep_feature_scope_decl) = &dep_metadata.feature_scope_decl {
+                        // Check if default features are disabled, requesting the `default`
+                        // pseudo-feature turns them back on. Only the target drops the default
+                        // scope, the entries of its dependencies can only add scopes.
+                        let scope_enable_default_features = scope.default_features.unwrap_or(true)
+                            || scope.features.iter().any(|f| f == "default");
+                        if !scope_enable_default_features && is_target {
+                            enable_scope_default = false;
+                        }
+
+                        let (removed, undeclared) =
+                            removed_features(&scope.removed, dep_feature_scope_decl);
+                        for feature in undeclared {
+                            diagnostics.push(Diagnostic::FeatureNotDeclaredInPackage {
+                                feature,
+                                package: scope_package.clone(),
+                            });
+                        }
+                        let mut kept = BTreeSet::new();
+                        let mut entry_features = HashSet::new();
+
+                        // Cross-validate and parse explicitly specified feature dependencies
+                        for feature in &scope.features {
+                            // Iteratively parse feature dependencies
+                            let mut enabled_features = HashSet::new();
+                            if resolve_selection(
+                                feature,
+                                &removed,
+                                dep_feature_scope_decl,
+                                &mut enabled_features,
+                                &mut kept,
+                            ) {
+                                warn_deprecated(
+                                    &scope_package,
+                                    feature,
+                                    dep_feature_scope_decl,
+                                    diagnostics,
+                                );
+                                entry_features.extend(enabled_features.iter().cloned());
+                                library_features.record(
+                                    &scope_package,
+                                    dep_feature_scope_decl,
+                                    Origin::Selection {
+                                        consumer: consumer.clone(),
+                                        feature: feature.clone(),
+                                    },
+                                    &mut enabled_features,
+                                );
+                                for enabled_feature in enabled_features {
+                                    cfg_args.push(String::from("--cfg"));
+                                    cfg_args.push(cfg_name::scope_cfg(&enabled_feature));
+                                }
+                            } else {
+                                diagnostics.push(Diagnostic::FeatureNotDeclaredInPackage {
+                                    feature: feature.clone(),
+                                    package: scope_package.clone(),
+                                });
+                            }
+                        }
+
+                        if check_default_migration {
+                            check_default_meaning(
+                                consumer,
+                                &scope_package,
+                                scope,
+                                &removed,
+                                dep_feature_scope_decl,
+                                &entry_features,
+                                diagnostics,
+                            );
+                        }
+
+                        let preferred_features = apply_preferences(
+                            consumer,
+                            &scope_package,
+                            dep_feature_scope_decl,
+                            &scope.prefer,
+                            overrides,
+                            &mut library_features,
+                            diagnostics,
+                        )?;
+                        for enabled_feature in preferred_features {
+                            cfg_args.push(String::from("--cfg"));
+                            cfg_args.push(cfg_name::scope_cfg(&enabled_feature));
+                        }
+
+                        // If default features are enabled and no features are explicitly specified, handle default features.
+                        // Removals alone still start from the defaults.
+                        let selects_features =
+                            !scope.features.is_empty() || !scope.prefer.is_empty();
+                        if scope_enable_default_features && !selects_features {
+                            if let Some(defaults) = &dep_feature_scope_decl.default {
+                                for default_feature in defaults {
+                                    let mut enabled_features = HashSet::new();
+                                    resolve_selection(
+                                        default_feature,
+                                        &removed,
+                                        dep_feature_scope_decl,
+                                        &mut enabled_features,
+                                        &mut kept,
+                                    );
+                                    library_features.record(
+                                        &scope_package,
+                                        dep_feature_scope_decl,
+                                        Origin::Defaults {
+                                            library: scope_package.clone(),
+                                        },
+                                        &mut enabled_features,
+                                    );
+
+                                    for enabled_feature in enabled_features {
+                                        cfg_args.push(String::from("--cfg"));
+                                        cfg_args.push(cfg_name::scope_cfg(&enabled_feature));
+                                    }
+                                }
+                            }
+                        }
+
+                        for feature in kept {
+                            diagnostics.push(Diagnostic::RemovedFeatureEnabled {
+                                consumer: consumer.clone(),
+                                package: scope_package.clone(),
+                                feature,
+                            });
+                        }
+
+                        apply_probes(
+                            &scope_package,
+                            dep_feature_scope_decl,
+                            overrides,
+                            &mut library_features,
+                            &mut cfg_args,
+                        );
+                    } else {
+                        diagnostics.push(Diagnostic::PackageWithoutDecl {
+                            package: scope_package.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    library_features.check(target_package)?;
+
+    // Finally decide whether to add __scope_default
+    if enable_scope_default {
+        cfg_args.insert(0, String::from("__scope_default"));
+        cfg_args.insert(0, String::from("--cfg"));
+    }
+
+    // Build check-cfg parameters
+    let mut check_cfg_args = Vec::new();
+    for scope_feature in all_scope_features {
+        check_cfg_args.push(String::from("--check-cfg"));
+        check_cfg_args.push(format!("cfg({scope_feature})"));
+    }
+
+    let cargo_features = library_features.cargo_features();
+    let probe_vars = std::mem::take(&mut library_features.probe_vars);
+    Ok((
+        cfg_args,
+        check_cfg_args,
+        library_features.into_origins(),
+        cargo_features,
+        probe_vars,
+    ))
+}
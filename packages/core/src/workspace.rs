@@ -0,0 +1,720 @@
+//! Workspace member resolution through `cargo metadata`.
+//!
+//! Which packages belong to a workspace depends on globbed `members`, `exclude`,
+//! `default-members` and path dependencies inside the workspace directory. Asking cargo with
+//! `cargo metadata --no-deps` keeps the members this tool sees identical to the ones cargo builds.
+//!
+//! Build sandboxes of cross and embedded targets may not allow spawning processes. For them,
+//! `cargo feature-scope index` writes the members to a workspace index ahead of time, and with
+//! `FEATURE_SCOPE_INDEX` pointing to it the members are read from the index instead, along with
+//! the cfgs of the platforms it was written for. Nothing else in the resolution spawns processes or reads files outside the workspace, except for
+//! entries selecting scopes of a package the manifests do not lead to: without an index, the
+//! full `cargo metadata` tells whether it is a dependency from a registry or git after all.
+//! Entries limited to a target of the package being built also ask `cargo metadata` for its
+//! targets, when the command or the selected targets say which are built.
+//!
+//! Tools that only need the members listed in the root manifest can also find them with
+//! [`MemberDiscovery`], which expands the `members` globs itself.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    sync::Mutex,
+};
+
+use crate::{
+    env_vars,
+    manifest::{normalize_path, CargoToml},
+    platform::{self, PlatformCfgs},
+};
+
+/// The member manifests of a workspace.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Members {
+    /// The manifests of all members, in the order cargo lists them.
+    pub all: Vec<PathBuf>,
+    /// The manifests of the members cargo builds when no package is selected.
+    pub default: Vec<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CargoMetadata {
+    packages: Vec<MetadataPackage>,
+    workspace_members: Vec<String>,
+    // Only reported by cargo 1.71 and later
+    workspace_default_members: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetadataPackage {
+    id: String,
+    #[serde(default)]
+    name: String,
+    manifest_path: PathBuf,
+    #[serde(default)]
+    targets: Vec<MetadataTarget>,
+    default_run: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetadataTarget {
+    name: String,
+    kind: Vec<String>,
+    #[serde(default)]
+    test: bool,
+    #[serde(default)]
+    bench: bool,
+}
+
+/// A target of a package, as `cargo metadata` lists it.
+#[derive(Debug, Clone)]
+pub(crate) struct PackageTarget {
+    /// `lib`, `bin`, `example`, `test` or `bench`, libraries of every crate type being `lib`.
+    pub kind: String,
+    pub name: String,
+    /// Whether `cargo test` builds it without selecting it, like with `test = true`.
+    pub test: bool,
+    /// Whether `cargo bench` builds it without selecting it, like with `bench = true`.
+    pub bench: bool,
+}
+
+/// The targets of a package besides its build script and the binary `cargo run` runs without
+/// `--bin`, if there is one.
+pub(crate) fn package_targets(
+    manifest_path: &Path,
+) -> Result<(Vec<PackageTarget>, Option<String>)> {
+    let output = process::Command::new("cargo")
+        .args([
+            "metadata",
+            "--format-version",
+            "1",
+            "--no-deps",
+            "--manifest-path",
+        ])
+        .arg(manifest_path)
+        .stderr(process::Stdio::inherit())
+        .output()
+        .context("Failed to run cargo metadata")?;
+    if !output.status.success() {
+        anyhow::bail!("cargo metadata failed for {}", manifest_path.display());
+    }
+    let metadata: CargoMetadata =
+        serde_json::from_slice(&output.stdout).context("Failed to parse cargo metadata output")?;
+    let Some(package) = metadata
+        .packages
+        .into_iter()
+        .find(|package| is_root_manifest(&package.manifest_path, manifest_path))
+    else {
+        anyhow::bail!("cargo metadata does not list {}", manifest_path.display());
+    };
+
+    let targets: Vec<PackageTarget> = package
+        .targets
+        .into_iter()
+        .filter(|target| !target.kind.iter().any(|kind| kind == "custom-build"))
+        .map(|target| {
+            let kind = match target.kind.first().map(String::as_str) {
+                Some(kind @ ("bin" | "example" | "test" | "bench")) => kind.to_string(),
+                _ => String::from("lib"),
+            };
+            PackageTarget {
+                kind,
+                name: target.name,
+                test: target.test,
+                bench: target.bench,
+            }
+        })
+        .collect();
+    // Like cargo, a single binary is the one to run
+    let mut bins = targets.iter().filter(|target| target.kind == "bin");
+    let default_run = package
+        .default_run
+        .or_else(|| match (bins.next(), bins.next()) {
+            (Some(bin), None) => Some(bin.name.clone()),
+            _ => None,
+        });
+    Ok((targets, default_run))
+}
+
+// The dependency graph of the full `cargo metadata`
+#[derive(Debug, Deserialize)]
+struct ResolvedMetadata {
+    packages: Vec<MetadataPackage>,
+    resolve: Option<Resolve>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Resolve {
+    nodes: Vec<ResolveNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResolveNode {
+    id: String,
+    deps: Vec<NodeDependency>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NodeDependency {
+    pkg: String,
+    dep_kinds: Vec<DependencyKind>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DependencyKind {
+    kind: Option<String>,
+}
+
+// Commands like `bench` and `e2e` look up the members several times per run
+static CACHE: Mutex<Option<HashMap<PathBuf, Members>>> = Mutex::new(None);
+
+// The workspaces whose members come from an index, which must not spawn `cargo metadata`
+static INDEXED: Mutex<Option<HashSet<PathBuf>>> = Mutex::new(None);
+
+// The full `cargo metadata` of each workspace, `None` if cargo could not resolve it
+static RESOLVED: Mutex<Option<HashMap<PathBuf, Option<ResolvedMetadata>>>> = Mutex::new(None);
+
+/// The file name `cargo feature-scope index` writes next to the root manifest.
+pub const INDEX_FILE_NAME: &str = "feature-scope-index.json";
+
+// The version of the indexes this release writes and reads
+const INDEX_VERSION: u32 = 2;
+
+// Paths are relative to the directory of the index, so the workspace can be moved
+#[derive(Debug, Serialize, Deserialize)]
+struct Index {
+    version: u32,
+    root: PathBuf,
+    members: Vec<PathBuf>,
+    default_members: Vec<PathBuf>,
+    // Missing from the indexes of older releases, which fail on their version instead
+    #[serde(default)]
+    platform: PlatformCfgs,
+}
+
+/// Returns the members of the workspace whose root manifest is given.
+pub fn members(root_manifest_path: &Path) -> Result<Members> {
+    let mut cache = CACHE.lock().unwrap();
+    let cache = cache.get_or_insert_with(HashMap::new);
+    if let Some(members) = cache.get(root_manifest_path) {
+        return Ok(members.clone());
+    }
+
+    if let Some(index_path) = env::var_os(env_vars::INDEX) {
+        let (index_root, members, platform) = read_index_file(Path::new(&index_path))?;
+        if !is_root_manifest(&index_root, root_manifest_path) {
+            anyhow::bail!(
+                "{} points to the index of {}, not of {}",
+                env_vars::INDEX,
+                index_root.display(),
+                root_manifest_path.display()
+            );
+        }
+        cache.insert(root_manifest_path.to_path_buf(), members.clone());
+        mark_indexed(root_manifest_path);
+        platform::use_index(platform);
+        return Ok(members);
+    }
+
+    let members = cargo_metadata_members(root_manifest_path)?;
+    cache.insert(root_manifest_path.to_path_buf(), members.clone());
+    Ok(members)
+}
+
+fn cargo_metadata_members(root_manifest_path: &Path) -> Result<Members> {
+    let output = process::Command::new("cargo")
+        .args([
+            "metadata",
+            "--format-version",
+            "1",
+            "--no-deps",
+            "--manifest-path",
+        ])
+        .arg(root_manifest_path)
+        .stderr(process::Stdio::inherit())
+        .output()
+        .context("Failed to run cargo metadata")?;
+    if !output.status.success() {
+        anyhow::bail!("cargo metadata failed for {}", root_manifest_path.display());
+    }
+    let metadata: CargoMetadata =
+        serde_json::from_slice(&output.stdout).context("Failed to parse cargo metadata output")?;
+
+    let manifests: HashMap<&str, &PathBuf> = metadata
+        .packages
+        .iter()
+        .map(|package| (package.id.as_str(), &package.manifest_path))
+        .collect();
+    let resolve = |ids: &[String]| -> Vec<PathBuf> {
+        ids.iter()
+            .filter_map(|id| manifests.get(id.as_str()).map(|path| path.to_path_buf()))
+            .collect()
+    };
+
+    let all = resolve(&metadata.workspace_members);
+    let default = match &metadata.workspace_default_members {
+        Some(ids) => resolve(ids),
+        None => all.clone(),
+    };
+    Ok(Members { all, default })
+}
+
+/// Writes the index of a workspace, from which [`read_index`] gets the members without spawning
+/// `cargo metadata`. The members are always asked from cargo, never from an existing index, and
+/// the cfgs of the host and of `targets` from rustc, so resolving through the index evaluates
+/// `platform`s without spawning rustc either.
+///
+/// The paths in the index are relative to its directory, the current one for a bare file name:
+///
+/// ```
+/// use feature_scope_core::workspace;
+/// use std::{env, path::Path};
+///
+/// let root_manifest_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../Cargo.toml");
+/// let root_manifest_path = root_manifest_path.canonicalize()?;
+/// # let dir = env::temp_dir().join(format!("feature-scope-index-doc-{}", std::process::id()));
+/// # std::fs::create_dir_all(&dir)?;
+/// # env::set_current_dir(&dir)?;
+/// workspace::write_index(&root_manifest_path, Path::new("scopes.json"), &[])?;
+///
+/// let (root, members) = workspace::read_index(Path::new("scopes.json"))?;
+/// assert_eq!(root, root_manifest_path);
+/// assert!(!members.all.is_empty());
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn write_index(root_manifest_path: &Path, index_path: &Path, targets: &[String]) -> Result<()> {
+    let members = cargo_metadata_members(root_manifest_path)?;
+    let index_dir = index_dir(index_path)?;
+    let relative = |path: &Path| -> PathBuf {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        path.strip_prefix(&index_dir)
+            .map(Path::to_path_buf)
+            .unwrap_or(path)
+    };
+
+    let index = Index {
+        version: INDEX_VERSION,
+        root: relative(root_manifest_path),
+        members: members.all.iter().map(|path| relative(path)).collect(),
+        default_members: members.default.iter().map(|path| relative(path)).collect(),
+        platform: platform::index_cfgs(targets)?,
+    };
+    let content = serde_json::to_string_pretty(&index)?;
+    fs::write(index_path, content + "\n")
+        .with_context(|| format!("Failed to write {}", index_path.display()))
+}
+
+/// Reads a workspace index, returning the root manifest and the members it lists.
+pub fn read_index(index_path: &Path) -> Result<(PathBuf, Members)> {
+    let (root_manifest_path, members, _) = read_index_file(index_path)?;
+    Ok((root_manifest_path, members))
+}
+
+// The platform cfgs a workspace index stores
+pub(crate) fn index_platform(index_path: &Path) -> Result<PlatformCfgs> {
+    let (_, _, platform) = read_index_file(index_path)?;
+    Ok(platform)
+}
+
+fn read_index_file(index_path: &Path) -> Result<(PathBuf, Members, PlatformCfgs)> {
+    let content = fs::read_to_string(index_path)
+        .with_context(|| format!("Failed to read {}", index_path.display()))?;
+    let index: Index = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", index_path.display()))?;
+    if index.version != INDEX_VERSION {
+        anyhow::bail!(
+            "{} has version {}, regenerate it with `cargo feature-scope index`",
+            index_path.display(),
+            index.version
+        );
+    }
+
+    let index_dir = index_dir(index_path)?;
+    let absolute = |paths: Vec<PathBuf>| -> Vec<PathBuf> {
+        paths.iter().map(|path| index_dir.join(path)).collect()
+    };
+
+    Ok((
+        index_dir.join(index.root),
+        Members {
+            all: absolute(index.members),
+            default: absolute(index.default_members),
+        },
+        index.platform,
+    ))
+}
+
+// The directory of an index, canonical like the paths cargo reports, which the root manifest is
+// compared with. A bare file name has an empty parent, which stands for the current directory.
+fn index_dir(index_path: &Path) -> Result<PathBuf> {
+    let dir = match index_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::canonicalize(dir).with_context(|| {
+        format!(
+            "Failed to read {}, the directory of {}",
+            dir.display(),
+            index_path.display()
+        )
+    })
+}
+
+// Lets `members` answer from an index for its workspace, returning the root manifest
+pub(crate) fn cache_index(index_path: &Path) -> Result<PathBuf> {
+    let (root_manifest_path, members, platform) = read_index_file(index_path)?;
+    let mut cache = CACHE.lock().unwrap();
+    cache
+        .get_or_insert_with(HashMap::new)
+        .insert(root_manifest_path.clone(), members);
+    mark_indexed(&root_manifest_path);
+    platform::use_index(platform);
+    Ok(root_manifest_path)
+}
+
+fn mark_indexed(root_manifest_path: &Path) {
+    INDEXED
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashSet::new)
+        .insert(root_manifest_path.to_path_buf());
+}
+
+/// Returns the packages in the build graph of the package with the given manifest, with their
+/// manifests, as the full `cargo metadata` resolves them, registry and git dependencies
+/// included. Dev-dependencies only count for that package itself.
+///
+/// Returns `None` for workspaces read from an index, which never spawn cargo, and when cargo
+/// cannot resolve the dependencies offline.
+pub fn resolved_dependencies(
+    root_manifest_path: &Path,
+    manifest_path: &Path,
+) -> Option<HashMap<String, PathBuf>> {
+    let is_indexed = INDEXED
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|indexed| indexed.contains(root_manifest_path));
+    if is_indexed || env::var_os(env_vars::INDEX).is_some() {
+        return None;
+    }
+
+    let mut resolved = RESOLVED.lock().unwrap();
+    let metadata = resolved
+        .get_or_insert_with(HashMap::new)
+        .entry(root_manifest_path.to_path_buf())
+        .or_insert_with(|| resolved_metadata(root_manifest_path))
+        .as_ref()?;
+    let nodes: HashMap<&str, &ResolveNode> = metadata
+        .resolve
+        .as_ref()?
+        .nodes
+        .iter()
+        .map(|node| (node.id.as_str(), node))
+        .collect();
+    let packages: HashMap<&str, &MetadataPackage> = metadata
+        .packages
+        .iter()
+        .map(|package| (package.id.as_str(), package))
+        .collect();
+
+    let manifest_path = fs::canonicalize(manifest_path).unwrap_or(manifest_path.to_path_buf());
+    let start = metadata
+        .packages
+        .iter()
+        .find(|package| package.manifest_path == manifest_path)?;
+
+    let mut dependencies = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut queue = vec![(start.id.as_str(), true)];
+    while let Some((id, is_start)) = queue.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        for dependency in nodes.get(id).map(|node| &node.deps).into_iter().flatten() {
+            let is_dev_only = dependency
+                .dep_kinds
+                .iter()
+                .all(|kind| kind.kind.as_deref() == Some("dev"));
+            if is_dev_only && !is_start {
+                continue;
+            }
+            if let Some(package) = packages.get(dependency.pkg.as_str()) {
+                dependencies.insert(package.name.clone(), package.manifest_path.clone());
+            }
+            queue.push((dependency.pkg.as_str(), false));
+        }
+    }
+
+    Some(dependencies)
+}
+
+// Without network access, so a missing lock file or registry cache fails instead of downloading
+fn resolved_metadata(root_manifest_path: &Path) -> Option<ResolvedMetadata> {
+    let output = process::Command::new("cargo")
+        .args([
+            "metadata",
+            "--format-version",
+            "1",
+            "--offline",
+            "--manifest-path",
+        ])
+        .arg(root_manifest_path)
+        .stderr(process::Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    serde_json::from_slice(&output.stdout).ok()
+}
+
+/// Returns the manifests of the workspace members besides the root package.
+pub fn workspace_member_manifests(
+    root_cargo_toml: &CargoToml,
+    root_manifest_path: &Path,
+) -> Result<Vec<PathBuf>> {
+    if root_cargo_toml.workspace.is_none() {
+        return Ok(Vec::new());
+    }
+
+    Ok(members(root_manifest_path)?
+        .all
+        .into_iter()
+        .filter(|manifest| !is_root_manifest(manifest, root_manifest_path))
+        .collect())
+}
+
+// cargo reports canonical paths, while the root manifest path is derived from the current
+// directory
+pub(crate) fn is_root_manifest(manifest_path: &Path, root_manifest_path: &Path) -> bool {
+    manifest_path == root_manifest_path
+        || std::fs::canonicalize(root_manifest_path).is_ok_and(|root| root == manifest_path)
+}
+
+/// Finds the members of a workspace from its root manifest alone, without `cargo metadata`.
+///
+/// `members` and `default-members` may use the `*`, `?` and `[...]` wildcards of cargo within a
+/// path component, and members below an `exclude` path are left out. Unlike cargo, path
+/// dependencies that no entry lists are not members.
+///
+/// ```
+/// # use std::fs;
+/// # let root = std::env::temp_dir().join(format!("feature-scope-discovery-{}", std::process::id()));
+/// # for (dir, name) in [("crates/a", "a"), ("crates/b", "b"), ("crates/old", "old")] {
+/// #     fs::create_dir_all(root.join(dir)).unwrap();
+/// #     fs::write(
+/// #         root.join(dir).join("Cargo.toml"),
+/// #         format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n"),
+/// #     )
+/// #     .unwrap();
+/// # }
+/// fs::write(
+///     root.join("Cargo.toml"),
+///     "[workspace]\nmembers = [\"crates/*\"]\nexclude = [\"crates/old\"]\n",
+/// )?;
+///
+/// let members = feature_scope_core::workspace::MemberDiscovery::new(root.join("Cargo.toml"))
+///     .follow_symlinks(false)
+///     .discover()?;
+/// let names: Vec<_> = members
+///     .all
+///     .iter()
+///     .map(|manifest| manifest.parent().unwrap().file_name().unwrap())
+///     .collect();
+/// assert_eq!(names, ["a", "b"]);
+/// assert_eq!(members.default, members.all);
+/// # fs::remove_dir_all(&root).unwrap();
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct MemberDiscovery {
+    root_manifest_path: PathBuf,
+    follow_symlinks: bool,
+    globs: bool,
+}
+
+impl MemberDiscovery {
+    pub fn new(root_manifest_path: impl Into<PathBuf>) -> Self {
+        MemberDiscovery {
+            root_manifest_path: root_manifest_path.into(),
+            follow_symlinks: true,
+            globs: true,
+        }
+    }
+
+    /// Whether members may be, or lie below, symbolic links to directories. On by default.
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Whether wildcards in member paths are expanded, or taken as literal names. On by default.
+    pub fn globs(mut self, globs: bool) -> Self {
+        self.globs = globs;
+        self
+    }
+
+    pub fn discover(&self) -> Result<Members> {
+        let content = fs::read_to_string(&self.root_manifest_path)
+            .with_context(|| format!("Failed to read {}", self.root_manifest_path.display()))?;
+        let root_cargo_toml: CargoToml = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", self.root_manifest_path.display()))?;
+        let root_dir = match self.root_manifest_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => fs::canonicalize(dir)
+                .with_context(|| format!("Failed to read {}", dir.display()))?,
+            _ => env::current_dir()?,
+        };
+        let root_manifest = root_dir.join(self.root_manifest_path.file_name().unwrap());
+
+        let workspace = root_cargo_toml.workspace.as_ref();
+        let excluded: Vec<PathBuf> = workspace
+            .and_then(|workspace| workspace.exclude.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|path| normalize_path(&root_dir.join(path)))
+            .collect();
+
+        let mut all = Vec::new();
+        if root_cargo_toml.package.is_some() {
+            all.push(root_manifest.clone());
+        }
+        let patterns = workspace.and_then(|workspace| workspace.members.as_ref());
+        for manifest in self.expand_all(&root_dir, patterns.into_iter().flatten())? {
+            let dir = manifest.parent().unwrap();
+            if !excluded.iter().any(|excluded| dir.starts_with(excluded))
+                && !all.contains(&manifest)
+            {
+                all.push(manifest);
+            }
+        }
+
+        let default = match workspace.and_then(|workspace| workspace.default_members.as_ref()) {
+            Some(patterns) => {
+                let defaults = self.expand_all(&root_dir, patterns)?;
+                if let Some(manifest) = defaults.iter().find(|manifest| !all.contains(manifest)) {
+                    anyhow::bail!(
+                        "Default member {} is not a member of the workspace",
+                        manifest.display()
+                    );
+                }
+                defaults
+            }
+            // Like cargo, a root package alone is built when no package is selected
+            None if root_cargo_toml.package.is_some() => vec![root_manifest],
+            None => all.clone(),
+        };
+
+        Ok(Members { all, default })
+    }
+
+    // The manifests of the directories the patterns name, in the order given
+    fn expand_all<'a>(
+        &self,
+        root_dir: &Path,
+        patterns: impl IntoIterator<Item = &'a String>,
+    ) -> Result<Vec<PathBuf>> {
+        let mut manifests = Vec::new();
+        for pattern in patterns {
+            let is_glob = self.globs && pattern.contains(['*', '?', '[']);
+            for dir in self.expand(root_dir, pattern)? {
+                let manifest = dir.join("Cargo.toml");
+                if !self.follow_symlinks && has_symlink(root_dir, &dir) {
+                    continue;
+                }
+                if manifest.is_file() {
+                    manifests.push(manifest);
+                } else if !is_glob {
+                    anyhow::bail!("Workspace member {} has no Cargo.toml", dir.display());
+                }
+            }
+        }
+        Ok(manifests)
+    }
+
+    fn expand(&self, root_dir: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+        let mut dirs = vec![root_dir.to_path_buf()];
+        for component in Path::new(pattern).components() {
+            let component = component.as_os_str().to_string_lossy();
+            if !self.globs || !component.contains(['*', '?', '[']) {
+                dirs = dirs.into_iter().map(|dir| dir.join(&*component)).collect();
+                continue;
+            }
+
+            let mut matched = Vec::new();
+            for dir in &dirs {
+                let Ok(entries) = fs::read_dir(dir) else {
+                    continue;
+                };
+                let mut names: Vec<String> = entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.path().is_dir())
+                    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                    .filter(|name| !name.starts_with('.') && glob_matches(&component, name))
+                    .collect();
+                names.sort();
+                matched.extend(names.into_iter().map(|name| dir.join(name)));
+            }
+            dirs = matched;
+        }
+        Ok(dirs.iter().map(|dir| normalize_path(dir)).collect())
+    }
+}
+
+// Whether a directory or one of its parents below the root is a symbolic link
+fn has_symlink(root_dir: &Path, dir: &Path) -> bool {
+    dir.ancestors()
+        .take_while(|ancestor| *ancestor != root_dir)
+        .any(|ancestor| fs::symlink_metadata(ancestor).is_ok_and(|m| m.file_type().is_symlink()))
+}
+
+// Matches one path component against a pattern of `*`, `?` and `[...]` (`[!...]` negated)
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches_from(&pattern, &name)
+}
+
+fn matches_from(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| matches_from(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && matches_from(&pattern[1..], &name[1..]),
+        Some('[') => {
+            let Some(close) = pattern
+                .iter()
+                .skip(2)
+                .position(|c| *c == ']')
+                .map(|i| i + 2)
+            else {
+                return name.first() == Some(&'[') && matches_from(&pattern[1..], &name[1..]);
+            };
+            let Some(c) = name.first() else {
+                return false;
+            };
+            let (negated, class) = match pattern[1] {
+                '!' => (true, &pattern[2..close]),
+                _ => (false, &pattern[1..close]),
+            };
+            let mut found = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == '-' {
+                    found |= (class[i]..=class[i + 2]).contains(c);
+                    i += 3;
+                } else {
+                    found |= class[i] == *c;
+                    i += 1;
+                }
+            }
+            found != negated && matches_from(&pattern[close + 1..], &name[1..])
+        }
+        Some(p) => name.first() == Some(p) && matches_from(&pattern[1..], &name[1..]),
+    }
+}
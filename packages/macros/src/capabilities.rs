@@ -0,0 +1,55 @@
+//! The declared scopes of the crate being compiled, for `feature_scope_capabilities!`.
+//!
+//! The scopes are the features of the crate's `[package.metadata.feature-scope-decl]`, sorted by
+//! name, each becoming an enum variant named in UpperCamelCase, so `log-level`
+//! becomes `LogLevel`. Features declared with `declare_feature_scopes!` are not seen, the macro
+//! only reads the manifest.
+
+use std::{env, fs, path::Path};
+
+// Keys of feature-scope-decl that are not features
+const RESERVED_KEYS: [&str; 4] = ["default", "exclusive", "presets", "stub-disabled-items"];
+
+/// Reads the declared features of the crate being compiled.
+pub fn declared_scopes() -> Result<Vec<String>, String> {
+    let manifest_dir = env::var_os("CARGO_MANIFEST_DIR")
+        .ok_or("CARGO_MANIFEST_DIR is not set, the capabilities are read from Cargo.toml")?;
+    let path = Path::new(&manifest_dir).join("Cargo.toml");
+    let content = fs::read_to_string(&path)
+        .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+    let manifest = toml::from_str::<toml::Table>(&content)
+        .map_err(|err| format!("failed to parse {}: {err}", path.display()))?;
+    let decl = manifest
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("feature-scope-decl"))
+        .and_then(toml::Value::as_table)
+        .ok_or("the crate declares no scopes in `[package.metadata.feature-scope-decl]`")?;
+    let mut scopes: Vec<String> = decl
+        .keys()
+        .filter(|key| !RESERVED_KEYS.contains(&key.as_str()))
+        .cloned()
+        .collect();
+    scopes.sort();
+    Ok(scopes)
+}
+
+/// The variant name of a feature: its words, split at anything but letters and digits,
+/// capitalized and joined, with a leading `_` before a digit.
+pub fn variant_name(feature: &str) -> String {
+    let name: String = feature
+        .split(|c: char| !c.is_alphanumeric())
+        .flat_map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .into_iter()
+                .flat_map(char::to_uppercase)
+                .chain(chars)
+        })
+        .collect();
+    match name.chars().next() {
+        Some(first) if first.is_alphabetic() => name,
+        _ => format!("_{name}"),
+    }
+}
@@ -33,6 +33,50 @@
 //! default-features = false
 //! ```
 //!
+//! Declarations can also live in the library's code instead of its `Cargo.toml`. The CLI reads
+//! them from the crate root and merges them with the TOML declarations, failing when the two
+//! disagree:
+//!
+//! ```rust
+//! feature_scope::declare_feature_scopes! {
+//!     default = [a];
+//!     a;
+//!     b;
+//!     c = [b];
+//! }
+//! ```
+//!
+//! Features that exclude each other, like alternative backends, form `exclusive` groups in
+//! the TOML declarations. Resolving a consumer that ends up with two features of one group is
+//! an error:
+//!
+//! ```toml
+//! [package.metadata.feature-scope-decl]
+//! sqlite = []
+//! postgres = []
+//! exclusive = [["sqlite", "postgres"]]
+//! ```
+//!
+//! A feature declared with a table can also carry documentation, conflicts and a deprecation
+//! note, which consumers selecting it are warned about:
+//!
+//! ```toml
+//! [package.metadata.feature-scope-decl]
+//! sqlite = []
+//! mysql = { doc = "The MySQL backend", conflicts = ["sqlite"] }
+//! legacy = { deps = ["mysql"], deprecated = "select mysql instead" }
+//! ```
+//!
+//! `presets` bundle features a consumer selects under one name. A preset expands into its
+//! features and has no scope of its own, so `#[feature_scope(web)]` would never be enabled:
+//!
+//! ```toml
+//! [package.metadata.feature-scope-decl]
+//! http = []
+//! json = []
+//! presets = { web = ["http", "json"] }
+//! ```
+//!
 //! ## Usage
 //!
 //! Use the macros in your library code:
@@ -56,6 +100,207 @@
 //! }
 //! ```
 //!
+//! The feature can also be given in the `key = value` form, which is where further options
+//! of the attributes go:
+//!
+//! ```rust
+//! use feature_scope::feature_scope;
+//!
+//! #[feature_scope(feature = "b")]
+//! pub fn another_feature_b_function() {}
+//! ```
+//!
+//! The string form also takes feature names that are not identifiers, like `"log-level"`. Such
+//! names are mapped to sanitized cfg names with a hash suffix, the same way the CLI maps them.
+//! Names cannot be empty or contain `/` or control characters.
+//!
+//! The bare feature comes first, and the option names `feature`, `doc` and `stub` are not taken
+//! as one, so swapped arguments fail instead of gating the item by the wrong scope. A feature of
+//! such a name is written in the string form:
+//!
+//! ```rust,compile_fail
+//! use feature_scope::feature_scope;
+//!
+//! // error: `stub` is an option, not a feature
+//! #[feature_scope(stub, postgres)]
+//! pub fn connect() {}
+//! ```
+//!
+//! Combinations of features are written as `all`, `any` and `not` predicates, like in `cfg`:
+//!
+//! ```rust
+//! use feature_scope::{feature_scope, feature_scope_default};
+//!
+//! #[feature_scope(all(a, not(b)))]
+//! pub fn only_a() {}
+//!
+//! #[feature_scope(any(a, c))]
+//! pub fn a_or_c() {}
+//!
+//! // Also compiled by default
+//! #[feature_scope_default(any(b, c))]
+//! pub fn b_c_or_default() {}
+//! ```
+//!
+//! ## Branching Inside Functions
+//!
+//! Attributes cannot gate arbitrary expressions. `feature_scope_select!` picks the block of the
+//! first arm whose predicate is enabled, or the `else` block, so a function can branch without
+//! being split into gated copies. The other blocks are compiled out like gated items, so they
+//! may use items that only exist in their scope. Without an `else` arm all blocks must evaluate
+//! to `()`. `feature_scope!` is taken by the attribute, hence the name:
+//!
+//! ```rust
+//! fn backend() -> &'static str {
+//!     feature_scope::feature_scope_select! {
+//!         any(postgres, mysql) => { "sql" },
+//!         sqlite => { "sqlite" },
+//!         else => { "memory" },
+//!     }
+//! }
+//! # assert_eq!(backend(), "memory");
+//! ```
+//!
+//! `feature_scope_switch!` does the same for items, typically to pick one backend of several.
+//! Each arm is an item, or several in braces, and the items of the `_` arm are compiled when no
+//! other arm is enabled. `_ => compile_error!("select a backend")` instead stops a build that
+//! selects none:
+//!
+//! ```rust
+//! pub mod sqlite_impl {}
+//! pub mod pg_impl {}
+//! pub mod memory_impl {
+//!     pub const NAME: &str = "memory";
+//! }
+//!
+//! feature_scope::feature_scope_switch! {
+//!     sqlite => pub use crate::sqlite_impl as backend,
+//!     postgres => pub use crate::pg_impl as backend,
+//!     _ => pub use crate::memory_impl as backend,
+//! }
+//! # fn main() { assert_eq!(backend::NAME, "memory"); }
+//! ```
+//!
+//! `cfg_scope!` is the `cfg!` of the scopes: it takes the same predicates and evaluates to a
+//! `bool` constant, also in const contexts. Both branches of an `if cfg_scope!(...)` are
+//! compiled, so they can only use items that exist in every scope:
+//!
+//! ```rust
+//! use feature_scope::cfg_scope;
+//!
+//! const METRICS: bool = cfg_scope!(metrics);
+//!
+//! fn interval() -> u32 {
+//!     if cfg_scope!(all(metrics, not(low_power))) { 10 } else { 60 }
+//! }
+//! # assert!(!METRICS && interval() == 60);
+//! ```
+//!
+//! ## Documentation
+//!
+//! By default, an item whose scope is disabled is missing from the documentation too, which is
+//! `doc = "hide"`. With `doc = "show-with-note"`, rustdoc always documents the item and appends
+//! a note naming the scope it requires. The item is then compiled under `cfg(doc)` whatever the
+//! scopes, so it should not have an alternative of the same name gated by another scope:
+//!
+//! ```rust
+//! use feature_scope::feature_scope;
+//!
+//! /// Exports the collected metrics.
+//! #[feature_scope(metrics, doc = "show-with-note")]
+//! pub fn export_metrics() {}
+//! ```
+//!
+//! ## Stubs
+//!
+//! Callers of a disabled item stop compiling, which makes partial builds noisy and leaves
+//! rust-analyzer without the item. With `stub`, a disabled function keeps its signature and
+//! gets an `unimplemented!()` body naming the scope instead, so its callers still type-check.
+//! Impl blocks and traits get their method bodies stubbed the same way. Functions returning
+//! `impl Trait` cannot be stubbed, there is no type for the stub to return:
+//!
+//! ```rust
+//! use feature_scope::feature_scope;
+//!
+//! #[feature_scope(postgres, stub)]
+//! pub fn connect(url: &str) -> u16 {
+//!     5432
+//! }
+//!
+//! // Compiles without `postgres` too, and panics when called
+//! pub fn port() -> u16 {
+//!     connect("localhost")
+//! }
+//! ```
+//!
+//! `stub-disabled-items = true` in `[package.metadata.feature-scope-decl]` stubs every disabled
+//! function, impl block and trait of the crate, and still removes the other items.
+//!
+//! ## Scoped Config Fields
+//!
+//! `#[feature_scope_serde]` on a serde struct handles fields that only exist under a scope. Mark
+//! the fields with `#[feature_scope_serde(feature)]`: in scope, the field is kept with
+//! `#[serde(default)]`; out of scope, a hidden placeholder still accepts the key and is never
+//! serialized, so the same config files keep parsing. An accessor of the same name returns the
+//! field as an `Option` either way. Put the attribute above `#[derive]`, and keep the field
+//! type itself outside of the scope:
+//!
+//! ```rust
+//! #[feature_scope::feature_scope_serde]
+//! #[derive(Default, serde::Serialize, serde::Deserialize)]
+//! pub struct Config {
+//!     pub interval: u32,
+//!     #[feature_scope_serde(metrics)]
+//!     pub metrics: MetricsConfig,
+//! }
+//!
+//! #[derive(Default, serde::Serialize, serde::Deserialize)]
+//! pub struct MetricsConfig {
+//!     pub port: u16,
+//! }
+//!
+//! fn metrics_port(config: &Config) -> Option<u16> {
+//!     config.metrics().map(|metrics| metrics.port)
+//! }
+//! ```
+//!
+//! ## Benchmarks
+//!
+//! `#[feature_scope_bench]` gates a benchmark function, of criterion or libtest, by scope like
+//! `#[feature_scope]`. Out of scope, the function is replaced by a placeholder of the same
+//! signature, so `criterion_group!` still finds it, which prints that the benchmark is skipped
+//! and marks it skipped for `cargo feature-scope bench --compare-scopes`, and `#[bench]`
+//! functions are ignored. The benchmark set stays the same across scope configurations.
+//! `name` gives the id criterion reports, which defaults to the function name:
+//!
+//! ```rust
+//! # struct Criterion;
+//! use feature_scope::feature_scope_bench;
+//!
+//! #[feature_scope_bench(tracing, name = "parse/traced")]
+//! fn parse_traced(c: &mut Criterion) {
+//!     c.benchmark_group("parse").bench_function("traced", |b| b.iter(|| ()));
+//! }
+//! # parse_traced(&mut Criterion);
+//! ```
+//!
+//! ## Capabilities
+//!
+//! `feature_scope_capabilities!()` generates a `pub enum Capability` with a variant for every
+//! scope the crate declares, in UpperCamelCase, so code can report at runtime what the build
+//! supports, e.g. in a `--version` output or a health endpoint. `Capability::ALL` lists every
+//! variant, `Capability::enabled()` the ones this build compiles, and `name` and `from_name`
+//! convert from and to the declared names. The enum follows the declarations, the crate is
+//! rebuilt when they change. The visibility and the name can be given:
+//!
+//! ```rust,ignore
+//! feature_scope::feature_scope_capabilities!(pub(crate) enum Feature);
+//!
+//! for feature in Feature::enabled() {
+//!     println!("{feature}");
+//! }
+//! ```
+//!
 //! ## Build Commands
 //!
 //! Use `cargo feature-scope` commands instead of regular `cargo` commands to build your project:
@@ -65,45 +310,579 @@
 //! cargo feature-scope run
 //! cargo feature-scope test
 //! ```
+//!
+//! ## Setup Check
+//!
+//! Built with plain `cargo`, every scoped item is silently compiled out. Put
+//! `feature_scope_setup!()` at the crate root to get a warning in that case, or
+//! `feature_scope_setup!(require)` to turn it into a compile error. The check needs no build
+//! script: it reads the `FEATURE_SCOPE_CFGS` variable that `cargo feature-scope` exports, and
+//! that `feature_scope_core::load()` sets for the crate when its build script passes the scopes.
+//!
+//! ```rust
+//! feature_scope::feature_scope_setup!();
+//! ```
+//!
+//! A crate with neither has to register the scope cfgs for the `unexpected_cfgs` lint itself,
+//! as macros cannot pass `--check-cfg` or add crate attributes. The warning lists the ones its
+//! `feature-scope-decl` declares that the `check-cfg` of its `[lints.rust]`, or of the
+//! workspace's with `lints.workspace = true`, is missing:
+//!
+//! ```toml
+//! [lints.rust]
+//! unexpected_cfgs = { level = "warn", check-cfg = ['cfg(__scope_default)', 'cfg(__scope_a)'] }
+//! ```
+//!
+//! ## Cargo Features
+//!
+//! A library published to crates.io is also built by consumers selecting cargo features with
+//! plain `cargo`. With the `cargo-features` feature of this crate, the cargo feature of a scope's
+//! name enables it too: `#[feature_scope(a)]` expands to `#[cfg(any(__scope_a, feature = "a"))]`
+//! and `#[feature_scope_default]` also compiles under `feature = "default"`. The library needs a
+//! `[features]` entry for each scope, which `cargo feature-scope init` and `migrate` add:
+//!
+//! ```toml
+//! [dependencies]
+//! feature-scope = { version = "...", features = ["cargo-features"] }
+//!
+//! [features]
+//! default = ["a"]
+//! a = []
+//! b = []
+//! ```
+//!
+//! Being a feature, it applies to every crate of the build using the macros, and cargo features
+//! of consumers are not isolated by package like scopes are.
 
+mod capabilities;
 mod parser;
+mod setup;
+mod stub;
+mod timing;
 
 use proc_macro::TokenStream;
+use proc_macro2::TokenTree;
 use quote::quote;
 use syn::parse_macro_input;
 
 #[proc_macro_attribute]
 pub fn feature_scope(attr: TokenStream, input: TokenStream) -> TokenStream {
-    let input = proc_macro2::TokenStream::from(input);
-    let attr = parse_macro_input!(attr as parser::FeatureScope);
-
-    let parser::FeatureScope { ident } = attr;
-    quote! {
-        #[allow(unexpected_cfgs)]
-        #[cfg(#ident)]
-        #input
-    }
-    .into()
+    timing::record("feature_scope", || {
+        let input = proc_macro2::TokenStream::from(input);
+        let attr = parse_macro_input!(attr as parser::FeatureScope);
+
+        let parser::FeatureScope {
+            predicate,
+            doc,
+            stub,
+        } = attr;
+        let note = format!("Requires scope `{predicate}`.");
+        gate(quote! { #predicate }, doc, stub, &note, input)
+    })
 }
 
 #[proc_macro_attribute]
 pub fn feature_scope_default(_attr: TokenStream, input: TokenStream) -> TokenStream {
-    let input = proc_macro2::TokenStream::from(input);
-    let attr = parse_macro_input!(_attr as parser::FeatureScopeDefault);
+    timing::record("feature_scope_default", || {
+        let input = proc_macro2::TokenStream::from(input);
+        let attr = parse_macro_input!(_attr as parser::FeatureScopeDefault);
 
-    if let Some(ident) = attr.ident {
-        quote! {
+        let default = parser::scope_cfg(
+            "default",
+            &syn::Ident::new("__scope_default", proc_macro2::Span::call_site()),
+        );
+        if let Some(predicate) = attr.predicate {
+            let note =
+                format!("Requires scope `{predicate}` unless the default scopes are enabled.");
+            gate(
+                quote! { any(#default, #predicate) },
+                attr.doc,
+                attr.stub,
+                &note,
+                input,
+            )
+        } else {
+            let note = "Requires the default scopes.";
+            gate(default, attr.doc, attr.stub, note, input)
+        }
+    })
+}
+
+// Gates an item on a cfg. With `doc = "show-with-note"`, rustdoc always sees the item, and the
+// note follows the item's own documentation. A stub, if any, is compiled whenever the item is not.
+fn gate(
+    cfg: proc_macro2::TokenStream,
+    doc: parser::DocMode,
+    stub: bool,
+    note: &str,
+    input: proc_macro2::TokenStream,
+) -> TokenStream {
+    let stub = match stub::stub_for(&input, note, stub) {
+        Ok(stub) => stub.map(|stub| {
+            let shown = match doc {
+                parser::DocMode::Hide => cfg.clone(),
+                parser::DocMode::ShowWithNote => quote! { any(doc, #cfg) },
+            };
+            quote! {
+                #[allow(unexpected_cfgs)]
+                #[cfg(not(#shown))]
+                #stub
+            }
+        }),
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let gated = match doc {
+        parser::DocMode::Hide => quote! {
             #[allow(unexpected_cfgs)]
-            #[cfg(any(__scope_default, #ident))]
+            #[cfg(#cfg)]
             #input
+        },
+        parser::DocMode::ShowWithNote => {
+            let (attrs, item) = split_outer_attributes(input);
+            let note = format!("\n**{note}**");
+            quote! {
+                #[allow(unexpected_cfgs)]
+                #[cfg(any(doc, #cfg))]
+                #attrs
+                #[cfg_attr(doc, doc = #note)]
+                #item
+            }
         }
-        .into()
-    } else {
+    };
+    quote! { #gated #stub }.into()
+}
+
+// Splits the outer attributes, doc comments included, off the item they belong to
+fn split_outer_attributes(
+    input: proc_macro2::TokenStream,
+) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let mut tokens = input.into_iter().peekable();
+    let mut attrs = proc_macro2::TokenStream::new();
+    while let Some(TokenTree::Punct(punct)) = tokens.peek() {
+        if punct.as_char() != '#' {
+            break;
+        }
+        attrs.extend(tokens.next());
+        attrs.extend(tokens.next());
+    }
+    (attrs, tokens.collect())
+}
+
+// Written by the placeholders of disabled benchmarks, must stay in sync with the bench
+// comparison of the CLI
+const SKIPPED_MARKER: &str = "feature-scope-skipped";
+
+#[proc_macro_attribute]
+pub fn feature_scope_bench(attr: TokenStream, input: TokenStream) -> TokenStream {
+    timing::record("feature_scope_bench", || {
+        let parser::FeatureScopeBench { predicate, name } =
+            parse_macro_input!(attr as parser::FeatureScopeBench);
+        let function = parse_macro_input!(input as syn::ItemFn);
+        if !matches!(function.sig.output, syn::ReturnType::Default) {
+            return syn::Error::new_spanned(
+                &function.sig.output,
+                "`#[feature_scope_bench]` only applies to benchmark functions returning `()`",
+            )
+            .to_compile_error()
+            .into();
+        }
+
+        // Out of scope, a function of the same signature keeps the registration with the
+        // harness compiling and reports the benchmark as skipped under its name
+        let name = name.unwrap_or_else(|| function.sig.ident.to_string());
+        let note = format!("requires scope `{predicate}`");
+        let mut placeholder = function.clone();
+        let is_libtest = placeholder
+            .attrs
+            .iter()
+            .any(|attr| attr.path().is_ident("bench"));
+        if is_libtest
+            && !placeholder
+                .attrs
+                .iter()
+                .any(|attr| attr.path().is_ident("ignore"))
+        {
+            placeholder.attrs.push(syn::parse_quote!(#[ignore]));
+        }
+        placeholder
+            .attrs
+            .push(syn::parse_quote!(#[allow(unused_variables)]));
+        placeholder.block = syn::parse_quote!({
+            ::std::println!("Benchmarking {}: skipped, {}", #name, #note);
+            if let ::core::option::Option::Some(home) = ::std::env::var_os("CRITERION_HOME") {
+                let dir = ::std::path::Path::new(&home).join(#name);
+                let _ = ::std::fs::create_dir_all(&dir)
+                    .and_then(|()| ::std::fs::write(dir.join(#SKIPPED_MARKER), #note));
+            }
+        });
+
         quote! {
             #[allow(unexpected_cfgs)]
-            #[cfg(__scope_default)]
-            #input
+            #[cfg(#predicate)]
+            #function
+
+            #[allow(unexpected_cfgs)]
+            #[cfg(not(#predicate))]
+            #placeholder
         }
         .into()
-    }
+    })
+}
+
+#[proc_macro_attribute]
+pub fn feature_scope_serde(attr: TokenStream, input: TokenStream) -> TokenStream {
+    timing::record("feature_scope_serde", || {
+        let _ = parse_macro_input!(attr as parser::FeatureScopeSerde);
+        let mut item = parse_macro_input!(input as syn::ItemStruct);
+
+        let syn::Fields::Named(fields) = &mut item.fields else {
+            return syn::Error::new_spanned(
+                &item.ident,
+                "`#[feature_scope_serde]` only supports structs with named fields",
+            )
+            .to_compile_error()
+            .into();
+        };
+
+        let mut expanded_fields = syn::punctuated::Punctuated::<syn::Field, syn::Token![,]>::new();
+        let mut accessors = Vec::new();
+        for mut field in std::mem::take(&mut fields.named) {
+            let Some(position) = field
+                .attrs
+                .iter()
+                .position(|attr| attr.path().is_ident("feature_scope_serde"))
+            else {
+                expanded_fields.push(field);
+                continue;
+            };
+
+            let attr = field.attrs.remove(position);
+            let predicate = match attr.parse_args::<parser::FeatureScope>() {
+                Ok(parser::FeatureScope {
+                    doc: parser::DocMode::ShowWithNote,
+                    ..
+                }) => {
+                    return syn::Error::new_spanned(
+                        &attr,
+                        "`doc = \"show-with-note\"` is not supported on fields",
+                    )
+                    .to_compile_error()
+                    .into()
+                }
+                Ok(parser::FeatureScope { predicate, .. }) => predicate,
+                Err(err) => return err.to_compile_error().into(),
+            };
+
+            let vis = &field.vis;
+            let ident = field.ident.as_ref().unwrap();
+            let ty = &field.ty;
+            accessors.push(quote! {
+                #[allow(unexpected_cfgs)]
+                #[cfg(#predicate)]
+                #vis fn #ident(&self) -> ::core::option::Option<&#ty> {
+                    ::core::option::Option::Some(&self.#ident)
+                }
+
+                #[allow(unexpected_cfgs)]
+                #[cfg(not(#predicate))]
+                #vis fn #ident(&self) -> ::core::option::Option<&#ty> {
+                    ::core::option::Option::None
+                }
+            });
+
+            // Out of scope, the field still accepts its key so the schema stays the same, but
+            // ignores the value and is never serialized. Only renames carry over to it.
+            let serde_entries = parser::serde_entries(&field.attrs);
+            let renames: Vec<&proc_macro2::TokenStream> = serde_entries
+                .iter()
+                .filter(|(key, _)| key == "rename" || key == "alias")
+                .map(|(_, tokens)| tokens)
+                .collect();
+            let placeholder: syn::Field = syn::parse_quote! {
+                #[allow(unexpected_cfgs, dead_code)]
+                #[cfg(not(#predicate))]
+                #[serde(default, skip_serializing #(, #renames)*)]
+                #[doc(hidden)]
+                #vis #ident: ::serde::de::IgnoredAny
+            };
+
+            field
+                .attrs
+                .insert(0, syn::parse_quote!(#[allow(unexpected_cfgs)]));
+            field.attrs.insert(1, syn::parse_quote!(#[cfg(#predicate)]));
+            if !serde_entries.iter().any(|(key, _)| key == "default") {
+                field.attrs.push(syn::parse_quote!(#[serde(default)]));
+            }
+
+            expanded_fields.push(field);
+            expanded_fields.push(placeholder);
+        }
+        fields.named = expanded_fields;
+
+        let name = &item.ident;
+        let (impl_generics, ty_generics, where_clause) = item.generics.split_for_impl();
+        quote! {
+            #item
+
+            impl #impl_generics #name #ty_generics #where_clause {
+                #(#accessors)*
+            }
+        }
+        .into()
+    })
+}
+
+#[proc_macro]
+pub fn cfg_scope(input: TokenStream) -> TokenStream {
+    timing::record("cfg_scope", || {
+        let args = parse_macro_input!(input as parser::ScopeArgs);
+        let predicate = match args {
+            parser::ScopeArgs {
+                feature: Some(predicate),
+                doc: None,
+                stub: false,
+            } => predicate,
+            parser::ScopeArgs { stub: true, .. } => {
+                return syn::Error::new(
+                    proc_macro2::Span::call_site(),
+                    "`cfg_scope!` only takes a feature or predicate, `stub` does not apply to it",
+                )
+                .to_compile_error()
+                .into();
+            }
+            parser::ScopeArgs { doc: Some(_), .. } => {
+                return syn::Error::new(
+                    proc_macro2::Span::call_site(),
+                    "`cfg_scope!` only takes a feature or predicate, `doc` does not apply to it",
+                )
+                .to_compile_error()
+                .into();
+            }
+            parser::ScopeArgs { feature: None, .. } => {
+                return syn::Error::new(
+                    proc_macro2::Span::call_site(),
+                    "expected a feature name, e.g. `cfg_scope!(a)`",
+                )
+                .to_compile_error()
+                .into();
+            }
+        };
+
+        // The lint attribute needs a statement to sit on, a `let` also works in const contexts
+        quote! {
+            {
+                #[allow(unexpected_cfgs)]
+                let enabled = cfg!(#predicate);
+                enabled
+            }
+        }
+        .into()
+    })
+}
+
+#[proc_macro]
+pub fn feature_scope_select(input: TokenStream) -> TokenStream {
+    timing::record("feature_scope_select", || {
+        let select = parse_macro_input!(input as parser::FeatureScopeSelect);
+
+        // Attributes on match arms are stable, unlike on expressions, and every arm but the
+        // enabled one is compiled out
+        let mut arms = Vec::new();
+        let mut previous = Vec::new();
+        for (predicate, block) in &select.arms {
+            arms.push(quote! {
+                #[allow(unexpected_cfgs)]
+                #[cfg(all(#predicate, not(any(#(#previous),*))))]
+                () => #block
+            });
+            previous.push(predicate);
+        }
+        let fallback = select
+            .fallback
+            .as_ref()
+            .map(|block| quote! { #block })
+            .unwrap_or_else(|| quote! { {} });
+
+        quote! {
+            match () {
+                #(#arms)*
+                #[allow(unexpected_cfgs)]
+                #[cfg(not(any(#(#previous),*)))]
+                () => #fallback
+            }
+        }
+        .into()
+    })
+}
+
+#[proc_macro]
+pub fn feature_scope_switch(input: TokenStream) -> TokenStream {
+    timing::record("feature_scope_switch", || {
+        let switch = parse_macro_input!(input as parser::FeatureScopeSwitch);
+
+        // Like `feature_scope_select!`, the first enabled arm wins, so the arms never collide
+        let mut items = Vec::new();
+        let mut previous = Vec::new();
+        for (predicate, arm) in &switch.arms {
+            for item in arm {
+                items.push(quote! {
+                    #[allow(unexpected_cfgs)]
+                    #[cfg(all(#predicate, not(any(#(#previous),*))))]
+                    #item
+                });
+            }
+            previous.push(predicate);
+        }
+        for item in switch.fallback.iter().flatten() {
+            items.push(quote! {
+                #[allow(unexpected_cfgs)]
+                #[cfg(not(any(#(#previous),*)))]
+                #item
+            });
+        }
+
+        quote! { #(#items)* }.into()
+    })
+}
+
+#[proc_macro]
+pub fn feature_scope_setup(input: TokenStream) -> TokenStream {
+    timing::record("feature_scope_setup", || {
+        let attr = parse_macro_input!(input as parser::FeatureScopeSetup);
+
+        // Exported by `cargo feature-scope` for every cargo invocation it spawns
+        if std::env::var_os(feature_scope_core::env_vars::CFGS).is_some() {
+            return TokenStream::new();
+        }
+
+        let mut message = String::from("this crate is not built through `cargo feature-scope`, so every scoped item is compiled out");
+        let unregistered = setup::unregistered_cfgs();
+        if !unregistered.is_empty() {
+            let specs: Vec<String> = unregistered.iter().map(|cfg| format!("'{cfg}'")).collect();
+            message.push_str(&format!(
+                "; without a build script, register the scope cfgs for the `unexpected_cfgs` lint by adding {} to the `check-cfg` of `[lints.rust] unexpected_cfgs` in Cargo.toml",
+                specs.join(", ")
+            ));
+        }
+        if attr.require {
+            quote! {
+                compile_error!(#message);
+            }
+            .into()
+        } else {
+            // There is no stable way to emit a warning from a proc macro, so use a deprecated item
+            quote! {
+                const _: () = {
+                    #[deprecated(note = #message)]
+                    #[allow(non_upper_case_globals)]
+                    const feature_scope_setup: () = ();
+                    feature_scope_setup
+                };
+            }
+            .into()
+        }
+    })
+}
+
+#[proc_macro]
+pub fn feature_scope_capabilities(input: TokenStream) -> TokenStream {
+    timing::record("feature_scope_capabilities", || {
+        let parser::FeatureScopeCapabilities { vis, name } =
+            parse_macro_input!(input as parser::FeatureScopeCapabilities);
+        let scopes = match capabilities::declared_scopes() {
+            Ok(scopes) => scopes,
+            Err(message) => {
+                return syn::Error::new(name.span(), message)
+                    .to_compile_error()
+                    .into();
+            }
+        };
+
+        let mut variants = Vec::new();
+        let mut cfgs = Vec::new();
+        let mut docs = Vec::new();
+        for scope in &scopes {
+            let variant = capabilities::variant_name(scope);
+            if let Some(index) = variants
+                .iter()
+                .position(|other: &syn::Ident| other == &variant)
+            {
+                let message = format!(
+                    "scopes `{}` and `{scope}` both become the variant `{variant}`",
+                    scopes[index]
+                );
+                return syn::Error::new(name.span(), message)
+                    .to_compile_error()
+                    .into();
+            }
+            variants.push(syn::Ident::new(&variant, name.span()));
+            cfgs.push(match parser::scope_ident(scope, name.span()) {
+                Ok(cfg) => parser::scope_cfg(scope, &cfg),
+                Err(err) => return err.to_compile_error().into(),
+            });
+            docs.push(format!("The `{scope}` scope."));
+        }
+
+        // Including the manifest makes rustc rebuild the crate when the declarations change
+        quote! {
+            const _: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"));
+
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+            #[non_exhaustive]
+            #[allow(dead_code)]
+            #vis enum #name {
+                #(#[doc = #docs] #variants,)*
+            }
+
+            #[allow(unexpected_cfgs, dead_code)]
+            impl #name {
+                /// Every declared scope, whether the build enables it or not.
+                pub const ALL: &'static [#name] = &[#(#name::#variants),*];
+
+                /// The scopes this build is compiled with.
+                pub fn enabled() -> &'static [#name] {
+                    const ENABLED: &[#name] = &[#(#[cfg(#cfgs)] #name::#variants,)*];
+                    ENABLED
+                }
+
+                /// Whether this build is compiled with the scope.
+                pub fn is_enabled(self) -> bool {
+                    match self {
+                        #(#name::#variants => cfg!(#cfgs),)*
+                    }
+                }
+
+                /// The name of the scope, as declared.
+                pub fn name(self) -> &'static str {
+                    match self {
+                        #(#name::#variants => #scopes,)*
+                    }
+                }
+
+                /// The scope declared with the name, if any.
+                pub fn from_name(name: &str) -> Option<Self> {
+                    #name::ALL.iter().copied().find(|scope| scope.name() == name)
+                }
+            }
+
+            impl ::core::fmt::Display for #name {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                    f.write_str(self.name())
+                }
+            }
+        }
+        .into()
+    })
+}
+
+#[proc_macro]
+pub fn declare_feature_scopes(input: TokenStream) -> TokenStream {
+    timing::record("declare_feature_scopes", || {
+        // The declarations are read from the source by `cargo feature-scope` and merged with the
+        // TOML ones, so the macro only checks the syntax
+        let _ = parse_macro_input!(input as parser::DeclareFeatureScopes);
+
+        TokenStream::new()
+    })
 }
@@ -0,0 +1,257 @@
+use feature_scope_core::cfg_name;
+use proc_macro2::{Span, TokenStream};
+use quote::{quote, ToTokens};
+use std::fmt;
+use syn::{
+    ext::IdentExt,
+    parenthesized,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    Ident, LitStr, Token,
+};
+
+/// Options accepted in the `key = value` form, listed in error messages.
+const OPTIONS: &[&str] = &["feature", "doc", "stub"];
+
+/// Arguments shared by the scope attributes.
+///
+/// The grammar is a comma-separated list that starts with an optional bare feature name or
+/// predicate, followed by `key = value` options and flags: `a`, `any(a, b)`, `feature = "a"`,
+/// `a, stub`. New options are added as new keys, so existing usages keep parsing.
+#[derive(Debug, Clone, Default)]
+pub struct ScopeArgs {
+    pub feature: Option<ScopePredicate>,
+    pub doc: Option<DocMode>,
+    /// `stub`: out of scope, functions keep their signatures with `unimplemented!()` bodies.
+    pub stub: bool,
+}
+
+/// How an item shows up in the documentation, set with `doc = "..."`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DocMode {
+    /// The item is documented only when its scope is enabled.
+    #[default]
+    Hide,
+    /// The item is always documented, with a note naming the scope it requires.
+    ShowWithNote,
+}
+
+/// A feature or an `all`/`any`/`not` combination of features, like `all(a, not(b))`.
+#[derive(Debug, Clone)]
+pub enum ScopePredicate {
+    /// A single feature, with the cfg name it maps to.
+    Feature {
+        name: String,
+        cfg: Ident,
+    },
+    All(Vec<ScopePredicate>),
+    Any(Vec<ScopePredicate>),
+    Not(Box<ScopePredicate>),
+}
+
+impl Parse for ScopePredicate {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.call(Ident::parse_any)?;
+        ScopePredicate::parse_after(name, input)
+    }
+}
+
+impl ScopePredicate {
+    // Parses the rest of a predicate whose first identifier has already been read
+    fn parse_after(name: Ident, input: ParseStream) -> syn::Result<Self> {
+        if !input.peek(syn::token::Paren) {
+            let name = name.unraw();
+            return Ok(ScopePredicate::Feature {
+                cfg: scope_ident(&name.to_string(), name.span())?,
+                name: name.to_string(),
+            });
+        }
+
+        let content;
+        parenthesized!(content in input);
+        let operands: Vec<ScopePredicate> =
+            Punctuated::<ScopePredicate, Token![,]>::parse_terminated(&content)?
+                .into_iter()
+                .collect();
+
+        match name.to_string().as_str() {
+            "all" => Ok(ScopePredicate::All(operands)),
+            "any" => Ok(ScopePredicate::Any(operands)),
+            "not" => match <[ScopePredicate; 1]>::try_from(operands) {
+                Ok([operand]) => Ok(ScopePredicate::Not(Box::new(operand))),
+                Err(_) => Err(syn::Error::new(
+                    name.span(),
+                    "`not` takes exactly one predicate",
+                )),
+            },
+            _ => Err(syn::Error::new(
+                name.span(),
+                format!("unknown predicate `{name}`, expected `all`, `any` or `not`"),
+            )),
+        }
+    }
+}
+
+impl ToTokens for ScopePredicate {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        tokens.extend(match self {
+            ScopePredicate::Feature { name, cfg } => scope_cfg(name, cfg),
+            ScopePredicate::All(operands) => quote! { all(#(#operands),*) },
+            ScopePredicate::Any(operands) => quote! { any(#(#operands),*) },
+            ScopePredicate::Not(operand) => quote! { not(#operand) },
+        });
+    }
+}
+
+// Written with the feature names, for the documentation notes
+impl fmt::Display for ScopePredicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |f: &mut fmt::Formatter<'_>, operands: &[ScopePredicate]| {
+            for (index, operand) in operands.iter().enumerate() {
+                if index > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{operand}")?;
+            }
+            Ok(())
+        };
+
+        match self {
+            ScopePredicate::Feature { name, .. } => write!(f, "{name}"),
+            ScopePredicate::All(operands) => {
+                write!(f, "all(")?;
+                join(f, operands)?;
+                write!(f, ")")
+            }
+            ScopePredicate::Any(operands) => {
+                write!(f, "any(")?;
+                join(f, operands)?;
+                write!(f, ")")
+            }
+            ScopePredicate::Not(operand) => write!(f, "not({operand})"),
+        }
+    }
+}
+
+impl Parse for ScopeArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = ScopeArgs::default();
+        let mut is_first = true;
+
+        while !input.is_empty() {
+            let key = input.call(Ident::parse_any)?;
+
+            if input.peek(Token![=]) {
+                input.parse::<Token![=]>()?;
+                match key.to_string().as_str() {
+                    "feature" => {
+                        let value: LitStr = input.parse()?;
+                        let cfg = scope_ident(&value.value(), value.span())?;
+                        let name = value.value();
+                        args.set_feature(ScopePredicate::Feature { name, cfg }, &key)?;
+                    }
+                    "doc" => {
+                        let value: LitStr = input.parse()?;
+                        let mode = match value.value().as_str() {
+                            "hide" => DocMode::Hide,
+                            "show-with-note" => DocMode::ShowWithNote,
+                            other => {
+                                return Err(syn::Error::new(
+                                    value.span(),
+                                    format!("unknown doc mode `{other}`, expected `hide` or `show-with-note`"),
+                                ))
+                            }
+                        };
+                        if args.doc.replace(mode).is_some() {
+                            return Err(syn::Error::new(
+                                key.span(),
+                                "`doc` is specified more than once",
+                            ));
+                        }
+                    }
+                    _ => return Err(unknown_option(&key)),
+                }
+            } else if is_first {
+                // An option first would silently become the feature, and the feature an option
+                let name = key.to_string();
+                if OPTIONS.contains(&name.as_str()) && !input.peek(syn::token::Paren) {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        format!("`{name}` is an option, not a feature: name the feature first, or write `feature = \"{name}\"` for a feature of that name"),
+                    ));
+                }
+                let predicate = ScopePredicate::parse_after(key.clone(), input)?;
+                args.set_feature(predicate, &key)?;
+            } else if key == "stub" {
+                if args.stub {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "`stub` is specified more than once",
+                    ));
+                }
+                args.stub = true;
+            } else {
+                return Err(unknown_option(&key));
+            }
+
+            is_first = false;
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+impl ScopeArgs {
+    fn set_feature(&mut self, predicate: ScopePredicate, key: &Ident) -> syn::Result<()> {
+        if self.feature.is_some() {
+            return Err(syn::Error::new(
+                key.span(),
+                "the feature is specified more than once",
+            ));
+        }
+
+        self.feature = Some(predicate);
+        Ok(())
+    }
+}
+
+/// Builds the internal cfg name of a feature, with the mapping of
+/// [`feature_scope_core::cfg_name`].
+pub fn scope_ident(feature: &str, span: Span) -> syn::Result<Ident> {
+    if feature.is_empty() {
+        return Err(syn::Error::new(span, "the feature name is empty"));
+    }
+    if !cfg_name::is_valid_feature_name(feature) {
+        return Err(syn::Error::new(
+            span,
+            format!("`{}` is not a valid feature name, feature names cannot contain `/` or control characters", feature.escape_debug()),
+        ));
+    }
+
+    Ok(Ident::new(&cfg_name::scope_cfg(feature), span))
+}
+
+/// The cfg predicate a feature is enabled under.
+///
+/// With the `cargo-features` feature of this crate, the cargo feature of the same name enables
+/// it too, for consumers building with plain cargo and `--features`.
+pub fn scope_cfg(feature: &str, cfg: &Ident) -> TokenStream {
+    if cfg!(feature = "cargo-features") {
+        quote! { any(#cfg, feature = #feature) }
+    } else {
+        quote! { #cfg }
+    }
+}
+
+fn unknown_option(key: &Ident) -> syn::Error {
+    syn::Error::new(
+        key.span(),
+        format!(
+            "unknown option `{key}`, expected one of: {}",
+            OPTIONS.join(", ")
+        ),
+    )
+}
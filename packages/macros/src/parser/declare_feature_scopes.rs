@@ -0,0 +1,44 @@
+use std::collections::HashSet;
+
+use syn::{
+    bracketed,
+    ext::IdentExt,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    Ident, Token,
+};
+
+/// Declarations written as `default = [a]; a; b = [a];`.
+///
+/// Only the syntax is checked here, the CLI reads the declarations from the source itself.
+#[derive(Debug, Clone)]
+pub struct DeclareFeatureScopes;
+
+impl Parse for DeclareFeatureScopes {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut seen = HashSet::new();
+
+        while !input.is_empty() {
+            let name = input.call(Ident::parse_any)?.unraw();
+            if input.peek(Token![=]) {
+                input.parse::<Token![=]>()?;
+                let content;
+                bracketed!(content in input);
+                Punctuated::<Ident, Token![,]>::parse_terminated_with(&content, Ident::parse_any)?;
+            }
+
+            if !seen.insert(name.to_string()) {
+                return Err(syn::Error::new(
+                    name.span(),
+                    format!("`{name}` is declared more than once"),
+                ));
+            }
+
+            if !input.is_empty() {
+                input.parse::<Token![;]>()?;
+            }
+        }
+
+        Ok(DeclareFeatureScopes)
+    }
+}
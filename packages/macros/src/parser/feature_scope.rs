@@ -1,17 +1,24 @@
-use syn::{
-    parse::{Parse, ParseStream},
-    Ident,
-};
+use syn::parse::{Parse, ParseStream};
+
+use super::{DocMode, ScopeArgs, ScopePredicate};
 
 #[derive(Debug, Clone)]
 pub struct FeatureScope {
-    pub ident: Ident,
+    pub predicate: ScopePredicate,
+    pub doc: DocMode,
+    pub stub: bool,
 }
 
 impl Parse for FeatureScope {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        let ident: Ident = input.parse()?;
-        let ident = Ident::new(&format!("__scope_{ident}"), ident.span());
-        Ok(FeatureScope { ident })
+        let args: ScopeArgs = input.parse()?;
+        let predicate = args
+            .feature
+            .ok_or_else(|| input.error("expected a feature name, e.g. `#[feature_scope(a)]`"))?;
+        Ok(FeatureScope {
+            predicate,
+            doc: args.doc.unwrap_or_default(),
+            stub: args.stub,
+        })
     }
 }
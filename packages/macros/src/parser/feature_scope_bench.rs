@@ -0,0 +1,53 @@
+use syn::{
+    ext::IdentExt,
+    parse::{Parse, ParseStream},
+    Ident, LitStr, Token,
+};
+
+use super::ScopePredicate;
+
+/// Arguments of `#[feature_scope_bench]`: a feature or predicate, and optionally the
+/// `name = "..."` the placeholder reports when the scope is disabled.
+///
+/// The name defaults to the one of the function, and should be the id criterion gives the
+/// benchmark, like `"parse/tracing"` for a benchmark in a group, so the comparison of scope sets
+/// lines up the placeholder with the measurements.
+#[derive(Debug, Clone)]
+pub struct FeatureScopeBench {
+    pub predicate: ScopePredicate,
+    pub name: Option<String>,
+}
+
+impl Parse for FeatureScopeBench {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.is_empty() {
+            return Err(input.error("expected a feature name, e.g. `#[feature_scope_bench(a)]`"));
+        }
+        let predicate: ScopePredicate = input.parse()?;
+
+        let mut name = None;
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let key = input.call(Ident::parse_any)?;
+            if key != "name" {
+                return Err(syn::Error::new(
+                    key.span(),
+                    format!("unknown option `{key}`, expected `name`"),
+                ));
+            }
+            input.parse::<Token![=]>()?;
+            let value: LitStr = input.parse()?;
+            if name.replace(value.value()).is_some() {
+                return Err(syn::Error::new(
+                    key.span(),
+                    "`name` is specified more than once",
+                ));
+            }
+        }
+
+        Ok(FeatureScopeBench { predicate, name })
+    }
+}
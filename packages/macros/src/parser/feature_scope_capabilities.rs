@@ -0,0 +1,30 @@
+use syn::{
+    parse::{Parse, ParseStream},
+    Ident, Token, Visibility,
+};
+
+/// Arguments of `feature_scope_capabilities!`: the visibility and name of the generated enum,
+/// `pub enum Capability` when none are given.
+#[derive(Clone)]
+pub struct FeatureScopeCapabilities {
+    pub vis: Visibility,
+    pub name: Ident,
+}
+
+impl Parse for FeatureScopeCapabilities {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.is_empty() {
+            return Ok(FeatureScopeCapabilities {
+                vis: syn::parse_quote!(pub),
+                name: Ident::new("Capability", input.span()),
+            });
+        }
+        let vis: Visibility = input.parse()?;
+        input.parse::<Token![enum]>()?;
+        let name: Ident = input.parse()?;
+        if !input.is_empty() {
+            return Err(input.error("expected only `[VISIBILITY] enum NAME`"));
+        }
+        Ok(FeatureScopeCapabilities { vis, name })
+    }
+}
@@ -1,21 +1,21 @@
-use syn::{
-    parse::{Parse, ParseStream},
-    Ident,
-};
+use syn::parse::{Parse, ParseStream};
+
+use super::{DocMode, ScopeArgs, ScopePredicate};
 
 #[derive(Debug, Clone)]
 pub struct FeatureScopeDefault {
-    pub ident: Option<Ident>,
+    pub predicate: Option<ScopePredicate>,
+    pub doc: DocMode,
+    pub stub: bool,
 }
 
 impl Parse for FeatureScopeDefault {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        if input.is_empty() {
-            Ok(FeatureScopeDefault { ident: None })
-        } else {
-            let ident: Ident = input.parse()?;
-            let ident = Ident::new(&format!("__scope_{ident}"), ident.span());
-            Ok(FeatureScopeDefault { ident: Some(ident) })
-        }
+        let args: ScopeArgs = input.parse()?;
+        Ok(FeatureScopeDefault {
+            predicate: args.feature,
+            doc: args.doc.unwrap_or_default(),
+            stub: args.stub,
+        })
     }
 }
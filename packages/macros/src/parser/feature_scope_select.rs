@@ -0,0 +1,46 @@
+use syn::{
+    parse::{Parse, ParseStream},
+    Block, Token,
+};
+
+use super::ScopePredicate;
+
+/// The arms of `feature_scope_select!`: `predicate => { ... }`, optionally followed by a final
+/// `else => { ... }`.
+#[derive(Clone)]
+pub struct FeatureScopeSelect {
+    pub arms: Vec<(ScopePredicate, Block)>,
+    pub fallback: Option<Block>,
+}
+
+impl Parse for FeatureScopeSelect {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut arms = Vec::new();
+        let mut fallback = None;
+
+        while !input.is_empty() {
+            if fallback.is_some() {
+                return Err(input.error("the `else` arm must come last"));
+            }
+
+            if input.peek(Token![else]) {
+                input.parse::<Token![else]>()?;
+                input.parse::<Token![=>]>()?;
+                fallback = Some(input.parse()?);
+            } else {
+                let predicate: ScopePredicate = input.parse()?;
+                input.parse::<Token![=>]>()?;
+                arms.push((predicate, input.parse()?));
+            }
+
+            if !input.is_empty() {
+                input.parse::<Option<Token![,]>>()?;
+            }
+        }
+
+        if arms.is_empty() {
+            return Err(input.error("expected at least one arm, e.g. `a => { ... }`"));
+        }
+        Ok(FeatureScopeSelect { arms, fallback })
+    }
+}
@@ -0,0 +1,56 @@
+use proc_macro2::{Group, TokenStream};
+use quote::quote;
+use syn::{
+    parse::{Parse, ParseStream},
+    token, Attribute, Expr, Token,
+};
+
+/// Arguments of `#[feature_scope_serde]` on a struct, which takes none.
+///
+/// The scopes are given on the fields, as `#[feature_scope_serde(a)]` with the same grammar as
+/// `#[feature_scope]`.
+#[derive(Debug, Clone)]
+pub struct FeatureScopeSerde;
+
+impl Parse for FeatureScopeSerde {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if !input.is_empty() {
+            return Err(input.error(
+                "`#[feature_scope_serde]` takes no arguments on the struct, mark the fields with `#[feature_scope_serde(feature)]` instead",
+            ));
+        }
+
+        Ok(FeatureScopeSerde)
+    }
+}
+
+/// Splits the `#[serde(...)]` attributes of a field into their entries, like `rename = "a"`.
+pub fn serde_entries(attrs: &[Attribute]) -> Vec<(String, TokenStream)> {
+    let mut entries = Vec::new();
+
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        // Malformed attributes are left for serde to report
+        let _ = attr.parse_nested_meta(|meta| {
+            let path = &meta.path;
+            let key = path
+                .get_ident()
+                .map(ToString::to_string)
+                .unwrap_or_default();
+
+            let tokens = if meta.input.peek(Token![=]) {
+                let value: Expr = meta.value()?.parse()?;
+                quote! { #path = #value }
+            } else if meta.input.peek(token::Paren) {
+                let group: Group = meta.input.parse()?;
+                quote! { #path #group }
+            } else {
+                quote! { #path }
+            };
+
+            entries.push((key, tokens));
+            Ok(())
+        });
+    }
+
+    entries
+}
@@ -0,0 +1,26 @@
+use syn::{
+    parse::{Parse, ParseStream},
+    Ident,
+};
+
+#[derive(Debug, Clone)]
+pub struct FeatureScopeSetup {
+    pub require: bool,
+}
+
+impl Parse for FeatureScopeSetup {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.is_empty() {
+            Ok(FeatureScopeSetup { require: false })
+        } else {
+            let ident: Ident = input.parse()?;
+            if ident != "require" {
+                return Err(syn::Error::new(
+                    ident.span(),
+                    "expected `require` or no arguments",
+                ));
+            }
+            Ok(FeatureScopeSetup { require: true })
+        }
+    }
+}
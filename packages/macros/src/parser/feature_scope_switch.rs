@@ -0,0 +1,97 @@
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::{
+    braced,
+    parse::{Parse, ParseStream, Parser},
+    token, Item, Token,
+};
+
+use super::ScopePredicate;
+
+/// The arms of `feature_scope_switch!`: `predicate => item` or `predicate => { items }`,
+/// optionally followed by a final `_ => ...`. The `;` ending an arm's item may be left out.
+#[derive(Clone)]
+pub struct FeatureScopeSwitch {
+    pub arms: Vec<(ScopePredicate, Vec<Item>)>,
+    pub fallback: Option<Vec<Item>>,
+}
+
+impl Parse for FeatureScopeSwitch {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut arms = Vec::new();
+        let mut fallback = None;
+
+        while !input.is_empty() {
+            if fallback.is_some() {
+                return Err(input.error("the `_` arm must come last"));
+            }
+
+            if input.peek(Token![_]) {
+                input.parse::<Token![_]>()?;
+                input.parse::<Token![=>]>()?;
+                fallback = Some(parse_items(input)?);
+            } else {
+                let predicate: ScopePredicate = input.parse()?;
+                input.parse::<Token![=>]>()?;
+                arms.push((predicate, parse_items(input)?));
+            }
+
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        if arms.is_empty() {
+            return Err(input
+                .error("expected at least one arm, e.g. `a => pub use crate::a_impl as backend`"));
+        }
+        Ok(FeatureScopeSwitch { arms, fallback })
+    }
+}
+
+// The items of one arm, braced or up to the comma starting the next arm
+fn parse_items(input: ParseStream) -> syn::Result<Vec<Item>> {
+    if input.peek(token::Brace) {
+        let content;
+        braced!(content in input);
+        return parse_item_list(&content);
+    }
+
+    // Commas also separate generic arguments, so only one followed by an arm ends the item
+    let mut tokens = TokenStream::new();
+    while !input.is_empty() && !at_arm_end(input) {
+        tokens.extend([input.parse::<TokenTree>()?]);
+    }
+    let ends_item = match tokens.clone().into_iter().last() {
+        Some(TokenTree::Punct(punct)) => punct.as_char() == ';',
+        Some(TokenTree::Group(group)) => group.delimiter() == proc_macro2::Delimiter::Brace,
+        _ => false,
+    };
+    if !ends_item {
+        <Token![;]>::default().to_tokens(&mut tokens);
+    }
+    parse_item_list.parse2(tokens)
+}
+
+fn parse_item_list(input: ParseStream) -> syn::Result<Vec<Item>> {
+    let mut items = Vec::new();
+    while !input.is_empty() {
+        items.push(input.parse()?);
+    }
+    Ok(items)
+}
+
+fn at_arm_end(input: ParseStream) -> bool {
+    if !input.peek(Token![,]) {
+        return false;
+    }
+    let fork = input.fork();
+    let _ = fork.parse::<Token![,]>();
+    if fork.is_empty() {
+        return true;
+    }
+    if fork.peek(Token![_]) {
+        return fork.peek2(Token![=>]);
+    }
+    fork.parse::<ScopePredicate>().is_ok() && fork.peek(Token![=>])
+}
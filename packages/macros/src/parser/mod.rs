@@ -1,5 +1,21 @@
+mod args;
+mod declare_feature_scopes;
 mod feature_scope;
+mod feature_scope_bench;
+mod feature_scope_capabilities;
 mod feature_scope_default;
+mod feature_scope_select;
+mod feature_scope_serde;
+mod feature_scope_setup;
+mod feature_scope_switch;
 
+pub use args::*;
+pub use declare_feature_scopes::*;
 pub use feature_scope::*;
+pub use feature_scope_bench::*;
+pub use feature_scope_capabilities::*;
 pub use feature_scope_default::*;
+pub use feature_scope_select::*;
+pub use feature_scope_serde::*;
+pub use feature_scope_setup::*;
+pub use feature_scope_switch::*;
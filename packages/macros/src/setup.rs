@@ -0,0 +1,60 @@
+//! The check-cfg registration of crates built without `cargo feature-scope` or a build script,
+//! for `feature_scope_setup!`.
+//!
+//! A macro cannot add crate attributes or compiler flags, so such a crate registers its scope
+//! cfgs in the `check-cfg` of its `[lints.rust] unexpected_cfgs`, or of the workspace's with
+//! `lints.workspace = true`. The scopes are the ones of its `feature-scope-decl`, like for
+//! `feature_scope_capabilities!`, and `__scope_default`.
+
+use feature_scope_core::{cfg_name, manifest::find_root_manifest};
+use std::{env, fs, path::Path};
+
+use crate::capabilities;
+
+/// The `cfg(...)` the crate being compiled has yet to list in its `check-cfg`, empty if its
+/// manifest cannot be read.
+pub fn unregistered_cfgs() -> Vec<String> {
+    let Some(manifest_dir) = env::var_os("CARGO_MANIFEST_DIR") else {
+        return Vec::new();
+    };
+    let manifest_dir = Path::new(&manifest_dir);
+    let Some(manifest) = read_manifest(&manifest_dir.join("Cargo.toml")) else {
+        return Vec::new();
+    };
+
+    let lints = manifest.get("lints");
+    let inherits = lints
+        .and_then(|lints| lints.get("workspace"))
+        .and_then(toml::Value::as_bool)
+        .unwrap_or(false);
+    let lints = if inherits {
+        find_root_manifest(manifest_dir)
+            .ok()
+            .and_then(|root| read_manifest(&root))
+            .and_then(|root| root.get("workspace")?.get("lints").cloned())
+    } else {
+        lints.cloned()
+    };
+    let registered: Vec<String> = lints
+        .as_ref()
+        .and_then(|lints| lints.get("rust")?.get("unexpected_cfgs")?.get("check-cfg"))
+        .and_then(toml::Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(toml::Value::as_str)
+        .filter_map(|spec| spec.trim().strip_prefix("cfg(")?.strip_suffix(')'))
+        .flat_map(|names| names.split(','))
+        .map(|name| name.trim().to_string())
+        .collect();
+
+    let scopes = capabilities::declared_scopes().unwrap_or_default();
+    std::iter::once(String::from("__scope_default"))
+        .chain(scopes.iter().map(|scope| cfg_name::scope_cfg(scope)))
+        .filter(|cfg| !registered.contains(cfg))
+        .map(|cfg| format!("cfg({cfg})"))
+        .collect()
+}
+
+fn read_manifest(path: &Path) -> Option<toml::Table> {
+    toml::from_str(&fs::read_to_string(path).ok()?).ok()
+}
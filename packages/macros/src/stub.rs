@@ -0,0 +1,107 @@
+//! Stubs of items whose scope is disabled.
+//!
+//! With `#[feature_scope(a, stub)]`, or `stub-disabled-items = true` in the crate's
+//! `[package.metadata.feature-scope-decl]`, a function is not removed when its scope is
+//! disabled but replaced by one with the same signature whose body is `unimplemented!()`, so
+//! its callers still type-check, in rust-analyzer too. Impl blocks and traits get their methods
+//! stubbed the same way. Items without bodies are not stubbed, and neither are functions
+//! returning `impl Trait`, whose stub would have no concrete type to return.
+
+use proc_macro2::TokenStream;
+use quote::ToTokens;
+use std::{env, fs, path::Path};
+use syn::{Block, ImplItem, Item, ReturnType, Signature, TraitItem, Type};
+
+const SWITCH: &str = "stub-disabled-items";
+
+// The item with its bodies replaced, or an error saying why it cannot have one
+fn stub_item(input: &TokenStream, note: &str) -> syn::Result<TokenStream> {
+    let mut item: Item = syn::parse2(input.clone())?;
+    match &mut item {
+        Item::Fn(function) => {
+            stub_function(&function.sig, &mut function.block, note)?;
+            function
+                .attrs
+                .push(syn::parse_quote!(#[allow(unused_variables)]));
+        }
+        Item::Impl(block) => {
+            for item in &mut block.items {
+                if let ImplItem::Fn(method) = item {
+                    stub_function(&method.sig, &mut method.block, note)?;
+                    method
+                        .attrs
+                        .push(syn::parse_quote!(#[allow(unused_variables)]));
+                }
+            }
+        }
+        Item::Trait(definition) => {
+            for item in &mut definition.items {
+                if let TraitItem::Fn(method) = item {
+                    if let Some(block) = &mut method.default {
+                        stub_function(&method.sig, block, note)?;
+                        method
+                            .attrs
+                            .push(syn::parse_quote!(#[allow(unused_variables)]));
+                    }
+                }
+            }
+        }
+        _ => {
+            return Err(syn::Error::new_spanned(
+                input,
+                "`stub` only applies to functions, impl blocks and traits",
+            ))
+        }
+    }
+    Ok(item.into_token_stream())
+}
+
+fn stub_function(sig: &Signature, block: &mut Block, note: &str) -> syn::Result<()> {
+    if let ReturnType::Type(_, ty) = &sig.output {
+        if matches!(**ty, Type::ImplTrait(_)) {
+            return Err(syn::Error::new_spanned(
+                ty,
+                "functions returning `impl Trait` cannot be stubbed, `unimplemented!()` has no type implementing it",
+            ));
+        }
+    }
+    *block = syn::parse_quote!({ ::core::unimplemented!("{}", #note) });
+    Ok(())
+}
+
+// Whether the manifest of the crate being compiled sets `stub-disabled-items = true`
+fn stub_disabled_items() -> bool {
+    let Some(manifest_dir) = env::var_os("CARGO_MANIFEST_DIR") else {
+        return false;
+    };
+    let Ok(content) = fs::read_to_string(Path::new(&manifest_dir).join("Cargo.toml")) else {
+        return false;
+    };
+
+    // Avoid parsing every manifest for every scoped item
+    if !content.contains(SWITCH) {
+        return false;
+    }
+    let Ok(manifest) = toml::from_str::<toml::Table>(&content) else {
+        return false;
+    };
+    manifest
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("feature-scope-decl"))
+        .and_then(|decl| decl.get(SWITCH))
+        .and_then(toml::Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// Returns the stub to compile in place of a disabled item, if it has one. `stub` asks for it
+/// explicitly, while the crate-wide switch only stubs the items that can have a stub.
+pub fn stub_for(input: &TokenStream, note: &str, stub: bool) -> syn::Result<Option<TokenStream>> {
+    if stub {
+        return stub_item(input, note).map(Some);
+    }
+    if stub_disabled_items() {
+        return Ok(stub_item(input, note).ok());
+    }
+    Ok(None)
+}
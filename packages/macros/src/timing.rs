@@ -0,0 +1,93 @@
+//! Opt-in profiling of macro expansion.
+//!
+//! With `FEATURE_SCOPE_MACRO_TIMING=1` in the environment of the compiler, every expansion is
+//! timed. The durations of each compilation are written to a file in
+//! `FEATURE_SCOPE_MACRO_TIMING_DIR`, which `cargo feature-scope` points to
+//! `<target-dir>/feature-scope/macro-timings`, falling back to a directory in the system
+//! temporary directory. `cargo feature-scope macro-timings` adds them up per macro.
+//!
+//! The compiler keeps the macro library loaded while it compiles a crate, so the first expansion
+//! of a compilation creates the file, replacing the one of the last compilation, and every
+//! expansion appends a line to it. The file is named after the crate, its crate type and the
+//! target, like `app-bin-host.tsv`, from the arguments of the compiler, so the library, binaries
+//! and tests of a package and the builds for other targets get files of their own.
+
+use feature_scope_core::env_vars::{MACRO_TIMING, MACRO_TIMING_DIR};
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+static FILE: Mutex<Option<File>> = Mutex::new(None);
+
+/// Runs an expansion, timing it if profiling is enabled.
+pub fn record<T>(name: &'static str, expand: impl FnOnce() -> T) -> T {
+    if env::var_os(MACRO_TIMING).is_none_or(|value| value == "0") {
+        return expand();
+    }
+
+    let started = Instant::now();
+    let output = expand();
+    let elapsed = started.elapsed();
+
+    // Profiling must never break a build, so failures to write are ignored
+    if let Ok(mut file) = FILE.lock() {
+        let _ = append(&mut file, name, elapsed);
+    }
+
+    output
+}
+
+fn append(file: &mut Option<File>, name: &str, elapsed: Duration) -> io::Result<()> {
+    let file = match file {
+        Some(file) => file,
+        None => file.insert(create()?),
+    };
+    writeln!(file, "{name}\t{}", elapsed.as_nanos())
+}
+
+fn create() -> io::Result<File> {
+    let dir = env::var_os(MACRO_TIMING_DIR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| env::temp_dir().join("feature-scope-macro-timings"));
+    fs::create_dir_all(&dir)?;
+
+    let mut file = File::create(dir.join(file_name(&env::args().collect::<Vec<_>>())))?;
+    file.write_all(b"macro\tns\n")?;
+    Ok(file)
+}
+
+// `<crate>-<crate type>-<target>.tsv`, the crate type being `test` for test harnesses and the
+// target `host` unless the compiler is given one
+fn file_name(args: &[String]) -> String {
+    let option = |name: &str| {
+        args.iter()
+            .enumerate()
+            .find_map(|(index, arg)| match arg.strip_prefix(name) {
+                Some("") => args.get(index + 1).cloned(),
+                Some(value) => value.strip_prefix('=').map(String::from),
+                None => None,
+            })
+    };
+
+    let crate_name = env::var("CARGO_CRATE_NAME").unwrap_or_else(|_| String::from("unknown"));
+    let crate_type = if args.iter().any(|arg| arg == "--test") {
+        String::from("test")
+    } else {
+        option("--crate-type").unwrap_or_else(|| String::from("bin"))
+    };
+    // Custom targets are given as paths to their specification
+    let target = option("--target").map_or_else(
+        || String::from("host"),
+        |target| {
+            Path::new(&target)
+                .file_stem()
+                .map_or(target.clone(), |stem| stem.to_string_lossy().into_owned())
+        },
+    );
+    format!("{crate_name}-{}-{target}.tsv", crate_type.replace('-', "_"))
+}